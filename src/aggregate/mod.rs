@@ -0,0 +1,767 @@
+use crate::error::NetFlowError;
+use crate::field::FieldTypes::{
+    FIRST_SWITCHED, FLOW_END_MILLISECONDS, FLOW_END_SECONDS, FLOW_START_MILLISECONDS,
+    FLOW_START_SECONDS, LAST_SWITCHED,
+};
+use crate::field::{
+    element, element_name, field_length, DataType, FieldLength, FieldValue, FlowField,
+};
+use crate::flowset::{DataTemplateItem, FlowSet, Record};
+use crate::ipfix::{IpfixMessage, IpfixSet};
+use crate::json::field_to_json;
+use crate::netflow::NetFlow9;
+use crate::transform::TemplateIds;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::Write;
+use std::time::Duration;
+
+/// Values of the key fields of a record, masked, None for fields it lacks.
+type GroupKey = Vec<Option<Vec<u8>>>;
+
+/// Length of an aggregate in a record, the longest the element may have.
+fn aggregate_length(type_id: u16) -> u16 {
+    match field_length(type_id) {
+        FieldLength::Fixed(length) => length,
+        FieldLength::Number { max, .. } => max,
+        FieldLength::OneOf(lengths) => lengths.iter().cloned().max().unwrap_or(8),
+        FieldLength::Variable => 8,
+    }
+}
+
+/// Largest number that fits in `length` bytes.
+fn max_value(length: u16) -> u64 {
+    match length {
+        0 => 0,
+        1..=7 => (1 << (8 * u32::from(length))) - 1,
+        _ => u64::MAX,
+    }
+}
+
+/// A group-by field, addresses may be masked to a prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyField {
+    pub type_id: u16,
+    pub prefix: Option<u8>,
+}
+
+impl KeyField {
+    /// Mask the value to its first `prefix` bits.
+    fn key_bytes(&self, field: &FlowField) -> Vec<u8> {
+        let mut bytes = field.value().to_bytes(field.length());
+
+        if let Some(prefix) = self.prefix {
+            let prefix = usize::from(prefix);
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                if prefix <= i * 8 {
+                    *byte = 0;
+                } else if prefix < (i + 1) * 8 {
+                    *byte &= 0xffu8 << ((i + 1) * 8 - prefix);
+                }
+            }
+        }
+
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AggregateOp {
+    Sum,
+    Min,
+    Max,
+}
+
+/// A counter computed over the records of a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aggregate {
+    pub type_id: u16,
+    pub op: AggregateOp,
+}
+
+impl Aggregate {
+    /// JSON name, the element name for sums and e.g. "octetDeltaCount_max" otherwise.
+    pub fn name(&self) -> String {
+        match self.op {
+            AggregateOp::Sum => element_name(self.type_id),
+            AggregateOp::Min => format!("{}_min", element_name(self.type_id)),
+            AggregateOp::Max => format!("{}_max", element_name(self.type_id)),
+        }
+    }
+
+    fn update(&self, current: Option<u64>, value: u64) -> u64 {
+        match (self.op, current) {
+            (_, None) => value,
+            (AggregateOp::Sum, Some(sum)) => sum.saturating_add(value),
+            (AggregateOp::Min, Some(min)) => min.min(value),
+            (AggregateOp::Max, Some(max)) => max.max(value),
+        }
+    }
+}
+
+/// Which flow timestamp puts a record in a time bin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowTime {
+    Start,
+    End,
+}
+
+/// Time of the flow in milliseconds since the epoch, taken from flowStartMilliseconds,
+/// flowStartSeconds or FIRST_SWITCHED for the start and their end counterparts for the end.
+/// FIRST_SWITCHED and LAST_SWITCHED need the `sys_uptime` of a v9 header, records without a
+/// usable time fall back to the export time.
+pub fn flow_time(
+    record: &Record,
+    which: FlowTime,
+    export_time: u32,
+    sys_uptime: Option<u32>,
+) -> u64 {
+    let (millis, seconds, switched) = match which {
+        FlowTime::Start => (FLOW_START_MILLISECONDS, FLOW_START_SECONDS, FIRST_SWITCHED),
+        FlowTime::End => (FLOW_END_MILLISECONDS, FLOW_END_SECONDS, LAST_SWITCHED),
+    };
+    let export_ms = u64::from(export_time) * 1000;
+    let value = |type_id| record.get(type_id).and_then(|field| field.value().to_u64());
+
+    if let Some(ms) = value(millis) {
+        ms
+    } else if let Some(secs) = value(seconds) {
+        secs * 1000
+    } else if let (Some(switched), Some(sys_uptime)) = (value(switched), sys_uptime) {
+        export_ms.saturating_sub(u64::from(sys_uptime.wrapping_sub(switched as u32)))
+    } else {
+        export_ms
+    }
+}
+
+/// One group of one time bin.
+#[derive(Debug, Clone)]
+pub struct AggregateRow {
+    /// bin bounds in milliseconds since the epoch, the end is excluded
+    pub start: u64,
+    pub end: u64,
+    /// the key fields the records had, masked
+    pub key: Vec<FlowField>,
+    /// None when no record of the group had the field
+    pub values: Vec<(Aggregate, Option<u64>)>,
+    /// number of records in the group
+    pub records: u64,
+}
+
+impl AggregateRow {
+    /// One flat JSON object keyed by element names, bin bounds are flowStartMilliseconds
+    /// and flowEndMilliseconds.
+    pub fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert(
+            element_name(FLOW_START_MILLISECONDS),
+            Value::from(self.start),
+        );
+        object.insert(element_name(FLOW_END_MILLISECONDS), Value::from(self.end));
+        for field in &self.key {
+            object.insert(element_name(field.type_id()), field_to_json(field));
+        }
+        for (aggregate, value) in &self.values {
+            object.insert(aggregate.name(), value.map_or(Value::Null, Value::from));
+        }
+        object.insert("records".to_string(), Value::from(self.records));
+
+        Value::Object(object)
+    }
+
+    /// Fields of the row as a template: bin bounds, key fields, then the aggregates at the
+    /// longest length of their element.
+    fn layout(&self) -> Vec<(u16, u16)> {
+        let mut layout = vec![(FLOW_START_MILLISECONDS, 8), (FLOW_END_MILLISECONDS, 8)];
+        layout.extend(
+            self.key
+                .iter()
+                .map(|field| (field.type_id(), field.length())),
+        );
+        layout.extend(
+            self.values
+                .iter()
+                .map(|(aggregate, _)| (aggregate.type_id, aggregate_length(aggregate.type_id))),
+        );
+        layout
+    }
+
+    /// The row as a record of the template, missing values are 0 and values too large for
+    /// the field, such as a sum of TCP flags, are capped to the largest it holds.
+    pub fn to_record(&self, template: &DataTemplateItem) -> Result<Record, NetFlowError> {
+        let mut builder = template
+            .record_builder()
+            .set(FLOW_START_MILLISECONDS, self.start)
+            .set(FLOW_END_MILLISECONDS, self.end);
+        for field in &self.key {
+            builder = builder.set(field.type_id(), field.value().clone());
+        }
+        for (aggregate, value) in &self.values {
+            let max = max_value(aggregate_length(aggregate.type_id));
+            builder = builder.set(aggregate.type_id, value.unwrap_or(0).min(max));
+        }
+        builder.build()
+    }
+}
+
+/// Write the rows as JSON lines, returns the number written.
+pub fn write_json_rows<W: Write>(writer: &mut W, rows: &[AggregateRow]) -> io::Result<usize> {
+    for row in rows {
+        serde_json::to_writer(&mut *writer, &row.to_json())?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(rows.len())
+}
+
+#[derive(Debug, Clone)]
+struct Group {
+    key: Vec<FlowField>,
+    values: Vec<Option<u64>>,
+    records: u64,
+}
+
+/// Builds an `Aggregator`, see `Aggregator::builder`.
+#[derive(Debug)]
+pub struct AggregatorBuilder {
+    bin: Duration,
+    time: FlowTime,
+    keys: Vec<KeyField>,
+    aggregates: Vec<Aggregate>,
+    error: Option<NetFlowError>,
+}
+
+impl AggregatorBuilder {
+    fn add_key(mut self, key: KeyField) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+
+        let data_type = element(key.type_id).map(|element| element.data_type);
+        let max = match data_type {
+            Some(DataType::Ipv4Address) => 32,
+            Some(DataType::Ipv6Address) => 128,
+            _ => 0,
+        };
+        match key.prefix {
+            Some(_) if max == 0 => {
+                self.error = Some(NetFlowError::invalid_config(format!(
+                    "{} is not an address",
+                    element_name(key.type_id)
+                )));
+            }
+            Some(prefix) if prefix > max => {
+                self.error = Some(NetFlowError::invalid_config(format!(
+                    "prefix /{} of {} is longer than {} bits",
+                    prefix,
+                    element_name(key.type_id),
+                    max
+                )));
+            }
+            _ => self.keys.push(key),
+        }
+        self
+    }
+
+    fn add_aggregate(mut self, type_id: u16, op: AggregateOp) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+
+        let data_type = element(type_id).map(|element| element.data_type);
+        match data_type {
+            None
+            | Some(DataType::Unsigned8)
+            | Some(DataType::Unsigned16)
+            | Some(DataType::Unsigned32)
+            | Some(DataType::Unsigned64)
+            | Some(DataType::DateTimeSeconds)
+            | Some(DataType::DateTimeMilliseconds) => {
+                self.aggregates.push(Aggregate { type_id, op });
+            }
+            Some(data_type) => {
+                self.error = Some(NetFlowError::invalid_config(format!(
+                    "{} is {:?}, not a number",
+                    element_name(type_id),
+                    data_type
+                )));
+            }
+        }
+        self
+    }
+
+    /// Group by the value of the field.
+    pub fn key(self, type_id: u16) -> Self {
+        self.add_key(KeyField {
+            type_id,
+            prefix: None,
+        })
+    }
+
+    /// Group by the first `prefix` bits of an address field.
+    pub fn key_prefix(self, type_id: u16, prefix: u8) -> Self {
+        self.add_key(KeyField {
+            type_id,
+            prefix: Some(prefix),
+        })
+    }
+
+    pub fn sum(self, type_id: u16) -> Self {
+        self.add_aggregate(type_id, AggregateOp::Sum)
+    }
+
+    pub fn min(self, type_id: u16) -> Self {
+        self.add_aggregate(type_id, AggregateOp::Min)
+    }
+
+    pub fn max(self, type_id: u16) -> Self {
+        self.add_aggregate(type_id, AggregateOp::Max)
+    }
+
+    /// Bin records on the flow start instead of the flow end.
+    pub fn bin_on(mut self, time: FlowTime) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn build(self) -> Result<Aggregator, NetFlowError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let bin = self.bin.as_millis() as u64;
+        if bin == 0 {
+            return Err(NetFlowError::invalid_config(
+                "time bin shorter than a millisecond",
+            ));
+        }
+        if self.aggregates.is_empty() {
+            return Err(NetFlowError::invalid_config("no aggregate"));
+        }
+        // a record holds each element once
+        let mut output = vec![FLOW_START_MILLISECONDS, FLOW_END_MILLISECONDS];
+        output.extend(self.keys.iter().map(|key| key.type_id));
+        for aggregate in &self.aggregates {
+            if output.contains(&aggregate.type_id) {
+                return Err(NetFlowError::invalid_config(format!(
+                    "{} is in the output twice",
+                    element_name(aggregate.type_id)
+                )));
+            }
+            output.push(aggregate.type_id);
+        }
+
+        Ok(Aggregator {
+            bin,
+            time: self.time,
+            keys: self.keys,
+            aggregates: self.aggregates,
+            bins: BTreeMap::new(),
+            template_ids: TemplateIds::new(),
+        })
+    }
+}
+
+/// Groups data records by key fields in tumbling time bins of the flow timestamps.
+///
+/// A bin stays open until `expire` is called with a time past its end, a record arriving
+/// later for that bin opens it again and its group is emitted a second time.
+#[derive(Debug)]
+pub struct Aggregator {
+    /// bin width in milliseconds
+    bin: u64,
+    time: FlowTime,
+    keys: Vec<KeyField>,
+    aggregates: Vec<Aggregate>,
+    bins: BTreeMap<u64, HashMap<GroupKey, Group>>,
+    template_ids: TemplateIds<Vec<(u16, u16)>>,
+}
+
+impl Aggregator {
+    /// Start an aggregation with bins of the duration, at least one aggregate is needed.
+    pub fn builder(bin: Duration) -> AggregatorBuilder {
+        AggregatorBuilder {
+            bin,
+            time: FlowTime::End,
+            keys: Vec::new(),
+            aggregates: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn keys(&self) -> &[KeyField] {
+        &self.keys
+    }
+
+    pub fn aggregates(&self) -> &[Aggregate] {
+        &self.aggregates
+    }
+
+    /// Number of open groups over all bins.
+    pub fn len(&self) -> usize {
+        self.bins.values().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bins.is_empty()
+    }
+
+    /// Add a data record timed in milliseconds since the epoch, option records are ignored.
+    pub fn add_record(&mut self, record: &Record, time: u64) {
+        if let Record::OptionData(_) = record {
+            return;
+        }
+
+        let mut key = Vec::with_capacity(self.keys.len());
+        let mut fields = Vec::new();
+        for key_field in &self.keys {
+            match record.get(key_field.type_id) {
+                Some(field) => {
+                    let bytes = key_field.key_bytes(field);
+                    fields.push(FlowField::new(
+                        field.type_id(),
+                        field.length(),
+                        FieldValue::new(field.type_id(), &bytes),
+                    ));
+                    key.push(Some(bytes));
+                }
+                None => key.push(None),
+            }
+        }
+
+        let start = time - time % self.bin;
+        let aggregates = &self.aggregates;
+        let group = self
+            .bins
+            .entry(start)
+            .or_default()
+            .entry(key)
+            .or_insert_with(|| Group {
+                key: fields,
+                values: vec![None; aggregates.len()],
+                records: 0,
+            });
+
+        group.records += 1;
+        for (aggregate, value) in aggregates.iter().zip(group.values.iter_mut()) {
+            if let Some(number) = record
+                .get(aggregate.type_id)
+                .and_then(|field| field.value().to_u64())
+            {
+                *value = Some(aggregate.update(*value, number));
+            }
+        }
+    }
+
+    /// Add the decoded data records of a v9 packet.
+    pub fn add_netflow9(&mut self, netflow: &NetFlow9) {
+        for flowset in &netflow.flow_sets {
+            if let FlowSet::DataFlow(dataflow) = flowset {
+                for record in dataflow.records.iter().flatten() {
+                    let time = flow_time(
+                        record,
+                        self.time,
+                        netflow.timestamp,
+                        Some(netflow.sys_uptime),
+                    );
+                    self.add_record(record, time);
+                }
+            }
+        }
+    }
+
+    /// Add the decoded data records of an IPFIX message.
+    pub fn add_ipfix(&mut self, message: &IpfixMessage) {
+        for set in &message.sets {
+            if let IpfixSet::Data(data) = set {
+                for record in data.records.iter().flatten() {
+                    let time = flow_time(record, self.time, message.export_time, None);
+                    self.add_record(record, time);
+                }
+            }
+        }
+    }
+
+    fn rows(&self, start: u64, groups: HashMap<GroupKey, Group>) -> Vec<AggregateRow> {
+        let mut rows: Vec<(GroupKey, AggregateRow)> = groups
+            .into_iter()
+            .map(|(key, group)| {
+                let row = AggregateRow {
+                    start,
+                    end: start + self.bin,
+                    key: group.key,
+                    values: self.aggregates.iter().cloned().zip(group.values).collect(),
+                    records: group.records,
+                };
+                (key, row)
+            })
+            .collect();
+        // stable output for the same input
+        rows.sort_by(|a, b| a.0.cmp(&b.0));
+        rows.into_iter().map(|(_, row)| row).collect()
+    }
+
+    /// Remove and return the groups of the bins ending at or before `now`, the time since
+    /// the epoch, oldest bin first.
+    pub fn expire(&mut self, now: Duration) -> Vec<AggregateRow> {
+        let now = now.as_millis() as u64;
+        let mut rows = Vec::new();
+
+        while let Some(start) = self.bins.keys().next().cloned() {
+            if start + self.bin > now {
+                break;
+            }
+            let groups = self.bins.remove(&start).unwrap();
+            rows.append(&mut self.rows(start, groups));
+        }
+
+        rows
+    }
+
+    /// Remove and return the groups of every bin.
+    pub fn flush(&mut self) -> Vec<AggregateRow> {
+        let bins = std::mem::take(&mut self.bins);
+        bins.into_iter()
+            .flat_map(|(start, groups)| self.rows(start, groups))
+            .collect()
+    }
+
+    /// Templates and records of the rows. Rows whose key fields differ in presence or
+    /// length get their own template, the same layout keeps its template id across calls.
+    pub fn to_records(
+        &mut self,
+        rows: &[AggregateRow],
+    ) -> Result<Vec<(DataTemplateItem, Vec<Record>)>, NetFlowError> {
+        let mut output: Vec<(DataTemplateItem, Vec<Record>)> = Vec::new();
+
+        for row in rows {
+            let layout = row.layout();
+            let template_id = self
+                .template_ids
+                .id(layout.clone())
+                .ok_or_else(|| NetFlowError::invalid_config("out of template ids"))?;
+
+            let index = match output
+                .iter()
+                .position(|(template, _)| template.template_id == template_id)
+            {
+                Some(index) => index,
+                None => {
+                    let template = layout
+                        .iter()
+                        .fold(
+                            DataTemplateItem::builder(template_id),
+                            |builder, (id, len)| builder.field_len(*id, *len),
+                        )
+                        .build()?;
+                    output.push((template, Vec::new()));
+                    output.len() - 1
+                }
+            };
+
+            let record = row.to_record(&output[index].0)?;
+            output[index].1.push(record);
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod test_aggregate {
+    use super::*;
+    use crate::field::FieldTypes::*;
+    use std::net::Ipv4Addr;
+
+    fn template() -> DataTemplateItem {
+        DataTemplateItem::builder(256)
+            .field(IPV4_SRC_ADDR)
+            .field(L4_DST_PORT)
+            .field_len(IN_BYTES, 4)
+            .field_len(IN_PKTS, 4)
+            .field(FLOW_END_MILLISECONDS)
+            .build()
+            .unwrap()
+    }
+
+    fn record(src: [u8; 4], port: u16, bytes: u32, end: u64) -> Record {
+        template()
+            .record_builder()
+            .set(IPV4_SRC_ADDR, Ipv4Addr::from(src))
+            .set(L4_DST_PORT, port)
+            .set(IN_BYTES, bytes)
+            .set(IN_PKTS, bytes / 100)
+            .set(FLOW_END_MILLISECONDS, end)
+            .build()
+            .unwrap()
+    }
+
+    fn aggregator() -> Aggregator {
+        Aggregator::builder(Duration::from_secs(60))
+            .key_prefix(IPV4_SRC_ADDR, 24)
+            .key(L4_DST_PORT)
+            .sum(IN_BYTES)
+            .max(IN_PKTS)
+            .build()
+            .unwrap()
+    }
+
+    fn add(aggregator: &mut Aggregator, record: Record) {
+        let time = flow_time(&record, FlowTime::End, 0, None);
+        aggregator.add_record(&record, time);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut aggregator = aggregator();
+        add(&mut aggregator, record([10, 0, 0, 1], 443, 1000, 1_000));
+        add(&mut aggregator, record([10, 0, 0, 200], 443, 500, 59_999));
+        add(&mut aggregator, record([10, 0, 1, 1], 443, 700, 30_000));
+        add(&mut aggregator, record([10, 0, 0, 1], 443, 300, 60_000));
+        assert_eq!(aggregator.len(), 3);
+
+        let rows = aggregator.expire(Duration::from_secs(60));
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].start, 0);
+        assert_eq!(rows[0].end, 60_000);
+        assert_eq!(
+            rows[0].key[0].value().to_bytes(4),
+            Ipv4Addr::new(10, 0, 0, 0).octets().to_vec()
+        );
+        assert_eq!(rows[0].records, 2);
+        assert_eq!(rows[0].values[0].1, Some(1500));
+        assert_eq!(rows[0].values[1].1, Some(10));
+        assert_eq!(rows[1].values[0].1, Some(700));
+
+        let json = rows[0].to_json();
+        assert_eq!(json["sourceIPv4Address"], "10.0.0.0");
+        assert_eq!(json["destinationTransportPort"], 443);
+        assert_eq!(json["octetDeltaCount"], 1500);
+        assert_eq!(json["packetDeltaCount_max"], 10);
+        assert_eq!(json["records"], 2);
+
+        let rows = aggregator.flush();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].start, 60_000);
+        assert!(aggregator.is_empty());
+    }
+
+    #[test]
+    fn test_to_records() {
+        let mut aggregator = aggregator();
+        add(&mut aggregator, record([10, 0, 0, 1], 443, 1000, 1_000));
+        add(&mut aggregator, record([10, 0, 1, 1], 443, 700, 2_000));
+        let without_port =
+            Record::make_data(vec![FlowField::new(IN_BYTES, 4, FieldValue::from(10u32))]);
+        aggregator.add_record(&without_port, 3_000);
+
+        let rows = aggregator.flush();
+        let output = aggregator.to_records(&rows).unwrap();
+        assert_eq!(output.len(), 2);
+        let (template, records) = &output[0];
+        assert_eq!(template.template_id, 256);
+        assert_eq!(template.fields.len(), 4);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].to_bytes().len(), 8 + 8 + 8 + 8);
+        assert_eq!(records[0].get(IN_BYTES).unwrap().value().to_u64(), Some(10));
+        assert_eq!(output[1].1.len(), 2);
+
+        // the same layout keeps its id
+        add(&mut aggregator, record([10, 0, 2, 1], 80, 100, 4_000));
+        let rows = aggregator.flush();
+        let output = aggregator.to_records(&rows).unwrap();
+        assert_eq!(output[0].0.template_id, 257);
+    }
+
+    #[test]
+    fn test_short_elements() {
+        let mut aggregator = Aggregator::builder(Duration::from_secs(60))
+            .sum(TOS)
+            .max(SRC_AS)
+            .max(INPUT_SNMP)
+            .min(FLOW_START_SECONDS)
+            .build()
+            .unwrap();
+        let record = |flags: u8, input: u16| {
+            Record::make_data(vec![
+                FlowField::new(TOS, 1, FieldValue::from(flags)),
+                FlowField::new(SRC_AS, 2, FieldValue::from(64_512u16)),
+                FlowField::new(INPUT_SNMP, 2, FieldValue::from(input)),
+                FlowField::new(FLOW_START_SECONDS, 4, FieldValue::from(1_000u32)),
+            ])
+        };
+        aggregator.add_record(&record(0x12, 3), 1_000);
+        aggregator.add_record(&record(0xff, 7), 2_000);
+
+        let rows = aggregator.flush();
+        let output = aggregator.to_records(&rows).unwrap();
+        let (template, records) = &output[0];
+        let lengths: Vec<u16> = template.fields.iter().map(|field| field.length).collect();
+        assert_eq!(lengths, vec![8, 8, 1, 4, 4, 4]);
+        // the sum of the type of service bytes is capped
+        assert_eq!(records[0].get(TOS).unwrap().value().to_u64(), Some(0xff));
+        assert_eq!(
+            records[0].get(SRC_AS).unwrap().value().to_u64(),
+            Some(64_512)
+        );
+        assert_eq!(
+            records[0].get(INPUT_SNMP).unwrap().value().to_u64(),
+            Some(7)
+        );
+        assert_eq!(
+            records[0].get(FLOW_START_SECONDS).unwrap().value().to_u64(),
+            Some(1_000)
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |builder: AggregatorBuilder| match builder.build() {
+            Err(NetFlowError::InvalidConfig { desc }) => desc,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        let builder = || Aggregator::builder(Duration::from_secs(60));
+
+        assert_eq!(error(builder().key(PROTOCOL)), "no aggregate");
+        assert_eq!(
+            error(builder().key_prefix(IPV4_SRC_ADDR, 33).sum(IN_BYTES)),
+            "prefix /33 of sourceIPv4Address is longer than 32 bits"
+        );
+        assert_eq!(
+            error(builder().key_prefix(L4_SRC_PORT, 8)),
+            "sourceTransportPort is not an address"
+        );
+        assert_eq!(
+            error(builder().sum(IPV4_SRC_ADDR)),
+            "sourceIPv4Address is Ipv4Address, not a number"
+        );
+        assert_eq!(
+            error(builder().min(IN_BYTES).max(IN_BYTES)),
+            "octetDeltaCount is in the output twice"
+        );
+        assert_eq!(
+            error(builder().key(PROTOCOL).sum(PROTOCOL)),
+            "protocolIdentifier is in the output twice"
+        );
+        assert_eq!(
+            error(Aggregator::builder(Duration::from_micros(10)).sum(IN_BYTES)),
+            "time bin shorter than a millisecond"
+        );
+    }
+
+    #[test]
+    fn test_flow_time() {
+        let record = Record::make_data(vec![FlowField::new(
+            LAST_SWITCHED,
+            4,
+            FieldValue::from(90_000u32),
+        )]);
+        assert_eq!(
+            flow_time(&record, FlowTime::End, 1_000, Some(100_000)),
+            990_000
+        );
+        assert_eq!(
+            flow_time(&record, FlowTime::Start, 1_000, Some(100_000)),
+            1_000_000
+        );
+    }
+}
@@ -0,0 +1,472 @@
+mod parquet;
+pub use self::parquet::*;
+
+use crate::error::NetFlowError;
+use crate::field::{element, element_name, DataType as ElementType, FieldValue, FlowField};
+use crate::flowset::{DataTemplateItem, FlowSet, Record};
+use crate::ipfix::{IpfixMessage, IpfixSet};
+use crate::json::RecordContext;
+use crate::netflow::NetFlow9;
+use arrow_array::builder::{
+    make_builder, ArrayBuilder, BinaryBuilder, BooleanBuilder, FixedSizeBinaryBuilder,
+    Float64Builder, StringBuilder, TimestampMillisecondBuilder, TimestampSecondBuilder,
+    UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder,
+};
+use arrow_array::RecordBatch;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+impl From<ArrowError> for NetFlowError {
+    fn from(e: ArrowError) -> Self {
+        NetFlowError::Export {
+            desc: e.to_string(),
+        }
+    }
+}
+
+/// Arrow type of IPv4 address columns.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Ipv4Layout {
+    /// the address as a number, compact and easy to compare by prefix
+    UInt32,
+    /// the 4 bytes of the address, like IPv6 columns
+    FixedBinary,
+}
+
+/// Arrow type of a field, from its type in the element registry.
+/// Unknown fields are kept as binary.
+pub fn arrow_type(type_id: u16, ipv4: Ipv4Layout) -> DataType {
+    let data_type = match element(type_id) {
+        Some(element) => element.data_type,
+        None => return DataType::Binary,
+    };
+
+    match data_type {
+        ElementType::Unsigned8 => DataType::UInt8,
+        ElementType::Unsigned16 => DataType::UInt16,
+        ElementType::Unsigned32 => DataType::UInt32,
+        ElementType::Unsigned64 => DataType::UInt64,
+        ElementType::Float64 => DataType::Float64,
+        ElementType::Boolean => DataType::Boolean,
+        ElementType::MacAddress => DataType::FixedSizeBinary(6),
+        ElementType::String => DataType::Utf8,
+        ElementType::DateTimeSeconds => DataType::Timestamp(TimeUnit::Second, None),
+        ElementType::DateTimeMilliseconds => DataType::Timestamp(TimeUnit::Millisecond, None),
+        // NTP timestamps, kept as they are
+        ElementType::DateTimeMicroseconds | ElementType::DateTimeNanoseconds => DataType::UInt64,
+        ElementType::Ipv4Address => match ipv4 {
+            Ipv4Layout::UInt32 => DataType::UInt32,
+            Ipv4Layout::FixedBinary => DataType::FixedSizeBinary(4),
+        },
+        ElementType::Ipv6Address => DataType::FixedSizeBinary(16),
+        ElementType::OctetArray
+        | ElementType::BasicList
+        | ElementType::SubTemplateList
+        | ElementType::SubTemplateMultiList => DataType::Binary,
+    }
+}
+
+fn downcast<T: ArrayBuilder>(builder: &mut dyn ArrayBuilder) -> &mut T {
+    builder
+        .as_any_mut()
+        .downcast_mut::<T>()
+        .expect("builder made for the column type")
+}
+
+fn number(field: Option<&FlowField>) -> Option<u64> {
+    field.and_then(|field| match field.value() {
+        FieldValue::Ipv4Addr(ip) => Some(u64::from(u32::from(*ip))),
+        value => value.to_u64(),
+    })
+}
+
+// append a field value, or a null when the record has no such field or it does not fit the type
+fn append(builder: &mut dyn ArrayBuilder, data_type: &DataType, field: Option<&FlowField>) {
+    let bytes = field.map(FlowField::to_bytes);
+
+    match data_type {
+        DataType::UInt8 => downcast::<UInt8Builder>(builder)
+            .append_option(number(field).and_then(|n| u8::try_from(n).ok())),
+        DataType::UInt16 => downcast::<UInt16Builder>(builder)
+            .append_option(number(field).and_then(|n| u16::try_from(n).ok())),
+        DataType::UInt32 => downcast::<UInt32Builder>(builder)
+            .append_option(number(field).and_then(|n| u32::try_from(n).ok())),
+        DataType::UInt64 => downcast::<UInt64Builder>(builder).append_option(number(field)),
+        DataType::Timestamp(TimeUnit::Second, _) => downcast::<TimestampSecondBuilder>(builder)
+            .append_option(number(field).map(|n| n as i64)),
+        DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            downcast::<TimestampMillisecondBuilder>(builder)
+                .append_option(number(field).map(|n| n as i64))
+        }
+        DataType::Float64 => {
+            let value = bytes.filter(|bytes| bytes.len() == 8).map(|bytes| {
+                let mut octets = [0u8; 8];
+                octets.copy_from_slice(&bytes);
+                f64::from_be_bytes(octets)
+            });
+            downcast::<Float64Builder>(builder).append_option(value)
+        }
+        DataType::Boolean => downcast::<BooleanBuilder>(builder).append_option(
+            bytes
+                .filter(|bytes| bytes.len() == 1)
+                .map(|bytes| bytes[0] == 1),
+        ),
+        DataType::FixedSizeBinary(len) => {
+            let builder = downcast::<FixedSizeBinaryBuilder>(builder);
+            match bytes {
+                Some(bytes) if bytes.len() == *len as usize => {
+                    builder.append_value(&bytes).expect("length is checked")
+                }
+                _ => builder.append_null(),
+            }
+        }
+        DataType::Utf8 => {
+            let value = bytes.map(|bytes| {
+                // fixed length strings are padded with zeros
+                let end = bytes
+                    .iter()
+                    .rposition(|byte| *byte != 0)
+                    .map_or(0, |pos| pos + 1);
+                String::from_utf8_lossy(&bytes[..end]).into_owned()
+            });
+            downcast::<StringBuilder>(builder).append_option(value)
+        }
+        _ => downcast::<BinaryBuilder>(builder).append_option(bytes),
+    }
+}
+
+enum Input {
+    Exporter,
+    SourceId,
+    Sequence,
+    ExportTime,
+    TemplateId,
+    Field(u16),
+}
+
+struct Column {
+    source: Input,
+    field: Field,
+    builder: Box<dyn ArrayBuilder>,
+}
+
+/// Collects data records into Arrow record batches, a column per field type.
+/// Templates with new field types add columns, rows already in the batch get nulls there,
+/// so a batch schema is always a superset of the previous ones.
+/// Option records and enterprise fields are skipped.
+pub struct FlowBatcher {
+    batch_size: usize,
+    ipv4: Ipv4Layout,
+    columns: Vec<Column>,
+    index: HashMap<u16, usize>,
+    rows: usize,
+}
+
+impl FlowBatcher {
+    pub fn new(batch_size: usize) -> FlowBatcher {
+        FlowBatcher::with_ipv4_layout(batch_size, Ipv4Layout::UInt32)
+    }
+
+    pub fn with_ipv4_layout(batch_size: usize, ipv4: Ipv4Layout) -> FlowBatcher {
+        let mut batcher = FlowBatcher {
+            batch_size: batch_size.max(1),
+            ipv4,
+            columns: Vec::new(),
+            index: HashMap::new(),
+            rows: 0,
+        };
+
+        let metadata = vec![
+            (Input::Exporter, "exporter", DataType::Utf8),
+            (Input::SourceId, "source_id", DataType::UInt32),
+            (Input::Sequence, "sequence", DataType::UInt32),
+            (
+                Input::ExportTime,
+                "export_time",
+                DataType::Timestamp(TimeUnit::Second, None),
+            ),
+            (Input::TemplateId, "template_id", DataType::UInt16),
+        ];
+        for (source, name, data_type) in metadata {
+            batcher.add_column(source, Field::new(name, data_type, false));
+        }
+
+        batcher
+    }
+
+    fn add_column(&mut self, source: Input, field: Field) {
+        let mut builder = make_builder(field.data_type(), self.batch_size);
+        for _ in 0..self.rows {
+            append(builder.as_mut(), field.data_type(), None);
+        }
+
+        self.columns.push(Column {
+            source,
+            field,
+            builder,
+        });
+    }
+
+    /// Current schema, new templates may add columns at the end.
+    pub fn schema(&self) -> SchemaRef {
+        let fields: Vec<Field> = self
+            .columns
+            .iter()
+            .map(|column| column.field.clone())
+            .collect();
+        Arc::new(Schema::new(fields))
+    }
+
+    /// Number of rows waiting for the next batch.
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// Add a column for every field type of the template not seen before.
+    pub fn add_template(&mut self, template: &DataTemplateItem) {
+        for field in template
+            .fields
+            .iter()
+            .filter(|field| field.enterprise.is_none())
+        {
+            if self.index.contains_key(&field.type_id) {
+                continue;
+            }
+
+            self.index.insert(field.type_id, self.columns.len());
+            let data_type = arrow_type(field.type_id, self.ipv4);
+            self.add_column(
+                Input::Field(field.type_id),
+                Field::new(element_name(field.type_id), data_type, true),
+            );
+        }
+    }
+
+    /// Append a data record, return a batch when it is full.
+    pub fn push(
+        &mut self,
+        context: &RecordContext,
+        template_id: u16,
+        record: &Record,
+    ) -> Result<Option<RecordBatch>, NetFlowError> {
+        if let Record::OptionData(_) = record {
+            return Ok(None);
+        }
+
+        for column in &mut self.columns {
+            let builder = column.builder.as_mut();
+            match column.source {
+                Input::Exporter => {
+                    downcast::<StringBuilder>(builder).append_value(context.exporter.to_string())
+                }
+                Input::SourceId => {
+                    downcast::<UInt32Builder>(builder).append_value(context.source_id)
+                }
+                Input::Sequence => {
+                    downcast::<UInt32Builder>(builder).append_value(context.sequence)
+                }
+                Input::ExportTime => downcast::<TimestampSecondBuilder>(builder)
+                    .append_value(i64::from(context.export_time)),
+                Input::TemplateId => downcast::<UInt16Builder>(builder).append_value(template_id),
+                Input::Field(id) => append(builder, column.field.data_type(), record.get(id)),
+            }
+        }
+        self.rows += 1;
+
+        if self.rows >= self.batch_size {
+            self.flush()
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Learn the templates of the packet and append its data records, return the full batches.
+    pub fn push_netflow9(
+        &mut self,
+        exporter: SocketAddr,
+        netflow: &NetFlow9,
+    ) -> Result<Vec<RecordBatch>, NetFlowError> {
+        for flowset in &netflow.flow_sets {
+            if let FlowSet::DataTemplate(template) = flowset {
+                for item in &template.templates {
+                    self.add_template(item);
+                }
+            }
+        }
+
+        let context = RecordContext::from_netflow9(exporter, netflow);
+        let mut batches = Vec::new();
+        for flowset in &netflow.flow_sets {
+            if let FlowSet::DataFlow(flow) = flowset {
+                for record in flow.records.iter().flatten() {
+                    batches.extend(self.push(&context, flow.flowset_id, record)?);
+                }
+            }
+        }
+
+        Ok(batches)
+    }
+
+    /// Learn the templates of the message and append its data records, return the full batches.
+    pub fn push_ipfix(
+        &mut self,
+        exporter: SocketAddr,
+        message: &IpfixMessage,
+    ) -> Result<Vec<RecordBatch>, NetFlowError> {
+        for set in &message.sets {
+            if let IpfixSet::Template(templates) = set {
+                for template in templates {
+                    self.add_template(template);
+                }
+            }
+        }
+
+        let context = RecordContext::from_ipfix(exporter, message);
+        let mut batches = Vec::new();
+        for set in &message.sets {
+            if let IpfixSet::Data(data) = set {
+                for record in data.records.iter().flatten() {
+                    batches.extend(self.push(&context, data.set_id, record)?);
+                }
+            }
+        }
+
+        Ok(batches)
+    }
+
+    /// Return the rows collected so far as a batch, None if there are none.
+    pub fn flush(&mut self) -> Result<Option<RecordBatch>, NetFlowError> {
+        if self.rows == 0 {
+            return Ok(None);
+        }
+
+        let schema = self.schema();
+        let arrays = self
+            .columns
+            .iter_mut()
+            .map(|column| column.builder.finish())
+            .collect();
+        self.rows = 0;
+
+        Ok(Some(RecordBatch::try_new(schema, arrays)?))
+    }
+}
+
+#[cfg(test)]
+mod test_arrow {
+    use super::*;
+    use crate::field::FieldTypes::*;
+    use crate::field::TypeLengthField;
+    use crate::ipfix::test_data;
+    use arrow_array::{Array, FixedSizeBinaryArray, StringArray, UInt32Array};
+
+    fn exporter() -> SocketAddr {
+        "192.0.2.1:4739".parse().unwrap()
+    }
+
+    #[test]
+    fn test_types() {
+        assert_eq!(
+            arrow_type(IPV4_SRC_ADDR, Ipv4Layout::UInt32),
+            DataType::UInt32
+        );
+        assert_eq!(
+            arrow_type(IPV4_SRC_ADDR, Ipv4Layout::FixedBinary),
+            DataType::FixedSizeBinary(4)
+        );
+        assert_eq!(
+            arrow_type(IPV6_SRC_ADDR, Ipv4Layout::UInt32),
+            DataType::FixedSizeBinary(16)
+        );
+        assert_eq!(arrow_type(IF_NAME, Ipv4Layout::UInt32), DataType::Utf8);
+        assert_eq!(arrow_type(IN_BYTES, Ipv4Layout::UInt32), DataType::UInt64);
+        assert_eq!(arrow_type(400, Ipv4Layout::UInt32), DataType::Binary);
+    }
+
+    #[test]
+    fn test_batches() {
+        let message = test_data::message();
+        let mut batcher = FlowBatcher::new(3);
+
+        assert!(batcher.push_ipfix(exporter(), &message).unwrap().is_empty());
+        assert_eq!(batcher.len(), 2);
+        let batches = batcher.push_ipfix(exporter(), &message).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batcher.len(), 1);
+
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(batch.num_columns(), 8);
+
+        let names = batch
+            .column_by_name("interfaceName")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(names.value(0), "eth0");
+        assert_eq!(names.value(1), "a long interface name");
+
+        let addrs = batch
+            .column_by_name("sourceIPv4Address")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .unwrap();
+        assert!(!addrs.is_null(0));
+    }
+
+    #[test]
+    fn test_schema_evolution() {
+        let mut batcher = FlowBatcher::with_ipv4_layout(10, Ipv4Layout::FixedBinary);
+        batcher
+            .push_ipfix(exporter(), &test_data::message())
+            .unwrap();
+
+        let template = DataTemplateItem::new(
+            257,
+            vec![
+                TypeLengthField::new(IPV6_SRC_ADDR, 16),
+                TypeLengthField::new(IPV4_SRC_ADDR, 4),
+            ],
+        );
+        batcher.add_template(&template);
+        let context = RecordContext::from_ipfix(exporter(), &test_data::message());
+        let record = Record::make_data(vec![
+            FlowField::new(IPV6_SRC_ADDR, 16, FieldValue::new(IPV6_SRC_ADDR, &[1; 16])),
+            FlowField::new(
+                IPV4_SRC_ADDR,
+                4,
+                FieldValue::new(IPV4_SRC_ADDR, &[10, 0, 0, 1]),
+            ),
+        ]);
+        batcher.push(&context, 257, &record).unwrap();
+
+        let batch = batcher.flush().unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(
+            batch.schema().fields().last().unwrap().name(),
+            "sourceIPv6Address"
+        );
+
+        let ipv6 = batch
+            .column_by_name("sourceIPv6Address")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<FixedSizeBinaryArray>()
+            .unwrap();
+        assert!(ipv6.is_null(0) && ipv6.is_null(1));
+        assert_eq!(ipv6.value(2), &[1; 16][..]);
+
+        let names = batch.column_by_name("interfaceName").unwrap();
+        assert!(names.is_null(2));
+        assert!(batcher.flush().unwrap().is_none());
+    }
+}
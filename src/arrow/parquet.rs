@@ -0,0 +1,132 @@
+use crate::error::NetFlowError;
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::path::PathBuf;
+
+impl From<ParquetError> for NetFlowError {
+    fn from(e: ParquetError) -> Self {
+        NetFlowError::Export {
+            desc: e.to_string(),
+        }
+    }
+}
+
+/// Writes record batches to Snappy compressed Parquet files named `<prefix>-<part>.parquet`.
+/// A Parquet file has a single schema, so a batch with new columns starts the next part.
+pub struct ParquetWriter {
+    prefix: PathBuf,
+    writer: Option<(SchemaRef, ArrowWriter<File>)>,
+    paths: Vec<PathBuf>,
+}
+
+impl ParquetWriter {
+    pub fn new<P: Into<PathBuf>>(prefix: P) -> ParquetWriter {
+        ParquetWriter {
+            prefix: prefix.into(),
+            writer: None,
+            paths: Vec::new(),
+        }
+    }
+
+    fn next_path(&self) -> PathBuf {
+        let mut name = self.prefix.file_name().unwrap_or_default().to_os_string();
+        name.push(format!("-{:04}.parquet", self.paths.len()));
+        self.prefix.with_file_name(name)
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), NetFlowError> {
+        if let Some((schema, _)) = &self.writer {
+            if *schema != batch.schema() {
+                self.close_part()?;
+            }
+        }
+
+        if self.writer.is_none() {
+            let path = self.next_path();
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            let writer =
+                ArrowWriter::try_new(File::create(&path)?, batch.schema(), Some(properties))?;
+
+            info!("writing {}", path.display());
+            self.paths.push(path);
+            self.writer = Some((batch.schema(), writer));
+        }
+
+        if let Some((_, writer)) = &mut self.writer {
+            writer.write(batch)?;
+        }
+
+        Ok(())
+    }
+
+    fn close_part(&mut self) -> Result<(), NetFlowError> {
+        if let Some((_, writer)) = self.writer.take() {
+            writer.close()?;
+        }
+
+        Ok(())
+    }
+
+    /// Close the current file, return the paths of every file written.
+    pub fn close(mut self) -> Result<Vec<PathBuf>, NetFlowError> {
+        self.close_part()?;
+        Ok(self.paths)
+    }
+}
+
+#[cfg(test)]
+mod test_parquet {
+    use super::*;
+    use crate::arrow::FlowBatcher;
+    use crate::field::FieldTypes::*;
+    use crate::field::TypeLengthField;
+    use crate::flowset::DataTemplateItem;
+    use crate::ipfix::test_data;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_parts() {
+        let dir = env::temp_dir().join(format!("netflow-parquet-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let exporter = "192.0.2.1:4739".parse().unwrap();
+        let mut batcher = FlowBatcher::new(100);
+        let mut writer = ParquetWriter::new(dir.join("flows"));
+
+        batcher.push_ipfix(exporter, &test_data::message()).unwrap();
+        writer.write(&batcher.flush().unwrap().unwrap()).unwrap();
+        batcher.push_ipfix(exporter, &test_data::message()).unwrap();
+        writer.write(&batcher.flush().unwrap().unwrap()).unwrap();
+
+        batcher.add_template(&DataTemplateItem::new(
+            257,
+            vec![TypeLengthField::new(IPV6_SRC_ADDR, 16)],
+        ));
+        batcher.push_ipfix(exporter, &test_data::message()).unwrap();
+        writer.write(&batcher.flush().unwrap().unwrap()).unwrap();
+
+        let paths = writer.close().unwrap();
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].ends_with("flows-0000.parquet"));
+
+        let rows: Vec<i64> = paths
+            .iter()
+            .map(|path| {
+                let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+                reader.metadata().file_metadata().num_rows()
+            })
+            .collect();
+        assert_eq!(rows, vec![4, 2]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,639 @@
+use crate::convert::ipfix_record;
+use crate::error::NetFlowError;
+use crate::field::FieldTypes::*;
+pub use crate::field::REVERSE_PEN;
+use crate::field::{FieldValue, FlowField, TypeLengthField};
+use crate::flowset::{DataTemplateItem, FlowSet, Record};
+use crate::ipfix::{DataSet, IpfixMessage, IpfixSet};
+use crate::netflow::NetFlow9;
+use crate::transform::TemplateIds;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Values of biflowDirection.
+const DIRECTION_ARBITRARY: u8 = 0;
+const DIRECTION_INITIATOR: u8 = 1;
+
+/// Fields that describe one direction of a flow and get a reverse element in a biflow.
+/// The flow key and the fields shared by both directions are not reversed.
+const REVERSIBLE: [u16; 12] = [
+    IN_BYTES,
+    IN_PKTS,
+    TOS,
+    TCP_FLAGS,
+    OUT_BYTES,
+    OUT_PKTS,
+    MIN_TTL,
+    MAX_TTL,
+    FLOW_START_SECONDS,
+    FLOW_END_SECONDS,
+    FLOW_START_MILLISECONDS,
+    FLOW_END_MILLISECONDS,
+];
+
+/// Flow start in milliseconds since the epoch, of a record with IPFIX times.
+fn start_time(record: &Record) -> Option<u64> {
+    let value = |type_id| record.get(type_id).and_then(|field| field.value().to_u64());
+    value(FLOW_START_MILLISECONDS).or_else(|| value(FLOW_START_SECONDS).map(|secs| secs * 1000))
+}
+
+/// Flow end in milliseconds since the epoch, of a record with IPFIX times.
+fn end_time(record: &Record) -> Option<u64> {
+    let value = |type_id| record.get(type_id).and_then(|field| field.value().to_u64());
+    value(FLOW_END_MILLISECONDS).or_else(|| value(FLOW_END_SECONDS).map(|secs| secs * 1000))
+}
+
+/// The 5-tuple of a flow within an observation domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct StitchKey {
+    domain: u32,
+    source: IpAddr,
+    destination: IpAddr,
+    source_port: u16,
+    destination_port: u16,
+    protocol: u8,
+}
+
+impl StitchKey {
+    fn of(domain: u32, record: &Record) -> Option<StitchKey> {
+        let address = |ids: &[u16]| {
+            ids.iter()
+                .filter_map(|id| record.get(*id))
+                .find_map(|field| match field.value() {
+                    FieldValue::Ipv4Addr(ip) => Some(IpAddr::V4(*ip)),
+                    FieldValue::Ipv6Addr(ip) => Some(IpAddr::V6(*ip)),
+                    _ => None,
+                })
+        };
+        let number = |id| {
+            record
+                .get(id)
+                .and_then(|field| field.value().to_u64())
+                .unwrap_or(0)
+        };
+
+        Some(StitchKey {
+            domain,
+            source: address(&[IPV4_SRC_ADDR, IPV6_SRC_ADDR])?,
+            destination: address(&[IPV4_DST_ADDR, IPV6_DST_ADDR])?,
+            source_port: number(L4_SRC_PORT) as u16,
+            destination_port: number(L4_DST_PORT) as u16,
+            protocol: number(PROTOCOL) as u8,
+        })
+    }
+
+    fn reverse(&self) -> StitchKey {
+        StitchKey {
+            source: self.destination,
+            destination: self.source,
+            source_port: self.destination_port,
+            destination_port: self.source_port,
+            ..*self
+        }
+    }
+}
+
+/// A forward record with its reverse record, if one was found.
+#[derive(Debug, Clone)]
+pub struct Biflow {
+    /// the record that started first, the initiator of the conversation
+    pub forward: Record,
+    pub reverse: Option<Record>,
+}
+
+impl Biflow {
+    fn reverse_fields(&self) -> impl Iterator<Item = &FlowField> {
+        self.forward
+            .fields()
+            .filter(|field| REVERSIBLE.contains(&field.type_id()))
+    }
+
+    /// Fields of the combined record: the forward fields, biflowDirection, then a reverse
+    /// element for each reversible forward field, flagged with `REVERSE_PEN`.
+    pub fn template_fields(&self) -> Vec<TypeLengthField> {
+        let mut fields: Vec<TypeLengthField> = self
+            .forward
+            .fields()
+            .map(|field| TypeLengthField::new(field.type_id(), field.length()))
+            .collect();
+        fields.push(TypeLengthField::new(BIFLOW_DIRECTION, 1));
+        fields.extend(self.reverse_fields().map(|field| {
+            TypeLengthField::with_enterprise(field.type_id(), field.length(), REVERSE_PEN)
+        }));
+        fields
+    }
+
+    /// The combined record, in the order of `template_fields`. Reverse counters of a flow
+    /// without a reverse record are 0, as RFC 5103 exports uniflows in a biflow template.
+    pub fn to_record(&self) -> Record {
+        let mut fields: Vec<FlowField> = self.forward.fields().cloned().collect();
+        let direction = match self.reverse {
+            Some(_) => DIRECTION_INITIATOR,
+            None => DIRECTION_ARBITRARY,
+        };
+        fields.push(FlowField::new(BIFLOW_DIRECTION, 1, direction.into()));
+
+        for field in self.reverse_fields() {
+            let (type_id, length) = (field.type_id(), field.length());
+            let bytes = match self.reverse.as_ref().and_then(|r| r.get(type_id)) {
+                Some(reverse) => reverse.value().to_bytes(length),
+                None => vec![0u8; length as usize],
+            };
+            fields.push(FlowField::with_enterprise(
+                type_id,
+                length,
+                REVERSE_PEN,
+                FieldValue::new(type_id, &bytes),
+            ));
+        }
+
+        Record::make_data(fields)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BiflowStats {
+    pub records: u64,
+    /// pairs of a forward and a reverse record
+    pub biflows: u64,
+    /// records expired without a reverse record
+    pub uniflows: u64,
+    /// records without addresses or a start time, passed on as uniflows right away
+    pub unkeyed: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    record: Record,
+    start: u64,
+    /// the start when the record has no end
+    end: u64,
+}
+
+/// Pairs the records of the two directions of a conversation, as routers report each
+/// direction as a flow of its own.
+///
+/// Records match when their 5-tuples are reversed, they come from the same observation
+/// domain, and their flow starts are at most the window apart. Records are held until their
+/// reverse arrives or `expire` passes their flow end by the window, so long flows exported
+/// at the active timeout still wait for their reverse. Times come from the millisecond or
+/// second IPFIX elements, v9 packets are converted to IPFIX records first.
+#[derive(Debug)]
+pub struct BiflowStitcher {
+    /// in milliseconds
+    window: u64,
+    pending: HashMap<StitchKey, VecDeque<Pending>>,
+    stats: BiflowStats,
+}
+
+impl BiflowStitcher {
+    pub fn new(window: Duration) -> BiflowStitcher {
+        BiflowStitcher {
+            window: window.as_millis() as u64,
+            pending: HashMap::new(),
+            stats: BiflowStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &BiflowStats {
+        &self.stats
+    }
+
+    /// Number of records waiting for their reverse.
+    pub fn len(&self) -> usize {
+        self.pending.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Add a data record with IPFIX times, returns the biflow it completes, if any.
+    /// Records without addresses or a start time come back right away as uniflows.
+    pub fn add_record(&mut self, domain: u32, record: Record) -> Option<Biflow> {
+        if let Record::OptionData(_) = record {
+            return None;
+        }
+        self.stats.records += 1;
+
+        let (key, start) = match (StitchKey::of(domain, &record), start_time(&record)) {
+            (Some(key), Some(start)) => (key, start),
+            _ => {
+                self.stats.unkeyed += 1;
+                return Some(Biflow {
+                    forward: record,
+                    reverse: None,
+                });
+            }
+        };
+
+        let reverse_key = key.reverse();
+        let window = self.window;
+        let found = self.pending.get_mut(&reverse_key).and_then(|queue| {
+            let index = queue.iter().position(|pending| {
+                pending.start.max(start) - pending.start.min(start) <= window
+            })?;
+            queue.remove(index)
+        });
+        if self
+            .pending
+            .get(&reverse_key)
+            .is_some_and(VecDeque::is_empty)
+        {
+            self.pending.remove(&reverse_key);
+        }
+
+        match found {
+            Some(other) => {
+                self.stats.biflows += 1;
+                let (forward, reverse) = if other.start <= start {
+                    (other.record, record)
+                } else {
+                    (record, other.record)
+                };
+                Some(Biflow {
+                    forward,
+                    reverse: Some(reverse),
+                })
+            }
+            None => {
+                let end = end_time(&record).unwrap_or(start).max(start);
+                // queues are kept in the order records expire
+                let queue = self.pending.entry(key).or_default();
+                let index = queue.partition_point(|pending| pending.end <= end);
+                queue.insert(index, Pending { record, start, end });
+                None
+            }
+        }
+    }
+
+    /// Add the decoded data records of a v9 packet, its uptime-relative times are made
+    /// absolute with the header. Returns the biflows completed.
+    pub fn add_netflow9(&mut self, netflow: &NetFlow9) -> Vec<Biflow> {
+        let mut biflows = Vec::new();
+
+        for flowset in &netflow.flow_sets {
+            if let FlowSet::DataFlow(dataflow) = flowset {
+                for record in dataflow.records.iter().flatten() {
+                    let record = ipfix_record(record, netflow.timestamp, netflow.sys_uptime);
+                    biflows.extend(self.add_record(netflow.source_id, record));
+                }
+            }
+        }
+
+        biflows
+    }
+
+    /// Add the decoded data records of an IPFIX message, returns the biflows completed.
+    pub fn add_ipfix(&mut self, message: &IpfixMessage) -> Vec<Biflow> {
+        let mut biflows = Vec::new();
+
+        for set in &message.sets {
+            if let IpfixSet::Data(data) = set {
+                for record in data.records.iter().flatten() {
+                    biflows.extend(self.add_record(message.observation_domain_id, record.clone()));
+                }
+            }
+        }
+
+        biflows
+    }
+
+    /// Return the records whose flows ended more than the window before `now`, the time since
+    /// the epoch, as uniflows.
+    pub fn expire(&mut self, now: Duration) -> Vec<Biflow> {
+        let now = now.as_millis() as u64;
+        let window = self.window;
+        let mut expired = Vec::new();
+
+        for queue in self.pending.values_mut() {
+            while queue
+                .front()
+                .is_some_and(|pending| pending.end + window < now)
+            {
+                expired.extend(queue.pop_front());
+            }
+        }
+        self.pending.retain(|_, queue| !queue.is_empty());
+
+        expired.sort_by_key(|pending| pending.start);
+        self.uniflows(expired)
+    }
+
+    /// Return every record still waiting, as uniflows.
+    pub fn flush(&mut self) -> Vec<Biflow> {
+        let mut pending: Vec<Pending> = self.pending.drain().flat_map(|(_, queue)| queue).collect();
+        pending.sort_by_key(|pending| pending.start);
+        self.uniflows(pending)
+    }
+
+    fn uniflows(&mut self, pending: Vec<Pending>) -> Vec<Biflow> {
+        self.stats.uniflows += pending.len() as u64;
+        pending
+            .into_iter()
+            .map(|pending| Biflow {
+                forward: pending.record,
+                reverse: None,
+            })
+            .collect()
+    }
+}
+
+/// Exports biflows as IPFIX messages with reverse elements.
+///
+/// Each message carries the templates of its records. Biflows of the same layout keep their
+/// template id across messages.
+#[derive(Debug)]
+pub struct BiflowExporter {
+    domain: u32,
+    sequence: u32,
+    max_records: usize,
+    template_ids: TemplateIds<Vec<(u16, u16)>>,
+}
+
+impl BiflowExporter {
+    pub fn new(domain: u32) -> BiflowExporter {
+        BiflowExporter {
+            domain,
+            sequence: 0,
+            max_records: 10,
+            template_ids: TemplateIds::new(),
+        }
+    }
+
+    /// Most records per message, 10 by default, which keeps messages of IPv6 biflows with
+    /// their template under 1452 bytes.
+    pub fn set_max_records(&mut self, max_records: usize) {
+        self.max_records = max_records.max(1);
+    }
+
+    pub fn messages(
+        &mut self,
+        biflows: &[Biflow],
+        export_time: u32,
+    ) -> Result<Vec<IpfixMessage>, NetFlowError> {
+        let mut messages = Vec::new();
+
+        for chunk in biflows.chunks(self.max_records) {
+            let mut sets: Vec<(DataTemplateItem, Vec<Record>)> = Vec::new();
+
+            for biflow in chunk {
+                let fields = biflow.template_fields();
+                let layout: Vec<(u16, u16)> = fields
+                    .iter()
+                    .map(|field| (field.type_id, field.length))
+                    .collect();
+                let template_id =
+                    self.template_ids
+                        .id(layout)
+                        .ok_or_else(|| NetFlowError::Export {
+                            desc: "out of template ids".to_string(),
+                        })?;
+
+                match sets
+                    .iter_mut()
+                    .find(|(template, _)| template.template_id == template_id)
+                {
+                    Some((_, records)) => records.push(biflow.to_record()),
+                    None => sets.push((
+                        DataTemplateItem::new(template_id, fields),
+                        vec![biflow.to_record()],
+                    )),
+                }
+            }
+
+            let mut message_sets = Vec::new();
+            for (template, records) in sets {
+                message_sets.push(IpfixSet::Template(vec![template.clone()]));
+                message_sets.push(IpfixSet::Data(DataSet::from_data_records(
+                    &template, records,
+                )));
+            }
+
+            messages.push(IpfixMessage::new(
+                export_time,
+                self.sequence,
+                self.domain,
+                message_sets,
+            ));
+            self.sequence = self.sequence.wrapping_add(chunk.len() as u32);
+        }
+
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod test_biflow {
+    use super::*;
+    use crate::ipfix::IpfixTemplates;
+    use crate::json::flat_ipfix;
+    use std::net::Ipv4Addr;
+
+    fn template() -> DataTemplateItem {
+        DataTemplateItem::builder(256)
+            .field(IPV4_SRC_ADDR)
+            .field(IPV4_DST_ADDR)
+            .field(L4_SRC_PORT)
+            .field(L4_DST_PORT)
+            .field(PROTOCOL)
+            .field_len(IN_BYTES, 4)
+            .field(FLOW_START_MILLISECONDS)
+            .build()
+            .unwrap()
+    }
+
+    fn record(src: [u8; 4], dst: [u8; 4], ports: (u16, u16), bytes: u32, start: u64) -> Record {
+        template()
+            .record_builder()
+            .set(IPV4_SRC_ADDR, Ipv4Addr::from(src))
+            .set(IPV4_DST_ADDR, Ipv4Addr::from(dst))
+            .set(L4_SRC_PORT, ports.0)
+            .set(L4_DST_PORT, ports.1)
+            .set(PROTOCOL, 6u8)
+            .set(IN_BYTES, bytes)
+            .set(FLOW_START_MILLISECONDS, start)
+            .build()
+            .unwrap()
+    }
+
+    const CLIENT: [u8; 4] = [10, 0, 0, 1];
+    const SERVER: [u8; 4] = [192, 0, 2, 1];
+
+    #[test]
+    fn test_stitch() {
+        let mut stitcher = BiflowStitcher::new(Duration::from_secs(5));
+
+        // the response is reported first but started later
+        let response = record(SERVER, CLIENT, (443, 40000), 9000, 1_100);
+        assert!(stitcher.add_record(1, response).is_none());
+        // other domain, other port, too late
+        assert!(stitcher
+            .add_record(2, record(CLIENT, SERVER, (40000, 443), 1, 1_000))
+            .is_none());
+        assert!(stitcher
+            .add_record(1, record(CLIENT, SERVER, (40001, 443), 1, 1_000))
+            .is_none());
+        assert!(stitcher
+            .add_record(1, record(CLIENT, SERVER, (40000, 443), 1, 7_000))
+            .is_none());
+
+        let request = record(CLIENT, SERVER, (40000, 443), 500, 1_000);
+        let biflow = stitcher.add_record(1, request).unwrap();
+        assert_eq!(
+            biflow
+                .forward
+                .get(IPV4_SRC_ADDR)
+                .unwrap()
+                .value()
+                .to_bytes(4),
+            CLIENT.to_vec()
+        );
+
+        let record = biflow.to_record();
+        let fields: Vec<&FlowField> = record.fields().collect();
+        assert_eq!(fields.len(), 7 + 1 + 2);
+        assert_eq!(fields[7].value().to_u64(), Some(1));
+        assert_eq!(fields[8].type_id(), IN_BYTES);
+        assert_eq!(fields[8].enterprise(), Some(REVERSE_PEN));
+        assert_eq!(fields[8].value().to_u64(), Some(9000));
+        assert_eq!(record.get(IN_BYTES).unwrap().value().to_u64(), Some(500));
+        assert_eq!(
+            record
+                .get_enterprise(REVERSE_PEN, IN_BYTES)
+                .unwrap()
+                .value()
+                .to_u64(),
+            Some(9000)
+        );
+        assert_eq!(fields[9].value().to_u64(), Some(1_100));
+
+        assert_eq!(stitcher.len(), 3);
+        let uniflows = stitcher.expire(Duration::from_millis(6_001));
+        assert_eq!(uniflows.len(), 2);
+        assert!(uniflows[0].reverse.is_none());
+        assert_eq!(
+            uniflows[0]
+                .to_record()
+                .get(BIFLOW_DIRECTION)
+                .unwrap()
+                .value()
+                .to_u64(),
+            Some(0)
+        );
+        assert_eq!(stitcher.flush().len(), 1);
+        assert!(stitcher.is_empty());
+
+        let stats = stitcher.stats();
+        assert_eq!(stats.records, 5);
+        assert_eq!(stats.biflows, 1);
+        assert_eq!(stats.uniflows, 3);
+    }
+
+    #[test]
+    fn test_json() {
+        let mut stitcher = BiflowStitcher::new(Duration::from_secs(5));
+        stitcher.add_record(1, record(CLIENT, SERVER, (40000, 443), 500, 1_000));
+        let biflow = stitcher
+            .add_record(1, record(SERVER, CLIENT, (443, 40000), 9000, 1_100))
+            .unwrap();
+
+        let messages = BiflowExporter::new(1)
+            .messages(std::slice::from_ref(&biflow), 1_600_000_000)
+            .unwrap();
+        let decoded = IpfixTemplates::new()
+            .decode(&messages[0].to_bytes())
+            .unwrap();
+        let objects = flat_ipfix("192.0.2.1:4739".parse().unwrap(), &decoded);
+        assert_eq!(objects[0]["octetDeltaCount"], 500);
+        assert_eq!(objects[0]["reverseOctetDeltaCount"], 9000);
+        assert_eq!(objects[0]["flowStartMilliseconds"], 1_000);
+        assert_eq!(objects[0]["reverseFlowStartMilliseconds"], 1_100);
+
+        let json = serde_json::to_string(&biflow.to_record()).unwrap();
+        let record: Record = serde_json::from_str(&json).unwrap();
+        assert_eq!(record.get(IN_BYTES).unwrap().value().to_u64(), Some(500));
+        let reverse = record.get_enterprise(REVERSE_PEN, IN_BYTES).unwrap();
+        assert_eq!(reverse.value().to_u64(), Some(9000));
+    }
+
+    #[test]
+    fn test_long_flow() {
+        let flow = |src, dst, ports: (u16, u16), start: u64, end: u64| {
+            Record::make_data(vec![
+                FlowField::new(IPV4_SRC_ADDR, 4, Ipv4Addr::from(src).into()),
+                FlowField::new(IPV4_DST_ADDR, 4, Ipv4Addr::from(dst).into()),
+                FlowField::new(L4_SRC_PORT, 2, ports.0.into()),
+                FlowField::new(L4_DST_PORT, 2, ports.1.into()),
+                FlowField::new(FLOW_START_MILLISECONDS, 8, start.into()),
+                FlowField::new(FLOW_END_MILLISECONDS, 8, end.into()),
+            ])
+        };
+        let mut stitcher = BiflowStitcher::new(Duration::from_secs(5));
+
+        // exported at the active timeout, minutes after it started
+        assert!(stitcher
+            .add_record(1, flow(CLIENT, SERVER, (40000, 443), 0, 300_000))
+            .is_none());
+        // a short flow of the same key queued behind it expires first
+        assert!(stitcher
+            .add_record(1, flow(CLIENT, SERVER, (40000, 443), 100_000, 101_000))
+            .is_none());
+        let uniflows = stitcher.expire(Duration::from_millis(301_000));
+        assert_eq!(uniflows.len(), 1);
+        assert_eq!(start_time(&uniflows[0].forward), Some(100_000));
+
+        let biflow = stitcher
+            .add_record(1, flow(SERVER, CLIENT, (443, 40000), 20, 300_000))
+            .unwrap();
+        assert_eq!(start_time(&biflow.forward), Some(0));
+        assert!(stitcher.is_empty());
+    }
+
+    #[test]
+    fn test_export() {
+        let mut stitcher = BiflowStitcher::new(Duration::from_secs(5));
+        stitcher.add_record(1, record(CLIENT, SERVER, (40000, 443), 500, 1_000));
+        let biflow = stitcher
+            .add_record(1, record(SERVER, CLIENT, (443, 40000), 9000, 1_100))
+            .unwrap();
+        let uniflow = stitcher.add_record(1, Record::make_data(vec![]));
+
+        let mut exporter = BiflowExporter::new(1);
+        let mut biflows = vec![biflow];
+        biflows.extend(uniflow);
+        let messages = exporter.messages(&biflows, 1_600_000_000).unwrap();
+        assert_eq!(messages.len(), 1);
+
+        let mut templates = IpfixTemplates::new();
+        let decoded = templates.decode(&messages[0].to_bytes()).unwrap();
+        match &decoded.sets[0] {
+            IpfixSet::Template(items) => {
+                let fields = &items[0].fields;
+                assert_eq!(fields[7].type_id, BIFLOW_DIRECTION);
+                assert_eq!(fields[8].type_id, IN_BYTES);
+                assert_eq!(fields[8].enterprise, Some(REVERSE_PEN));
+                assert_eq!(fields[9].type_id, FLOW_START_MILLISECONDS);
+                assert_eq!(fields[9].enterprise, Some(REVERSE_PEN));
+            }
+            other => panic!("unexpected set: {:?}", other),
+        }
+        match &decoded.sets[1] {
+            IpfixSet::Data(data) => {
+                let records = data.records.as_ref().unwrap();
+                let fields: Vec<&FlowField> = records[0].fields().collect();
+                assert_eq!(fields[8].value().to_u64(), Some(9000));
+            }
+            other => panic!("unexpected set: {:?}", other),
+        }
+
+        // the same layout keeps its template id
+        let again = exporter.messages(&biflows[..1], 1_600_000_001).unwrap();
+        assert_eq!(again[0].sequence_number, 2);
+        match &again[0].sets[0] {
+            IpfixSet::Template(items) => assert_eq!(items[0].template_id, 256),
+            other => panic!("unexpected set: {:?}", other),
+        }
+    }
+}
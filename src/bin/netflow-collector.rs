@@ -0,0 +1,208 @@
+#[macro_use]
+extern crate log;
+
+use netflow::collector::{write_json_line, Collector};
+use netflow::csv::{CsvWriter, Delimited};
+use netflow::error::NetFlowError;
+use netflow::filter::Filter;
+use netflow::json::write_flat_netflow9;
+use netflow::netflow::{CountCheck, NetFlow9};
+use netflow::sink::{open_sink, Rotation, SinkWriter};
+use std::env;
+use std::io;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: netflow-collector [-l ADDR]... [-o TARGET] [-r SECONDS] [-m BYTES]
+                         [-f FORMAT] [-s SECONDS] [-S] [-F FILTER]
+
+  -l ADDR     listen address, can be repeated (default: 0.0.0.0:2055)
+  -o TARGET   write to a file, udp:HOST:PORT or unix:PATH instead of stdout
+  -r SECONDS  write to files named TARGET.YYYYMMDDhhmm, a new one every SECONDS (e.g. 300)
+  -m BYTES    start a new file before one grows past BYTES, alone the files are named
+              TARGET.YYYYMMDDhhmm by the minute they are opened in
+  -f FORMAT   packet: a JSON line per packet (default), flat: a JSON line per record,
+              csv or tsv: a row per data record, columns are taken from the templates
+              received before the first record, later fields are dropped
+  -s SECONDS  interval of exporter counters printed to stderr (default: 60, 0 disables)
+  -S          drop packets whose header count does not match their records
+  -F FILTER   keep only the data records matching FILTER,
+              e.g. 'src net 10.0.0.0/8 and proto tcp and dst port in [80 443]'";
+
+enum Format {
+    Packet,
+    Flat,
+    Delimited(Delimited),
+}
+
+struct Args {
+    listen: Vec<String>,
+    output: Option<String>,
+    rotation: Rotation,
+    format: Format,
+    stats_interval: u64,
+    count_check: CountCheck,
+    filter: Option<Filter>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        listen: Vec::new(),
+        output: None,
+        rotation: Rotation::none(),
+        format: Format::Packet,
+        stats_interval: 60,
+        count_check: CountCheck::Lenient,
+        filter: None,
+    };
+    let mut iter = env::args().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or(format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "-l" => args.listen.push(value()?),
+            "-o" => args.output = Some(value()?),
+            "-r" => {
+                let secs = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid rotation interval: {}", e))?;
+                args.rotation.interval = Some(Duration::from_secs(secs));
+            }
+            "-m" => {
+                let size = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid size: {}", e))?;
+                args.rotation.max_size = Some(size);
+            }
+            "-f" => args.format = parse_format(&value()?)?,
+            "-s" => {
+                args.stats_interval = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid interval: {}", e))?
+            }
+            "-S" => args.count_check = CountCheck::Strict,
+            "-F" => args.filter = Some(value()?.parse().map_err(|e: NetFlowError| e.to_string())?),
+            "-h" | "--help" => return Err(String::new()),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    if args.listen.is_empty() {
+        args.listen.push("0.0.0.0:2055".to_string());
+    }
+
+    Ok(args)
+}
+
+fn parse_format(format: &str) -> Result<Format, String> {
+    match format {
+        "packet" => Ok(Format::Packet),
+        "flat" => Ok(Format::Flat),
+        "csv" => Ok(Format::Delimited(Delimited::Csv)),
+        "tsv" => Ok(Format::Delimited(Delimited::Tsv)),
+        _ => Err(format!("unknown format: {}", format)),
+    }
+}
+
+enum Output {
+    Packet(Box<dyn Write>),
+    Flat(Box<dyn Write>),
+    Delimited(CsvWriter<Box<dyn Write>>),
+}
+
+impl Output {
+    fn new(format: Format, out: Box<dyn Write>) -> Output {
+        match format {
+            Format::Packet => Output::Packet(out),
+            Format::Flat => Output::Flat(out),
+            Format::Delimited(delimited) => Output::Delimited(CsvWriter::new(out, delimited)),
+        }
+    }
+
+    fn write(&mut self, exporter: SocketAddr, netflow: &NetFlow9) -> io::Result<()> {
+        match self {
+            Output::Packet(out) => write_json_line(out, exporter, netflow),
+            Output::Flat(out) => write_flat_netflow9(out, exporter, netflow).map(|_| ()),
+            Output::Delimited(csv) => csv.write_netflow9(exporter, netflow).map(|_| ()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Packet(out) | Output::Flat(out) => out.flush(),
+            Output::Delimited(csv) => csv.flush(),
+        }
+    }
+}
+
+fn print_stats(collector: &Collector) {
+    for (exporter, stats) in collector.stats() {
+        eprintln!(
+            "{}",
+            serde_json::json!({ "exporter": exporter, "stats": stats })
+        );
+    }
+
+    for (key, stats) in collector.sequences().iter() {
+        eprintln!(
+            "{}",
+            serde_json::json!({
+                "exporter": key.exporter,
+                "source_id": key.domain,
+                "sequence": stats,
+            })
+        );
+    }
+}
+
+fn run(args: Args) -> io::Result<()> {
+    let mut collector = Collector::bind(&args.listen)?;
+    collector.set_count_check(args.count_check);
+    collector.set_filter(args.filter);
+    let target = args.output.as_deref().unwrap_or("-");
+    let out: Box<dyn Write> = Box::new(SinkWriter::new(open_sink(target, args.rotation)?));
+    let mut out = Output::new(args.format, out);
+
+    let interval = Duration::from_secs(args.stats_interval);
+    let mut last_stats = Instant::now();
+
+    loop {
+        match collector.recv_timeout(Duration::from_secs(1)) {
+            Some(Ok(datagram)) => match collector.decode(&datagram) {
+                Ok(netflow) => out.write(datagram.exporter, &netflow)?,
+                Err(e) => warn!("invalid packet from {}: {}", datagram.exporter, e),
+            },
+            Some(Err(e)) => return Err(e),
+            None => out.flush()?,
+        }
+
+        if args.stats_interval > 0 && last_stats.elapsed() >= interval {
+            out.flush()?;
+            print_stats(&collector);
+            last_stats = Instant::now();
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("{}", msg);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("netflow-collector: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,221 @@
+#[macro_use]
+extern crate log;
+
+use netflow::error::NetFlowError;
+use netflow::exporter::{Exporter, ExporterConfig};
+use netflow::meter::{export_netflow9, ipfix_messages, Flow, FlowMeter, MeterConfig};
+use netflow::pcap::{parse_ip, PcapReader};
+use netflow::sink::{open_sink, OutputSink, Rotation};
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+use std::time::{Duration, UNIX_EPOCH};
+
+const USAGE: &str =
+    "usage: netflow-meter [-V VERSION] [-a SECONDS] [-i SECONDS] [-I INDEX] [-s ID] CAPTURE TARGET
+
+  -V VERSION  9 exports Netflow v9 (default), 10 exports IPFIX
+  -a SECONDS  active timeout, long flows are exported this often (default: 1800)
+  -i SECONDS  inactive timeout (default: 15)
+  -I INDEX    interface index recorded as INPUT_SNMP (default: 0)
+  -s ID       source id, or observation domain for IPFIX (default: 0)
+
+CAPTURE is a pcap or pcapng file, TARGET a file, udp:HOST:PORT or unix:PATH.
+Flows are timed by the capture, so the file is metered as fast as it can be read.";
+
+enum Version {
+    Netflow9,
+    Ipfix,
+}
+
+struct Args {
+    version: Version,
+    config: MeterConfig,
+    interface: u32,
+    source_id: u32,
+    capture: String,
+    target: String,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut version = Version::Netflow9;
+    let mut config = MeterConfig::default();
+    let mut interface = 0;
+    let mut source_id = 0;
+    let mut positional = Vec::new();
+    let mut iter = env::args().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or(format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "-V" => {
+                version = match value()?.as_str() {
+                    "9" => Version::Netflow9,
+                    "10" => Version::Ipfix,
+                    v => return Err(format!("unknown version: {}", v)),
+                }
+            }
+            "-a" => {
+                config.active_timeout = Duration::from_secs(
+                    value()?
+                        .parse()
+                        .map_err(|e| format!("invalid active timeout: {}", e))?,
+                )
+            }
+            "-i" => {
+                config.inactive_timeout = Duration::from_secs(
+                    value()?
+                        .parse()
+                        .map_err(|e| format!("invalid inactive timeout: {}", e))?,
+                )
+            }
+            "-I" => {
+                interface = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid interface: {}", e))?
+            }
+            "-s" => {
+                source_id = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid source id: {}", e))?
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ if !arg.starts_with('-') => positional.push(arg),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    if positional.len() != 2 {
+        return Err("a capture and a target are required".to_string());
+    }
+    let target = positional.pop().unwrap_or_default();
+    let capture = positional.pop().unwrap_or_default();
+
+    Ok(Args {
+        version,
+        config,
+        interface,
+        source_id,
+        capture,
+        target,
+    })
+}
+
+/// Sends expired flows in the chosen format.
+enum Output {
+    Netflow9(Exporter<Box<dyn OutputSink>>),
+    Ipfix {
+        sink: Box<dyn OutputSink>,
+        domain: u32,
+        sequence: u32,
+    },
+}
+
+impl Output {
+    fn send(&mut self, flows: &[Flow], now: Duration) -> Result<(), NetFlowError> {
+        match self {
+            Output::Netflow9(exporter) => {
+                exporter.set_clock(Some(UNIX_EPOCH + now));
+                export_netflow9(exporter, flows)
+            }
+            Output::Ipfix {
+                sink,
+                domain,
+                sequence,
+            } => {
+                // 1452 bytes hold 10 IPv6 records with their template
+                for message in ipfix_messages(flows, now.as_secs() as u32, *domain, sequence, 10) {
+                    sink.write_record(&message.to_bytes())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), NetFlowError> {
+        match self {
+            Output::Netflow9(exporter) => {
+                exporter.finish()?;
+            }
+            Output::Ipfix { mut sink, .. } => sink.flush()?,
+        }
+        Ok(())
+    }
+}
+
+fn run(args: Args) -> Result<(), NetFlowError> {
+    let mut reader = PcapReader::new(BufReader::new(File::open(&args.capture)?))?;
+    let sink = open_sink(&args.target, Rotation::none())?;
+    let mut meter = FlowMeter::new(args.config);
+
+    let mut output = match args.version {
+        Version::Netflow9 => {
+            let config = ExporterConfig {
+                source_id: args.source_id,
+                ..ExporterConfig::default()
+            };
+            Output::Netflow9(Exporter::new(sink, config))
+        }
+        Version::Ipfix => Output::Ipfix {
+            sink,
+            domain: args.source_id,
+            sequence: 0,
+        },
+    };
+
+    let mut last_expire: Option<Duration> = None;
+    let mut now = Duration::from_secs(0);
+    let mut ignored = 0u64;
+
+    while let Some(packet) = reader.next_packet()? {
+        now = now.max(packet.timestamp);
+
+        // the meter was booted when the capture started
+        if let (None, Output::Netflow9(exporter)) = (last_expire, &mut output) {
+            exporter.set_boot_time(UNIX_EPOCH + now);
+        }
+
+        match parse_ip(packet.link_type, &packet.data) {
+            Some(ip) => meter.observe(&ip, packet.timestamp, args.interface),
+            None => ignored += 1,
+        }
+
+        // check the timeouts once a second of capture time
+        if last_expire.is_none_or(|last| now - last >= Duration::from_secs(1)) {
+            let flows = meter.expire(now);
+            output.send(&flows, now)?;
+            last_expire = Some(now);
+        }
+    }
+
+    let flows = meter.flush();
+    output.send(&flows, now)?;
+    output.finish()?;
+
+    debug!("{} frames without an IP packet", ignored);
+    eprintln!("{}", serde_json::json!(meter.stats()));
+
+    Ok(())
+}
+
+fn main() {
+    env_logger::init();
+
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("{}", msg);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("netflow-meter: {}", e);
+        process::exit(1);
+    }
+}
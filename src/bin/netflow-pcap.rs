@@ -0,0 +1,171 @@
+#[macro_use]
+extern crate log;
+
+use netflow::csv::{CsvWriter, Delimited};
+use netflow::error::NetFlowError;
+use netflow::filter::Filter;
+use netflow::json::write_flat_netflow9;
+use netflow::pcap::{CapturedFlow, DatagramFilter, FlowReader};
+use netflow::sink::{open_sink, Rotation, SinkWriter};
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Write};
+use std::process;
+
+const USAGE: &str = "usage: netflow-pcap [-p PORT]... [-o TARGET] [-f FORMAT] [-F FILTER] CAPTURE
+
+  -p PORT  decode UDP datagrams sent to PORT, can be repeated
+           (default: detect Netflow v9 from the payload)
+  -o TARGET
+           write to a file, udp:HOST:PORT or unix:PATH instead of stdout
+  -f FORMAT
+           packet: a JSON line per packet (default), flat: a JSON line per record,
+           csv or tsv: a row per data record, columns are taken from the templates
+           read before the first record, later fields are dropped
+  -F FILTER
+           keep only the data records matching FILTER, e.g. 'proto udp and port 53'";
+
+enum Format {
+    Packet,
+    Flat,
+    Delimited(Delimited),
+}
+
+struct Args {
+    ports: Vec<u16>,
+    output: Option<String>,
+    format: Format,
+    filter: Option<Filter>,
+    capture: String,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut ports = Vec::new();
+    let mut output = None;
+    let mut format = Format::Packet;
+    let mut filter = None;
+    let mut capture = None;
+    let mut iter = env::args().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or(format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "-p" => ports.push(
+                value()?
+                    .parse()
+                    .map_err(|e| format!("invalid port: {}", e))?,
+            ),
+            "-o" => output = Some(value()?),
+            "-f" => {
+                format = match value()?.as_str() {
+                    "packet" => Format::Packet,
+                    "flat" => Format::Flat,
+                    "csv" => Format::Delimited(Delimited::Csv),
+                    "tsv" => Format::Delimited(Delimited::Tsv),
+                    name => return Err(format!("unknown format: {}", name)),
+                }
+            }
+            "-F" => filter = Some(value()?.parse().map_err(|e: NetFlowError| e.to_string())?),
+            "-h" | "--help" => return Err(String::new()),
+            _ if capture.is_none() && !arg.starts_with('-') => capture = Some(arg),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    Ok(Args {
+        ports,
+        output,
+        format,
+        filter,
+        capture: capture.ok_or("capture file is required")?,
+    })
+}
+
+enum Output {
+    Packet(Box<dyn Write>),
+    Flat(Box<dyn Write>),
+    Delimited(CsvWriter<Box<dyn Write>>),
+}
+
+impl Output {
+    fn new(format: Format, out: Box<dyn Write>) -> Output {
+        match format {
+            Format::Packet => Output::Packet(out),
+            Format::Flat => Output::Flat(out),
+            Format::Delimited(delimited) => Output::Delimited(CsvWriter::new(out, delimited)),
+        }
+    }
+
+    fn write(&mut self, flow: &CapturedFlow) -> io::Result<()> {
+        match self {
+            Output::Packet(out) => {
+                serde_json::to_writer(&mut *out, flow)?;
+                out.write_all(b"\n")
+            }
+            Output::Flat(out) => write_flat_netflow9(out, flow.exporter, &flow.netflow).map(|_| ()),
+            Output::Delimited(csv) => csv.write_netflow9(flow.exporter, &flow.netflow).map(|_| ()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Packet(out) | Output::Flat(out) => out.flush(),
+            Output::Delimited(csv) => csv.flush(),
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), NetFlowError> {
+    let filter = if args.ports.is_empty() {
+        DatagramFilter::Auto
+    } else {
+        DatagramFilter::Ports(args.ports)
+    };
+
+    let file = BufReader::new(File::open(&args.capture)?);
+    let mut reader = FlowReader::new(file, filter)?;
+    let target = args.output.as_deref().unwrap_or("-");
+    let out: Box<dyn Write> = Box::new(SinkWriter::new(open_sink(target, Rotation::none())?));
+    let mut out = Output::new(args.format, out);
+
+    loop {
+        match reader.next_flow() {
+            Ok(Some(mut flow)) => {
+                if let Some(filter) = &args.filter {
+                    filter.filter_netflow9(&mut flow.netflow);
+                }
+                out.write(&flow)?
+            }
+            Ok(None) => break,
+            Err(e @ NetFlowError::Io(_)) | Err(e @ NetFlowError::InvalidCapture { .. }) => {
+                return Err(e)
+            }
+            Err(e) => warn!("invalid packet: {}", e),
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn main() {
+    env_logger::init();
+
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("{}", msg);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("netflow-pcap: {}", e);
+        process::exit(1);
+    }
+}
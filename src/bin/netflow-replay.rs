@@ -0,0 +1,121 @@
+use netflow::error::NetFlowError;
+use netflow::pcap::DatagramFilter;
+use netflow::replay::{read_json_lines, read_pcap, ReplayOptions, Replayer};
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::net::UdpSocket;
+use std::process;
+
+const USAGE: &str =
+    "usage: netflow-replay [-p PORT]... [-x SPEED] [-n LOOPS] [-r] [-b ADDR] FILE HOST:PORT
+
+  -p PORT   replay UDP datagrams sent to PORT, can be repeated
+            (default: detect Netflow v9 from the payload)
+  -x SPEED  2 replays twice as fast as captured, 0.5 half as fast, 0 without waiting (default: 1)
+  -n LOOPS  times the file is replayed, 0 repeats forever (default: 1)
+  -r        rewrite timestamp, sys_uptime and flow_sequence so the replay looks live
+  -b ADDR   address packets are sent from (default: 0.0.0.0:0)
+
+FILE is a pcap or pcapng capture, or JSON lines written by netflow-collector -f packet.";
+
+struct Args {
+    ports: Vec<u16>,
+    options: ReplayOptions,
+    bind: String,
+    file: String,
+    destination: String,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut ports = Vec::new();
+    let mut options = ReplayOptions::default();
+    let mut bind = "0.0.0.0:0".to_string();
+    let mut positional = Vec::new();
+    let mut iter = env::args().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or(format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "-p" => ports.push(
+                value()?
+                    .parse()
+                    .map_err(|e| format!("invalid port: {}", e))?,
+            ),
+            "-x" => {
+                options.speed = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid speed: {}", e))?
+            }
+            "-n" => {
+                let loops: u32 = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid loop count: {}", e))?;
+                options.loops = if loops == 0 { None } else { Some(loops) };
+            }
+            "-r" => options.rewrite = true,
+            "-b" => bind = value()?,
+            "-h" | "--help" => return Err(String::new()),
+            _ if !arg.starts_with('-') => positional.push(arg),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    if positional.len() != 2 {
+        return Err("a file and a destination are required".to_string());
+    }
+    let destination = positional.pop().unwrap_or_default();
+    let file = positional.pop().unwrap_or_default();
+
+    Ok(Args {
+        ports,
+        options,
+        bind,
+        file,
+        destination,
+    })
+}
+
+fn run(args: Args) -> Result<(), NetFlowError> {
+    let mut file = BufReader::new(File::open(&args.file)?);
+
+    // JSON lines start with an object, captures with a magic number
+    let packets = if file.fill_buf()?.first() == Some(&b'{') {
+        read_json_lines(file)?
+    } else {
+        let filter = if args.ports.is_empty() {
+            DatagramFilter::Auto
+        } else {
+            DatagramFilter::Ports(args.ports)
+        };
+        read_pcap(file, &filter)?
+    };
+
+    let socket = UdpSocket::bind(&args.bind)?;
+    let replayer = Replayer::new(packets, args.options);
+    let stats = replayer.run(&socket, &args.destination)?;
+    eprintln!("{}", serde_json::json!(stats));
+
+    Ok(())
+}
+
+fn main() {
+    env_logger::init();
+
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("{}", msg);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("netflow-replay: {}", e);
+        process::exit(1);
+    }
+}
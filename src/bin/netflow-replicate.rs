@@ -0,0 +1,159 @@
+#[macro_use]
+extern crate log;
+
+use netflow::collector::Collector;
+use netflow::filter::Filter;
+use netflow::replicate::{Destination, Encapsulation, Replicator, Selector};
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: netflow-replicate [-l ADDR]... [-b ADDR] [-s SECONDS] DEST[,OPTION]...
+
+  -l ADDR     listen address, can be repeated (default: 0.0.0.0:2055)
+  -b ADDR     address datagrams are sent from (default: 0.0.0.0:0)
+  -s SECONDS  interval of destination rates printed to stderr (default: 60, 0 disables)
+
+DEST is HOST:PORT followed by comma separated options:
+  wrap          prefix datagrams with the exporter address
+  exporter=IP   forward datagrams of this exporter only, can be repeated
+  source_id=ID  forward packets of this v9 source id or IPFIX observation domain only,
+                can be repeated
+  template=ID   forward flowsets of this template only, can be repeated
+  filter=EXPR   forward only the data records matching EXPR, e.g. 'filter=proto udp'
+
+Without wrap the destination sees the replicator as the source of the datagrams.";
+
+struct Args {
+    listen: Vec<String>,
+    bind: String,
+    stats_interval: u64,
+    destinations: Vec<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        listen: Vec::new(),
+        bind: "0.0.0.0:0".to_string(),
+        stats_interval: 60,
+        destinations: Vec::new(),
+    };
+    let mut iter = env::args().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or(format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "-l" => args.listen.push(value()?),
+            "-b" => args.bind = value()?,
+            "-s" => {
+                args.stats_interval = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid interval: {}", e))?
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ if !arg.starts_with('-') => args.destinations.push(arg),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    if args.listen.is_empty() {
+        args.listen.push("0.0.0.0:2055".to_string());
+    }
+    if args.destinations.is_empty() {
+        return Err("a destination is required".to_string());
+    }
+
+    Ok(args)
+}
+
+fn parse_destination(spec: &str) -> Result<Destination, String> {
+    let mut parts = spec.split(',');
+    let addr = parts.next().unwrap_or_default();
+    let mut destination =
+        Destination::new(addr).map_err(|e| format!("invalid destination {}: {}", addr, e))?;
+    let mut selector = Selector::default();
+
+    for option in parts {
+        let (name, value) = option.split_once('=').unwrap_or((option, ""));
+        let invalid = |e: &dyn std::fmt::Display| format!("invalid {}: {}", option, e);
+
+        match name {
+            "wrap" => destination = destination.encapsulation(Encapsulation::Wrapped),
+            "exporter" => selector
+                .exporters
+                .push(value.parse().map_err(|e| invalid(&e))?),
+            "source_id" => selector
+                .source_ids
+                .push(value.parse().map_err(|e| invalid(&e))?),
+            "template" => selector
+                .templates
+                .push(value.parse().map_err(|e| invalid(&e))?),
+            "filter" => {
+                let filter: Filter = value.parse().map_err(|e| invalid(&e))?;
+                destination = destination.filter(filter);
+            }
+            _ => return Err(format!("unknown destination option: {}", option)),
+        }
+    }
+
+    Ok(destination.selector(selector))
+}
+
+fn print_rates(replicator: &mut Replicator) {
+    let stats = replicator.stats();
+
+    for rate in replicator.rates() {
+        eprintln!(
+            "{}",
+            serde_json::json!({ "rate": rate, "stats": stats[&rate.destination] })
+        );
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mut replicator = Replicator::bind(&args.bind).map_err(|e| e.to_string())?;
+    for spec in &args.destinations {
+        replicator.add_destination(parse_destination(spec)?);
+    }
+    let collector = Collector::bind(&args.listen).map_err(|e| e.to_string())?;
+
+    let interval = Duration::from_secs(args.stats_interval);
+    let mut last_stats = Instant::now();
+
+    loop {
+        match collector.recv_timeout(Duration::from_secs(1)) {
+            Some(Ok(datagram)) if replicator.forward(&datagram) == 0 => {
+                debug!("datagram from {} was not forwarded", datagram.exporter)
+            }
+            Some(Ok(_)) => (),
+            Some(Err(e)) => return Err(e.to_string()),
+            None => (),
+        }
+
+        if args.stats_interval > 0 && last_stats.elapsed() >= interval {
+            print_rates(&mut replicator);
+            last_stats = Instant::now();
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("{}", msg);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("netflow-replicate: {}", e);
+        process::exit(1);
+    }
+}
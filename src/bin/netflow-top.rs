@@ -0,0 +1,377 @@
+#[macro_use]
+extern crate log;
+
+use netflow::collector::Collector;
+use netflow::dedup::{DedupFlow, Deduplicator, Direction, Preference};
+use netflow::error::NetFlowError;
+use netflow::filter::Filter;
+use netflow::ipfix::{IpfixFileReader, IPFIX_VERSION};
+use netflow::pcap::{DatagramFilter, FlowReader};
+use netflow::report::{Dimension, Metric, Report, ALL_DIMENSIONS};
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "usage: netflow-top [-n COUNT] [-k KEYS] [-m METRIC] [-f FORMAT] [-F FILTER]
+                   [-D SECONDS [-P ADDR]... [-i] [-I]] [-b TIME] [-e TIME] CAPTURE
+       netflow-top [-n COUNT] [-k KEYS] [-m METRIC] [-f FORMAT] [-F FILTER]
+                   [-D SECONDS [-P ADDR]... [-i] [-I]] [-w SECONDS] -l ADDR...
+
+  -n COUNT    entries per table (default: 10)
+  -k KEYS     comma separated tables: src-ip, dst-ip, src-port, dst-port, proto,
+              src-as, dst-as, conversation (default: all)
+  -m METRIC   rank by bytes (default), packets or flows
+  -f FORMAT   table (default) or json
+  -F FILTER   count only the records matching FILTER, e.g. 'dst port 443'
+  -D SECONDS  count a flow reported by several exporters once, matching records
+              whose times overlap within SECONDS
+  -P ADDR     with -D, keep the record of this exporter, e.g. an edge router,
+              can be repeated in order of preference
+  -i          with -D, keep the ingress record
+  -I          with -D, also match IPV4_IDENT
+  -b TIME     only flows ending at or after TIME, in seconds since the epoch
+  -e TIME     only flows ending before TIME
+  -l ADDR     collect live on the address, can be repeated
+  -w SECONDS  with -l, print a report every SECONDS (default: 60)
+
+CAPTURE is a pcap or pcapng file of Netflow v9 packets, or an IPFIX file.";
+
+enum Format {
+    Table,
+    Json,
+}
+
+struct Args {
+    count: usize,
+    dimensions: Vec<Dimension>,
+    metric: Metric,
+    format: Format,
+    filter: Option<Filter>,
+    dedup: Option<Duration>,
+    preference: Preference,
+    match_ident: bool,
+    begin: Option<u64>,
+    end: Option<u64>,
+    listen: Vec<String>,
+    interval: Duration,
+    capture: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut count = 10;
+    let mut dimensions = ALL_DIMENSIONS.to_vec();
+    let mut metric = Metric::Bytes;
+    let mut format = Format::Table;
+    let mut filter = None;
+    let mut dedup = None;
+    let mut preference = Preference::default();
+    let mut match_ident = false;
+    let mut begin = None;
+    let mut end = None;
+    let mut listen = Vec::new();
+    let mut interval = Duration::from_secs(60);
+    let mut capture = None;
+    let mut iter = env::args().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or(format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "-n" => {
+                count = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid count: {}", e))?
+            }
+            "-k" => {
+                dimensions = value()?
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?
+            }
+            "-m" => metric = value()?.parse()?,
+            "-f" => {
+                format = match value()?.as_str() {
+                    "table" => Format::Table,
+                    "json" => Format::Json,
+                    name => return Err(format!("unknown format: {}", name)),
+                }
+            }
+            "-F" => filter = Some(value()?.parse().map_err(|e: NetFlowError| e.to_string())?),
+            "-D" => {
+                dedup = Some(Duration::from_secs(
+                    value()?
+                        .parse()
+                        .map_err(|e| format!("invalid dedup window: {}", e))?,
+                ))
+            }
+            "-P" => preference.exporters.push(
+                value()?
+                    .parse()
+                    .map_err(|e| format!("invalid exporter address: {}", e))?,
+            ),
+            "-i" => preference.direction = Some(Direction::Ingress),
+            "-I" => match_ident = true,
+            "-b" => {
+                begin = Some(
+                    value()?
+                        .parse()
+                        .map_err(|e| format!("invalid begin time: {}", e))?,
+                )
+            }
+            "-e" => {
+                end = Some(
+                    value()?
+                        .parse()
+                        .map_err(|e| format!("invalid end time: {}", e))?,
+                )
+            }
+            "-l" => listen.push(value()?),
+            "-w" => {
+                interval = Duration::from_secs(
+                    value()?
+                        .parse()
+                        .map_err(|e| format!("invalid interval: {}", e))?,
+                )
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ if capture.is_none() && !arg.starts_with('-') => capture = Some(arg),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    if listen.is_empty() == capture.is_none() {
+        return Err("either a capture or -l is required".to_string());
+    }
+    if !listen.is_empty() && (begin.is_some() || end.is_some()) {
+        return Err("-b and -e only apply to a capture".to_string());
+    }
+    if dedup.is_none() && (preference != Preference::default() || match_ident) {
+        return Err("-P, -i and -I need -D".to_string());
+    }
+    if interval.as_secs() == 0 {
+        return Err("the interval must be at least a second".to_string());
+    }
+
+    Ok(Args {
+        count,
+        dimensions,
+        metric,
+        format,
+        filter,
+        dedup,
+        preference,
+        match_ident,
+        begin,
+        end,
+        listen,
+        interval,
+        capture,
+    })
+}
+
+fn print(args: &Args, report: &Report) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    match args.format {
+        Format::Table => {
+            report.write_table(&mut out, args.metric, args.count)?;
+            writeln!(out)?;
+        }
+        Format::Json => {
+            serde_json::to_writer(&mut out, &report.to_json(args.metric, args.count))?;
+            writeln!(out)?;
+        }
+    }
+    out.flush()
+}
+
+fn add_flows(report: &mut Report, flows: Vec<DedupFlow>) {
+    for flow in flows {
+        report.add_record(&flow.record, flow.end);
+    }
+}
+
+fn print_duplicates(dedup: &Deduplicator, before: u64) {
+    eprintln!(
+        "netflow-top: {} duplicate records removed",
+        dedup.stats().duplicates - before
+    );
+}
+
+/// Count the flows over at `now`, the time in the capture, checking once a second of it so
+/// the deduplicator does not hold the whole capture.
+fn expire_flows(
+    dedup: &mut Deduplicator,
+    now: Duration,
+    last_expire: &mut Duration,
+    report: &mut Report,
+) {
+    if now >= *last_expire + Duration::from_secs(1) {
+        add_flows(report, dedup.expire(now));
+        *last_expire = now;
+    }
+}
+
+fn read_capture(
+    path: &str,
+    filter: Option<&Filter>,
+    mut dedup: Option<&mut Deduplicator>,
+    report: &mut Report,
+) -> Result<(), NetFlowError> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut last_expire = Duration::from_secs(0);
+
+    // IPFIX files start with a message header, captures with the pcap or pcapng magic
+    if file.fill_buf()?.starts_with(&IPFIX_VERSION.to_be_bytes()) {
+        let mut reader = IpfixFileReader::new(file);
+        while let Some(mut message) = reader.next_message()? {
+            if let Some(filter) = filter {
+                filter.filter_ipfix(&mut message);
+            }
+            match dedup.as_deref_mut() {
+                // files do not keep the exporter address, domains and directions still differ
+                Some(dedup) => {
+                    add_flows(
+                        report,
+                        dedup.add_ipfix(IpAddr::V4(Ipv4Addr::UNSPECIFIED), &message),
+                    );
+                    let now = Duration::from_secs(u64::from(message.export_time));
+                    expire_flows(dedup, now, &mut last_expire, report);
+                }
+                None => report.add_ipfix(&message),
+            }
+        }
+        if let Some(dedup) = dedup {
+            add_flows(report, dedup.flush());
+        }
+        return Ok(());
+    }
+
+    let mut reader = FlowReader::new(file, DatagramFilter::Auto)?;
+    loop {
+        match reader.next_flow() {
+            Ok(Some(mut flow)) => {
+                if let Some(filter) = filter {
+                    filter.filter_netflow9(&mut flow.netflow);
+                }
+                match dedup.as_deref_mut() {
+                    Some(dedup) => {
+                        add_flows(
+                            report,
+                            dedup.add_netflow9(flow.exporter.ip(), &flow.netflow),
+                        );
+                        expire_flows(dedup, flow.timestamp, &mut last_expire, report);
+                    }
+                    None => report.add_netflow9(&flow.netflow),
+                }
+            }
+            Ok(None) => {
+                if let Some(dedup) = dedup {
+                    add_flows(report, dedup.flush());
+                }
+                return Ok(());
+            }
+            Err(e @ NetFlowError::Io(_)) | Err(e @ NetFlowError::InvalidCapture { .. }) => {
+                return Err(e)
+            }
+            Err(e) => warn!("invalid packet: {}", e),
+        }
+    }
+}
+
+fn collect(
+    args: &Args,
+    mut dedup: Option<Deduplicator>,
+    report: &mut Report,
+) -> Result<(), NetFlowError> {
+    let mut collector = Collector::bind(&args.listen)?;
+    collector.set_filter(args.filter.clone());
+    let mut last_report = Instant::now();
+    let mut last_duplicates = 0;
+
+    loop {
+        match collector.recv_timeout(Duration::from_secs(1)) {
+            Some(Ok(datagram)) => match collector.decode(&datagram) {
+                Ok(netflow) => match dedup.as_mut() {
+                    Some(dedup) => {
+                        add_flows(report, dedup.add_netflow9(datagram.exporter.ip(), &netflow))
+                    }
+                    None => report.add_netflow9(&netflow),
+                },
+                Err(e) => warn!("invalid packet from {}: {}", datagram.exporter, e),
+            },
+            Some(Err(e)) => return Err(e.into()),
+            None => (),
+        }
+
+        if let Some(dedup) = dedup.as_mut() {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            add_flows(report, dedup.expire(now));
+        }
+
+        if last_report.elapsed() >= args.interval {
+            print(args, report)?;
+            if let Some(dedup) = &dedup {
+                print_duplicates(dedup, last_duplicates);
+                last_duplicates = dedup.stats().duplicates;
+            }
+            report.clear();
+            last_report = Instant::now();
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), NetFlowError> {
+    let mut report = Report::new(&args.dimensions);
+    let mut dedup = args.dedup.map(|window| {
+        let mut dedup = Deduplicator::new(window);
+        dedup.set_preference(args.preference.clone());
+        dedup.set_match_ident(args.match_ident);
+        dedup
+    });
+
+    match &args.capture {
+        Some(capture) => {
+            if args.begin.is_some() || args.end.is_some() {
+                report.set_window(
+                    args.begin.unwrap_or(0) * 1000,
+                    args.end.map_or(u64::MAX, |end| end * 1000),
+                );
+            }
+            read_capture(capture, args.filter.as_ref(), dedup.as_mut(), &mut report)?;
+            print(&args, &report)?;
+            if let Some(dedup) = &dedup {
+                print_duplicates(dedup, 0);
+            }
+            Ok(())
+        }
+        None => collect(&args, dedup, &mut report),
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("{}", msg);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("netflow-top: {}", e);
+        process::exit(1);
+    }
+}
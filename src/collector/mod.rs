@@ -0,0 +1,280 @@
+use crate::error::NetFlowError;
+use crate::filter::Filter;
+use crate::flowset::FlowSet;
+use crate::netflow::{CountCheck, NetFlow9, TemplateCache};
+use crate::sequence::SequenceTracker;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+// Netflow v9 packets are sent on UDP, so a datagram never exceeds this.
+const MAX_DATAGRAM_LEN: usize = 65535;
+
+/// A UDP payload with its sender and the local address it was received on.
+#[derive(Debug, Clone)]
+pub struct Datagram {
+    pub exporter: SocketAddr,
+    pub local: SocketAddr,
+    pub payload: Vec<u8>,
+}
+
+/// Counters kept for every exporter address.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExporterStats {
+    pub packets: u64,
+    pub bytes: u64,
+    pub parse_errors: u64,
+    pub templates: u64,
+    pub records: u64,
+    /// dataflows dropped because their template is not received yet
+    pub missing_templates: u64,
+    /// packets whose header count does not match their records
+    pub count_mismatches: u64,
+    /// data records dropped by the filter
+    pub filtered: u64,
+}
+
+/// Receives Netflow v9 packets on one or more UDP sockets.
+/// Every socket is read by its own thread, packets are decoded by the caller.
+pub struct Collector {
+    local_addrs: Vec<SocketAddr>,
+    receiver: Receiver<io::Result<Datagram>>,
+    templates: TemplateCache,
+    sequences: SequenceTracker,
+    stats: HashMap<SocketAddr, ExporterStats>,
+    count_check: CountCheck,
+    filter: Option<Filter>,
+}
+
+impl Collector {
+    pub fn bind<A: ToSocketAddrs>(addrs: &[A]) -> io::Result<Collector> {
+        let (sender, receiver) = mpsc::channel();
+        let mut local_addrs = Vec::new();
+
+        for addr in addrs {
+            let socket = UdpSocket::bind(addr)?;
+            let local = socket.local_addr()?;
+            info!("listening on {}", local);
+
+            local_addrs.push(local);
+            Collector::spawn_receiver(socket, local, sender.clone());
+        }
+
+        Ok(Collector {
+            local_addrs,
+            receiver,
+            templates: TemplateCache::new(),
+            sequences: SequenceTracker::new(),
+            stats: HashMap::new(),
+            count_check: CountCheck::Lenient,
+            filter: None,
+        })
+    }
+
+    fn spawn_receiver(socket: UdpSocket, local: SocketAddr, sender: Sender<io::Result<Datagram>>) {
+        thread::spawn(move || {
+            let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+
+            loop {
+                let res = socket.recv_from(&mut buf).map(|(len, exporter)| Datagram {
+                    exporter,
+                    local,
+                    payload: buf[..len].to_vec(),
+                });
+
+                // collector was dropped
+                if sender.send(res).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// With `CountCheck::Strict` a packet whose header count does not match its records is rejected.
+    pub fn set_count_check(&mut self, check: CountCheck) {
+        self.count_check = check;
+    }
+
+    /// Drop the data records the filter rejects from the decoded packets.
+    pub fn set_filter(&mut self, filter: Option<Filter>) {
+        self.filter = filter;
+    }
+
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Wait for a datagram on any socket.
+    /// Return None if nothing arrived before the timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<io::Result<Datagram>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(res) => Some(res),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "all receivers stopped",
+            ))),
+        }
+    }
+
+    /// Decode a datagram with the templates received so far and update the exporter counters.
+    /// Record counts are taken before the filter drops records.
+    pub fn decode(&mut self, datagram: &Datagram) -> Result<NetFlow9, NetFlowError> {
+        let stats = self.stats.entry(datagram.exporter).or_default();
+        stats.packets += 1;
+        stats.bytes += datagram.payload.len() as u64;
+
+        let mut netflow = match NetFlow9::from_bytes(&datagram.payload) {
+            Ok(netflow) => netflow,
+            Err(e) => {
+                stats.parse_errors += 1;
+                return Err(e);
+            }
+        };
+
+        self.sequences.observe_netflow9(datagram.exporter, &netflow);
+        self.templates.learn(datagram.exporter, &netflow);
+        stats.missing_templates += self.templates.apply(datagram.exporter, &mut netflow) as u64;
+
+        if let Err(e) = netflow.check_count() {
+            stats.count_mismatches += 1;
+            if self.count_check == CountCheck::Strict {
+                return Err(e);
+            }
+        }
+
+        for flowset in &netflow.flow_sets {
+            match flowset {
+                FlowSet::DataTemplate(template) => {
+                    stats.templates += template.templates.len() as u64
+                }
+                FlowSet::OptionTemplate(_) => stats.templates += 1,
+                FlowSet::DataFlow(dataflow) => {
+                    stats.records += dataflow.records.as_ref().map_or(0, |r| r.len()) as u64
+                }
+            }
+        }
+
+        if let Some(filter) = &self.filter {
+            stats.filtered += filter.filter_netflow9(&mut netflow) as u64;
+        }
+
+        Ok(netflow)
+    }
+
+    pub fn stats(&self) -> &HashMap<SocketAddr, ExporterStats> {
+        &self.stats
+    }
+
+    pub fn templates(&self) -> &TemplateCache {
+        &self.templates
+    }
+
+    pub fn sequences(&self) -> &SequenceTracker {
+        &self.sequences
+    }
+}
+
+#[derive(Serialize)]
+struct JsonPacket<'a> {
+    exporter: SocketAddr,
+    packet: &'a NetFlow9,
+}
+
+/// Write a decoded packet as a line of JSON.
+pub fn write_json_line<W: Write>(
+    writer: &mut W,
+    exporter: SocketAddr,
+    netflow: &NetFlow9,
+) -> io::Result<()> {
+    let packet = JsonPacket {
+        exporter,
+        packet: netflow,
+    };
+
+    serde_json::to_writer(&mut *writer, &packet)?;
+    writer.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod test_collector {
+    use super::{write_json_line, Collector};
+    use crate::netflow::test_data;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_collect() {
+        let mut collector = Collector::bind(&["127.0.0.1:0", "127.0.0.1:0"]).unwrap();
+        assert_eq!(collector.local_addrs().len(), 2);
+
+        let exporter = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for addr in collector.local_addrs() {
+            exporter.send_to(&test_data::NETFLOWV9_DATA, addr).unwrap();
+        }
+        exporter
+            .send_to(&[0x00, 0x05], collector.local_addrs()[0])
+            .unwrap();
+
+        let mut decoded = 0;
+        let mut errors = 0;
+        for _ in 0..3 {
+            let datagram = collector.recv_timeout(TIMEOUT).unwrap().unwrap();
+            assert_eq!(datagram.exporter, exporter.local_addr().unwrap());
+
+            match collector.decode(&datagram) {
+                Ok(_) => decoded += 1,
+                Err(_) => errors += 1,
+            }
+        }
+        assert_eq!(decoded, 2);
+        assert_eq!(errors, 1);
+
+        let stats = &collector.stats()[&exporter.local_addr().unwrap()];
+        assert_eq!(stats.packets, 3);
+        assert_eq!(stats.parse_errors, 1);
+        assert_eq!(stats.templates, 10);
+        assert_eq!(stats.records, 4);
+        assert_eq!(stats.missing_templates, 0);
+        assert_eq!(stats.bytes, 2 * test_data::NETFLOWV9_DATA.len() as u64 + 2);
+
+        // same packet was sent twice
+        let (_, sequence) = collector.sequences().iter().next().unwrap();
+        assert_eq!(sequence.duplicates, 1);
+
+        assert!(collector.recv_timeout(Duration::from_millis(10)).is_none());
+    }
+
+    #[test]
+    fn test_write_json_line() {
+        let mut collector = Collector::bind(&["127.0.0.1:0"]).unwrap();
+        let exporter = UdpSocket::bind("127.0.0.1:0").unwrap();
+        exporter
+            .send_to(&test_data::NETFLOWV9_DATA, collector.local_addrs()[0])
+            .unwrap();
+
+        let datagram = collector.recv_timeout(TIMEOUT).unwrap().unwrap();
+        let netflow = collector.decode(&datagram).unwrap();
+
+        let mut out = Vec::new();
+        write_json_line(&mut out, datagram.exporter, &netflow).unwrap();
+
+        let line = String::from_utf8(out).unwrap();
+        assert!(line.ends_with('\n'));
+        assert_eq!(line.lines().count(), 1);
+
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["packet"]["flow_sequence"], 883);
+        assert_eq!(
+            json["exporter"].as_str().unwrap(),
+            datagram.exporter.to_string()
+        );
+    }
+}
@@ -0,0 +1,678 @@
+use crate::error::NetFlowError;
+use crate::field::FieldTypes::*;
+use crate::field::{FieldValue, FlowField, ScopeTypes, TypeLengthField};
+use crate::flowset::{
+    DataFlow, DataTemplate, DataTemplateItem, FlowSet, OptionTemplate, OptionTemplateItem, Record,
+};
+use crate::ipfix::{DataSet, IpfixMessage, IpfixSet};
+use crate::netflow::{NetFlow5, NetFlow9, TemplateCache};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// Template ids of the v9 packets converted from v5.
+pub const V5_TEMPLATE_ID: u16 = 256;
+pub const V5_SAMPLING_TEMPLATE_ID: u16 = 257;
+
+const IPFIX_HEADER_LEN: usize = 16;
+const SET_HEADER_LEN: usize = 4;
+
+/// The v9 template carrying every field of a v5 record, in v5 order.
+pub fn v5_template() -> DataTemplateItem {
+    // v5 widths are the registry defaults
+    DataTemplateItem::builder(V5_TEMPLATE_ID)
+        .field(IPV4_SRC_ADDR)
+        .field(IPV4_DST_ADDR)
+        .field(IPV4_NEXT_HOP)
+        .field(INPUT_SNMP)
+        .field(OUTPUT_SNMP)
+        .field(IN_PKTS)
+        .field(IN_BYTES)
+        .field(FIRST_SWITCHED)
+        .field(LAST_SWITCHED)
+        .field(L4_SRC_PORT)
+        .field(L4_DST_PORT)
+        .field(TCP_FLAGS)
+        .field(PROTOCOL)
+        .field(TOS)
+        .field(SRC_AS)
+        .field(DST_AS)
+        .field(SRC_MASK)
+        .field(DST_MASK)
+        .build()
+        .expect("the v5 template is valid")
+}
+
+/// The v9 option template carrying the sampling of a v5 exporter.
+pub fn v5_sampling_template() -> OptionTemplateItem {
+    OptionTemplateItem::builder(V5_SAMPLING_TEMPLATE_ID)
+        .scope(ScopeTypes::System, 4)
+        .field(SAMPLING_INTERVAL)
+        .field(SAMPLING_ALGORITHM)
+        .build()
+        .expect("the v5 sampling template is valid")
+}
+
+#[derive(Debug, Default)]
+struct V5Stream {
+    sequence: u32,
+    packets_since_templates: Option<u32>,
+}
+
+/// Converts Netflow v5 packets to v9 packets of `v5_template`, for collectors without v5.
+///
+/// Each exporter engine becomes a v9 source id, `engine_type * 256 + engine_id`, with its own
+/// `flow_sequence` counting packets as v9 does. Templates are sent with the first packet of
+/// a source and again every `template_packets` packets. A sampling interval is exported as
+/// an option record sent with the templates.
+pub struct V5Converter {
+    template: DataTemplateItem,
+    sampling_template: OptionTemplateItem,
+    template_packets: u32,
+    streams: HashMap<(SocketAddr, u32), V5Stream>,
+}
+
+impl Default for V5Converter {
+    fn default() -> Self {
+        V5Converter::new()
+    }
+}
+
+impl V5Converter {
+    pub fn new() -> V5Converter {
+        V5Converter {
+            template: v5_template(),
+            sampling_template: v5_sampling_template(),
+            template_packets: 20,
+            streams: HashMap::new(),
+        }
+    }
+
+    /// Send the templates again after this many packets of a source, 20 by default.
+    pub fn set_template_packets(&mut self, packets: u32) {
+        self.template_packets = packets.max(1);
+    }
+
+    fn sampling_record(&self, exporter: SocketAddr, sampling_interval: u16) -> Record {
+        let system = match exporter.ip() {
+            IpAddr::V4(ip) => ip.octets(),
+            IpAddr::V6(_) => [0; 4],
+        };
+        // the mode bits of v5 are the algorithm codes of v9
+        let interval = u32::from(sampling_interval & 0x3fff);
+        let algorithm = (sampling_interval >> 14) as u8;
+
+        Record::make_option(
+            vec![FlowField::new(
+                ScopeTypes::System,
+                4,
+                FieldValue::new(ScopeTypes::System, &system),
+            )],
+            vec![
+                FlowField::new(SAMPLING_INTERVAL, 4, interval.into()),
+                FlowField::new(SAMPLING_ALGORITHM, 1, algorithm.into()),
+            ],
+        )
+    }
+
+    pub fn convert(
+        &mut self,
+        exporter: SocketAddr,
+        netflow: &NetFlow5,
+    ) -> Result<NetFlow9, NetFlowError> {
+        let source_id = u32::from(netflow.engine_type) << 8 | u32::from(netflow.engine_id);
+        let template_packets = self.template_packets;
+        let stream = self.streams.entry((exporter, source_id)).or_default();
+
+        let send_templates = stream
+            .packets_since_templates
+            .is_none_or(|packets| packets >= template_packets);
+        stream.packets_since_templates = if send_templates {
+            Some(1)
+        } else {
+            stream.packets_since_templates.map(|packets| packets + 1)
+        };
+        let sequence = stream.sequence;
+        stream.sequence = stream.sequence.wrapping_add(1);
+
+        let mut flowsets = Vec::new();
+        let sampled = netflow.sampling_interval & 0x3fff != 0;
+        if send_templates {
+            flowsets.push(DataTemplate::new(vec![self.template.clone()]).into());
+            if sampled {
+                flowsets.push(OptionTemplate::new(self.sampling_template.clone()).into());
+                flowsets.push(
+                    DataFlow::new(
+                        V5_SAMPLING_TEMPLATE_ID,
+                        vec![self.sampling_record(exporter, netflow.sampling_interval)],
+                    )
+                    .into(),
+                );
+            }
+        }
+
+        let mut records = Vec::with_capacity(netflow.records.len());
+        for record in &netflow.records {
+            records.push(
+                self.template
+                    .record_builder()
+                    .set(IPV4_SRC_ADDR, record.src_addr)
+                    .set(IPV4_DST_ADDR, record.dst_addr)
+                    .set(IPV4_NEXT_HOP, record.next_hop)
+                    .set(INPUT_SNMP, record.input)
+                    .set(OUTPUT_SNMP, record.output)
+                    .set(IN_PKTS, record.packets)
+                    .set(IN_BYTES, record.octets)
+                    .set(FIRST_SWITCHED, record.first)
+                    .set(LAST_SWITCHED, record.last)
+                    .set(L4_SRC_PORT, record.src_port)
+                    .set(L4_DST_PORT, record.dst_port)
+                    .set(TCP_FLAGS, record.tcp_flags)
+                    .set(PROTOCOL, record.protocol)
+                    .set(TOS, record.tos)
+                    .set(SRC_AS, record.src_as)
+                    .set(DST_AS, record.dst_as)
+                    .set(SRC_MASK, record.src_mask)
+                    .set(DST_MASK, record.dst_mask)
+                    .build()?,
+            );
+        }
+        if !records.is_empty() {
+            flowsets.push(DataFlow::new(V5_TEMPLATE_ID, records).into());
+        }
+
+        Ok(NetFlow9::new(
+            netflow.sys_uptime,
+            netflow.unix_secs,
+            sequence,
+            source_id,
+            flowsets,
+        ))
+    }
+}
+
+/// The IPFIX element of a v9 field, with its length.
+/// Uptime-relative switched times become absolute milliseconds.
+fn ipfix_field(field: &TypeLengthField) -> TypeLengthField {
+    match field.type_id {
+        FIRST_SWITCHED => TypeLengthField::new(FLOW_START_MILLISECONDS, 8),
+        LAST_SWITCHED => TypeLengthField::new(FLOW_END_MILLISECONDS, 8),
+        _ => *field,
+    }
+}
+
+/// The IPFIX element of a v9 scope type.
+fn ipfix_scope(scope_type: u16) -> u16 {
+    match scope_type {
+        ScopeTypes::System => EXPORTING_PROCESS_ID,
+        ScopeTypes::Interface => INPUT_SNMP,
+        ScopeTypes::Line_Card => LINE_CARD_ID,
+        ScopeTypes::NetFlow_Cache => METERING_PROCESS_ID,
+        ScopeTypes::Template => TEMPLATE_ID,
+        other => other,
+    }
+}
+
+/// The IPFIX template of a v9 data template, with the same id.
+pub fn ipfix_template(template: &DataTemplateItem) -> DataTemplateItem {
+    DataTemplateItem::new(
+        template.template_id,
+        template.fields.iter().map(ipfix_field).collect(),
+    )
+}
+
+/// The IPFIX option template of a v9 option template, with the same id.
+pub fn ipfix_option_template(template: &OptionTemplateItem) -> OptionTemplateItem {
+    let scopes = template
+        .scopes
+        .iter()
+        .map(|scope| TypeLengthField::new(ipfix_scope(scope.type_id), scope.length))
+        .collect();
+    let options = template.options.iter().map(ipfix_field).collect();
+
+    OptionTemplateItem::new(template.template_id, scopes, options)
+}
+
+fn convert_field(field: &FlowField, export_ms: u64, sys_uptime: u32) -> FlowField {
+    // switched times taken before the uptime wrapped are larger than it
+    let absolute = |value: &FieldValue| {
+        let switched = value.to_u64().unwrap_or(0) as u32;
+        export_ms.saturating_sub(u64::from(sys_uptime.wrapping_sub(switched)))
+    };
+
+    match field.type_id() {
+        FIRST_SWITCHED => {
+            FlowField::new(FLOW_START_MILLISECONDS, 8, absolute(field.value()).into())
+        }
+        LAST_SWITCHED => FlowField::new(FLOW_END_MILLISECONDS, 8, absolute(field.value()).into()),
+        _ => field.clone(),
+    }
+}
+
+/// The IPFIX record of a decoded v9 record, switched times are made absolute with the
+/// `timestamp` and `sys_uptime` of the packet header.
+pub fn ipfix_record(record: &Record, timestamp: u32, sys_uptime: u32) -> Record {
+    let export_ms = u64::from(timestamp) * 1000;
+    match record {
+        Record::Data(data) => Record::make_data(
+            data.fields()
+                .iter()
+                .map(|field| convert_field(field, export_ms, sys_uptime))
+                .collect(),
+        ),
+        Record::OptionData(option) => {
+            let scopes = option
+                .scope_fields()
+                .iter()
+                .map(|field| {
+                    let type_id = ipfix_scope(field.type_id());
+                    FlowField::new(
+                        type_id,
+                        field.length(),
+                        FieldValue::new(type_id, &field.to_bytes()),
+                    )
+                })
+                .collect();
+            let options = option
+                .option_fields()
+                .iter()
+                .map(|field| convert_field(field, export_ms, sys_uptime))
+                .collect();
+            Record::make_option(scopes, options)
+        }
+    }
+}
+
+/// The IPFIX template a data set is encoded with.
+enum SetTemplate {
+    Data(DataTemplateItem),
+    Option(OptionTemplateItem),
+}
+
+impl SetTemplate {
+    fn data_set(&self, records: Vec<Record>) -> DataSet {
+        match self {
+            SetTemplate::Data(template) => DataSet::from_data_records(template, records),
+            SetTemplate::Option(template) => DataSet::from_option_records(template, records),
+        }
+    }
+}
+
+enum PendingSet {
+    Set(IpfixSet),
+    Data(SetTemplate, Vec<Record>),
+}
+
+/// Fills messages with sets up to a length, counting the data records of each.
+struct Packer {
+    max_length: usize,
+    messages: Vec<(Vec<IpfixSet>, usize)>,
+    sets: Vec<IpfixSet>,
+    length: usize,
+    records: usize,
+}
+
+impl Packer {
+    fn new(max_length: usize) -> Packer {
+        Packer {
+            max_length,
+            messages: Vec::new(),
+            sets: Vec::new(),
+            length: IPFIX_HEADER_LEN,
+            records: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    fn room(&self) -> usize {
+        self.max_length.saturating_sub(self.length)
+    }
+
+    fn finish_message(&mut self) {
+        if !self.sets.is_empty() {
+            let sets = std::mem::take(&mut self.sets);
+            self.messages.push((sets, self.records));
+        }
+        self.length = IPFIX_HEADER_LEN;
+        self.records = 0;
+    }
+
+    fn push(&mut self, set: IpfixSet, records: usize) {
+        let length = set.byte_length();
+        if !self.is_empty() && length > self.room() {
+            self.finish_message();
+        }
+        self.sets.push(set);
+        self.length += length;
+        self.records += records;
+    }
+
+    fn into_messages(mut self) -> Vec<(Vec<IpfixSet>, usize)> {
+        self.finish_message();
+        self.messages
+    }
+}
+
+/// Counters of an `IpfixConverter`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConvertStats {
+    pub packets: u64,
+    pub messages: u64,
+    pub records: u64,
+    /// dataflows dropped because their template was not received yet
+    pub missing_templates: u64,
+}
+
+/// Converts Netflow v9 packets to IPFIX messages, for collectors without v9.
+///
+/// The source id becomes the observation domain and template ids are kept. Templates are
+/// learned per exporter and source id, so the packets of each exporter must all be converted
+/// by the same converter. FIRST_SWITCHED and LAST_SWITCHED become flowStartMilliseconds and
+/// flowEndMilliseconds, using the boot time the header gives to within a second, and times
+/// taken before the 32 bit uptime wrapped end up before the export time.
+/// The IPFIX sequence number counts the data records sent in the domain, and a packet is
+/// split in several messages when its records no longer fit `max_length`.
+pub struct IpfixConverter {
+    templates: TemplateCache,
+    sequences: HashMap<(SocketAddr, u32), u32>,
+    max_length: usize,
+    stats: ConvertStats,
+}
+
+impl Default for IpfixConverter {
+    fn default() -> Self {
+        IpfixConverter::new()
+    }
+}
+
+impl IpfixConverter {
+    pub fn new() -> IpfixConverter {
+        IpfixConverter {
+            templates: TemplateCache::new(),
+            sequences: HashMap::new(),
+            max_length: 1452,
+            stats: ConvertStats::default(),
+        }
+    }
+
+    /// Longest message to build, 1452 bytes by default to fit the UDP payload of a 1500 MTU.
+    pub fn set_max_length(&mut self, max_length: usize) {
+        self.max_length = max_length;
+    }
+
+    pub fn stats(&self) -> &ConvertStats {
+        &self.stats
+    }
+
+    /// IPFIX sets of the packet, data records are kept apart to be split between messages.
+    fn sets(&mut self, exporter: SocketAddr, netflow: &NetFlow9) -> Vec<PendingSet> {
+        let source_id = netflow.source_id;
+        let mut sets = Vec::new();
+
+        for flowset in &netflow.flow_sets {
+            match flowset {
+                FlowSet::DataTemplate(template) => {
+                    let items = template.templates.iter().map(ipfix_template).collect();
+                    sets.push(PendingSet::Set(IpfixSet::Template(items)));
+                }
+                FlowSet::OptionTemplate(template) => {
+                    let item = ipfix_option_template(&template.templates);
+                    sets.push(PendingSet::Set(IpfixSet::OptionTemplate(vec![item])));
+                }
+                FlowSet::DataFlow(dataflow) => {
+                    let id = dataflow.flowset_id;
+                    let template = match dataflow.records.as_ref().and_then(|r| r.first()) {
+                        Some(Record::Data(_)) => self
+                            .templates
+                            .get_data_template(exporter, source_id, id)
+                            .map(|t| SetTemplate::Data(ipfix_template(t))),
+                        Some(Record::OptionData(_)) => self
+                            .templates
+                            .get_option_template(exporter, source_id, id)
+                            .map(|t| SetTemplate::Option(ipfix_option_template(t))),
+                        None => None,
+                    };
+
+                    match (template, &dataflow.records) {
+                        (Some(template), Some(records)) => {
+                            let records = records
+                                .iter()
+                                .map(|record| {
+                                    ipfix_record(record, netflow.timestamp, netflow.sys_uptime)
+                                })
+                                .collect();
+                            sets.push(PendingSet::Data(template, records));
+                        }
+                        _ => self.stats.missing_templates += 1,
+                    }
+                }
+            }
+        }
+
+        sets
+    }
+
+    /// Convert a packet, raw or decoded, to one or more IPFIX messages.
+    pub fn convert(&mut self, exporter: SocketAddr, netflow: &NetFlow9) -> Vec<IpfixMessage> {
+        let mut netflow = netflow.clone();
+        self.templates.learn(exporter, &netflow);
+        self.templates.apply(exporter, &mut netflow);
+        self.stats.packets += 1;
+
+        let domain = netflow.source_id;
+        let mut packer = Packer::new(self.max_length);
+        for set in self.sets(exporter, &netflow) {
+            match set {
+                PendingSet::Set(set) => packer.push(set, 0),
+                PendingSet::Data(template, mut records) => {
+                    let record_len = records[0].byte_length().max(1);
+                    while !records.is_empty() {
+                        let mut fit = packer.room().saturating_sub(SET_HEADER_LEN) / record_len;
+                        if fit == 0 && !packer.is_empty() {
+                            packer.finish_message();
+                            fit = packer.room().saturating_sub(SET_HEADER_LEN) / record_len;
+                        }
+                        // a record longer than a message still goes out alone
+                        let rest = records.split_off(fit.max(1).min(records.len()));
+                        let count = records.len();
+                        packer.push(IpfixSet::Data(template.data_set(records)), count);
+                        records = rest;
+                    }
+                }
+            }
+        }
+        let messages = packer.into_messages();
+
+        let sequence = self.sequences.entry((exporter, domain)).or_insert(0);
+        let mut converted = Vec::with_capacity(messages.len());
+        for (sets, records) in messages {
+            converted.push(IpfixMessage::new(
+                netflow.timestamp,
+                *sequence,
+                domain,
+                sets,
+            ));
+            *sequence = sequence.wrapping_add(records as u32);
+            self.stats.messages += 1;
+            self.stats.records += records as u64;
+        }
+
+        converted
+    }
+}
+
+#[cfg(test)]
+mod test_convert {
+    use super::*;
+    use crate::ipfix::IpfixTemplates;
+    use crate::netflow::test_v5;
+
+    fn exporter() -> SocketAddr {
+        "192.0.2.1:2055".parse().unwrap()
+    }
+
+    #[test]
+    fn test_v5_to_v9() {
+        let v5 = test_v5::packet(vec![test_v5::record([10, 0, 0, 1], 90_000, 95_000); 3]);
+        let mut converter = V5Converter::new();
+        converter.set_template_packets(2);
+
+        let mut cache = TemplateCache::new();
+        let packets: Vec<NetFlow9> = (0..3)
+            .map(|_| {
+                let bytes = converter.convert(exporter(), &v5).unwrap().to_bytes();
+                cache.decode(exporter(), &bytes).unwrap()
+            })
+            .collect();
+
+        let first = &packets[0];
+        assert_eq!(first.source_id, 0x0102);
+        assert_eq!(first.timestamp, 1_600_000_000);
+        assert_eq!(first.sys_uptime, 100_000);
+        assert_eq!(first.count, 6);
+        assert_eq!(first.flow_sets.len(), 4);
+        let sequences: Vec<u32> = packets.iter().map(|p| p.flow_sequence).collect();
+        assert_eq!(sequences, vec![0, 1, 2]);
+        // templates again on the third packet
+        assert_eq!(packets[1].flow_sets.len(), 1);
+        assert_eq!(packets[2].flow_sets.len(), 4);
+
+        let sampling = match &first.flow_sets[2] {
+            FlowSet::DataFlow(dataflow) => &dataflow.records.as_ref().unwrap()[0],
+            flowset => panic!("unexpected flowset: {:?}", flowset),
+        };
+        assert_eq!(
+            sampling.get(SAMPLING_INTERVAL).unwrap().value().to_u64(),
+            Some(100)
+        );
+        assert_eq!(
+            sampling.get(SAMPLING_ALGORITHM).unwrap().value().to_u64(),
+            Some(1)
+        );
+
+        let record = match &first.flow_sets[3] {
+            FlowSet::DataFlow(dataflow) => &dataflow.records.as_ref().unwrap()[2],
+            flowset => panic!("unexpected flowset: {:?}", flowset),
+        };
+        let value = |id| record.get(id).unwrap().value().to_u64().unwrap();
+        assert_eq!(value(IN_BYTES), 1500);
+        assert_eq!(value(FIRST_SWITCHED), 90_000);
+        assert_eq!(value(SRC_AS), 64500);
+        assert_eq!(value(TCP_FLAGS), 0x1b);
+    }
+
+    #[test]
+    fn test_v9_to_ipfix() {
+        let v5 = test_v5::packet(vec![test_v5::record([10, 0, 0, 1], 90_000, 95_000); 30]);
+        let v9 = V5Converter::new().convert(exporter(), &v5).unwrap();
+        let raw = NetFlow9::from_bytes(&v9.to_bytes()).unwrap();
+
+        let mut converter = IpfixConverter::new();
+        converter.set_max_length(1000);
+        let messages = converter.convert(exporter(), &raw);
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|m| m.to_bytes().len() <= 1000));
+        assert_eq!(messages[0].sequence_number, 0);
+        assert_eq!(converter.stats().records, 31);
+
+        let mut templates = IpfixTemplates::new();
+        let decoded: Vec<IpfixMessage> = messages
+            .iter()
+            .map(|message| templates.decode(&message.to_bytes()).unwrap())
+            .collect();
+        assert_eq!(decoded[0].observation_domain_id, 0x0102);
+        assert_eq!(decoded[0].export_time, 1_600_000_000);
+
+        let records: Vec<&Record> = decoded
+            .iter()
+            .flat_map(|message| message.sets.iter())
+            .filter_map(|set| match set {
+                IpfixSet::Data(data) if data.set_id == V5_TEMPLATE_ID => data.records.as_ref(),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(records.len(), 30);
+        assert_eq!(decoded[1].sequence_number, records_in(&decoded[0]) as u32);
+
+        // booted at 1_600_000_000 s - 100 s
+        let start = records[0].get(FLOW_START_MILLISECONDS).unwrap();
+        assert_eq!(start.length(), 8);
+        assert_eq!(start.value().to_u64(), Some(1_599_999_990_000));
+        assert_eq!(
+            records[0]
+                .get(FLOW_END_MILLISECONDS)
+                .unwrap()
+                .value()
+                .to_u64(),
+            Some(1_599_999_995_000)
+        );
+        assert!(records[0].get(FIRST_SWITCHED).is_none());
+
+        // the option scope became exportingProcessId
+        let scope = decoded[0]
+            .sets
+            .iter()
+            .find_map(|set| match set {
+                IpfixSet::OptionTemplate(items) => Some(items[0].scopes[0]),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(scope.type_id, EXPORTING_PROCESS_ID);
+
+        // data without a template is dropped
+        let mut converter = IpfixConverter::new();
+        let mut data_only = raw.clone();
+        data_only.flow_sets.retain(|set| match set {
+            FlowSet::DataFlow(dataflow) => dataflow.flowset_id == V5_TEMPLATE_ID,
+            _ => false,
+        });
+        assert!(converter.convert(exporter(), &data_only).is_empty());
+        assert_eq!(converter.stats().missing_templates, 1);
+    }
+
+    #[test]
+    fn test_uptime_wrap() {
+        // the flow started 10 s before the export, 5 s before the uptime wrapped
+        let mut v5 = test_v5::packet(vec![test_v5::record(
+            [10, 0, 0, 1],
+            u32::MAX - 4_999,
+            2_000,
+        )]);
+        v5.sys_uptime = 5_000;
+        let raw = V5Converter::new().convert(exporter(), &v5).unwrap();
+        let netflow = TemplateCache::new()
+            .decode(exporter(), &raw.to_bytes())
+            .unwrap();
+
+        let messages = IpfixConverter::new().convert(exporter(), &netflow);
+        let mut templates = IpfixTemplates::new();
+        let decoded = templates.decode(&messages[0].to_bytes()).unwrap();
+        let record = decoded
+            .sets
+            .iter()
+            .find_map(|set| match set {
+                IpfixSet::Data(data) if data.set_id == V5_TEMPLATE_ID => data.records.as_ref(),
+                _ => None,
+            })
+            .map(|records| &records[0])
+            .unwrap();
+
+        let value = |type_id| record.get(type_id).unwrap().value().to_u64();
+        assert_eq!(value(FLOW_START_MILLISECONDS), Some(1_599_999_990_000));
+        assert_eq!(value(FLOW_END_MILLISECONDS), Some(1_599_999_997_000));
+    }
+
+    fn records_in(message: &IpfixMessage) -> usize {
+        message
+            .sets
+            .iter()
+            .filter_map(|set| match set {
+                IpfixSet::Data(data) => data.records.as_ref().map(Vec::len),
+                _ => None,
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,370 @@
+use crate::field::{element_by_name, element_name, FlowField};
+use crate::flowset::{DataTemplateItem, FlowSet, Record};
+use crate::ipfix::{IpfixMessage, IpfixSet};
+use crate::json::{field_to_json, RecordContext};
+use crate::netflow::NetFlow9;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io;
+use std::io::Write;
+use std::net::SocketAddr;
+
+/// Separator and escaping of the output.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Delimited {
+    /// comma separated, values are quoted when needed (RFC 4180)
+    Csv,
+    /// tab separated, tabs, newlines and backslashes are escaped like ClickHouse TabSeparated
+    Tsv,
+}
+
+/// A column of the output, packet metadata or a field type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Column {
+    Exporter,
+    SourceId,
+    Sequence,
+    ExportTime,
+    TemplateId,
+    Field(u16),
+}
+
+impl Column {
+    /// Metadata columns written before the fields when columns come from templates.
+    pub const METADATA: [Column; 5] = [
+        Column::Exporter,
+        Column::SourceId,
+        Column::Sequence,
+        Column::ExportTime,
+        Column::TemplateId,
+    ];
+
+    /// Parse a metadata name, an element name or a field type id.
+    pub fn from_name(name: &str) -> Option<Column> {
+        match name {
+            "exporter" => Some(Column::Exporter),
+            "source_id" => Some(Column::SourceId),
+            "sequence" => Some(Column::Sequence),
+            "export_time" => Some(Column::ExportTime),
+            "template_id" => Some(Column::TemplateId),
+            _ => match name.parse() {
+                Ok(id) => Some(Column::Field(id)),
+                Err(_) => element_by_name(name).map(|element| Column::Field(element.id)),
+            },
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Column::Exporter => "exporter".to_string(),
+            Column::SourceId => "source_id".to_string(),
+            Column::Sequence => "sequence".to_string(),
+            Column::ExportTime => "export_time".to_string(),
+            Column::TemplateId => "template_id".to_string(),
+            Column::Field(id) => element_name(*id),
+        }
+    }
+}
+
+fn field_text(field: &FlowField) -> String {
+    match field_to_json(field) {
+        Value::String(s) => s,
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// Writes data records as CSV or TSV rows, one row per record.
+/// Columns are fixed by the user or taken from the data templates seen before the first row,
+/// fields of templates learned after the header is written are dropped.
+/// Option records are skipped, their fields rarely match the data columns.
+pub struct CsvWriter<W: Write> {
+    writer: W,
+    format: Delimited,
+    columns: Vec<Column>,
+    known: HashSet<Column>,
+    fixed: bool,
+    header_written: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Columns are the metadata followed by the union of template fields.
+    pub fn new(writer: W, format: Delimited) -> CsvWriter<W> {
+        CsvWriter {
+            writer,
+            format,
+            columns: Column::METADATA.to_vec(),
+            known: Column::METADATA.iter().cloned().collect(),
+            fixed: false,
+            header_written: false,
+        }
+    }
+
+    pub fn with_columns(writer: W, format: Delimited, columns: Vec<Column>) -> CsvWriter<W> {
+        CsvWriter {
+            writer,
+            format,
+            known: columns.iter().cloned().collect(),
+            columns,
+            fixed: true,
+            header_written: false,
+        }
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Add the fields of the template as columns, unless the header is already written.
+    /// Fields of a template added after the first row have no column, their values are
+    /// dropped with a warning logged once per field.
+    pub fn add_template(&mut self, template: &DataTemplateItem) {
+        if self.fixed {
+            return;
+        }
+
+        // columns are IANA elements, enterprise fields get none
+        for field in template
+            .fields
+            .iter()
+            .filter(|field| field.enterprise.is_none())
+        {
+            let column = Column::Field(field.type_id);
+            if self.known.contains(&column) {
+                continue;
+            }
+
+            if self.header_written {
+                warn!(
+                    "column {} is not in the header, it is dropped",
+                    column.name()
+                );
+            } else {
+                self.columns.push(column);
+            }
+            self.known.insert(column);
+        }
+    }
+
+    fn write_value(&mut self, value: &str) -> io::Result<()> {
+        match self.format {
+            Delimited::Csv => {
+                if value.contains(&[',', '"', '\r', '\n'][..]) {
+                    write!(self.writer, "\"{}\"", value.replace('"', "\"\""))
+                } else {
+                    self.writer.write_all(value.as_bytes())
+                }
+            }
+            Delimited::Tsv => {
+                for c in value.chars() {
+                    match c {
+                        '\\' => self.writer.write_all(b"\\\\")?,
+                        '\t' => self.writer.write_all(b"\\t")?,
+                        '\n' => self.writer.write_all(b"\\n")?,
+                        '\r' => self.writer.write_all(b"\\r")?,
+                        _ => write!(self.writer, "{}", c)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn write_row<I: Iterator<Item = String>>(&mut self, values: I) -> io::Result<()> {
+        let separator: &[u8] = match self.format {
+            Delimited::Csv => b",",
+            Delimited::Tsv => b"\t",
+        };
+
+        for (i, value) in values.enumerate() {
+            if i > 0 {
+                self.writer.write_all(separator)?;
+            }
+            self.write_value(&value)?;
+        }
+
+        self.writer.write_all(b"\n")
+    }
+
+    /// Write the header now, the columns can not change afterwards.
+    pub fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            let names: Vec<String> = self.columns.iter().map(Column::name).collect();
+            self.write_row(names.into_iter())?;
+            self.header_written = true;
+        }
+
+        Ok(())
+    }
+
+    /// Write a data record as a row, missing fields are left empty.
+    pub fn write_record(
+        &mut self,
+        context: &RecordContext,
+        template_id: u16,
+        record: &Record,
+    ) -> io::Result<()> {
+        if let Record::OptionData(_) = record {
+            return Ok(());
+        }
+        self.write_header()?;
+
+        let values: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| match column {
+                Column::Exporter => context.exporter.to_string(),
+                Column::SourceId => context.source_id.to_string(),
+                Column::Sequence => context.sequence.to_string(),
+                Column::ExportTime => context.export_time.to_string(),
+                Column::TemplateId => template_id.to_string(),
+                Column::Field(id) => record.get(*id).map(field_text).unwrap_or_default(),
+            })
+            .collect();
+
+        self.write_row(values.into_iter())
+    }
+
+    /// Learn the templates of the packet and write its data records, return the number of rows.
+    pub fn write_netflow9(
+        &mut self,
+        exporter: SocketAddr,
+        netflow: &NetFlow9,
+    ) -> io::Result<usize> {
+        for flowset in &netflow.flow_sets {
+            if let FlowSet::DataTemplate(template) = flowset {
+                for item in &template.templates {
+                    self.add_template(item);
+                }
+            }
+        }
+
+        let context = RecordContext::from_netflow9(exporter, netflow);
+        let mut rows = 0;
+        for flowset in &netflow.flow_sets {
+            if let FlowSet::DataFlow(flow) = flowset {
+                for record in flow.records.iter().flatten() {
+                    if let Record::Data(_) = record {
+                        self.write_record(&context, flow.flowset_id, record)?;
+                        rows += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(rows)
+    }
+
+    /// Learn the templates of the message and write its data records, return the number of rows.
+    pub fn write_ipfix(
+        &mut self,
+        exporter: SocketAddr,
+        message: &IpfixMessage,
+    ) -> io::Result<usize> {
+        for set in &message.sets {
+            if let IpfixSet::Template(templates) = set {
+                for template in templates {
+                    self.add_template(template);
+                }
+            }
+        }
+
+        let context = RecordContext::from_ipfix(exporter, message);
+        let mut rows = 0;
+        for set in &message.sets {
+            if let IpfixSet::Data(data) = set {
+                for record in data.records.iter().flatten() {
+                    if let Record::Data(_) = record {
+                        self.write_record(&context, data.set_id, record)?;
+                        rows += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(rows)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod test_csv {
+    use super::*;
+    use crate::field::FieldTypes::*;
+    use crate::ipfix::test_data;
+
+    fn exporter() -> SocketAddr {
+        "192.0.2.1:4739".parse().unwrap()
+    }
+
+    fn output(writer: CsvWriter<Vec<u8>>) -> Vec<String> {
+        String::from_utf8(writer.into_inner())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_template_columns() {
+        let mut writer = CsvWriter::new(Vec::new(), Delimited::Csv);
+        let rows = writer
+            .write_ipfix(exporter(), &test_data::message())
+            .unwrap();
+        assert_eq!(rows, 2);
+
+        let lines = output(writer);
+        assert_eq!(
+            lines[0],
+            "exporter,source_id,sequence,export_time,template_id,\
+             sourceIPv4Address,packetDeltaCount,interfaceName"
+        );
+        assert!(lines[1].starts_with("192.0.2.1:4739,1,0,"));
+        assert!(lines[2].ends_with(",a long interface name"));
+    }
+
+    #[test]
+    fn test_user_columns() {
+        let columns = ["exporter", "interfaceName", "destinationIPv4Address", "8"]
+            .iter()
+            .map(|name| Column::from_name(name).unwrap())
+            .collect();
+        let mut writer = CsvWriter::with_columns(Vec::new(), Delimited::Tsv, columns);
+        writer
+            .write_ipfix(exporter(), &test_data::message())
+            .unwrap();
+
+        let lines = output(writer);
+        assert_eq!(
+            lines[0],
+            "exporter\tinterfaceName\tdestinationIPv4Address\tsourceIPv4Address"
+        );
+        // destination address is not in the template
+        let values: Vec<&str> = lines[1].split('\t').collect();
+        assert_eq!(values[1], "eth0");
+        assert_eq!(values[2], "");
+        assert_eq!(values.len(), 4);
+        assert!(Column::from_name("noSuchElement").is_none());
+        assert_eq!(Column::from_name("11"), Some(Column::Field(L4_DST_PORT)));
+    }
+
+    #[test]
+    fn test_escape() {
+        let mut csv = CsvWriter::with_columns(Vec::new(), Delimited::Csv, vec![]);
+        csv.write_row(["a,b", "say \"hi\"", "plain"].iter().map(|s| s.to_string()))
+            .unwrap();
+        assert_eq!(output(csv), vec!["\"a,b\",\"say \"\"hi\"\"\",plain"]);
+
+        let mut tsv = CsvWriter::with_columns(Vec::new(), Delimited::Tsv, vec![]);
+        tsv.write_row(["a\tb", "c\\d"].iter().map(|s| s.to_string()))
+            .unwrap();
+        assert_eq!(output(tsv), vec!["a\\tb\tc\\\\d"]);
+    }
+}
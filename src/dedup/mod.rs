@@ -0,0 +1,452 @@
+use crate::aggregate::{flow_time, FlowTime};
+use crate::field::FieldTypes::*;
+use crate::field::FieldValue;
+use crate::flowset::{FlowSet, Record};
+use crate::ipfix::{IpfixMessage, IpfixSet};
+use crate::netflow::NetFlow9;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Direction of the flow at the observation point, from flowDirection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Direction {
+    Ingress,
+    Egress,
+}
+
+impl Direction {
+    fn of(record: &Record) -> Option<Direction> {
+        match record.get(DIRECTION)?.value().to_u64()? {
+            0 => Some(Direction::Ingress),
+            1 => Some(Direction::Egress),
+            _ => None,
+        }
+    }
+}
+
+/// Where a record was metered: the exporter, its observation domain (the v9 source id) and
+/// the direction of the flow there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct ObservationPoint {
+    pub exporter: IpAddr,
+    pub domain: u32,
+    pub direction: Option<Direction>,
+}
+
+/// Which copy of a flow seen at several observation points to keep.
+///
+/// Records of exporters earlier in `exporters` win over later ones and over exporters not
+/// listed, then records of `direction` win. The first record seen wins a tie.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preference {
+    /// e.g. the edge routers
+    pub exporters: Vec<IpAddr>,
+    pub direction: Option<Direction>,
+}
+
+impl Preference {
+    /// Lower is better.
+    fn rank(&self, point: &ObservationPoint) -> (usize, bool) {
+        let exporter = self
+            .exporters
+            .iter()
+            .position(|exporter| *exporter == point.exporter)
+            .unwrap_or(self.exporters.len());
+        let direction = self.direction.is_some() && point.direction != self.direction;
+        (exporter, direction)
+    }
+}
+
+/// The 5-tuple of a flow, with its IPv4 identification when matched on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct DedupKey {
+    source: IpAddr,
+    destination: IpAddr,
+    source_port: u16,
+    destination_port: u16,
+    protocol: u8,
+    ident: Option<u16>,
+}
+
+impl DedupKey {
+    fn of(record: &Record, match_ident: bool) -> Option<DedupKey> {
+        let address = |ids: &[u16]| {
+            ids.iter()
+                .filter_map(|id| record.get(*id))
+                .find_map(|field| match field.value() {
+                    FieldValue::Ipv4Addr(ip) => Some(IpAddr::V4(*ip)),
+                    FieldValue::Ipv6Addr(ip) => Some(IpAddr::V6(*ip)),
+                    _ => None,
+                })
+        };
+        let number = |id| record.get(id).and_then(|field| field.value().to_u64());
+
+        Some(DedupKey {
+            source: address(&[IPV4_SRC_ADDR, IPV6_SRC_ADDR])?,
+            destination: address(&[IPV4_DST_ADDR, IPV6_DST_ADDR])?,
+            source_port: number(L4_SRC_PORT).unwrap_or(0) as u16,
+            destination_port: number(L4_DST_PORT).unwrap_or(0) as u16,
+            protocol: number(PROTOCOL).unwrap_or(0) as u8,
+            ident: if match_ident {
+                number(IPV4_IDENT).map(|ident| ident as u16)
+            } else {
+                None
+            },
+        })
+    }
+}
+
+/// The record kept for a flow.
+#[derive(Debug, Clone)]
+pub struct DedupFlow {
+    pub point: ObservationPoint,
+    pub record: Record,
+    /// flow times of the kept record in milliseconds since the epoch
+    pub start: u64,
+    pub end: u64,
+    /// copies of the flow dropped from other observation points
+    pub duplicates: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DedupStats {
+    pub records: u64,
+    /// records passed on, one per flow
+    pub flows: u64,
+    /// records removed as copies of a flow from another observation point
+    pub duplicates: u64,
+    /// records without addresses, passed on right away
+    pub unkeyed: u64,
+}
+
+#[derive(Debug)]
+struct Group {
+    kept: DedupFlow,
+    rank: (usize, bool),
+    points: Vec<ObservationPoint>,
+    /// latest end of the copies in milliseconds, the group is held until it is over
+    end: u64,
+}
+
+/// Removes the copies of a flow reported by several exporters along its path, which would
+/// otherwise be counted once per exporter.
+///
+/// Records are copies when they have the same 5-tuple, and the same IPv4 identification if
+/// `set_match_ident` is on, come from different observation points, and their flow times
+/// overlap those of the kept copy give or take the window. The copy of the preferred
+/// observation point is kept.
+/// Flows are held until `expire` passes their end by the window, which should cover the
+/// export delay of the exporters.
+#[derive(Debug)]
+pub struct Deduplicator {
+    /// in milliseconds
+    window: u64,
+    match_ident: bool,
+    preference: Preference,
+    groups: HashMap<DedupKey, Vec<Group>>,
+    stats: DedupStats,
+}
+
+impl Deduplicator {
+    pub fn new(window: Duration) -> Deduplicator {
+        Deduplicator {
+            window: window.as_millis() as u64,
+            match_ident: false,
+            preference: Preference::default(),
+            groups: HashMap::new(),
+            stats: DedupStats::default(),
+        }
+    }
+
+    /// Also require the same IPV4_IDENT, which tells apart flows that reuse a 5-tuple.
+    pub fn set_match_ident(&mut self, match_ident: bool) {
+        self.match_ident = match_ident;
+    }
+
+    pub fn set_preference(&mut self, preference: Preference) {
+        self.preference = preference;
+    }
+
+    pub fn stats(&self) -> &DedupStats {
+        &self.stats
+    }
+
+    /// Number of flows held.
+    pub fn len(&self) -> usize {
+        self.groups.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Add a data record metered at `point` with its flow times in milliseconds since the
+    /// epoch. Records without addresses are passed on right away.
+    pub fn add_record(
+        &mut self,
+        point: ObservationPoint,
+        record: Record,
+        start: u64,
+        end: u64,
+    ) -> Option<DedupFlow> {
+        if let Record::OptionData(_) = record {
+            return None;
+        }
+        self.stats.records += 1;
+
+        let flow = DedupFlow {
+            point,
+            record,
+            start,
+            end,
+            duplicates: 0,
+        };
+        let key = match DedupKey::of(&flow.record, self.match_ident) {
+            Some(key) => key,
+            None => {
+                self.stats.unkeyed += 1;
+                self.stats.flows += 1;
+                return Some(flow);
+            }
+        };
+
+        let window = self.window;
+        let rank = self.preference.rank(&point);
+        let groups = self.groups.entry(key).or_default();
+        let group = groups.iter_mut().find(|group| {
+            !group.points.contains(&point)
+                && start <= group.kept.end + window
+                && group.kept.start <= end + window
+        });
+
+        match group {
+            Some(group) => {
+                self.stats.duplicates += 1;
+                group.points.push(point);
+                group.end = group.end.max(end);
+                let duplicates = group.kept.duplicates + 1;
+                if rank < group.rank {
+                    group.kept = flow;
+                    group.rank = rank;
+                }
+                group.kept.duplicates = duplicates;
+            }
+            None => groups.push(Group {
+                kept: flow,
+                rank,
+                points: vec![point],
+                end,
+            }),
+        }
+        None
+    }
+
+    /// Add the decoded data records of a v9 packet from `exporter`.
+    /// Returns the records passed on right away.
+    pub fn add_netflow9(&mut self, exporter: IpAddr, netflow: &NetFlow9) -> Vec<DedupFlow> {
+        let mut flows = Vec::new();
+
+        for flowset in &netflow.flow_sets {
+            if let FlowSet::DataFlow(dataflow) = flowset {
+                for record in dataflow.records.iter().flatten() {
+                    let time = |which| {
+                        flow_time(record, which, netflow.timestamp, Some(netflow.sys_uptime))
+                    };
+                    let point = ObservationPoint {
+                        exporter,
+                        domain: netflow.source_id,
+                        direction: Direction::of(record),
+                    };
+                    let (start, end) = (time(FlowTime::Start), time(FlowTime::End));
+                    flows.extend(self.add_record(point, record.clone(), start, end));
+                }
+            }
+        }
+
+        flows
+    }
+
+    /// Add the decoded data records of an IPFIX message from `exporter`.
+    /// Returns the records passed on right away.
+    pub fn add_ipfix(&mut self, exporter: IpAddr, message: &IpfixMessage) -> Vec<DedupFlow> {
+        let mut flows = Vec::new();
+
+        for set in &message.sets {
+            if let IpfixSet::Data(data) = set {
+                for record in data.records.iter().flatten() {
+                    let time = |which| flow_time(record, which, message.export_time, None);
+                    let point = ObservationPoint {
+                        exporter,
+                        domain: message.observation_domain_id,
+                        direction: Direction::of(record),
+                    };
+                    let (start, end) = (time(FlowTime::Start), time(FlowTime::End));
+                    flows.extend(self.add_record(point, record.clone(), start, end));
+                }
+            }
+        }
+
+        flows
+    }
+
+    /// Return the flows that ended more than the window before `now`, the time since the
+    /// epoch, in the order they started.
+    pub fn expire(&mut self, now: Duration) -> Vec<DedupFlow> {
+        let now = now.as_millis() as u64;
+        let window = self.window;
+        let mut expired = Vec::new();
+
+        for groups in self.groups.values_mut() {
+            let (done, held): (Vec<Group>, Vec<Group>) =
+                groups.drain(..).partition(|group| group.end + window < now);
+            *groups = held;
+            expired.extend(done);
+        }
+        self.groups.retain(|_, groups| !groups.is_empty());
+
+        self.flows(expired)
+    }
+
+    /// Return every flow held, in the order they started.
+    pub fn flush(&mut self) -> Vec<DedupFlow> {
+        let groups: Vec<Group> = self.groups.drain().flat_map(|(_, groups)| groups).collect();
+        self.flows(groups)
+    }
+
+    fn flows(&mut self, mut groups: Vec<Group>) -> Vec<DedupFlow> {
+        groups.sort_by_key(|group| group.kept.start);
+        self.stats.flows += groups.len() as u64;
+        groups.into_iter().map(|group| group.kept).collect()
+    }
+}
+
+#[cfg(test)]
+mod test_dedup {
+    use super::*;
+    use crate::flowset::DataTemplateItem;
+    use std::net::Ipv4Addr;
+
+    fn record(src_port: u16, ident: u16, direction: u8, bytes: u32) -> Record {
+        DataTemplateItem::builder(256)
+            .field(IPV4_SRC_ADDR)
+            .field(IPV4_DST_ADDR)
+            .field(L4_SRC_PORT)
+            .field(L4_DST_PORT)
+            .field(PROTOCOL)
+            .field(IPV4_IDENT)
+            .field(DIRECTION)
+            .field_len(IN_BYTES, 4)
+            .build()
+            .unwrap()
+            .record_builder()
+            .set(IPV4_SRC_ADDR, Ipv4Addr::new(10, 0, 0, 1))
+            .set(IPV4_DST_ADDR, Ipv4Addr::new(192, 0, 2, 1))
+            .set(L4_SRC_PORT, src_port)
+            .set(L4_DST_PORT, 443u16)
+            .set(PROTOCOL, 6u8)
+            .set(IPV4_IDENT, ident)
+            .set(DIRECTION, direction)
+            .set(IN_BYTES, bytes)
+            .build()
+            .unwrap()
+    }
+
+    fn point(exporter: [u8; 4], direction: Direction) -> ObservationPoint {
+        ObservationPoint {
+            exporter: IpAddr::V4(Ipv4Addr::from(exporter)),
+            domain: 0,
+            direction: Some(direction),
+        }
+    }
+
+    const EDGE: [u8; 4] = [198, 51, 100, 1];
+    const CORE: [u8; 4] = [198, 51, 100, 2];
+
+    #[test]
+    fn test_dedup() {
+        let mut dedup = Deduplicator::new(Duration::from_secs(1));
+        dedup.set_preference(Preference {
+            exporters: vec![IpAddr::V4(Ipv4Addr::from(EDGE))],
+            direction: Some(Direction::Ingress),
+        });
+
+        // the core router reports first, then both directions at the edge
+        let add = |dedup: &mut Deduplicator, at, direction, bytes, start| {
+            let direction_value = (direction == Direction::Egress) as u8;
+            dedup.add_record(
+                point(at, direction),
+                record(40000, 7, direction_value, bytes),
+                start,
+                start + 10_000,
+            )
+        };
+        assert!(add(&mut dedup, CORE, Direction::Ingress, 1, 1_000).is_none());
+        assert!(add(&mut dedup, EDGE, Direction::Egress, 2, 1_200).is_none());
+        assert!(add(&mut dedup, EDGE, Direction::Ingress, 3, 1_100).is_none());
+        // the same point again is another flow, as is one starting after the window
+        assert!(add(&mut dedup, EDGE, Direction::Ingress, 4, 30_000).is_none());
+        assert!(add(&mut dedup, CORE, Direction::Ingress, 5, 12_500).is_none());
+        assert_eq!(dedup.len(), 3);
+
+        let flows = dedup.expire(Duration::from_millis(12_201));
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].point, point(EDGE, Direction::Ingress));
+        assert_eq!(
+            flows[0].record.get(IN_BYTES).unwrap().value().to_u64(),
+            Some(3)
+        );
+        assert_eq!(flows[0].duplicates, 2);
+
+        let flows = dedup.flush();
+        assert_eq!(flows.len(), 2);
+        assert_eq!(flows[0].duplicates, 0);
+        assert!(dedup.is_empty());
+
+        let stats = dedup.stats();
+        assert_eq!(stats.records, 5);
+        assert_eq!(stats.flows, 3);
+        assert_eq!(stats.duplicates, 2);
+    }
+
+    #[test]
+    fn test_no_chaining() {
+        let at = |last: u8| ObservationPoint {
+            exporter: IpAddr::V4(Ipv4Addr::new(198, 51, 100, last)),
+            domain: 0,
+            direction: None,
+        };
+        let mut dedup = Deduplicator::new(Duration::from_secs(1));
+        dedup.add_record(at(1), record(40000, 7, 0, 1), 0, 1_000);
+        dedup.add_record(at(2), record(40000, 7, 0, 1), 1_500, 2_500);
+        // overlaps the second copy but not the kept record, a later flow of the 5-tuple
+        dedup.add_record(at(3), record(40000, 7, 0, 1), 3_000, 4_000);
+
+        let flows = dedup.flush();
+        assert_eq!(flows.len(), 2);
+        assert_eq!(flows[0].duplicates, 1);
+        assert_eq!(flows[1].start, 3_000);
+        assert_eq!(dedup.stats().duplicates, 1);
+    }
+
+    #[test]
+    fn test_match_ident() {
+        let at = |exporter| ObservationPoint {
+            exporter: IpAddr::V4(Ipv4Addr::from(exporter)),
+            domain: 0,
+            direction: None,
+        };
+        for (match_ident, flows) in [(false, 1), (true, 2)] {
+            let mut dedup = Deduplicator::new(Duration::from_secs(1));
+            dedup.set_match_ident(match_ident);
+            dedup.add_record(at(EDGE), record(40000, 7, 0, 1), 0, 100);
+            dedup.add_record(at(CORE), record(40000, 8, 0, 1), 0, 100);
+            assert_eq!(dedup.flush().len(), flows);
+        }
+
+        let mut dedup = Deduplicator::new(Duration::from_secs(1));
+        let unkeyed = dedup.add_record(at(EDGE), Record::make_data(vec![]), 0, 0);
+        assert!(unkeyed.is_some());
+        assert_eq!(dedup.stats().unkeyed, 1);
+    }
+}
@@ -1,5 +1,6 @@
 use nom::{Err, IResult};
 use std::error::Error;
+use std::io;
 
 #[derive(Debug, Fail)]
 pub enum NetFlowError {
@@ -14,6 +15,50 @@ pub enum NetFlowError {
     TemplateNotFound,
     #[fail(display = "Invalid netflow packet")]
     UnexpectedIncomplete,
+    #[fail(display = "{}", _0)]
+    Io(#[cause] io::Error),
+    #[fail(display = "Invalid capture file: {}", desc)]
+    InvalidCapture { desc: String },
+    #[fail(display = "Export failed: {}", desc)]
+    Export { desc: String },
+    #[fail(display = "Header count {} does not match {} records", header, records)]
+    CountMismatch { header: u16, records: u16 },
+    #[fail(display = "Invalid record: {}", desc)]
+    InvalidRecord { desc: String },
+    #[fail(display = "Invalid template: {}", desc)]
+    InvalidTemplate { desc: String },
+    #[fail(display = "Invalid configuration: {}", desc)]
+    InvalidConfig { desc: String },
+    #[fail(display = "Invalid filter: {}", desc)]
+    InvalidFilter { desc: String },
+}
+
+impl NetFlowError {
+    pub fn invalid_capture(desc: impl Into<String>) -> NetFlowError {
+        NetFlowError::InvalidCapture { desc: desc.into() }
+    }
+
+    pub fn invalid_record(desc: impl Into<String>) -> NetFlowError {
+        NetFlowError::InvalidRecord { desc: desc.into() }
+    }
+
+    pub fn invalid_template(desc: impl Into<String>) -> NetFlowError {
+        NetFlowError::InvalidTemplate { desc: desc.into() }
+    }
+
+    pub fn invalid_config(desc: impl Into<String>) -> NetFlowError {
+        NetFlowError::InvalidConfig { desc: desc.into() }
+    }
+
+    pub fn invalid_filter(desc: impl Into<String>) -> NetFlowError {
+        NetFlowError::InvalidFilter { desc: desc.into() }
+    }
+}
+
+impl From<io::Error> for NetFlowError {
+    fn from(e: io::Error) -> Self {
+        NetFlowError::Io(e)
+    }
 }
 
 pub type ParseResult<'a, T> = Result<(&'a [u8], T), NetFlowError>;
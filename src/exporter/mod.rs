@@ -0,0 +1,508 @@
+use crate::error::NetFlowError;
+use crate::flowset::{
+    DataFlow, DataTemplate, DataTemplateItem, FlowSet, OptionTemplate, OptionTemplateItem, Record,
+};
+use crate::netflow::NetFlow9;
+use crate::sink::OutputSink;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const HEADER_LEN: usize = 20;
+const FLOWSET_HEADER_LEN: usize = 4;
+
+/// Settings of an export stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExporterConfig {
+    pub source_id: u32,
+    /// largest packet sent, without IP and UDP headers
+    pub mtu: usize,
+    /// templates are sent again after this many packets
+    pub template_packets: u32,
+    /// templates are sent again after this time
+    pub template_interval: Duration,
+}
+
+impl Default for ExporterConfig {
+    fn default() -> Self {
+        ExporterConfig {
+            source_id: 0,
+            // 1500 byte Ethernet MTU less IPv6 and UDP headers
+            mtu: 1452,
+            template_packets: 20,
+            template_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Counters of an exporter.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExporterStats {
+    pub packets: u64,
+    pub bytes: u64,
+    pub records: u64,
+    pub template_refreshes: u64,
+}
+
+#[derive(Debug, Clone)]
+enum Template {
+    Data(DataTemplateItem),
+    Option(OptionTemplateItem),
+}
+
+impl Template {
+    fn id(&self) -> u16 {
+        match self {
+            Template::Data(item) => item.template_id,
+            Template::Option(item) => item.template_id,
+        }
+    }
+
+    fn record_len(&self) -> usize {
+        match self {
+            Template::Data(item) => item.fields.iter().map(|f| f.length as usize).sum(),
+            Template::Option(item) => item
+                .scopes
+                .iter()
+                .chain(item.options.iter())
+                .map(|f| f.length as usize)
+                .sum(),
+        }
+    }
+
+    fn to_flowset(&self) -> FlowSet {
+        match self {
+            Template::Data(item) => DataTemplate::new(vec![item.clone()]).into(),
+            Template::Option(item) => OptionTemplate::new(item.clone()).into(),
+        }
+    }
+}
+
+// padded to 4 bytes like DataFlow::to_bytes does
+fn dataflow_len(record_len: usize, records: usize) -> usize {
+    let len = record_len * records;
+    FLOWSET_HEADER_LEN + len + (4 - len % 4) % 4
+}
+
+/// Builds a Netflow v9 export stream (RFC 3954) from records and sends it to a sink,
+/// such as `sink::UdpSink` or `sink::WriteSink`.
+///
+/// Records are buffered per template and sent when a packet is full or on `flush`.
+/// Templates are sent before the first data, then again after `template_packets` packets
+/// or `template_interval`, whichever comes first.
+/// `flow_sequence` counts the packets, `sys_uptime` is the time since the boot time.
+pub struct Exporter<S: OutputSink> {
+    sink: S,
+    config: ExporterConfig,
+    templates: Vec<Template>,
+    pending: Vec<(u16, Vec<Record>)>,
+    pending_len: usize,
+    sequence: u32,
+    boot: SystemTime,
+    clock: Option<SystemTime>,
+    packets_since_templates: u32,
+    last_templates: Option<Instant>,
+    stats: ExporterStats,
+}
+
+impl<S: OutputSink> Exporter<S> {
+    pub fn new(sink: S, config: ExporterConfig) -> Exporter<S> {
+        Exporter {
+            sink,
+            config,
+            templates: Vec::new(),
+            pending: Vec::new(),
+            pending_len: 0,
+            sequence: 0,
+            boot: SystemTime::now(),
+            clock: None,
+            packets_since_templates: 0,
+            last_templates: None,
+            stats: ExporterStats::default(),
+        }
+    }
+
+    fn add(&mut self, template: Template) -> Result<(), NetFlowError> {
+        let record_len = template.record_len();
+        let flowset_len = template.to_flowset().byte_length();
+        if record_len == 0 || HEADER_LEN + flowset_len > self.config.mtu {
+            return Err(NetFlowError::InvalidLength);
+        }
+
+        // records of the old definition are sent first
+        if self.pending.iter().any(|(id, _)| *id == template.id()) {
+            self.flush()?;
+        }
+
+        self.templates.retain(|t| t.id() != template.id());
+        self.templates.push(template);
+        self.last_templates = None;
+
+        Ok(())
+    }
+
+    /// Add or replace a data template, it is sent with the next packet.
+    pub fn add_template(&mut self, template: DataTemplateItem) -> Result<(), NetFlowError> {
+        self.add(Template::Data(template))
+    }
+
+    /// Add or replace an option template, it is sent with the next packet.
+    pub fn add_option_template(
+        &mut self,
+        template: OptionTemplateItem,
+    ) -> Result<(), NetFlowError> {
+        self.add(Template::Option(template))
+    }
+
+    /// Whether a data or option template with the id was added.
+    pub fn has_template(&self, template_id: u16) -> bool {
+        self.template(template_id).is_some()
+    }
+
+    fn template(&self, template_id: u16) -> Option<&Template> {
+        self.templates.iter().find(|t| t.id() == template_id)
+    }
+
+    /// Queue a record of the template, full packets are sent right away.
+    /// The record must be as long as the template says.
+    pub fn push(&mut self, template_id: u16, record: Record) -> Result<(), NetFlowError> {
+        let record_len = self
+            .template(template_id)
+            .ok_or(NetFlowError::TemplateNotFound)?
+            .record_len();
+        if record.byte_length() != record_len
+            || HEADER_LEN + dataflow_len(record_len, 1) > self.config.mtu
+        {
+            return Err(NetFlowError::InvalidLength);
+        }
+
+        match self.pending.iter_mut().find(|(id, _)| *id == template_id) {
+            Some((_, records)) => records.push(record),
+            None => self.pending.push((template_id, vec![record])),
+        }
+        self.pending_len += record_len;
+
+        // the pending records no longer fit in a packet
+        while HEADER_LEN + self.pending_len + FLOWSET_HEADER_LEN * self.pending.len()
+            > self.config.mtu
+        {
+            self.send_packet(false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Send every queued record.
+    pub fn flush(&mut self) -> Result<(), NetFlowError> {
+        while !self.pending.is_empty() {
+            self.send_packet(false)?;
+        }
+        self.sink.flush()?;
+
+        Ok(())
+    }
+
+    /// Send the templates now, without waiting for the refresh.
+    pub fn send_templates(&mut self) -> Result<(), NetFlowError> {
+        self.send_packet(true)?;
+        self.sink.flush()?;
+
+        Ok(())
+    }
+
+    fn templates_due(&self) -> bool {
+        match self.last_templates {
+            Some(last) => {
+                self.packets_since_templates >= self.config.template_packets
+                    || last.elapsed() >= self.config.template_interval
+            }
+            None => true,
+        }
+    }
+
+    // Fill a packet with the templates when they are due, then with as many records as fit.
+    fn send_packet(&mut self, force_templates: bool) -> Result<(), NetFlowError> {
+        let mut flow_sets = Vec::new();
+        let mut len = HEADER_LEN;
+
+        if force_templates || self.templates_due() {
+            let mut sent = 0;
+            for template in &self.templates {
+                let flowset = template.to_flowset();
+                let flowset_len = flowset.byte_length();
+                if len + flowset_len > self.config.mtu {
+                    break;
+                }
+
+                len += flowset_len;
+                flow_sets.push(flowset);
+                sent += 1;
+            }
+
+            // the rest go in their own packets before any data
+            if sent < self.templates.len() {
+                let rest: Vec<FlowSet> = self.templates[sent..]
+                    .iter()
+                    .map(Template::to_flowset)
+                    .collect();
+                self.send(flow_sets)?;
+                return self.send_templates_only(rest);
+            }
+
+            self.mark_templates_sent();
+        }
+
+        while !self.pending.is_empty() {
+            let template_id = self.pending[0].0;
+            let record_len = self
+                .template(template_id)
+                .map(Template::record_len)
+                .ok_or(NetFlowError::TemplateNotFound)?;
+
+            let available = self.config.mtu.saturating_sub(len + FLOWSET_HEADER_LEN);
+            let mut fit = (available / record_len).min(self.pending[0].1.len());
+            while fit > 0 && len + dataflow_len(record_len, fit) > self.config.mtu {
+                fit -= 1;
+            }
+            if fit == 0 {
+                break;
+            }
+
+            let records: Vec<Record> = self.pending[0].1.drain(..fit).collect();
+            if self.pending[0].1.is_empty() {
+                self.pending.remove(0);
+            }
+            self.pending_len -= record_len * fit;
+            self.stats.records += fit as u64;
+
+            len += dataflow_len(record_len, fit);
+            flow_sets.push(DataFlow::new(template_id, records).into());
+        }
+
+        if flow_sets.is_empty() {
+            return Ok(());
+        }
+        self.send(flow_sets)
+    }
+
+    fn send_templates_only(&mut self, flowsets: Vec<FlowSet>) -> Result<(), NetFlowError> {
+        let mut packet = Vec::new();
+        let mut len = HEADER_LEN;
+
+        for flowset in flowsets {
+            let flowset_len = flowset.byte_length();
+            if len + flowset_len > self.config.mtu {
+                self.send(packet)?;
+                packet = Vec::new();
+                len = HEADER_LEN;
+            }
+
+            len += flowset_len;
+            packet.push(flowset);
+        }
+
+        self.send(packet)?;
+        self.mark_templates_sent();
+
+        Ok(())
+    }
+
+    fn mark_templates_sent(&mut self) {
+        self.packets_since_templates = 0;
+        self.last_templates = Some(Instant::now());
+        self.stats.template_refreshes += 1;
+    }
+
+    fn send(&mut self, flow_sets: Vec<FlowSet>) -> Result<(), NetFlowError> {
+        let now = self.clock.unwrap_or_else(SystemTime::now);
+        let uptime = now.duration_since(self.boot).unwrap_or_default();
+        let netflow = NetFlow9::new(
+            uptime.as_millis() as u32,
+            now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32,
+            self.sequence,
+            self.config.source_id,
+            flow_sets,
+        );
+        let bytes = netflow.to_bytes();
+        self.sink.write_record(&bytes)?;
+
+        self.sequence = self.sequence.wrapping_add(1);
+        self.packets_since_templates += 1;
+        self.stats.packets += 1;
+        self.stats.bytes += bytes.len() as u64;
+
+        Ok(())
+    }
+
+    /// Time `sys_uptime` counts from, the creation of the exporter by default.
+    /// FIRST_SWITCHED and LAST_SWITCHED of the records are relative to it.
+    pub fn boot_time(&self) -> SystemTime {
+        self.boot
+    }
+
+    pub fn set_boot_time(&mut self, boot: SystemTime) {
+        self.boot = boot;
+    }
+
+    /// Stamp the following packets with this time instead of the current one,
+    /// e.g. when exporting flows metered from a capture.
+    pub fn set_clock(&mut self, now: Option<SystemTime>) {
+        self.clock = now;
+    }
+
+    /// Sequence number of the next packet.
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    pub fn stats(&self) -> &ExporterStats {
+        &self.stats
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Send the queued records and return the sink.
+    pub fn finish(mut self) -> Result<S, NetFlowError> {
+        self.flush()?;
+        Ok(self.sink)
+    }
+}
+
+#[cfg(test)]
+mod test_exporter {
+    use super::*;
+    use crate::field::FieldTypes::*;
+    use crate::field::{FieldValue, FlowField, TypeLengthField};
+    use crate::netflow::TemplateCache;
+    use crate::sink::{UdpSink, WriteSink};
+    use std::net::UdpSocket;
+
+    fn template() -> DataTemplateItem {
+        DataTemplateItem::new(
+            256,
+            vec![
+                TypeLengthField::new(IPV4_SRC_ADDR, 4),
+                TypeLengthField::new(IN_BYTES, 4),
+            ],
+        )
+    }
+
+    fn record(bytes: u32) -> Record {
+        Record::make_data(vec![
+            FlowField::new(
+                IPV4_SRC_ADDR,
+                4,
+                FieldValue::new(IPV4_SRC_ADDR, &[10, 0, 0, 1]),
+            ),
+            FlowField::new(IN_BYTES, 4, FieldValue::new(IN_BYTES, &bytes.to_be_bytes())),
+        ])
+    }
+
+    struct Packets(Vec<Vec<u8>>);
+
+    impl OutputSink for Packets {
+        fn write_record(&mut self, record: &[u8]) -> std::io::Result<()> {
+            self.0.push(record.to_vec());
+            Ok(())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn rotate(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_packetization() {
+        let config = ExporterConfig {
+            source_id: 7,
+            mtu: 100,
+            template_packets: 3,
+            ..ExporterConfig::default()
+        };
+        let mut exporter = Exporter::new(Packets(Vec::new()), config);
+        exporter.add_template(template()).unwrap();
+        for i in 0..20 {
+            exporter.push(256, record(i)).unwrap();
+        }
+        let packets = exporter.finish().unwrap().0;
+
+        let exporter_addr = "192.0.2.1:2055".parse().unwrap();
+        let mut cache = TemplateCache::new();
+        let mut records = 0;
+        for (i, bytes) in packets.iter().enumerate() {
+            assert!(bytes.len() <= 100);
+
+            let netflow = cache.decode(exporter_addr, bytes).unwrap();
+            assert_eq!(netflow.flow_sequence, i as u32);
+            assert_eq!(netflow.source_id, 7);
+            // templates in the first packet and every third
+            assert_eq!(netflow.flow_sets[0].is_template(), i % 3 == 0);
+
+            let mut count = 0;
+            for flowset in &netflow.flow_sets {
+                match flowset {
+                    FlowSet::DataTemplate(template) => count += template.templates.len(),
+                    FlowSet::DataFlow(dataflow) => {
+                        let decoded = dataflow.records.as_ref().unwrap();
+                        assert_eq!(
+                            decoded[0].get(IN_BYTES).unwrap().value().to_u64(),
+                            Some(records as u64)
+                        );
+                        records += decoded.len();
+                        count += decoded.len();
+                    }
+                    _ => (),
+                }
+            }
+            assert_eq!(netflow.count as usize, count);
+        }
+        assert_eq!(records, 20);
+        assert_eq!(packets.len(), 3);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut exporter = Exporter::new(WriteSink::new(Vec::new()), ExporterConfig::default());
+        assert!(exporter.push(256, record(1)).is_err());
+
+        exporter.add_template(template()).unwrap();
+        let short = Record::make_data(vec![FlowField::new(
+            IN_BYTES,
+            4,
+            FieldValue::new(IN_BYTES, &[0, 0, 0, 1]),
+        )]);
+        assert!(exporter.push(256, short).is_err());
+
+        exporter.send_templates().unwrap();
+        let bytes = exporter.finish().unwrap().into_inner();
+        assert!(NetFlow9::from_bytes(&bytes).unwrap().flow_sets[0].is_template());
+    }
+
+    #[test]
+    fn test_udp() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let sink = UdpSink::connect(receiver.local_addr().unwrap()).unwrap();
+
+        let mut exporter = Exporter::new(sink, ExporterConfig::default());
+        exporter.add_template(template()).unwrap();
+        exporter.push(256, record(1500)).unwrap();
+        exporter.flush().unwrap();
+        assert_eq!(exporter.sequence(), 1);
+
+        let mut buf = vec![0u8; 65535];
+        let len = receiver.recv(&mut buf).unwrap();
+        let mut cache = TemplateCache::new();
+        let netflow = cache
+            .decode(receiver.local_addr().unwrap(), &buf[..len])
+            .unwrap();
+        assert_eq!(netflow.count, 2);
+        assert_eq!(netflow.flow_sets.len(), 2);
+    }
+}
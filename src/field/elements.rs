@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+
+/// Abstract data types of information elements (RFC 7012, section 3.1).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataType {
+    Unsigned8,
+    Unsigned16,
+    Unsigned32,
+    Unsigned64,
+    Float64,
+    Boolean,
+    MacAddress,
+    OctetArray,
+    String,
+    DateTimeSeconds,
+    DateTimeMilliseconds,
+    DateTimeMicroseconds,
+    DateTimeNanoseconds,
+    Ipv4Address,
+    Ipv6Address,
+    BasicList,
+    SubTemplateList,
+    SubTemplateMultiList,
+}
+
+/// Private enterprise number of the reverse information elements (RFC 5103), they have the
+/// ids and types of the IANA elements they reverse.
+pub const REVERSE_PEN: u32 = 29305;
+
+/// An IANA information element, ids below 128 are the Netflow v9 field types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InformationElement {
+    pub id: u16,
+    pub name: &'static str,
+    pub data_type: DataType,
+}
+
+const fn ie(id: u16, name: &'static str, data_type: DataType) -> InformationElement {
+    InformationElement {
+        id,
+        name,
+        data_type,
+    }
+}
+
+// https://www.iana.org/assignments/ipfix/ipfix.xhtml
+static ELEMENTS: &[InformationElement] = &[
+    ie(1, "octetDeltaCount", DataType::Unsigned64),
+    ie(2, "packetDeltaCount", DataType::Unsigned64),
+    ie(3, "deltaFlowCount", DataType::Unsigned64),
+    ie(4, "protocolIdentifier", DataType::Unsigned8),
+    ie(5, "ipClassOfService", DataType::Unsigned8),
+    ie(6, "tcpControlBits", DataType::Unsigned16),
+    ie(7, "sourceTransportPort", DataType::Unsigned16),
+    ie(8, "sourceIPv4Address", DataType::Ipv4Address),
+    ie(9, "sourceIPv4PrefixLength", DataType::Unsigned8),
+    ie(10, "ingressInterface", DataType::Unsigned32),
+    ie(11, "destinationTransportPort", DataType::Unsigned16),
+    ie(12, "destinationIPv4Address", DataType::Ipv4Address),
+    ie(13, "destinationIPv4PrefixLength", DataType::Unsigned8),
+    ie(14, "egressInterface", DataType::Unsigned32),
+    ie(15, "ipNextHopIPv4Address", DataType::Ipv4Address),
+    ie(16, "bgpSourceAsNumber", DataType::Unsigned32),
+    ie(17, "bgpDestinationAsNumber", DataType::Unsigned32),
+    ie(18, "bgpNextHopIPv4Address", DataType::Ipv4Address),
+    ie(19, "postMCastPacketDeltaCount", DataType::Unsigned64),
+    ie(20, "postMCastOctetDeltaCount", DataType::Unsigned64),
+    ie(21, "flowEndSysUpTime", DataType::Unsigned32),
+    ie(22, "flowStartSysUpTime", DataType::Unsigned32),
+    ie(23, "postOctetDeltaCount", DataType::Unsigned64),
+    ie(24, "postPacketDeltaCount", DataType::Unsigned64),
+    ie(25, "minimumIpTotalLength", DataType::Unsigned64),
+    ie(26, "maximumIpTotalLength", DataType::Unsigned64),
+    ie(27, "sourceIPv6Address", DataType::Ipv6Address),
+    ie(28, "destinationIPv6Address", DataType::Ipv6Address),
+    ie(29, "sourceIPv6PrefixLength", DataType::Unsigned8),
+    ie(30, "destinationIPv6PrefixLength", DataType::Unsigned8),
+    ie(31, "flowLabelIPv6", DataType::Unsigned32),
+    ie(32, "icmpTypeCodeIPv4", DataType::Unsigned16),
+    ie(33, "igmpType", DataType::Unsigned8),
+    ie(34, "samplingInterval", DataType::Unsigned32),
+    ie(35, "samplingAlgorithm", DataType::Unsigned8),
+    ie(36, "flowActiveTimeout", DataType::Unsigned16),
+    ie(37, "flowIdleTimeout", DataType::Unsigned16),
+    ie(38, "engineType", DataType::Unsigned8),
+    ie(39, "engineId", DataType::Unsigned8),
+    ie(40, "exportedOctetTotalCount", DataType::Unsigned64),
+    ie(41, "exportedMessageTotalCount", DataType::Unsigned64),
+    ie(42, "exportedFlowRecordTotalCount", DataType::Unsigned64),
+    ie(43, "ipv4RouterSc", DataType::Ipv4Address),
+    ie(44, "sourceIPv4Prefix", DataType::Ipv4Address),
+    ie(45, "destinationIPv4Prefix", DataType::Ipv4Address),
+    ie(46, "mplsTopLabelType", DataType::Unsigned8),
+    ie(47, "mplsTopLabelIPv4Address", DataType::Ipv4Address),
+    ie(48, "samplerId", DataType::Unsigned8),
+    ie(49, "samplerMode", DataType::Unsigned8),
+    ie(50, "samplerRandomInterval", DataType::Unsigned32),
+    ie(51, "classId", DataType::Unsigned8),
+    ie(52, "minimumTTL", DataType::Unsigned8),
+    ie(53, "maximumTTL", DataType::Unsigned8),
+    ie(54, "fragmentIdentification", DataType::Unsigned32),
+    ie(55, "postIpClassOfService", DataType::Unsigned8),
+    ie(56, "sourceMacAddress", DataType::MacAddress),
+    ie(57, "postDestinationMacAddress", DataType::MacAddress),
+    ie(58, "vlanId", DataType::Unsigned16),
+    ie(59, "postVlanId", DataType::Unsigned16),
+    ie(60, "ipVersion", DataType::Unsigned8),
+    ie(61, "flowDirection", DataType::Unsigned8),
+    ie(62, "ipNextHopIPv6Address", DataType::Ipv6Address),
+    ie(63, "bgpNextHopIPv6Address", DataType::Ipv6Address),
+    ie(64, "ipv6ExtensionHeaders", DataType::Unsigned32),
+    ie(70, "mplsTopLabelStackSection", DataType::OctetArray),
+    ie(71, "mplsLabelStackSection2", DataType::OctetArray),
+    ie(72, "mplsLabelStackSection3", DataType::OctetArray),
+    ie(73, "mplsLabelStackSection4", DataType::OctetArray),
+    ie(74, "mplsLabelStackSection5", DataType::OctetArray),
+    ie(75, "mplsLabelStackSection6", DataType::OctetArray),
+    ie(76, "mplsLabelStackSection7", DataType::OctetArray),
+    ie(77, "mplsLabelStackSection8", DataType::OctetArray),
+    ie(78, "mplsLabelStackSection9", DataType::OctetArray),
+    ie(79, "mplsLabelStackSection10", DataType::OctetArray),
+    ie(80, "destinationMacAddress", DataType::MacAddress),
+    ie(81, "postSourceMacAddress", DataType::MacAddress),
+    ie(82, "interfaceName", DataType::String),
+    ie(83, "interfaceDescription", DataType::String),
+    ie(84, "samplerName", DataType::String),
+    ie(85, "octetTotalCount", DataType::Unsigned64),
+    ie(86, "packetTotalCount", DataType::Unsigned64),
+    ie(87, "flagsAndSamplerId", DataType::Unsigned32),
+    ie(88, "fragmentOffset", DataType::Unsigned16),
+    ie(89, "forwardingStatus", DataType::Unsigned32),
+    ie(90, "mplsVpnRouteDistinguisher", DataType::OctetArray),
+    ie(91, "mplsTopLabelPrefixLength", DataType::Unsigned8),
+    ie(92, "srcTrafficIndex", DataType::Unsigned32),
+    ie(93, "dstTrafficIndex", DataType::Unsigned32),
+    ie(94, "applicationDescription", DataType::String),
+    ie(95, "applicationId", DataType::OctetArray),
+    ie(96, "applicationName", DataType::String),
+    ie(98, "postIpDiffServCodePoint", DataType::Unsigned8),
+    ie(99, "multicastReplicationFactor", DataType::Unsigned32),
+    ie(100, "className", DataType::String),
+    ie(101, "classificationEngineId", DataType::Unsigned8),
+    ie(102, "layer2packetSectionOffset", DataType::Unsigned16),
+    ie(103, "layer2packetSectionSize", DataType::Unsigned16),
+    ie(104, "layer2packetSectionData", DataType::OctetArray),
+    ie(128, "bgpNextAdjacentAsNumber", DataType::Unsigned32),
+    ie(129, "bgpPrevAdjacentAsNumber", DataType::Unsigned32),
+    ie(130, "exporterIPv4Address", DataType::Ipv4Address),
+    ie(131, "exporterIPv6Address", DataType::Ipv6Address),
+    ie(132, "droppedOctetDeltaCount", DataType::Unsigned64),
+    ie(133, "droppedPacketDeltaCount", DataType::Unsigned64),
+    ie(134, "droppedOctetTotalCount", DataType::Unsigned64),
+    ie(135, "droppedPacketTotalCount", DataType::Unsigned64),
+    ie(136, "flowEndReason", DataType::Unsigned8),
+    ie(137, "commonPropertiesId", DataType::Unsigned64),
+    ie(138, "observationPointId", DataType::Unsigned64),
+    ie(139, "icmpTypeCodeIPv6", DataType::Unsigned16),
+    ie(140, "mplsTopLabelIPv6Address", DataType::Ipv6Address),
+    ie(141, "lineCardId", DataType::Unsigned32),
+    ie(142, "portId", DataType::Unsigned32),
+    ie(143, "meteringProcessId", DataType::Unsigned32),
+    ie(144, "exportingProcessId", DataType::Unsigned32),
+    ie(145, "templateId", DataType::Unsigned16),
+    ie(146, "wlanChannelId", DataType::Unsigned8),
+    ie(147, "wlanSSID", DataType::String),
+    ie(148, "flowId", DataType::Unsigned64),
+    ie(149, "observationDomainId", DataType::Unsigned32),
+    ie(150, "flowStartSeconds", DataType::DateTimeSeconds),
+    ie(151, "flowEndSeconds", DataType::DateTimeSeconds),
+    ie(152, "flowStartMilliseconds", DataType::DateTimeMilliseconds),
+    ie(153, "flowEndMilliseconds", DataType::DateTimeMilliseconds),
+    ie(154, "flowStartMicroseconds", DataType::DateTimeMicroseconds),
+    ie(155, "flowEndMicroseconds", DataType::DateTimeMicroseconds),
+    ie(156, "flowStartNanoseconds", DataType::DateTimeNanoseconds),
+    ie(157, "flowEndNanoseconds", DataType::DateTimeNanoseconds),
+    ie(158, "flowStartDeltaMicroseconds", DataType::Unsigned32),
+    ie(159, "flowEndDeltaMicroseconds", DataType::Unsigned32),
+    ie(
+        160,
+        "systemInitTimeMilliseconds",
+        DataType::DateTimeMilliseconds,
+    ),
+    ie(161, "flowDurationMilliseconds", DataType::Unsigned32),
+    ie(162, "flowDurationMicroseconds", DataType::Unsigned32),
+    ie(163, "observedFlowTotalCount", DataType::Unsigned64),
+    ie(164, "ignoredPacketTotalCount", DataType::Unsigned64),
+    ie(165, "ignoredOctetTotalCount", DataType::Unsigned64),
+    ie(166, "notSentFlowTotalCount", DataType::Unsigned64),
+    ie(167, "notSentPacketTotalCount", DataType::Unsigned64),
+    ie(168, "notSentOctetTotalCount", DataType::Unsigned64),
+    ie(169, "destinationIPv6Prefix", DataType::Ipv6Address),
+    ie(170, "sourceIPv6Prefix", DataType::Ipv6Address),
+    ie(171, "postOctetTotalCount", DataType::Unsigned64),
+    ie(172, "postPacketTotalCount", DataType::Unsigned64),
+    ie(173, "flowKeyIndicator", DataType::Unsigned64),
+    ie(174, "postMCastPacketTotalCount", DataType::Unsigned64),
+    ie(175, "postMCastOctetTotalCount", DataType::Unsigned64),
+    ie(176, "icmpTypeIPv4", DataType::Unsigned8),
+    ie(177, "icmpCodeIPv4", DataType::Unsigned8),
+    ie(178, "icmpTypeIPv6", DataType::Unsigned8),
+    ie(179, "icmpCodeIPv6", DataType::Unsigned8),
+    ie(180, "udpSourcePort", DataType::Unsigned16),
+    ie(181, "udpDestinationPort", DataType::Unsigned16),
+    ie(182, "tcpSourcePort", DataType::Unsigned16),
+    ie(183, "tcpDestinationPort", DataType::Unsigned16),
+    ie(184, "tcpSequenceNumber", DataType::Unsigned32),
+    ie(185, "tcpAcknowledgementNumber", DataType::Unsigned32),
+    ie(186, "tcpWindowSize", DataType::Unsigned16),
+    ie(187, "tcpUrgentPointer", DataType::Unsigned16),
+    ie(188, "tcpHeaderLength", DataType::Unsigned8),
+    ie(189, "ipHeaderLength", DataType::Unsigned8),
+    ie(190, "totalLengthIPv4", DataType::Unsigned16),
+    ie(191, "payloadLengthIPv6", DataType::Unsigned16),
+    ie(192, "ipTTL", DataType::Unsigned8),
+    ie(193, "nextHeaderIPv6", DataType::Unsigned8),
+    ie(194, "mplsPayloadLength", DataType::Unsigned32),
+    ie(195, "ipDiffServCodePoint", DataType::Unsigned8),
+    ie(196, "ipPrecedence", DataType::Unsigned8),
+    ie(197, "fragmentFlags", DataType::Unsigned8),
+    ie(198, "octetDeltaSumOfSquares", DataType::Unsigned64),
+    ie(199, "octetTotalSumOfSquares", DataType::Unsigned64),
+    ie(200, "mplsTopLabelTTL", DataType::Unsigned8),
+    ie(201, "mplsLabelStackLength", DataType::Unsigned32),
+    ie(202, "mplsLabelStackDepth", DataType::Unsigned32),
+    ie(203, "mplsTopLabelExp", DataType::Unsigned8),
+    ie(204, "ipPayloadLength", DataType::Unsigned32),
+    ie(205, "udpMessageLength", DataType::Unsigned16),
+    ie(206, "isMulticast", DataType::Unsigned8),
+    ie(207, "ipv4IHL", DataType::Unsigned8),
+    ie(208, "ipv4Options", DataType::Unsigned32),
+    ie(209, "tcpOptions", DataType::Unsigned64),
+    ie(210, "paddingOctets", DataType::OctetArray),
+    ie(211, "collectorIPv4Address", DataType::Ipv4Address),
+    ie(212, "collectorIPv6Address", DataType::Ipv6Address),
+    ie(213, "exportInterface", DataType::Unsigned32),
+    ie(214, "exportProtocolVersion", DataType::Unsigned8),
+    ie(215, "exportTransportProtocol", DataType::Unsigned8),
+    ie(216, "collectorTransportPort", DataType::Unsigned16),
+    ie(217, "exporterTransportPort", DataType::Unsigned16),
+    ie(218, "tcpSynTotalCount", DataType::Unsigned64),
+    ie(219, "tcpFinTotalCount", DataType::Unsigned64),
+    ie(220, "tcpRstTotalCount", DataType::Unsigned64),
+    ie(221, "tcpPshTotalCount", DataType::Unsigned64),
+    ie(222, "tcpAckTotalCount", DataType::Unsigned64),
+    ie(223, "tcpUrgTotalCount", DataType::Unsigned64),
+    ie(224, "ipTotalLength", DataType::Unsigned64),
+    ie(225, "postNATSourceIPv4Address", DataType::Ipv4Address),
+    ie(226, "postNATDestinationIPv4Address", DataType::Ipv4Address),
+    ie(227, "postNAPTSourceTransportPort", DataType::Unsigned16),
+    ie(
+        228,
+        "postNAPTDestinationTransportPort",
+        DataType::Unsigned16,
+    ),
+    ie(229, "natOriginatingAddressRealm", DataType::Unsigned8),
+    ie(230, "natEvent", DataType::Unsigned8),
+    ie(231, "initiatorOctets", DataType::Unsigned64),
+    ie(232, "responderOctets", DataType::Unsigned64),
+    ie(233, "firewallEvent", DataType::Unsigned8),
+    ie(234, "ingressVRFID", DataType::Unsigned32),
+    ie(235, "egressVRFID", DataType::Unsigned32),
+    ie(236, "VRFname", DataType::String),
+    ie(237, "postMplsTopLabelExp", DataType::Unsigned8),
+    ie(238, "tcpWindowScale", DataType::Unsigned16),
+    ie(239, "biflowDirection", DataType::Unsigned8),
+    ie(240, "ethernetHeaderLength", DataType::Unsigned8),
+    ie(241, "ethernetPayloadLength", DataType::Unsigned16),
+    ie(242, "ethernetTotalLength", DataType::Unsigned16),
+    ie(243, "dot1qVlanId", DataType::Unsigned16),
+    ie(244, "dot1qPriority", DataType::Unsigned8),
+    ie(245, "dot1qCustomerVlanId", DataType::Unsigned16),
+    ie(246, "dot1qCustomerPriority", DataType::Unsigned8),
+    ie(247, "metroEvcId", DataType::String),
+    ie(248, "metroEvcType", DataType::Unsigned8),
+    ie(249, "pseudoWireId", DataType::Unsigned32),
+    ie(250, "pseudoWireType", DataType::Unsigned16),
+    ie(251, "pseudoWireControlWord", DataType::Unsigned32),
+    ie(252, "ingressPhysicalInterface", DataType::Unsigned32),
+    ie(253, "egressPhysicalInterface", DataType::Unsigned32),
+    ie(254, "postDot1qVlanId", DataType::Unsigned16),
+    ie(255, "postDot1qCustomerVlanId", DataType::Unsigned16),
+    ie(256, "ethernetType", DataType::Unsigned16),
+    ie(257, "postIpPrecedence", DataType::Unsigned8),
+    ie(
+        258,
+        "collectionTimeMilliseconds",
+        DataType::DateTimeMilliseconds,
+    ),
+    ie(259, "exportSctpStreamId", DataType::Unsigned16),
+    ie(260, "maxExportSeconds", DataType::DateTimeSeconds),
+    ie(261, "maxFlowEndSeconds", DataType::DateTimeSeconds),
+    ie(262, "messageMD5Checksum", DataType::OctetArray),
+    ie(263, "messageScope", DataType::Unsigned8),
+    ie(264, "minExportSeconds", DataType::DateTimeSeconds),
+    ie(265, "minFlowStartSeconds", DataType::DateTimeSeconds),
+    ie(266, "opaqueOctets", DataType::OctetArray),
+    ie(267, "sessionScope", DataType::Unsigned8),
+    ie(
+        268,
+        "maxFlowEndMicroseconds",
+        DataType::DateTimeMicroseconds,
+    ),
+    ie(
+        269,
+        "maxFlowEndMilliseconds",
+        DataType::DateTimeMilliseconds,
+    ),
+    ie(270, "maxFlowEndNanoseconds", DataType::DateTimeNanoseconds),
+    ie(
+        271,
+        "minFlowStartMicroseconds",
+        DataType::DateTimeMicroseconds,
+    ),
+    ie(
+        272,
+        "minFlowStartMilliseconds",
+        DataType::DateTimeMilliseconds,
+    ),
+    ie(
+        273,
+        "minFlowStartNanoseconds",
+        DataType::DateTimeNanoseconds,
+    ),
+    ie(274, "collectorCertificate", DataType::OctetArray),
+    ie(275, "exporterCertificate", DataType::OctetArray),
+    ie(276, "dataRecordsReliability", DataType::Boolean),
+    ie(277, "observationPointType", DataType::Unsigned8),
+    ie(278, "newConnectionDeltaCount", DataType::Unsigned32),
+    ie(279, "connectionSumDurationSeconds", DataType::Unsigned64),
+    ie(280, "connectionTransactionId", DataType::Unsigned64),
+    ie(281, "postNATSourceIPv6Address", DataType::Ipv6Address),
+    ie(282, "postNATDestinationIPv6Address", DataType::Ipv6Address),
+    ie(283, "natPoolId", DataType::Unsigned32),
+    ie(284, "natPoolName", DataType::String),
+    ie(285, "anonymizationFlags", DataType::Unsigned16),
+    ie(286, "anonymizationTechnique", DataType::Unsigned16),
+    ie(287, "informationElementIndex", DataType::Unsigned16),
+    ie(288, "p2pTechnology", DataType::String),
+    ie(289, "tunnelTechnology", DataType::String),
+    ie(290, "encryptedTechnology", DataType::String),
+    ie(291, "basicList", DataType::BasicList),
+    ie(292, "subTemplateList", DataType::SubTemplateList),
+    ie(293, "subTemplateMultiList", DataType::SubTemplateMultiList),
+    ie(294, "bgpValidityState", DataType::Unsigned8),
+    ie(295, "IPSecSPI", DataType::Unsigned32),
+    ie(296, "greKey", DataType::Unsigned32),
+    ie(297, "natType", DataType::Unsigned8),
+    ie(298, "initiatorPackets", DataType::Unsigned64),
+    ie(299, "responderPackets", DataType::Unsigned64),
+    ie(300, "observationDomainName", DataType::String),
+    ie(301, "selectionSequenceId", DataType::Unsigned64),
+    ie(302, "selectorId", DataType::Unsigned64),
+    ie(303, "informationElementId", DataType::Unsigned16),
+    ie(304, "selectorAlgorithm", DataType::Unsigned16),
+    ie(305, "samplingPacketInterval", DataType::Unsigned32),
+    ie(306, "samplingPacketSpace", DataType::Unsigned32),
+    ie(307, "samplingTimeInterval", DataType::Unsigned32),
+    ie(308, "samplingTimeSpace", DataType::Unsigned32),
+    ie(309, "samplingSize", DataType::Unsigned32),
+    ie(310, "samplingPopulation", DataType::Unsigned32),
+    ie(311, "samplingProbability", DataType::Float64),
+    ie(312, "dataLinkFrameSize", DataType::Unsigned16),
+    ie(313, "ipHeaderPacketSection", DataType::OctetArray),
+    ie(314, "ipPayloadPacketSection", DataType::OctetArray),
+    ie(315, "dataLinkFrameSection", DataType::OctetArray),
+    ie(316, "mplsLabelStackSection", DataType::OctetArray),
+    ie(317, "mplsPayloadPacketSection", DataType::OctetArray),
+    ie(318, "selectorIdTotalPktsObserved", DataType::Unsigned64),
+    ie(319, "selectorIdTotalPktsSelected", DataType::Unsigned64),
+    ie(320, "absoluteError", DataType::Float64),
+    ie(321, "relativeError", DataType::Float64),
+    ie(322, "observationTimeSeconds", DataType::DateTimeSeconds),
+    ie(
+        323,
+        "observationTimeMilliseconds",
+        DataType::DateTimeMilliseconds,
+    ),
+    ie(
+        324,
+        "observationTimeMicroseconds",
+        DataType::DateTimeMicroseconds,
+    ),
+    ie(
+        325,
+        "observationTimeNanoseconds",
+        DataType::DateTimeNanoseconds,
+    ),
+    ie(326, "digestHashValue", DataType::Unsigned64),
+    ie(327, "hashIPPayloadOffset", DataType::Unsigned64),
+    ie(328, "hashIPPayloadSize", DataType::Unsigned64),
+    ie(329, "hashOutputRangeMin", DataType::Unsigned64),
+    ie(330, "hashOutputRangeMax", DataType::Unsigned64),
+    ie(331, "hashSelectedRangeMin", DataType::Unsigned64),
+    ie(332, "hashSelectedRangeMax", DataType::Unsigned64),
+    ie(333, "hashDigestOutput", DataType::Boolean),
+    ie(334, "hashInitialiserValue", DataType::Unsigned64),
+    ie(335, "selectorName", DataType::String),
+    ie(336, "upperCILimit", DataType::Float64),
+    ie(337, "lowerCILimit", DataType::Float64),
+    ie(338, "confidenceLevel", DataType::Float64),
+    ie(339, "informationElementDataType", DataType::Unsigned8),
+    ie(340, "informationElementDescription", DataType::String),
+    ie(341, "informationElementName", DataType::String),
+    ie(342, "informationElementRangeBegin", DataType::Unsigned64),
+    ie(343, "informationElementRangeEnd", DataType::Unsigned64),
+    ie(344, "informationElementSemantics", DataType::Unsigned8),
+    ie(345, "informationElementUnits", DataType::Unsigned16),
+    ie(346, "privateEnterpriseNumber", DataType::Unsigned32),
+];
+
+lazy_static! {
+    static ref BY_ID: HashMap<u16, &'static InformationElement> = ELEMENTS
+        .iter()
+        .map(|element| (element.id, element))
+        .collect();
+    static ref BY_NAME: HashMap<&'static str, &'static InformationElement> = ELEMENTS
+        .iter()
+        .map(|element| (element.name, element))
+        .collect();
+}
+
+/// Look up an information element by its id.
+pub fn element(id: u16) -> Option<&'static InformationElement> {
+    BY_ID.get(&id).cloned()
+}
+
+/// Look up an information element by its IANA name, e.g. "sourceIPv4Address".
+pub fn element_by_name(name: &str) -> Option<&'static InformationElement> {
+    BY_NAME.get(name).cloned()
+}
+
+/// Name of the element, "field_<id>" for an id missing from the registry.
+pub fn element_name(id: u16) -> String {
+    match element(id) {
+        Some(element) => element.name.to_string(),
+        None => format!("field_{}", id),
+    }
+}
+
+/// Lengths a field may have in a template.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldLength {
+    /// always this many bytes
+    Fixed(u16),
+    /// a number encoded in 1 up to `max` bytes, `default` unless the exporter says otherwise
+    Number { default: u16, max: u16 },
+    /// one of the lengths, the first is the default
+    OneOf(&'static [u16]),
+    /// any length, e.g. strings, so there is no default
+    Variable,
+}
+
+impl FieldLength {
+    pub fn default_length(self) -> Option<u16> {
+        match self {
+            FieldLength::Fixed(length) => Some(length),
+            FieldLength::Number { default, .. } => Some(default),
+            FieldLength::OneOf(lengths) => lengths.first().cloned(),
+            FieldLength::Variable => None,
+        }
+    }
+
+    pub fn accepts(self, length: u16) -> bool {
+        match self {
+            FieldLength::Fixed(fixed) => length == fixed,
+            FieldLength::Number { max, .. } => (1..=max).contains(&length),
+            FieldLength::OneOf(lengths) => lengths.contains(&length),
+            FieldLength::Variable => length >= 1,
+        }
+    }
+}
+
+/// Lengths of the field type, from RFC 3954 for the Netflow v9 ids and from
+/// the data type for the other elements of the registry.
+pub fn field_length(id: u16) -> FieldLength {
+    use self::FieldLength::*;
+
+    match id {
+        // counters, N with a default of 4
+        1..=3 | 19 | 20 | 23 | 24 | 40..=42 | 85 | 86 => Number { default: 4, max: 8 },
+        // interface indexes, N with a default of 2
+        10 | 14 => Number { default: 2, max: 4 },
+        16 | 17 => OneOf(&[2, 4]),
+        6 => OneOf(&[1, 2]),
+        31 => OneOf(&[3, 4]),
+        4 | 5 | 9 | 13 | 29 | 30 | 33 | 35 | 38 | 39 | 46 | 48 | 49 | 52 | 53 | 55 | 60 | 61
+        | 89 | 91 | 98 => Fixed(1),
+        7 | 11 | 25 | 26 | 32 | 36 | 37 | 54 | 58 | 59 | 88 => Fixed(2),
+        70..=79 => Fixed(3),
+        8 | 12 | 15 | 18 | 21 | 22 | 34 | 44 | 45 | 47 | 50 | 64 | 92 | 93 | 99 => Fixed(4),
+        56 | 57 | 80 | 81 => Fixed(6),
+        90 => Fixed(8),
+        27 | 28 | 62 | 63 => Fixed(16),
+        1..=127 => Variable,
+        _ => match element(id).map(|element| element.data_type) {
+            // reduced size encoding, RFC 7011 section 6.2
+            Some(DataType::Unsigned8) | Some(DataType::Boolean) => Fixed(1),
+            Some(DataType::Unsigned16) => Number { default: 2, max: 2 },
+            Some(DataType::Unsigned32) => Number { default: 4, max: 4 },
+            Some(DataType::Unsigned64) => Number { default: 8, max: 8 },
+            Some(DataType::Float64) => OneOf(&[8, 4]),
+            Some(DataType::MacAddress) => Fixed(6),
+            Some(DataType::DateTimeSeconds) | Some(DataType::Ipv4Address) => Fixed(4),
+            Some(DataType::DateTimeMilliseconds)
+            | Some(DataType::DateTimeMicroseconds)
+            | Some(DataType::DateTimeNanoseconds) => Fixed(8),
+            Some(DataType::Ipv6Address) => Fixed(16),
+            _ => Variable,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test_elements {
+    use super::*;
+    use crate::field::FieldTypes::*;
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(element(IPV4_SRC_ADDR).unwrap().name, "sourceIPv4Address");
+        assert_eq!(element(IN_BYTES).unwrap().data_type, DataType::Unsigned64);
+        assert_eq!(
+            element_by_name("destinationTransportPort").unwrap().id,
+            L4_DST_PORT
+        );
+        assert!(element(VENDOR_PROPRIETARY_65).is_none());
+        assert_eq!(element_name(VENDOR_PROPRIETARY_65), "field_65");
+        assert_eq!(element_name(SRC_MAC), "sourceMacAddress");
+    }
+
+    #[test]
+    fn test_field_length() {
+        assert_eq!(field_length(IN_BYTES).default_length(), Some(4));
+        assert!(field_length(IN_BYTES).accepts(8));
+        assert!(!field_length(IN_BYTES).accepts(9));
+        assert_eq!(field_length(L4_SRC_PORT), FieldLength::Fixed(2));
+        assert_eq!(field_length(SRC_AS).default_length(), Some(2));
+        assert!(!field_length(SRC_AS).accepts(3));
+        assert_eq!(field_length(IF_NAME).default_length(), None);
+        // flowStartMilliseconds and postNATSourceIPv4Address
+        assert_eq!(field_length(152), FieldLength::Fixed(8));
+        assert_eq!(field_length(225), FieldLength::Fixed(4));
+        assert_eq!(field_length(40000), FieldLength::Variable);
+    }
+
+    #[test]
+    fn test_unique() {
+        assert_eq!(BY_ID.len(), ELEMENTS.len());
+        assert_eq!(BY_NAME.len(), ELEMENTS.len());
+    }
+}
@@ -9,6 +9,7 @@ lazy_static! {
         set.insert(6);
         set.insert(7);
         set.insert(10);
+        set.insert(11);
         set.insert(13);
         set.insert(14);
         set.insert(16);
@@ -8,6 +8,7 @@ pub const L4_SRC_PORT: u16 = 7; // 2
 pub const IPV4_SRC_ADDR: u16 = 8; // 4, IPv4 address
 pub const SRC_MASK: u16 = 9; // 1,
 pub const INPUT_SNMP: u16 = 10; // N, default is 2
+pub const L4_DST_PORT: u16 = 11; // 2
 pub const IPV4_DST_ADDR: u16 = 12; // 4, IPv4 address
 pub const DST_MASK: u16 = 13; // 1
 pub const OUTPUT_SNMP: u16 = 14; // N, default is 2
@@ -106,3 +107,28 @@ pub const layer2packetSectionSize: u16 = 103; // N
 pub const layer2packetSectionData: u16 = 104; // N
                                               // 105 to 127 are reserved
                                               // 128 to 32768 are in IANA
+
+// IANA elements past the Netflow v9 types
+pub const EXPORTER_IPV4_ADDRESS: u16 = 130; // 4, IPv4 address
+pub const EXPORTER_IPV6_ADDRESS: u16 = 131; // 16, IPv6 address
+pub const FLOW_END_REASON: u16 = 136; // 1
+pub const LINE_CARD_ID: u16 = 141; // 4
+pub const METERING_PROCESS_ID: u16 = 143; // 4
+pub const EXPORTING_PROCESS_ID: u16 = 144; // 4
+pub const TEMPLATE_ID: u16 = 145; // 2
+pub const FLOW_START_SECONDS: u16 = 150; // 4, seconds since the epoch
+pub const FLOW_END_SECONDS: u16 = 151; // 4, seconds since the epoch
+pub const FLOW_START_MILLISECONDS: u16 = 152; // 8, milliseconds since the epoch
+pub const FLOW_END_MILLISECONDS: u16 = 153; // 8, milliseconds since the epoch
+pub const COLLECTOR_IPV4_ADDRESS: u16 = 211; // 4, IPv4 address
+pub const COLLECTOR_IPV6_ADDRESS: u16 = 212; // 16, IPv6 address
+pub const EXPORT_PROTOCOL_VERSION: u16 = 214; // 1
+pub const EXPORT_TRANSPORT_PROTOCOL: u16 = 215; // 1
+pub const COLLECTOR_TRANSPORT_PORT: u16 = 216; // 2
+pub const EXPORTER_TRANSPORT_PORT: u16 = 217; // 2
+pub const BIFLOW_DIRECTION: u16 = 239; // 1
+pub const MAX_EXPORT_SECONDS: u16 = 260; // 4
+pub const MESSAGE_MD5_CHECKSUM: u16 = 262; // 16
+pub const MESSAGE_SCOPE: u16 = 263; // 1
+pub const MIN_EXPORT_SECONDS: u16 = 264; // 4
+pub const SESSION_SCOPE: u16 = 267; // 1
@@ -59,23 +59,26 @@ impl FieldValue {
         BITS_ID.contains(&type_id)
     }
 
-    // TODO: add error handling
+    /// Value typed by the field id, a value with an unexpected length is kept as bytes.
     pub fn new(type_id: u16, value: &[u8]) -> FieldValue {
         if FieldValue::is_num_field(type_id) {
             FieldValue::NumField(UInt::from_bytes(value))
         } else if FieldValue::is_bytes_field(type_id) {
             FieldValue::ByteArray(value.to_vec())
-        } else if FieldValue::is_ipv4_field(type_id) {
+        } else if FieldValue::is_ipv4_field(type_id) && value.len() == 4 {
             let ip = take_u32(&value).unwrap().1;
             FieldValue::Ipv4Addr(Ipv4Addr::from(ip))
-        } else if FieldValue::is_ipv6_field(type_id) {
+        } else if FieldValue::is_ipv6_field(type_id) && value.len() == 16 {
             FieldValue::Ipv6Addr(Ipv6Addr::from(take_u128(value).unwrap().1))
-        } else if FieldValue::is_mac_field(type_id) {
+        } else if FieldValue::is_mac_field(type_id) && value.len() == 6 {
             FieldValue::MacAddr(MacAddr::new(
                 value[0], value[1], value[2], value[3], value[4], value[5],
             ))
         } else if FieldValue::is_string_field(type_id) {
-            FieldValue::String(String::from_utf8(value.to_vec()).unwrap())
+            match String::from_utf8(value.to_vec()) {
+                Ok(s) => FieldValue::String(s),
+                Err(e) => FieldValue::ByteArray(e.into_bytes()),
+            }
         } else if FieldValue::is_bits_field(type_id) {
             FieldValue::NumField(UInt::from_bytes(value))
         } else {
@@ -83,6 +86,32 @@ impl FieldValue {
         }
     }
 
+    /// Return the value as a number, bytes up to 8 long are read as big endian.
+    pub fn to_u64(&self) -> Option<u64> {
+        let bytes = match self {
+            FieldValue::NumField(UInt::UInt8(num)) => return Some(u64::from(*num)),
+            FieldValue::NumField(UInt::UInt16(num)) => return Some(u64::from(*num)),
+            FieldValue::NumField(UInt::UInt32(num)) => return Some(u64::from(*num)),
+            FieldValue::NumField(UInt::UInt64(num)) => return Some(*num),
+            FieldValue::NumField(UInt::UInt128(num)) if *num <= u128::from(u64::MAX) => {
+                return Some(*num as u64)
+            }
+            FieldValue::NumField(UInt::UIntFlex(array)) => array,
+            FieldValue::ByteArray(array) | FieldValue::Unknown(array) => array,
+            _ => return None,
+        };
+
+        if bytes.len() <= 8 {
+            Some(
+                bytes
+                    .iter()
+                    .fold(0u64, |sum, byte| (sum << 8) | u64::from(*byte)),
+            )
+        } else {
+            None
+        }
+    }
+
     pub fn to_bytes(&self, length: u16) -> Vec<u8> {
         match &self {
             FieldValue::NumField(uint) => uint.to_bytes(length),
@@ -96,6 +125,56 @@ impl FieldValue {
     }
 }
 
+macro_rules! num_field_from {
+    ($($num:ty => $variant:ident),*) => {
+        $(
+            impl From<$num> for FieldValue {
+                fn from(num: $num) -> Self {
+                    FieldValue::NumField(UInt::$variant(num))
+                }
+            }
+        )*
+    };
+}
+
+num_field_from!(u8 => UInt8, u16 => UInt16, u32 => UInt32, u64 => UInt64, u128 => UInt128);
+
+impl From<Ipv4Addr> for FieldValue {
+    fn from(ip: Ipv4Addr) -> Self {
+        FieldValue::Ipv4Addr(ip)
+    }
+}
+
+impl From<Ipv6Addr> for FieldValue {
+    fn from(ip: Ipv6Addr) -> Self {
+        FieldValue::Ipv6Addr(ip)
+    }
+}
+
+impl From<MacAddr> for FieldValue {
+    fn from(mac: MacAddr) -> Self {
+        FieldValue::MacAddr(mac)
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(s: String) -> Self {
+        FieldValue::String(s)
+    }
+}
+
+impl<'a> From<&'a str> for FieldValue {
+    fn from(s: &'a str) -> Self {
+        FieldValue::String(s.to_string())
+    }
+}
+
+impl From<Vec<u8>> for FieldValue {
+    fn from(bytes: Vec<u8>) -> Self {
+        FieldValue::ByteArray(bytes)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UInt {
     UInt8(u8),
@@ -110,14 +189,16 @@ pub enum UInt {
 /// every field can have various bit-length
 /// so, field must be able to accept such config
 impl UInt {
+    // big endian, so the fill goes in front
     fn fit_vec(len: usize, val: u8, bytes: &[u8]) -> Vec<u8> {
-        let mut vec = Vec::from(bytes);
+        let mut vec = Vec::with_capacity(len.max(bytes.len()));
 
         if bytes.len() < len {
             for _ in 0..len - bytes.len() {
                 vec.push(val);
             }
         }
+        vec.extend_from_slice(bytes);
 
         vec
     }
@@ -192,4 +273,33 @@ impl UInt {
     }
 }
 
-// TODO: add test
+#[cfg(test)]
+mod test_field_value {
+    use crate::field::UInt;
+
+    #[test]
+    fn test_odd_length_uint() {
+        // big endian, the missing high bytes are zero
+        match UInt::from_bytes(&[0x01, 0x02, 0x03]) {
+            UInt::UInt32(num) => assert_eq!(num, 0x01_0203),
+            other => panic!("unexpected value: {:?}", other),
+        }
+        match UInt::from_bytes(&[0x01, 0x02, 0x03, 0x04, 0x05]) {
+            UInt::UInt64(num) => assert_eq!(num, 0x01_0203_0405),
+            other => panic!("unexpected value: {:?}", other),
+        }
+        match UInt::from_bytes(&[0xff; 7]) {
+            UInt::UInt64(num) => assert_eq!(num, 0x00ff_ffff_ffff_ffff),
+            other => panic!("unexpected value: {:?}", other),
+        }
+        match UInt::from_bytes(&[0x01, 0, 0, 0, 0, 0, 0, 0, 0x02]) {
+            UInt::UInt128(num) => assert_eq!(num, 0x01_0000_0000_0000_0002),
+            other => panic!("unexpected value: {:?}", other),
+        }
+
+        for len in [3, 5, 6, 7, 9, 15] {
+            let bytes: Vec<u8> = (1..=len as u8).collect();
+            assert_eq!(UInt::from_bytes(&bytes).to_bytes(len), bytes);
+        }
+    }
+}
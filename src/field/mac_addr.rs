@@ -1,7 +1,9 @@
+use std::fmt;
+
 // TODO: from_str and compare(?)
 // TODO: impl converter for Field
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct MacAddr {
     a: u8,
     b: u8,
@@ -27,3 +29,13 @@ impl MacAddr {
         [self.a, self.b, self.c, self.d, self.e, self.f]
     }
 }
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            self.a, self.b, self.c, self.d, self.e, self.f
+        )
+    }
+}
@@ -3,8 +3,7 @@
 mod field_types;
 #[allow(non_snake_case)]
 pub mod FieldTypes {
-    #[allow(unused_imports)]
-    use crate::field::field_types::*;
+    pub use crate::field::field_types::*;
 }
 
 #[allow(non_upper_case_globals)]
@@ -12,10 +11,12 @@ pub mod FieldTypes {
 mod scope_types;
 #[allow(non_snake_case)]
 pub mod ScopeTypes {
-    #[allow(unused_imports)]
-    use crate::field::scope_types::*;
+    pub use crate::field::scope_types::*;
 }
 
+mod elements;
+pub use self::elements::*;
+
 mod field_value;
 pub use self::field_value::*;
 
@@ -35,6 +36,9 @@ pub struct FlowField {
     type_id: u16,
     length: u16,
     value: FieldValue,
+    /// private enterprise number of an IPFIX enterprise-specific element
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    enterprise: Option<u32>,
 }
 
 impl FlowField {
@@ -43,9 +47,29 @@ impl FlowField {
             type_id,
             length,
             value,
+            enterprise: None,
         }
     }
 
+    pub fn with_enterprise(
+        type_id: u16,
+        length: u16,
+        enterprise: u32,
+        value: FieldValue,
+    ) -> FlowField {
+        FlowField {
+            type_id,
+            length,
+            value,
+            enterprise: Some(enterprise),
+        }
+    }
+
+    /// A field as long as the bytes, the value typed by the element.
+    pub fn with_bytes(type_id: u16, bytes: &[u8]) -> FlowField {
+        FlowField::new(type_id, bytes.len() as u16, FieldValue::new(type_id, bytes))
+    }
+
     pub fn from_bytes(type_id: u16, length: u16, bytes: &[u8]) -> ParseResult<FlowField> {
         if (length as usize) <= bytes.len() {
             Ok((
@@ -61,6 +85,22 @@ impl FlowField {
         }
     }
 
+    pub fn type_id(&self) -> u16 {
+        self.type_id
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn enterprise(&self) -> Option<u32> {
+        self.enterprise
+    }
+
+    pub fn value(&self) -> &FieldValue {
+        &self.value
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         self.value.to_bytes(self.length)
     }
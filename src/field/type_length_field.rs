@@ -11,11 +11,26 @@ named!(netflowfield <&[u8], TypeLengthField>,
 pub struct TypeLengthField {
     pub type_id: u16,
     pub length: u16,
+    /// private enterprise number of an IPFIX enterprise-specific element
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enterprise: Option<u32>,
 }
 
 impl TypeLengthField {
     pub fn new(type_id: u16, length: u16) -> TypeLengthField {
-        TypeLengthField { type_id, length }
+        TypeLengthField {
+            type_id,
+            length,
+            enterprise: None,
+        }
+    }
+
+    pub fn with_enterprise(type_id: u16, length: u16, enterprise: u32) -> TypeLengthField {
+        TypeLengthField {
+            type_id,
+            length,
+            enterprise: Some(enterprise),
+        }
     }
 
     pub fn parse_bytes(count: usize, data: &[u8]) -> ParseResult<Vec<TypeLengthField>> {
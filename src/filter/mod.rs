@@ -0,0 +1,849 @@
+use crate::error::NetFlowError;
+use crate::field::FieldTypes::*;
+use crate::field::{element, element_by_name, DataType, FieldValue, UInt};
+use crate::flowset::{FlowSet, Record};
+use crate::ipfix::{IpfixMessage, IpfixSet};
+use crate::netflow::NetFlow9;
+use std::cmp::Ordering;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    OpenList,
+    CloseList,
+    Op(Op),
+    Word(String),
+    Quoted(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::OpenList => write!(f, "["),
+            Token::CloseList => write!(f, "]"),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Quoted(s) => write!(f, "\"{}\"", s),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, NetFlowError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            _ if c.is_whitespace() => (),
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '[' => tokens.push(Token::OpenList),
+            ']' => tokens.push(Token::CloseList),
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => s.push(c),
+                        None => return Err(NetFlowError::invalid_filter("unterminated string")),
+                    }
+                }
+                tokens.push(Token::Quoted(s));
+            }
+            '=' | '!' | '<' | '>' => {
+                let equals = chars.peek() == Some(&'=');
+                if equals {
+                    chars.next();
+                }
+                let op = match (c, equals) {
+                    ('=', _) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    _ => return Err(NetFlowError::invalid_filter("unexpected !")),
+                };
+                tokens.push(Token::Op(op));
+            }
+            _ => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || "()[]\"=!<>".contains(*c) {
+                        break;
+                    }
+                    word.push(*c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn from_word(word: &str) -> Option<Op> {
+        match word {
+            "eq" => Some(Op::Eq),
+            "ne" => Some(Op::Ne),
+            "lt" => Some(Op::Lt),
+            "le" => Some(Op::Le),
+            "gt" => Some(Op::Gt),
+            "ge" => Some(Op::Ge),
+            _ => None,
+        }
+    }
+
+    fn test(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A value of the filter, typed by the field it is compared with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Literal {
+    Number(u128),
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+    /// address bits and prefix length
+    Net4(u32, u8),
+    Net6(u128, u8),
+    Mac([u8; 6]),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+/// Numbers may end with k, M or G, as 1000 multiples like nfdump.
+fn parse_number(word: &str) -> Option<u128> {
+    let (digits, scale) = match word.chars().last()? {
+        'k' | 'K' => (&word[..word.len() - 1], 1_000),
+        'm' | 'M' => (&word[..word.len() - 1], 1_000_000),
+        'g' | 'G' => (&word[..word.len() - 1], 1_000_000_000),
+        _ => (word, 1),
+    };
+    let number = match digits.strip_prefix("0x") {
+        Some(hex) => u128::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<u128>().ok()?,
+    };
+    number.checked_mul(scale)
+}
+
+fn parse_net(word: &str) -> Option<Literal> {
+    let (addr, prefix) = word.split_once('/')?;
+    let prefix: u8 = prefix.parse().ok()?;
+
+    match IpAddr::from_str(addr).ok()? {
+        IpAddr::V4(ip) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            Some(Literal::Net4(u32::from(ip) & mask, prefix))
+        }
+        IpAddr::V6(ip) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            Some(Literal::Net6(u128::from(ip) & mask, prefix))
+        }
+        _ => None,
+    }
+}
+
+fn parse_mac(word: &str) -> Option<[u8; 6]> {
+    let parts: Vec<&str> = word.split([':', '-']).collect();
+    if parts.len() != 6 {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    for (byte, part) in mac.iter_mut().zip(parts) {
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    Some(mac)
+}
+
+fn parse_bytes(word: &str) -> Option<Vec<u8>> {
+    let hex = word.strip_prefix("0x")?;
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn parse_address(word: &str) -> Option<Literal> {
+    if word.contains('/') {
+        return parse_net(word);
+    }
+    match IpAddr::from_str(word).ok()? {
+        IpAddr::V4(ip) => Some(Literal::Ipv4(ip)),
+        IpAddr::V6(ip) => Some(Literal::Ipv6(ip)),
+    }
+}
+
+fn protocol_number(word: &str) -> Option<u128> {
+    match word {
+        "icmp" => Some(1),
+        "tcp" => Some(6),
+        "udp" => Some(17),
+        "gre" => Some(47),
+        "esp" => Some(50),
+        "icmp6" | "ipv6-icmp" => Some(58),
+        "sctp" => Some(132),
+        _ => parse_number(word),
+    }
+}
+
+/// What a value is parsed as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Number,
+    Protocol,
+    Address,
+    Mac,
+    String,
+    /// an element missing from the registry or of a type without its own syntax
+    Any,
+}
+
+impl Kind {
+    fn of(type_id: u16) -> Kind {
+        match element(type_id).map(|element| element.data_type) {
+            Some(DataType::Unsigned8)
+            | Some(DataType::Unsigned16)
+            | Some(DataType::Unsigned32)
+            | Some(DataType::Unsigned64)
+            | Some(DataType::Boolean)
+            | Some(DataType::DateTimeSeconds)
+            | Some(DataType::DateTimeMilliseconds)
+            | Some(DataType::DateTimeMicroseconds)
+            | Some(DataType::DateTimeNanoseconds) => Kind::Number,
+            Some(DataType::Ipv4Address) | Some(DataType::Ipv6Address) => Kind::Address,
+            Some(DataType::MacAddress) => Kind::Mac,
+            Some(DataType::String) => Kind::String,
+            _ => Kind::Any,
+        }
+    }
+
+    fn parse(self, token: &Token) -> Option<Literal> {
+        let word = match token {
+            Token::Quoted(s) if self == Kind::String || self == Kind::Any => {
+                return Some(Literal::String(s.clone()))
+            }
+            Token::Word(word) => word.as_str(),
+            _ => return None,
+        };
+
+        match self {
+            Kind::Number => parse_number(word).map(Literal::Number),
+            Kind::Protocol => protocol_number(word).map(Literal::Number),
+            Kind::Address => parse_address(word),
+            Kind::Mac => parse_mac(word).map(Literal::Mac),
+            Kind::String => Some(Literal::String(word.to_string())),
+            Kind::Any => parse_bytes(word)
+                .map(Literal::Bytes)
+                .or_else(|| parse_number(word).map(Literal::Number))
+                .or_else(|| parse_address(word))
+                .or_else(|| parse_mac(word).map(Literal::Mac)),
+        }
+    }
+}
+
+/// Compare a field value with a literal, None when they do not compare.
+fn compare(value: &FieldValue, literal: &Literal) -> Option<Ordering> {
+    match (value, literal) {
+        (FieldValue::NumField(UInt::UInt128(num)), Literal::Number(n)) => Some(num.cmp(n)),
+        (FieldValue::NumField(_), Literal::Number(n))
+        | (FieldValue::ByteArray(_), Literal::Number(n))
+        | (FieldValue::Unknown(_), Literal::Number(n)) => {
+            value.to_u64().map(|num| u128::from(num).cmp(n))
+        }
+        (FieldValue::Ipv4Addr(ip), Literal::Ipv4(l)) => Some(ip.cmp(l)),
+        (FieldValue::Ipv6Addr(ip), Literal::Ipv6(l)) => Some(ip.cmp(l)),
+        (FieldValue::MacAddr(mac), Literal::Mac(l)) => Some(mac.octets()[..].cmp(&l[..])),
+        // exporters pad strings with NUL
+        (FieldValue::String(s), Literal::String(l)) => {
+            Some(s.trim_end_matches('\0').cmp(l.as_str()))
+        }
+        (FieldValue::ByteArray(bytes), Literal::Bytes(l))
+        | (FieldValue::Unknown(bytes), Literal::Bytes(l)) => Some(bytes.cmp(l)),
+        _ => None,
+    }
+}
+
+/// Whether an address field is in a network.
+fn in_net(value: &FieldValue, literal: &Literal) -> Option<bool> {
+    let mask4 = |prefix: u8| u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+    let mask6 = |prefix: u8| u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+
+    match (value, literal) {
+        (FieldValue::Ipv4Addr(ip), Literal::Net4(net, prefix)) => {
+            Some(u32::from(*ip) & mask4(*prefix) == *net)
+        }
+        (FieldValue::Ipv6Addr(ip), Literal::Net6(net, prefix)) => {
+            Some(u128::from(*ip) & mask6(*prefix) == *net)
+        }
+        _ => None,
+    }
+}
+
+fn test(value: &FieldValue, op: Op, literal: &Literal) -> bool {
+    match literal {
+        Literal::Net4(..) | Literal::Net6(..) => {
+            in_net(value, literal).is_some_and(|inside| inside == (op == Op::Eq))
+        }
+        _ => compare(value, literal).is_some_and(|ordering| op.test(ordering)),
+    }
+}
+
+/// Which fields of a record a test looks at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Fields {
+    /// the first of the ids present, e.g. IN_BYTES then OUT_BYTES
+    First(Vec<u16>),
+    /// any of the ids present, e.g. source or destination port
+    Any(Vec<u16>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Any,
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Compare(Fields, Op, Literal),
+    In(Fields, Vec<Literal>),
+}
+
+impl Node {
+    fn matches(&self, record: &Record) -> bool {
+        match self {
+            Node::Any => true,
+            Node::Not(node) => !node.matches(record),
+            Node::And(a, b) => a.matches(record) && b.matches(record),
+            Node::Or(a, b) => a.matches(record) || b.matches(record),
+            // `port != 80` needs a port, and no field of the record equal to the value
+            Node::Compare(fields, Op::Ne, literal) => {
+                let mut values = Node::values(fields, record).peekable();
+                values.peek().is_some() && !values.any(|value| test(value, Op::Eq, literal))
+            }
+            Node::Compare(fields, op, literal) => {
+                Node::values(fields, record).any(|value| test(value, *op, literal))
+            }
+            Node::In(fields, literals) => Node::values(fields, record)
+                .any(|value| literals.iter().any(|l| test(value, Op::Eq, l))),
+        }
+    }
+
+    fn values<'a>(
+        fields: &'a Fields,
+        record: &'a Record,
+    ) -> Box<dyn Iterator<Item = &'a FieldValue> + 'a> {
+        match fields {
+            Fields::First(ids) => Box::new(
+                ids.iter()
+                    .filter_map(move |id| record.get(*id))
+                    .map(|field| field.value())
+                    .take(1),
+            ),
+            Fields::Any(ids) => Box::new(
+                record
+                    .fields()
+                    .filter(move |field| {
+                        ids.contains(&field.type_id()) && field.enterprise().is_none()
+                    })
+                    .map(|field| field.value()),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Src,
+    Dst,
+    Either,
+}
+
+impl Direction {
+    fn pick(self, src: &[u16], dst: &[u16]) -> Fields {
+        match self {
+            Direction::Src => Fields::Any(src.to_vec()),
+            Direction::Dst => Fields::Any(dst.to_vec()),
+            Direction::Either => Fields::Any(src.iter().chain(dst).cloned().collect()),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, NetFlowError> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| NetFlowError::invalid_filter("unexpected end of filter"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn next_word(&mut self) -> Result<String, NetFlowError> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            token => Err(NetFlowError::invalid_filter(format!(
+                "unexpected {}",
+                token
+            ))),
+        }
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if self.peek() == Some(&Token::Word(word.to_string())) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Node, NetFlowError> {
+        let mut node = self.and()?;
+        while self.eat_word("or") || self.eat_word("||") {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, NetFlowError> {
+        let mut node = self.not()?;
+        while self.eat_word("and") || self.eat_word("&&") {
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, NetFlowError> {
+        if self.eat_word("not") {
+            return Ok(Node::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.position += 1;
+            let node = self.or()?;
+            return match self.next()? {
+                Token::Close => Ok(node),
+                token => Err(NetFlowError::invalid_filter(format!(
+                    "unexpected {}, ) expected",
+                    token
+                ))),
+            };
+        }
+        self.predicate()
+    }
+
+    fn literal(&mut self, kind: Kind) -> Result<Literal, NetFlowError> {
+        let token = self.next()?;
+        kind.parse(&token)
+            .ok_or_else(|| NetFlowError::invalid_filter(format!("invalid value {}", token)))
+    }
+
+    /// `[op] value` or `in [value...]` for the fields.
+    fn test(&mut self, fields: Fields, kind: Kind) -> Result<Node, NetFlowError> {
+        if self.eat_word("in") {
+            match self.next()? {
+                Token::OpenList => (),
+                token => {
+                    return Err(NetFlowError::invalid_filter(format!(
+                        "unexpected {}, [ expected",
+                        token
+                    )))
+                }
+            }
+            let mut literals = Vec::new();
+            while self.peek() != Some(&Token::CloseList) {
+                literals.push(self.literal(kind)?);
+            }
+            self.position += 1;
+            if literals.iter().any(is_net) {
+                return Err(NetFlowError::invalid_filter(
+                    "networks are not allowed in lists",
+                ));
+            }
+            return Ok(Node::In(fields, literals));
+        }
+
+        let op = match self.peek() {
+            Some(Token::Op(op)) => Some(*op),
+            Some(Token::Word(word)) => Op::from_word(word),
+            _ => None,
+        };
+        if op.is_some() {
+            self.position += 1;
+        }
+        let op = op.unwrap_or(Op::Eq);
+
+        let literal = self.literal(kind)?;
+        if is_net(&literal) && op != Op::Eq && op != Op::Ne {
+            return Err(NetFlowError::invalid_filter(format!(
+                "a network does not compare with {}",
+                op
+            )));
+        }
+        Ok(Node::Compare(fields, op, literal))
+    }
+
+    fn predicate(&mut self) -> Result<Node, NetFlowError> {
+        let direction = if self.eat_word("src") {
+            Direction::Src
+        } else if self.eat_word("dst") {
+            Direction::Dst
+        } else {
+            Direction::Either
+        };
+        let word = self.next_word()?;
+
+        let directed = |src: &[u16], dst: &[u16]| direction.pick(src, dst);
+        match word.as_str() {
+            "ip" | "host" | "net" => {
+                let fields = directed(
+                    &[IPV4_SRC_ADDR, IPV6_SRC_ADDR],
+                    &[IPV4_DST_ADDR, IPV6_DST_ADDR],
+                );
+                let literal = self.literal(Kind::Address)?;
+                if word == "net" && !is_net(&literal) {
+                    return Err(NetFlowError::invalid_filter("net needs a prefix length"));
+                }
+                Ok(Node::Compare(fields, Op::Eq, literal))
+            }
+            "port" => self.test(directed(&[L4_SRC_PORT], &[L4_DST_PORT]), Kind::Number),
+            "as" => self.test(directed(&[SRC_AS], &[DST_AS]), Kind::Number),
+            _ if direction != Direction::Either => Err(NetFlowError::invalid_filter(format!(
+                "{} after a direction",
+                word
+            ))),
+            "any" => Ok(Node::Any),
+            "proto" => self.test(Fields::Any(vec![PROTOCOL]), Kind::Protocol),
+            "bytes" => self.test(Fields::First(vec![IN_BYTES, OUT_BYTES]), Kind::Number),
+            "packets" => self.test(Fields::First(vec![IN_PKTS, OUT_PKTS]), Kind::Number),
+            "flows" => self.test(Fields::First(vec![FLOWS]), Kind::Number),
+            "tos" => self.test(Fields::Any(vec![TOS]), Kind::Number),
+            _ => {
+                // any element by IANA name or id
+                let type_id = match word.parse::<u16>() {
+                    Ok(type_id) => type_id,
+                    Err(_) => {
+                        element_by_name(&word)
+                            .ok_or_else(|| {
+                                NetFlowError::invalid_filter(format!("unknown field {}", word))
+                            })?
+                            .id
+                    }
+                };
+                self.test(Fields::Any(vec![type_id]), Kind::of(type_id))
+            }
+        }
+    }
+}
+
+fn is_net(literal: &Literal) -> bool {
+    matches!(literal, Literal::Net4(..) | Literal::Net6(..))
+}
+
+/// A parsed filter expression in the style of nfdump, e.g.
+/// `src net 10.0.0.0/8 and proto tcp and dst port in [80 443] and bytes > 1M`.
+///
+/// Predicates are `[src|dst] ip ADDR`, `[src|dst] net CIDR`, `[src|dst] port`,
+/// `[src|dst] as`, `proto`, `bytes`, `packets`, `flows`, `tos` and any element by IANA name
+/// or id, e.g. `interfaceName "eth0"` or `61 = 1`. Numbers take an optional operator
+/// (`= != < <= > >=` or `eq ne lt le gt ge`) and a k, M or G suffix, and `in [...]` matches
+/// any of the values. Predicates combine with `and`, `or`, `not` and parentheses.
+///
+/// A predicate on a field the record lacks is false, `!=` included, while `not` of it is
+/// true. Without a direction, addresses, ports and AS numbers match either side, and `!=`
+/// holds when no side is equal.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    source: String,
+    root: Node,
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Filter, NetFlowError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        if parser.tokens.is_empty() {
+            return Err(NetFlowError::invalid_filter("empty filter"));
+        }
+
+        let root = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(NetFlowError::invalid_filter(format!(
+                "unexpected {}",
+                token
+            )));
+        }
+
+        Ok(Filter {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    /// The expression the filter was parsed from.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the record passes, option records always do.
+    pub fn matches(&self, record: &Record) -> bool {
+        match record {
+            Record::OptionData(_) => true,
+            Record::Data(_) => self.root.matches(record),
+        }
+    }
+
+    fn retain(&self, records: &mut Option<Vec<Record>>) -> usize {
+        match records {
+            Some(records) => {
+                let before = records.len();
+                records.retain(|record| self.matches(record));
+                before - records.len()
+            }
+            None => 0,
+        }
+    }
+
+    /// Drop the data records of a decoded v9 packet that do not pass, and the dataflows
+    /// left empty, and count the records left in the header. Returns the number of records
+    /// dropped.
+    pub fn filter_netflow9(&self, netflow: &mut NetFlow9) -> usize {
+        let mut dropped = 0;
+        for flowset in &mut netflow.flow_sets {
+            if let FlowSet::DataFlow(dataflow) = flowset {
+                dropped += self.retain(&mut dataflow.records);
+            }
+        }
+        netflow.flow_sets.retain(|flowset| match flowset {
+            FlowSet::DataFlow(dataflow) => dataflow.records.as_ref().is_none_or(|r| !r.is_empty()),
+            _ => true,
+        });
+        netflow.count = netflow
+            .record_count()
+            .unwrap_or_else(|| netflow.count.saturating_sub(dropped as u16));
+        dropped
+    }
+
+    /// Drop the data records of a decoded IPFIX message that do not pass, and the data sets
+    /// left empty. Returns the number of records dropped.
+    pub fn filter_ipfix(&self, message: &mut IpfixMessage) -> usize {
+        let mut dropped = 0;
+        for set in &mut message.sets {
+            if let IpfixSet::Data(data) = set {
+                dropped += self.retain(&mut data.records);
+            }
+        }
+        message.sets.retain(|set| match set {
+            IpfixSet::Data(data) => data.records.as_ref().is_none_or(|r| !r.is_empty()),
+            _ => true,
+        });
+        dropped
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = NetFlowError;
+
+    fn from_str(s: &str) -> Result<Filter, NetFlowError> {
+        Filter::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod test_filter {
+    use super::*;
+    use crate::field::{FlowField, MacAddr};
+    use crate::flowset::DataTemplateItem;
+
+    fn record(src: [u8; 4], dst_port: u16, protocol: u8, bytes: u32) -> Record {
+        DataTemplateItem::builder(256)
+            .field(IPV4_SRC_ADDR)
+            .field(IPV4_DST_ADDR)
+            .field(L4_SRC_PORT)
+            .field(L4_DST_PORT)
+            .field(PROTOCOL)
+            .field_len(IN_BYTES, 4)
+            .field(SRC_MAC)
+            .field_len(IF_NAME, 8)
+            .build()
+            .unwrap()
+            .record_builder()
+            .set(IPV4_SRC_ADDR, Ipv4Addr::from(src))
+            .set(IPV4_DST_ADDR, Ipv4Addr::new(192, 0, 2, 1))
+            .set(L4_SRC_PORT, 40000u16)
+            .set(L4_DST_PORT, dst_port)
+            .set(PROTOCOL, protocol)
+            .set(IN_BYTES, bytes)
+            .set(SRC_MAC, MacAddr::new(0, 0x11, 0x22, 0x33, 0x44, 0x55))
+            .set(IF_NAME, "eth0")
+            .build()
+            .unwrap()
+    }
+
+    fn matches(filter: &str, record: &Record) -> bool {
+        Filter::parse(filter).unwrap().matches(record)
+    }
+
+    #[test]
+    fn test_matches() {
+        let web = record([10, 1, 2, 3], 443, 6, 2_000_000);
+        let dns = record([172, 16, 0, 1], 53, 17, 100);
+
+        let filter = "src net 10.0.0.0/8 and proto tcp and dst port in [80 443] and bytes > 1M";
+        assert!(matches(filter, &web));
+        assert!(!matches(filter, &dns));
+
+        assert!(matches("ip 192.0.2.1", &web));
+        assert!(!matches("src ip 192.0.2.1", &web));
+        assert!(matches("net 192.0.2.0/24 and not net 10.0.0.0/8", &dns));
+        assert!(matches("port 40000", &dns));
+        assert!(matches("port != 80", &dns));
+        assert!(!matches("port != 53", &dns));
+        assert!(matches("dst port < 100 or bytes ge 1k", &dns));
+        assert!(matches("proto udp or (proto tcp and dst port 22)", &dns));
+        assert!(matches("not (proto tcp)", &dns));
+        assert!(matches("any", &dns));
+
+        // fields by name or id, strings, MAC addresses and bytes
+        assert!(matches("interfaceName \"eth0\"", &dns));
+        assert!(matches("interfaceName = eth0", &dns));
+        assert!(!matches("interfaceName eth1", &dns));
+        assert!(matches("sourceMacAddress 00:11:22:33:44:55", &dns));
+        assert!(matches("8 = 172.16.0.1", &dns));
+        assert!(matches("sourceIPv4Address > 172.15.255.255", &dns));
+
+        // missing fields never match
+        assert!(!matches("src as 64500", &dns));
+        assert!(matches("not src as 64500", &dns));
+        let raw = Record::make_data(vec![FlowField::new(
+            32000,
+            2,
+            FieldValue::ByteArray(vec![0xab, 0xcd]),
+        )]);
+        assert!(matches("32000 = 0xabcd", &raw));
+        assert!(matches("32000 > 100", &raw));
+        assert!(!matches("bytes != 5", &raw));
+        assert!(!matches("src as != 64500", &dns));
+        assert!(matches("not bytes 5", &raw));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |filter: &str| match Filter::parse(filter) {
+            Err(NetFlowError::InvalidFilter { desc }) => desc,
+            other => panic!("unexpected result: {:?}", other),
+        };
+
+        assert_eq!(error(""), "empty filter");
+        assert_eq!(error("proto tcp and"), "unexpected end of filter");
+        assert_eq!(error("proto tcp udp"), "unexpected udp");
+        assert_eq!(error("(proto tcp"), "unexpected end of filter");
+        assert_eq!(error("port http"), "invalid value http");
+        assert_eq!(error("noSuchField 1"), "unknown field noSuchField");
+        assert_eq!(error("src proto tcp"), "proto after a direction");
+        assert_eq!(error("src net 10.0.0.0/8 > 1"), "unexpected >");
+        assert_eq!(
+            error("sourceIPv4Address > 10.0.0.0/8"),
+            "a network does not compare with >"
+        );
+        assert_eq!(error("interfaceName \"eth0"), "unterminated string");
+    }
+
+    #[test]
+    fn test_filter_netflow9() {
+        use crate::flowset::{DataFlow, DataTemplate};
+
+        let template = DataTemplateItem::builder(256)
+            .field(PROTOCOL)
+            .build()
+            .unwrap();
+        let proto = |protocol: u8| {
+            template
+                .record_builder()
+                .set(PROTOCOL, protocol)
+                .build()
+                .unwrap()
+        };
+        let mut netflow = NetFlow9::new(
+            0,
+            0,
+            0,
+            0,
+            vec![
+                DataTemplate::new(vec![template.clone()]).into(),
+                DataFlow::new(256, vec![proto(6), proto(17)]).into(),
+                DataFlow::new(256, vec![proto(17)]).into(),
+            ],
+        );
+
+        assert_eq!(netflow.count, 4);
+
+        let filter: Filter = "proto tcp".parse().unwrap();
+        assert_eq!(filter.filter_netflow9(&mut netflow), 2);
+        assert_eq!(netflow.flow_sets.len(), 2);
+        // the template and the tcp record
+        assert_eq!(netflow.count, 2);
+        assert!(netflow.check_count().is_ok());
+        assert_eq!(filter.to_string(), "proto tcp");
+    }
+}
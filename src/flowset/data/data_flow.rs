@@ -39,6 +39,11 @@ impl DataFlow {
 
         let (rest, flowset_id) = take_u16(&data)?;
         let (rest, length) = take_u16(&rest)?;
+
+        if length < Self::HEADER_LEN || rest.len() < (length - Self::HEADER_LEN) as usize {
+            return Err(NetFlowError::InvalidLength);
+        }
+
         let record_len = (length - Self::HEADER_LEN) as usize;
         let record_bytes = &rest[..record_len];
         let rest = &rest[record_len..];
@@ -100,6 +105,23 @@ impl DataFlow {
         }
     }
 
+    /// Decode records of a dataflow parsed by from_bytes_notemplate.
+    /// Records already decoded are replaced.
+    pub fn apply_template<T>(&mut self, template: &T) -> Result<(), NetFlowError>
+    where
+        T: TemplateParser,
+    {
+        if template.get_id() != self.flowset_id || template.get_template_len() == 0 {
+            return Err(NetFlowError::TemplateNotFound);
+        }
+
+        let (_rest, records) =
+            template.parse_dataflows(self.length - Self::HEADER_LEN, &self.record_bytes)?;
+        self.records = Some(records);
+
+        Ok(())
+    }
+
     fn get_padding_size(payload_len: u16, template_len: u16) -> u16 {
         (payload_len - Self::HEADER_LEN) % template_len
     }
@@ -178,6 +200,27 @@ mod test_data_flow {
         // TODO: add field value test
     }
 
+    #[test]
+    fn from_bytes_notemplate_invalid_length() {
+        let packet_bytes = [0x04, 0x00, 0x00, 0x02];
+        assert!(DataFlow::from_bytes_notemplate(&packet_bytes).is_err());
+
+        let packet_bytes = [0x04, 0x00, 0x00, 0x10, 0x00, 0x00];
+        assert!(DataFlow::from_bytes_notemplate(&packet_bytes).is_err());
+    }
+
+    #[test]
+    fn apply_template() {
+        let (test_template, testdata) = test_data::TEMPLATE_AND_DATA;
+        let template = DataTemplate::from_bytes(&test_template).unwrap().1;
+        let mut dataflow = DataFlow::from_bytes_notemplate(&testdata).unwrap().1;
+        assert!(dataflow.records.is_none());
+
+        dataflow.apply_template(&template.templates[0]).unwrap();
+        assert_eq!(dataflow.records.as_ref().unwrap().len(), 8);
+        assert_eq!(&dataflow.to_bytes().as_slice(), &testdata.as_ref());
+    }
+
     #[test]
     fn from_bytes() {
         let (test_template, testdata) = test_data::TEMPLATE_AND_DATA;
@@ -3,3 +3,6 @@ pub use self::data_flow::*;
 
 mod record;
 pub use self::record::*;
+
+mod record_builder;
+pub use self::record_builder::*;
@@ -24,6 +24,29 @@ impl Record {
         }
     }
 
+    /// Iterate every field, scope fields come first in option records.
+    pub fn fields(&self) -> impl Iterator<Item = &FlowField> {
+        let (first, second): (&[FlowField], &[FlowField]) = match self {
+            Record::Data(data) => (&data.fields, &[]),
+            Record::OptionData(option) => (&option.scope_fields, &option.option_fields),
+        };
+
+        first.iter().chain(second.iter())
+    }
+
+    /// Return the first field with the type id, enterprise fields are skipped as their
+    /// ids are not IANA ones.
+    pub fn get(&self, type_id: u16) -> Option<&FlowField> {
+        self.fields()
+            .find(|field| field.type_id() == type_id && field.enterprise().is_none())
+    }
+
+    /// Return the first field with the type id of the enterprise.
+    pub fn get_enterprise(&self, enterprise: u32, type_id: u16) -> Option<&FlowField> {
+        self.fields()
+            .find(|field| field.type_id() == type_id && field.enterprise() == Some(enterprise))
+    }
+
     // TODO: Need convertor?
 
     pub fn byte_length(&self) -> usize {
@@ -42,6 +65,10 @@ impl DataRecord {
         DataRecord { fields }
     }
 
+    pub fn fields(&self) -> &[FlowField] {
+        &self.fields
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
@@ -72,6 +99,14 @@ impl OptionRecord {
         }
     }
 
+    pub fn scope_fields(&self) -> &[FlowField] {
+        &self.scope_fields
+    }
+
+    pub fn option_fields(&self) -> &[FlowField] {
+        &self.option_fields
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
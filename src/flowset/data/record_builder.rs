@@ -0,0 +1,285 @@
+use crate::error::NetFlowError;
+use crate::field::{element, element_by_name, element_name, DataType, FieldValue, FlowField, UInt};
+use crate::flowset::{DataTemplateItem, Record};
+
+/// Big endian bytes of a number without leading zeros.
+fn significant_bytes(uint: &UInt) -> Vec<u8> {
+    let bytes = match uint {
+        UInt::UIntFlex(array) => array.clone(),
+        _ => uint.to_bytes(16),
+    };
+    let first = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    bytes[first..].to_vec()
+}
+
+fn is_numeric(data_type: DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Unsigned8
+            | DataType::Unsigned16
+            | DataType::Unsigned32
+            | DataType::Unsigned64
+            | DataType::Boolean
+            | DataType::DateTimeSeconds
+            | DataType::DateTimeMilliseconds
+            | DataType::DateTimeMicroseconds
+            | DataType::DateTimeNanoseconds
+    )
+}
+
+/// The bytes of the value in a field of the length, or why it does not fit.
+fn encode(type_id: u16, length: u16, value: &FieldValue) -> Result<Vec<u8>, String> {
+    let length = length as usize;
+    // elements missing from the registry take any kind of value
+    let data_type = element(type_id).map(|element| element.data_type);
+    let expect = |expected: DataType| match data_type {
+        Some(data_type) if data_type != expected => Err(format!("{:?} expected", data_type)),
+        _ => Ok(()),
+    };
+
+    let bytes = match value {
+        FieldValue::NumField(uint) => {
+            match data_type {
+                Some(data_type) if !is_numeric(data_type) => {
+                    return Err(format!("{:?} expected", data_type));
+                }
+                _ => (),
+            }
+            let significant = significant_bytes(uint);
+            if significant.len() > length {
+                return Err(format!("number does not fit in {} bytes", length));
+            }
+            let mut bytes = vec![0u8; length - significant.len()];
+            bytes.extend_from_slice(&significant);
+            bytes
+        }
+        FieldValue::Ipv4Addr(ip) => {
+            expect(DataType::Ipv4Address)?;
+            ip.octets().to_vec()
+        }
+        FieldValue::Ipv6Addr(ip) => {
+            expect(DataType::Ipv6Address)?;
+            ip.octets().to_vec()
+        }
+        FieldValue::MacAddr(mac) => {
+            expect(DataType::MacAddress)?;
+            mac.octets().to_vec()
+        }
+        FieldValue::String(s) => {
+            expect(DataType::String)?;
+            // shorter strings are padded with NUL like exporters do in fixed length fields
+            if s.len() > length {
+                return Err(format!("string does not fit in {} bytes", length));
+            }
+            let mut bytes = s.clone().into_bytes();
+            bytes.resize(length, 0);
+            bytes
+        }
+        FieldValue::ByteArray(array) | FieldValue::Unknown(array) => array.clone(),
+    };
+
+    if bytes.len() != length {
+        return Err(format!(
+            "{} bytes value for a {} bytes field",
+            bytes.len(),
+            length
+        ));
+    }
+
+    Ok(bytes)
+}
+
+/// Builds a data record of a template, checking each value against the field it fills.
+///
+/// Values are set by field id or IANA name and may come in any order. Numbers must fit the
+/// field length, addresses and byte arrays must match it, and values must suit the data type
+/// of the element. `build` fails if a field of the template was left unset, so the record
+/// always serializes to the record length of the template.
+#[derive(Debug)]
+pub struct RecordBuilder<'a> {
+    template: &'a DataTemplateItem,
+    values: Vec<Option<FlowField>>,
+    error: Option<NetFlowError>,
+}
+
+impl<'a> RecordBuilder<'a> {
+    pub fn new(template: &'a DataTemplateItem) -> RecordBuilder<'a> {
+        RecordBuilder {
+            template,
+            values: vec![None; template.fields.len()],
+            error: None,
+        }
+    }
+
+    fn try_set(&mut self, type_id: u16, value: FieldValue) -> Result<(), NetFlowError> {
+        let fields = &self.template.fields;
+        let name = element_name(type_id);
+
+        // a template may repeat an id, values fill its fields in order
+        let index = (0..fields.len())
+            .find(|i| fields[*i].type_id == type_id && self.values[*i].is_none())
+            .ok_or_else(|| {
+                if fields.iter().any(|field| field.type_id == type_id) {
+                    NetFlowError::invalid_record(format!("{} is set more than once", name))
+                } else {
+                    NetFlowError::invalid_record(format!(
+                        "{} is not in template {}",
+                        name, self.template.template_id
+                    ))
+                }
+            })?;
+
+        let length = fields[index].length;
+        let bytes = encode(type_id, length, &value)
+            .map_err(|desc| NetFlowError::invalid_record(format!("{}: {}", name, desc)))?;
+
+        // numbers stay numbers for elements missing from the registry
+        let value = match value {
+            FieldValue::NumField(_) => FieldValue::NumField(UInt::from_bytes(&bytes)),
+            _ => FieldValue::new(type_id, &bytes),
+        };
+        self.values[index] = Some(FlowField::new(type_id, length, value));
+
+        Ok(())
+    }
+
+    /// Set the value of the field with the id.
+    pub fn set<V: Into<FieldValue>>(mut self, type_id: u16, value: V) -> Self {
+        if self.error.is_none() {
+            self.error = self.try_set(type_id, value.into()).err();
+        }
+        self
+    }
+
+    /// Set the value of the field with the IANA name, e.g. "sourceIPv4Address".
+    pub fn set_by_name<V: Into<FieldValue>>(mut self, name: &str, value: V) -> Self {
+        if self.error.is_none() {
+            self.error = match element_by_name(name) {
+                Some(element) => self.try_set(element.id, value.into()).err(),
+                None => Some(NetFlowError::invalid_record(format!(
+                    "unknown element {}",
+                    name
+                ))),
+            };
+        }
+        self
+    }
+
+    /// Return the record, or the first error met while setting values.
+    pub fn build(self) -> Result<Record, NetFlowError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let missing: Vec<String> = self
+            .template
+            .fields
+            .iter()
+            .zip(&self.values)
+            .filter(|(_, value)| value.is_none())
+            .map(|(field, _)| element_name(field.type_id))
+            .collect();
+        if !missing.is_empty() {
+            return Err(NetFlowError::invalid_record(format!(
+                "missing {}",
+                missing.join(", ")
+            )));
+        }
+
+        Ok(Record::make_data(
+            self.values.into_iter().map(Option::unwrap).collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test_record_builder {
+    use super::*;
+    use crate::field::FieldTypes::*;
+    use crate::field::TypeLengthField;
+    use crate::flowset::TemplateParser;
+    use std::net::Ipv4Addr;
+
+    fn template() -> DataTemplateItem {
+        DataTemplateItem::new(
+            256,
+            vec![
+                TypeLengthField::new(IPV4_SRC_ADDR, 4),
+                TypeLengthField::new(L4_SRC_PORT, 2),
+                TypeLengthField::new(IN_BYTES, 3),
+                TypeLengthField::new(TCP_FLAGS, 1),
+                TypeLengthField::new(IF_NAME, 8),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_build() {
+        let template = template();
+        let record = template
+            .record_builder()
+            .set_by_name("interfaceName", "eth0")
+            .set(IN_BYTES, 70_000u32)
+            .set(IPV4_SRC_ADDR, Ipv4Addr::new(192, 0, 2, 1))
+            .set(L4_SRC_PORT, 2055u16)
+            .set(TCP_FLAGS, 0x12u16)
+            .build()
+            .unwrap();
+
+        let bytes = record.to_bytes();
+        assert_eq!(bytes.len(), template.get_template_len() as usize);
+        assert_eq!(&bytes[6..9], &[0x01, 0x11, 0x70]);
+
+        let (_, parsed) = template.parse_dataflow(&bytes).unwrap();
+        assert_eq!(parsed.get(IN_BYTES).unwrap().value().to_u64(), Some(70_000));
+        assert_eq!(parsed.get(TCP_FLAGS).unwrap().value().to_u64(), Some(0x12));
+        assert_eq!(parsed.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let template = template();
+        let error = |builder: RecordBuilder| match builder.build() {
+            Err(NetFlowError::InvalidRecord { desc }) => desc,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        let complete = || {
+            template
+                .record_builder()
+                .set(IPV4_SRC_ADDR, Ipv4Addr::new(192, 0, 2, 1))
+                .set(L4_SRC_PORT, 2055u16)
+                .set(IN_BYTES, 1u8)
+                .set(TCP_FLAGS, 0u8)
+        };
+
+        assert_eq!(error(complete()), "missing interfaceName");
+        assert_eq!(
+            error(complete().set(IN_BYTES, 1u8)),
+            "octetDeltaCount is set more than once"
+        );
+        assert_eq!(
+            error(complete().set(IN_PKTS, 1u8)),
+            "packetDeltaCount is not in template 256"
+        );
+        assert_eq!(
+            error(complete().set(IF_NAME, "too long name")),
+            "interfaceName: string does not fit in 8 bytes"
+        );
+        assert_eq!(
+            error(template.record_builder().set(L4_SRC_PORT, 70_000u32)),
+            "sourceTransportPort: number does not fit in 2 bytes"
+        );
+        assert_eq!(
+            error(template.record_builder().set(IPV4_SRC_ADDR, 1u32)),
+            "sourceIPv4Address: Ipv4Address expected"
+        );
+        assert_eq!(
+            error(template.record_builder().set(IF_NAME, vec![0u8; 4])),
+            "interfaceName: 4 bytes value for a 8 bytes field"
+        );
+        assert_eq!(
+            error(template.record_builder().set_by_name("noSuchElement", 1u8)),
+            "unknown element noSuchElement"
+        );
+    }
+}
@@ -97,6 +97,16 @@ impl FlowSet {
     pub fn byte_length(&self) -> usize {
         self.to_bytes().len()
     }
+
+    /// Number of records counted by the packet header: template records, the option template record
+    /// or data records. None for a dataflow not decoded yet.
+    pub fn record_count(&self) -> Option<usize> {
+        match self {
+            FlowSet::DataTemplate(template) => Some(template.templates.len()),
+            FlowSet::OptionTemplate(_) => Some(1),
+            FlowSet::DataFlow(dataflow) => dataflow.records.as_ref().map(Vec::len),
+        }
+    }
 }
 
 impl From<DataFlow> for FlowSet {
@@ -3,3 +3,6 @@ pub use self::option_template::*;
 
 mod option_template_item;
 pub use self::option_template_item::*;
+
+mod option_template_builder;
+pub use self::option_template_builder::*;
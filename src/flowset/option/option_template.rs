@@ -38,6 +38,11 @@ impl OptionTemplate {
 
         if flowset_id == OPTION_FLOWSET_ID {
             let (rest, length) = take_u16(&rest)?;
+
+            if length < Self::HEADER_LEN + OptionTemplateItem::get_header_len() {
+                return Err(NetFlowError::InvalidLength);
+            }
+
             let (rest, option_item) = OptionTemplateItem::from_bytes(length - 4, rest)?;
             // if payload length is not multiple of 4 and length is multiple of 4, padding exists
             let is_padding =
@@ -0,0 +1,138 @@
+use crate::error::NetFlowError;
+use crate::field::ScopeTypes;
+use crate::field::TypeLengthField;
+use crate::flowset::{field_id, template_field, OptionTemplateItem, MIN_TEMPLATE_ID};
+
+/// Builds an option template, the scopes and options may be added in any order.
+///
+/// Scopes are the Netflow v9 scope types, see `ScopeTypes`, and have no default length.
+/// Options are checked like the fields of `TemplateBuilder`.
+#[derive(Debug)]
+pub struct OptionTemplateBuilder {
+    template_id: u16,
+    scopes: Vec<TypeLengthField>,
+    options: Vec<TypeLengthField>,
+    error: Option<NetFlowError>,
+}
+
+impl OptionTemplateBuilder {
+    pub fn new(template_id: u16) -> OptionTemplateBuilder {
+        OptionTemplateBuilder {
+            template_id,
+            scopes: Vec::new(),
+            options: Vec::new(),
+            error: None,
+        }
+    }
+
+    fn push(mut self, option: Result<TypeLengthField, NetFlowError>) -> Self {
+        if self.error.is_none() {
+            match option {
+                Ok(option) => self.options.push(option),
+                Err(error) => self.error = Some(error),
+            }
+        }
+        self
+    }
+
+    /// Add a scope of the type, e.g. `ScopeTypes::Interface`, with the length.
+    pub fn scope(mut self, scope_type: u16, length: u16) -> Self {
+        if self.error.is_none() {
+            if !(ScopeTypes::System..=ScopeTypes::Template).contains(&scope_type) {
+                self.error = Some(NetFlowError::invalid_template(format!(
+                    "unknown scope type {}",
+                    scope_type
+                )));
+            } else if length == 0 {
+                self.error = Some(NetFlowError::invalid_template(format!(
+                    "scope {} needs a length",
+                    scope_type
+                )));
+            } else {
+                self.scopes.push(TypeLengthField::new(scope_type, length));
+            }
+        }
+        self
+    }
+
+    /// Add an option field with its default length.
+    pub fn field(self, type_id: u16) -> Self {
+        self.push(template_field(type_id, None))
+    }
+
+    /// Add an option field with the length.
+    pub fn field_len(self, type_id: u16, length: u16) -> Self {
+        self.push(template_field(type_id, Some(length)))
+    }
+
+    /// Add an option field by IANA name with its default length.
+    pub fn field_by_name(self, name: &str) -> Self {
+        self.push(field_id(name).and_then(|id| template_field(id, None)))
+    }
+
+    /// Add an option field by IANA name with the length.
+    pub fn field_len_by_name(self, name: &str, length: u16) -> Self {
+        self.push(field_id(name).and_then(|id| template_field(id, Some(length))))
+    }
+
+    /// Return the template, or the first invalid scope or field.
+    pub fn build(self) -> Result<OptionTemplateItem, NetFlowError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if self.template_id < MIN_TEMPLATE_ID {
+            return Err(NetFlowError::invalid_template(format!(
+                "template id {} is below {}",
+                self.template_id, MIN_TEMPLATE_ID
+            )));
+        }
+        if self.scopes.is_empty() {
+            return Err(NetFlowError::invalid_template("no scopes"));
+        }
+
+        Ok(OptionTemplateItem::new(
+            self.template_id,
+            self.scopes,
+            self.options,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test_option_template_builder {
+    use super::*;
+    use crate::field::FieldTypes::*;
+
+    #[test]
+    fn test_build() {
+        let template = OptionTemplateItem::builder(257)
+            .scope(ScopeTypes::System, 4)
+            .field(SAMPLING_INTERVAL)
+            .field(SAMPLING_ALGORITHM)
+            .field_len_by_name("samplerName", 32)
+            .build()
+            .unwrap();
+
+        assert_eq!(template.scope_count, 1);
+        let lengths: Vec<u16> = template.options.iter().map(|f| f.length).collect();
+        assert_eq!(lengths, vec![4, 1, 32]);
+
+        let bytes = template.to_bytes();
+        let (_, parsed) = OptionTemplateItem::from_bytes(bytes.len() as u16, &bytes).unwrap();
+        assert_eq!(parsed.options.len(), 3);
+
+        match OptionTemplateItem::builder(257)
+            .field(SAMPLING_INTERVAL)
+            .build()
+        {
+            Err(NetFlowError::InvalidTemplate { desc }) => assert_eq!(desc, "no scopes"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match OptionTemplateItem::builder(257).scope(9, 4).build() {
+            Err(NetFlowError::InvalidTemplate { desc }) => {
+                assert_eq!(desc, "unknown scope type 9")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::error::{NetFlowError, ParseResult};
 use crate::field::{FlowField, TypeLengthField};
-use crate::flowset::{Record, TemplateParser};
+use crate::flowset::{OptionTemplateBuilder, Record, TemplateParser};
 use crate::util::{take_u16, u16_to_bytes};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +29,11 @@ impl OptionTemplateItem {
         }
     }
 
+    /// Start an option template with the id, see `OptionTemplateBuilder`.
+    pub fn builder(template_id: u16) -> OptionTemplateBuilder {
+        OptionTemplateBuilder::new(template_id)
+    }
+
     /// Return OptionTemplateItem from data
     /// length is OptionTemplateItem's length, not OptionTemplate's
     /// validate with length, need this?
@@ -38,7 +43,10 @@ impl OptionTemplateItem {
         let (rest, option_length) = take_u16(&rest)?;
 
         // TODO: need this check?
-        if (length - OptionTemplateItem::HEADER_LEN) >= (scope_length + option_length) {
+        if length >= OptionTemplateItem::HEADER_LEN
+            && u32::from(length - OptionTemplateItem::HEADER_LEN)
+                >= u32::from(scope_length) + u32::from(option_length)
+        {
             let scope_count = scope_length / 4; // TODO: remove mgk num
             let (rest, scopes): (&[u8], Vec<TypeLengthField>) =
                 TypeLengthField::parse_bytes(scope_count as usize, &rest)?;
@@ -30,6 +30,11 @@ impl DataTemplate {
     pub fn from_bytes(data: &[u8]) -> ParseResult<DataTemplate> {
         let (rest, flowset_id) = take_u16(&data)?;
         let (rest, flowset_length) = take_u16(&rest)?;
+
+        if flowset_length < Self::HEADER_LEN {
+            return Err(NetFlowError::InvalidLength);
+        }
+
         let (rest, templates) =
             DataTemplateItem::parse_bytes(flowset_length - Self::HEADER_LEN, &rest)?;
 
@@ -1,6 +1,6 @@
 use crate::error::{NetFlowError, ParseResult};
 use crate::field::{FlowField, TypeLengthField};
-use crate::flowset::{Record, TemplateParser};
+use crate::flowset::{Record, RecordBuilder, TemplateBuilder, TemplateParser};
 use crate::util::{take_u16, u16_to_bytes};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +21,11 @@ impl DataTemplateItem {
         }
     }
 
+    /// Start a template with the id, see `TemplateBuilder`.
+    pub fn builder(template_id: u16) -> TemplateBuilder {
+        TemplateBuilder::new(template_id)
+    }
+
     /// Return DataTemplateItem from data
     /// length is DataTemplateItem's length, not DataTemplate's
     /// validate with length, need this?
@@ -28,7 +33,9 @@ impl DataTemplateItem {
         let (rest, template_id) = take_u16(&data)?;
         let (rest, field_count) = take_u16(&rest)?;
 
-        if length - DataTemplateItem::HEADER_LEN >= field_count * 4 {
+        if length >= DataTemplateItem::HEADER_LEN
+            && u32::from(length - DataTemplateItem::HEADER_LEN) >= u32::from(field_count) * 4
+        {
             let (rest, fields): (&[u8], Vec<TypeLengthField>) =
                 TypeLengthField::parse_bytes(field_count as usize, &rest)?;
 
@@ -88,6 +95,11 @@ impl DataTemplateItem {
     pub fn byte_length(&self) -> usize {
         self.to_bytes().len()
     }
+
+    /// Start a record of the template, see `RecordBuilder`.
+    pub fn record_builder(&self) -> RecordBuilder<'_> {
+        RecordBuilder::new(self)
+    }
 }
 
 impl TemplateParser for DataTemplateItem {
@@ -3,3 +3,6 @@ pub use self::data_template::*;
 
 mod data_template_item;
 pub use self::data_template_item::*;
+
+mod template_builder;
+pub use self::template_builder::*;
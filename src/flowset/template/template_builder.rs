@@ -0,0 +1,161 @@
+use crate::error::NetFlowError;
+use crate::field::{element_by_name, element_name, field_length, TypeLengthField};
+use crate::flowset::DataTemplateItem;
+
+/// Lowest template id, lower ids are flowset ids.
+pub const MIN_TEMPLATE_ID: u16 = 256;
+
+/// The template field of the type, with its default length or one the registry accepts.
+pub(crate) fn template_field(
+    type_id: u16,
+    length: Option<u16>,
+) -> Result<TypeLengthField, NetFlowError> {
+    let lengths = field_length(type_id);
+    let name = element_name(type_id);
+
+    let length = match length {
+        Some(length) if !lengths.accepts(length) => {
+            return Err(NetFlowError::invalid_template(format!(
+                "{} cannot be {} bytes long",
+                name, length
+            )));
+        }
+        Some(length) => length,
+        None => lengths
+            .default_length()
+            .ok_or_else(|| NetFlowError::invalid_template(format!("{} needs a length", name)))?,
+    };
+
+    Ok(TypeLengthField::new(type_id, length))
+}
+
+pub(crate) fn field_id(name: &str) -> Result<u16, NetFlowError> {
+    element_by_name(name)
+        .map(|element| element.id)
+        .ok_or_else(|| NetFlowError::invalid_template(format!("unknown element {}", name)))
+}
+
+/// Builds a data template from field ids or IANA names.
+///
+/// Fields take the default length of the registry, e.g. 4 bytes for `IN_BYTES`, unless
+/// `field_len` gives one, which must be a length the field can have. Fields without a
+/// default length, such as strings, need `field_len`.
+#[derive(Debug)]
+pub struct TemplateBuilder {
+    template_id: u16,
+    fields: Vec<TypeLengthField>,
+    error: Option<NetFlowError>,
+}
+
+impl TemplateBuilder {
+    pub fn new(template_id: u16) -> TemplateBuilder {
+        TemplateBuilder {
+            template_id,
+            fields: Vec::new(),
+            error: None,
+        }
+    }
+
+    fn push(mut self, field: Result<TypeLengthField, NetFlowError>) -> Self {
+        if self.error.is_none() {
+            match field {
+                Ok(field) => self.fields.push(field),
+                Err(error) => self.error = Some(error),
+            }
+        }
+        self
+    }
+
+    /// Add a field with its default length.
+    pub fn field(self, type_id: u16) -> Self {
+        self.push(template_field(type_id, None))
+    }
+
+    /// Add a field with the length.
+    pub fn field_len(self, type_id: u16, length: u16) -> Self {
+        self.push(template_field(type_id, Some(length)))
+    }
+
+    /// Add a field by IANA name, e.g. "sourceIPv4Address", with its default length.
+    pub fn field_by_name(self, name: &str) -> Self {
+        self.push(field_id(name).and_then(|id| template_field(id, None)))
+    }
+
+    /// Add a field by IANA name with the length.
+    pub fn field_len_by_name(self, name: &str, length: u16) -> Self {
+        self.push(field_id(name).and_then(|id| template_field(id, Some(length))))
+    }
+
+    /// Return the template, or the first invalid field.
+    pub fn build(self) -> Result<DataTemplateItem, NetFlowError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if self.template_id < MIN_TEMPLATE_ID {
+            return Err(NetFlowError::invalid_template(format!(
+                "template id {} is below {}",
+                self.template_id, MIN_TEMPLATE_ID
+            )));
+        }
+        if self.fields.is_empty() {
+            return Err(NetFlowError::invalid_template("no fields"));
+        }
+
+        Ok(DataTemplateItem::new(self.template_id, self.fields))
+    }
+}
+
+#[cfg(test)]
+mod test_template_builder {
+    use super::*;
+    use crate::field::FieldTypes::*;
+    use crate::flowset::TemplateParser;
+
+    #[test]
+    fn test_build() {
+        let template = DataTemplateItem::builder(256)
+            .field(IPV4_SRC_ADDR)
+            .field(L4_SRC_PORT)
+            .field(IN_BYTES)
+            .field_len(IN_PKTS, 8)
+            .field_len(IF_NAME, 16)
+            .field_by_name("flowStartMilliseconds")
+            .build()
+            .unwrap();
+
+        let lengths: Vec<u16> = template.fields.iter().map(|f| f.length).collect();
+        assert_eq!(lengths, vec![4, 2, 4, 8, 16, 8]);
+        assert_eq!(template.field_count, 6);
+        assert_eq!(template.get_template_len(), 42);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |builder: TemplateBuilder| match builder.build() {
+            Err(NetFlowError::InvalidTemplate { desc }) => desc,
+            other => panic!("unexpected result: {:?}", other),
+        };
+
+        assert_eq!(
+            error(DataTemplateItem::builder(256).field_len(L4_SRC_PORT, 4)),
+            "sourceTransportPort cannot be 4 bytes long"
+        );
+        assert_eq!(
+            error(DataTemplateItem::builder(256).field_len(IN_BYTES, 9)),
+            "octetDeltaCount cannot be 9 bytes long"
+        );
+        assert_eq!(
+            error(DataTemplateItem::builder(256).field(IF_NAME)),
+            "interfaceName needs a length"
+        );
+        assert_eq!(
+            error(DataTemplateItem::builder(256).field_by_name("bytes")),
+            "unknown element bytes"
+        );
+        assert_eq!(
+            error(DataTemplateItem::builder(255).field(IN_BYTES)),
+            "template id 255 is below 256"
+        );
+        assert_eq!(error(DataTemplateItem::builder(256)), "no fields");
+    }
+}
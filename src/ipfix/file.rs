@@ -0,0 +1,634 @@
+use super::{
+    DataSet, IpfixMessage, IpfixSet, IpfixTemplates, HEADER_LEN, IPFIX_VERSION, SET_HEADER_LEN,
+    VARIABLE_LENGTH,
+};
+use crate::error::NetFlowError;
+use crate::field::FieldTypes::*;
+// exportedMessageTotalCount and exportedFlowRecordTotalCount of RFC 5655 are the v9 totals
+use crate::field::FieldTypes::{
+    TOTAL_FLOWS_EXP as EXPORTED_FLOW_RECORD_TOTAL_COUNT,
+    TOTAL_PKTS_EXP as EXPORTED_MESSAGE_TOTAL_COUNT,
+};
+use crate::field::{FlowField, TypeLengthField};
+use crate::flowset::{DataTemplateItem, OptionTemplateItem, Record};
+use crate::util::{address_bytes, read_exact, read_or_eof};
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+const MD5_LEN: usize = 16;
+
+// options templates added by the writer, ids from the top of the range
+const CHECKSUM_TEMPLATE_ID: u16 = 65534;
+const SESSION_TEMPLATE_ID: u16 = 65535;
+// the writer describes sessions in its own observation domain
+const FILE_DOMAIN: u32 = 0;
+
+/// Export session details (RFC 5655, section 8.1.3).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExportSession {
+    pub exporter: SocketAddr,
+    pub collector: SocketAddr,
+    /// IANA protocol number of the transport, 17 for UDP
+    pub transport_protocol: u8,
+    pub protocol_version: u8,
+    /// export time of the first and last message, seconds since UNIX epoch
+    pub min_export_seconds: u32,
+    pub max_export_seconds: u32,
+    pub messages: u64,
+    pub records: u64,
+}
+
+fn specifiers(fields: &[FlowField]) -> Vec<TypeLengthField> {
+    fields
+        .iter()
+        .map(|field| TypeLengthField::new(field.type_id(), field.length()))
+        .collect()
+}
+
+fn field_u64(fields: &[FlowField], type_id: u16) -> Option<u64> {
+    fields
+        .iter()
+        .find(|field| field.type_id() == type_id)
+        .and_then(|field| field.value().to_u64())
+}
+
+fn field_ip(fields: &[FlowField], ids: (u16, u16)) -> Option<IpAddr> {
+    let field = fields
+        .iter()
+        .find(|field| field.type_id() == ids.0 || field.type_id() == ids.1)?;
+    let bytes = field.to_bytes();
+
+    match bytes.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::from(BigEndian::read_u32(&bytes)))),
+        16 => Some(IpAddr::V6(Ipv6Addr::from(BigEndian::read_u128(&bytes)))),
+        _ => None,
+    }
+}
+
+impl ExportSession {
+    fn to_fields(&self) -> Vec<FlowField> {
+        let exporter_id = if self.exporter.is_ipv4() {
+            EXPORTER_IPV4_ADDRESS
+        } else {
+            EXPORTER_IPV6_ADDRESS
+        };
+        let collector_id = if self.collector.is_ipv4() {
+            COLLECTOR_IPV4_ADDRESS
+        } else {
+            COLLECTOR_IPV6_ADDRESS
+        };
+
+        vec![
+            FlowField::with_bytes(exporter_id, &address_bytes(self.exporter.ip())),
+            FlowField::with_bytes(EXPORTER_TRANSPORT_PORT, &self.exporter.port().to_be_bytes()),
+            FlowField::with_bytes(collector_id, &address_bytes(self.collector.ip())),
+            FlowField::with_bytes(
+                COLLECTOR_TRANSPORT_PORT,
+                &self.collector.port().to_be_bytes(),
+            ),
+            FlowField::with_bytes(EXPORT_TRANSPORT_PROTOCOL, &[self.transport_protocol]),
+            FlowField::with_bytes(EXPORT_PROTOCOL_VERSION, &[self.protocol_version]),
+            FlowField::with_bytes(MIN_EXPORT_SECONDS, &self.min_export_seconds.to_be_bytes()),
+            FlowField::with_bytes(MAX_EXPORT_SECONDS, &self.max_export_seconds.to_be_bytes()),
+            FlowField::with_bytes(EXPORTED_MESSAGE_TOTAL_COUNT, &self.messages.to_be_bytes()),
+            FlowField::with_bytes(
+                EXPORTED_FLOW_RECORD_TOTAL_COUNT,
+                &self.records.to_be_bytes(),
+            ),
+        ]
+    }
+
+    fn from_fields(fields: &[FlowField]) -> Option<ExportSession> {
+        let exporter = field_ip(fields, (EXPORTER_IPV4_ADDRESS, EXPORTER_IPV6_ADDRESS))?;
+        let collector = field_ip(fields, (COLLECTOR_IPV4_ADDRESS, COLLECTOR_IPV6_ADDRESS))?;
+        let number = |type_id| field_u64(fields, type_id).unwrap_or(0);
+
+        Some(ExportSession {
+            exporter: SocketAddr::new(exporter, number(EXPORTER_TRANSPORT_PORT) as u16),
+            collector: SocketAddr::new(collector, number(COLLECTOR_TRANSPORT_PORT) as u16),
+            transport_protocol: number(EXPORT_TRANSPORT_PROTOCOL) as u8,
+            protocol_version: number(EXPORT_PROTOCOL_VERSION) as u8,
+            min_export_seconds: number(MIN_EXPORT_SECONDS) as u32,
+            max_export_seconds: number(MAX_EXPORT_SECONDS) as u32,
+            messages: number(EXPORTED_MESSAGE_TOTAL_COUNT),
+            records: number(EXPORTED_FLOW_RECORD_TOTAL_COUNT),
+        })
+    }
+}
+
+fn has_scope(template: &OptionTemplateItem, scope: u16) -> bool {
+    template.scopes.len() == 1 && template.scopes[0].type_id == scope
+}
+
+fn checksum_template() -> OptionTemplateItem {
+    OptionTemplateItem::new(
+        CHECKSUM_TEMPLATE_ID,
+        vec![TypeLengthField::new(MESSAGE_SCOPE, 1)],
+        vec![TypeLengthField::new(MESSAGE_MD5_CHECKSUM, MD5_LEN as u16)],
+    )
+}
+
+/// Writes IPFIX messages to a file (RFC 5655).
+/// Templates are repeated before their first use in the file, so the file can be read alone,
+/// and each message gets a messageMD5Checksum options record unless checksums are disabled.
+pub struct IpfixFileWriter<W: Write> {
+    writer: W,
+    templates: IpfixTemplates,
+    // templates already in the file, by observation domain and id
+    written: HashSet<(u32, u16)>,
+    checksums: bool,
+    session: Option<ExportSession>,
+}
+
+impl<W: Write> IpfixFileWriter<W> {
+    pub fn new(writer: W) -> IpfixFileWriter<W> {
+        IpfixFileWriter {
+            writer,
+            templates: IpfixTemplates::new(),
+            written: HashSet::new(),
+            checksums: true,
+            session: None,
+        }
+    }
+
+    /// Learn the templates of a message without writing it, e.g. of the messages received
+    /// before the file was opened, so data sets using them can be written.
+    pub fn learn(&mut self, message: &IpfixMessage) {
+        self.templates.learn(message);
+    }
+
+    pub fn set_checksums(&mut self, checksums: bool) {
+        self.checksums = checksums;
+    }
+
+    /// Describe the session the messages came from, the details are written by finish().
+    pub fn set_session(&mut self, exporter: SocketAddr, collector: SocketAddr, transport: u8) {
+        self.session = Some(ExportSession {
+            exporter,
+            collector,
+            transport_protocol: transport,
+            protocol_version: IPFIX_VERSION as u8,
+            min_export_seconds: u32::MAX,
+            max_export_seconds: 0,
+            messages: 0,
+            records: 0,
+        });
+    }
+
+    /// Write the message, templates its data sets need are written first.
+    /// Data sets with a template never seen are written as they are.
+    pub fn write_message(&mut self, message: &IpfixMessage) -> Result<(), NetFlowError> {
+        let domain = message.observation_domain_id;
+        self.templates.learn(message);
+
+        let mut defined = HashSet::new();
+        for set in &message.sets {
+            match set {
+                IpfixSet::Template(templates) => {
+                    defined.extend(templates.iter().map(|template| template.template_id))
+                }
+                IpfixSet::OptionTemplate(templates) => {
+                    defined.extend(templates.iter().map(|template| template.template_id))
+                }
+                IpfixSet::Data(_) => (),
+            }
+        }
+
+        let mut data_templates: Vec<DataTemplateItem> = Vec::new();
+        let mut option_templates: Vec<OptionTemplateItem> = Vec::new();
+        for set in &message.sets {
+            let id = match set {
+                IpfixSet::Data(data) => data.set_id,
+                _ => continue,
+            };
+            if defined.contains(&id) || self.written.contains(&(domain, id)) {
+                continue;
+            }
+
+            if let Some(template) = self.templates.get_data_template(domain, id) {
+                data_templates.push(template.clone());
+            } else if let Some(template) = self.templates.get_option_template(domain, id) {
+                option_templates.push(template.clone());
+            } else {
+                debug!("no template for data set {:?}", (domain, id));
+            }
+            defined.insert(id);
+        }
+
+        let mut sets = Vec::with_capacity(message.sets.len() + 4);
+        if !data_templates.is_empty() {
+            sets.push(IpfixSet::Template(data_templates));
+        }
+        if !option_templates.is_empty() {
+            sets.push(IpfixSet::OptionTemplate(option_templates));
+        }
+        sets.extend(message.sets.iter().cloned());
+
+        if self.checksums {
+            let template = checksum_template();
+            if !self.written.contains(&(domain, CHECKSUM_TEMPLATE_ID)) {
+                sets.push(IpfixSet::OptionTemplate(vec![template.clone()]));
+            }
+            let record = Record::make_option(
+                vec![FlowField::with_bytes(MESSAGE_SCOPE, &[0])],
+                vec![FlowField::with_bytes(MESSAGE_MD5_CHECKSUM, &[0; MD5_LEN])],
+            );
+            sets.push(IpfixSet::Data(DataSet::from_option_records(
+                &template,
+                vec![record],
+            )));
+        }
+
+        for set in &sets {
+            match set {
+                IpfixSet::Template(templates) => self.written.extend(
+                    templates
+                        .iter()
+                        .map(|template| (domain, template.template_id)),
+                ),
+                IpfixSet::OptionTemplate(templates) => self.written.extend(
+                    templates
+                        .iter()
+                        .map(|template| (domain, template.template_id)),
+                ),
+                IpfixSet::Data(_) => (),
+            }
+        }
+
+        let out = IpfixMessage::new(message.export_time, message.sequence_number, domain, sets);
+        let mut bytes = out.to_bytes();
+        if bytes.len() > usize::from(u16::MAX) {
+            return Err(NetFlowError::InvalidLength);
+        }
+
+        // the checksum is computed with its own field zeroed, it is the last field of the message
+        if self.checksums {
+            let digest = md5::compute(&bytes);
+            let len = bytes.len();
+            bytes[len - MD5_LEN..].copy_from_slice(&digest.0);
+        }
+
+        self.writer.write_all(&bytes)?;
+
+        if let Some(session) = &mut self.session {
+            session.min_export_seconds = session.min_export_seconds.min(message.export_time);
+            session.max_export_seconds = session.max_export_seconds.max(message.export_time);
+            session.messages += 1;
+            session.records += message.record_count() as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Write the export session details if a session was set, return the inner writer.
+    pub fn finish(mut self) -> Result<W, NetFlowError> {
+        if let Some(mut session) = self.session.take() {
+            if session.messages == 0 {
+                session.min_export_seconds = 0;
+            }
+
+            let fields = session.to_fields();
+            let template = OptionTemplateItem::new(
+                SESSION_TEMPLATE_ID,
+                vec![TypeLengthField::new(SESSION_SCOPE, 1)],
+                specifiers(&fields),
+            );
+            let record =
+                Record::make_option(vec![FlowField::with_bytes(SESSION_SCOPE, &[0])], fields);
+            let data = DataSet::from_option_records(&template, vec![record]);
+
+            let message = IpfixMessage::new(
+                session.max_export_seconds,
+                0,
+                FILE_DOMAIN,
+                vec![
+                    IpfixSet::OptionTemplate(vec![template]),
+                    IpfixSet::Data(data),
+                ],
+            );
+            self.write_message(&message)?;
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads IPFIX messages from a file (RFC 5655) and decodes them with the templates in the file.
+/// A message whose messageMD5Checksum does not match is returned as an error.
+pub struct IpfixFileReader<R> {
+    reader: R,
+    templates: IpfixTemplates,
+    sessions: Vec<ExportSession>,
+}
+
+impl<R: Read> IpfixFileReader<R> {
+    pub fn new(reader: R) -> IpfixFileReader<R> {
+        IpfixFileReader {
+            reader,
+            templates: IpfixTemplates::new(),
+            sessions: Vec::new(),
+        }
+    }
+
+    pub fn templates(&self) -> &IpfixTemplates {
+        &self.templates
+    }
+
+    /// Export session details read so far.
+    pub fn sessions(&self) -> &[ExportSession] {
+        &self.sessions
+    }
+
+    pub fn next_message(&mut self) -> Result<Option<IpfixMessage>, NetFlowError> {
+        let mut header = [0u8; HEADER_LEN];
+        if !read_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+
+        if BigEndian::read_u16(&header) != IPFIX_VERSION {
+            return Err(NetFlowError::invalid_capture("not an IPFIX message"));
+        }
+        let length = BigEndian::read_u16(&header[2..]) as usize;
+        if length < HEADER_LEN {
+            return Err(NetFlowError::invalid_capture("invalid message length"));
+        }
+
+        let mut buf = vec![0u8; length];
+        buf[..HEADER_LEN].copy_from_slice(&header);
+        read_exact(&mut self.reader, &mut buf[HEADER_LEN..])?;
+
+        let message = self.templates.decode(&buf)?;
+        self.check_options(&buf, &message)?;
+
+        Ok(Some(message))
+    }
+
+    // verify checksums and collect session details
+    fn check_options(&mut self, buf: &[u8], message: &IpfixMessage) -> Result<(), NetFlowError> {
+        let domain = message.observation_domain_id;
+        let mut verified = Vec::new();
+
+        for set in &message.sets {
+            let (data, records) = match set {
+                IpfixSet::Data(data) => match &data.records {
+                    Some(records) => (data, records),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let template = match self.templates.get_option_template(domain, data.set_id) {
+                Some(template) => template,
+                None => continue,
+            };
+
+            if has_scope(template, MESSAGE_SCOPE) {
+                let has_checksum = template
+                    .options
+                    .iter()
+                    .any(|field| field.type_id == MESSAGE_MD5_CHECKSUM);
+                if has_checksum && !verified.contains(&data.set_id) {
+                    verify_checksums(buf, template)?;
+                    verified.push(data.set_id);
+                }
+                continue;
+            }
+
+            for record in records {
+                let options = match record {
+                    Record::OptionData(option) => option.option_fields(),
+                    Record::Data(_) => continue,
+                };
+
+                if has_scope(template, SESSION_SCOPE) {
+                    if let Some(session) = ExportSession::from_fields(options) {
+                        self.sessions.push(session);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Offsets in the message of the messageMD5Checksum fields in the data sets of the
+/// checksum options template, from the set and record layout.
+fn checksum_offsets(buf: &[u8], template: &OptionTemplateItem) -> Vec<usize> {
+    let fields: Vec<&TypeLengthField> = template.scopes.iter().chain(&template.options).collect();
+    let mut offsets = Vec::new();
+    let mut set = HEADER_LEN;
+
+    while set + SET_HEADER_LEN <= buf.len() {
+        let set_id = BigEndian::read_u16(&buf[set..]);
+        let end = set + BigEndian::read_u16(&buf[set + 2..]) as usize;
+        if end < set + SET_HEADER_LEN || end > buf.len() {
+            break;
+        }
+
+        let mut pos = set + SET_HEADER_LEN;
+        'records: while set_id == template.template_id && pos < end {
+            let mut checksum = None;
+            for field in &fields {
+                let length = if field.length == VARIABLE_LENGTH {
+                    match buf[pos..end] {
+                        [255, high, low, ..] => {
+                            pos += 3;
+                            usize::from(u16::from_be_bytes([high, low]))
+                        }
+                        [length, ..] => {
+                            pos += 1;
+                            usize::from(length)
+                        }
+                        [] => break 'records,
+                    }
+                } else {
+                    usize::from(field.length)
+                };
+                // what is left is padding
+                if pos + length > end {
+                    break 'records;
+                }
+                if field.type_id == MESSAGE_MD5_CHECKSUM
+                    && field.enterprise.is_none()
+                    && length == MD5_LEN
+                {
+                    checksum = Some(pos);
+                }
+                pos += length;
+            }
+            offsets.extend(checksum);
+        }
+
+        set = end;
+    }
+
+    offsets
+}
+
+/// Check the checksums of the message, computed with the checksum fields zeroed.
+fn verify_checksums(buf: &[u8], template: &OptionTemplateItem) -> Result<(), NetFlowError> {
+    let offsets = checksum_offsets(buf, template);
+    if offsets.is_empty() {
+        return Err(NetFlowError::invalid_capture("invalid message checksum"));
+    }
+
+    let mut zeroed = buf.to_vec();
+    for &pos in &offsets {
+        for byte in &mut zeroed[pos..pos + MD5_LEN] {
+            *byte = 0;
+        }
+    }
+
+    let digest = md5::compute(&zeroed);
+    if offsets
+        .iter()
+        .all(|&pos| buf[pos..pos + MD5_LEN] == digest.0[..])
+    {
+        Ok(())
+    } else {
+        Err(NetFlowError::invalid_capture("message checksum mismatch"))
+    }
+}
+
+impl<R: Read> Iterator for IpfixFileReader<R> {
+    type Item = Result<IpfixMessage, NetFlowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_message().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test_file {
+    use super::*;
+    use crate::ipfix::test_data;
+
+    fn data_only(message: &IpfixMessage, sequence_number: u32) -> IpfixMessage {
+        let sets = message
+            .sets
+            .iter()
+            .filter(|set| matches!(set, IpfixSet::Data(_)))
+            .cloned()
+            .collect();
+
+        IpfixMessage::new(message.export_time + 60, sequence_number, 1, sets)
+    }
+
+    // records of the test template, without the checksum record
+    fn flow_records(message: &IpfixMessage) -> usize {
+        message
+            .sets
+            .iter()
+            .filter_map(|set| match set {
+                IpfixSet::Data(data) if data.set_id == 256 => data.records.as_ref().map(Vec::len),
+                _ => None,
+            })
+            .sum()
+    }
+
+    fn write_file(checksums: bool) -> Vec<u8> {
+        let message = test_data::message();
+        let mut writer = IpfixFileWriter::new(Vec::new());
+        writer.set_checksums(checksums);
+        writer.set_session(
+            "192.0.2.1:4739".parse().unwrap(),
+            "[2001:db8::1]:4739".parse().unwrap(),
+            17,
+        );
+
+        // the second file starts with a message without templates
+        writer.write_message(&message).unwrap();
+        writer.write_message(&data_only(&message, 2)).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let file = write_file(true);
+        let mut reader = IpfixFileReader::new(&file[..]);
+
+        let messages: Vec<_> = reader.by_ref().map(|message| message.unwrap()).collect();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(flow_records(&messages[0]), 2);
+        assert_eq!(flow_records(&messages[1]), 2);
+        assert_eq!(messages[1].record_count(), 3);
+
+        let sessions = reader.sessions();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].exporter, "192.0.2.1:4739".parse().unwrap());
+        assert_eq!(sessions[0].collector, "[2001:db8::1]:4739".parse().unwrap());
+        assert_eq!(sessions[0].min_export_seconds, test_data::EXPORT_TIME);
+        assert_eq!(sessions[0].max_export_seconds, test_data::EXPORT_TIME + 60);
+        assert_eq!(sessions[0].messages, 2);
+        assert_eq!(sessions[0].records, 4);
+    }
+
+    #[test]
+    fn test_templates_in_file() {
+        // a file starting after the templates were sent still carries them
+        let mut writer = IpfixFileWriter::new(Vec::new());
+        let message = test_data::message();
+        writer.write_message(&message).unwrap();
+
+        let mut second = IpfixFileWriter::new(Vec::new());
+        second.learn(&message);
+        second.write_message(&data_only(&message, 2)).unwrap();
+        let file = second.finish().unwrap();
+
+        let message = IpfixFileReader::new(&file[..])
+            .next_message()
+            .unwrap()
+            .unwrap();
+        assert_eq!(flow_records(&message), 2);
+    }
+
+    #[test]
+    fn test_checksum_not_last() {
+        // another writer may put the checksum record before the data
+        let template = checksum_template();
+        let checksum = DataSet::from_option_records(
+            &template,
+            vec![Record::make_option(
+                vec![FlowField::with_bytes(MESSAGE_SCOPE, &[0])],
+                vec![FlowField::with_bytes(MESSAGE_MD5_CHECKSUM, &[0; MD5_LEN])],
+            )],
+        );
+        let mut sets = vec![
+            IpfixSet::OptionTemplate(vec![template]),
+            IpfixSet::Data(checksum),
+        ];
+        let offset = IpfixMessage::new(0, 0, 1, sets.clone()).to_bytes().len() - MD5_LEN;
+        sets.extend(test_data::message().sets);
+
+        let mut file = IpfixMessage::new(0, 0, 1, sets).to_bytes();
+        let digest = md5::compute(&file);
+        file[offset..offset + MD5_LEN].copy_from_slice(&digest.0);
+
+        let message = IpfixFileReader::new(&file[..])
+            .next_message()
+            .unwrap()
+            .unwrap();
+        assert_eq!(flow_records(&message), 2);
+
+        let last = file.len() - 1;
+        file[last] ^= 0xff;
+        assert!(IpfixFileReader::new(&file[..]).next_message().is_err());
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let mut file = write_file(true);
+        // first record of the first data set
+        file[60] ^= 0xff;
+
+        let mut reader = IpfixFileReader::new(&file[..]);
+        assert!(reader.next_message().is_err());
+        assert!(reader.next_message().unwrap().is_some());
+
+        let file = write_file(false);
+        assert_eq!(IpfixFileReader::new(&file[..]).count(), 3);
+    }
+}
@@ -0,0 +1,591 @@
+mod template_store;
+pub use self::template_store::*;
+
+mod file;
+pub use self::file::*;
+
+use crate::error::{NetFlowError, ParseResult};
+use crate::field::{FieldValue, FlowField, TypeLengthField, REVERSE_PEN};
+use crate::flowset::{DataTemplateItem, OptionTemplateItem, Record};
+use crate::util::{take_u16, take_u32, u16_to_bytes, u32_to_bytes};
+
+// IPFIX -> Header + (Template Set | Options Template Set | Data Set)* (RFC 7011)
+
+pub const IPFIX_VERSION: u16 = 10;
+pub const TEMPLATE_SET_ID: u16 = 2;
+pub const OPTION_TEMPLATE_SET_ID: u16 = 3;
+/// Field length of a variable-length element in a template.
+pub const VARIABLE_LENGTH: u16 = 65535;
+
+const HEADER_LEN: usize = 16;
+const SET_HEADER_LEN: usize = 4;
+const MIN_TEMPLATE_ID: u16 = 256;
+const ENTERPRISE_BIT: u16 = 0x8000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IpfixMessage {
+    pub version: u16,
+    pub length: u16,
+    /// seconds since UNIX epoch
+    pub export_time: u32,
+    pub sequence_number: u32,
+    pub observation_domain_id: u32,
+    pub sets: Vec<IpfixSet>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum IpfixSet {
+    Template(Vec<DataTemplateItem>),
+    OptionTemplate(Vec<OptionTemplateItem>),
+    Data(DataSet),
+}
+
+/// Data set, records stay undecoded until its template is applied.
+#[derive(Debug, Clone, Serialize)]
+pub struct DataSet {
+    pub set_id: u16,
+    #[serde(skip)]
+    record_bytes: Vec<u8>,
+    pub records: Option<Vec<Record>>,
+}
+
+impl IpfixMessage {
+    pub fn new(
+        export_time: u32,
+        sequence_number: u32,
+        observation_domain_id: u32,
+        sets: Vec<IpfixSet>,
+    ) -> IpfixMessage {
+        let length = HEADER_LEN + sets.iter().map(IpfixSet::byte_length).sum::<usize>();
+
+        IpfixMessage {
+            version: IPFIX_VERSION,
+            length: length as u16,
+            export_time,
+            sequence_number,
+            observation_domain_id,
+            sets,
+        }
+    }
+
+    pub fn from_bytes(payload: &[u8]) -> Result<IpfixMessage, NetFlowError> {
+        let (rest, version) = take_u16(payload)?;
+        if version != IPFIX_VERSION {
+            return Err(NetFlowError::InvalidFieldValue);
+        }
+
+        let (rest, length) = take_u16(rest)?;
+        let (rest, export_time) = take_u32(rest)?;
+        let (rest, sequence_number) = take_u32(rest)?;
+        let (_, observation_domain_id) = take_u32(rest)?;
+
+        if (length as usize) < HEADER_LEN || (length as usize) > payload.len() {
+            return Err(NetFlowError::InvalidLength);
+        }
+
+        let mut rest = &payload[HEADER_LEN..length as usize];
+        let mut sets = Vec::new();
+        while !rest.is_empty() {
+            let (next, set) = IpfixSet::from_bytes(rest)?;
+            sets.push(set);
+            rest = next;
+        }
+
+        Ok(IpfixMessage {
+            version,
+            length,
+            export_time,
+            sequence_number,
+            observation_domain_id,
+            sets,
+        })
+    }
+
+    /// Serialize with the length computed from the sets.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut sets = Vec::new();
+        for set in &self.sets {
+            sets.append(&mut set.to_bytes());
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + sets.len());
+        let mut u16_buf = [0u8; 2];
+        let mut u32_buf = [0u8; 4];
+
+        u16_to_bytes(self.version, &mut u16_buf);
+        bytes.extend_from_slice(&u16_buf);
+        u16_to_bytes((HEADER_LEN + sets.len()) as u16, &mut u16_buf);
+        bytes.extend_from_slice(&u16_buf);
+        u32_to_bytes(self.export_time, &mut u32_buf);
+        bytes.extend_from_slice(&u32_buf);
+        u32_to_bytes(self.sequence_number, &mut u32_buf);
+        bytes.extend_from_slice(&u32_buf);
+        u32_to_bytes(self.observation_domain_id, &mut u32_buf);
+        bytes.extend_from_slice(&u32_buf);
+        bytes.append(&mut sets);
+
+        bytes
+    }
+
+    pub fn byte_length(&self) -> usize {
+        self.to_bytes().len()
+    }
+
+    /// Number of decoded data records, used for the sequence number of the next message.
+    pub fn record_count(&self) -> usize {
+        self.sets
+            .iter()
+            .filter_map(|set| match set {
+                IpfixSet::Data(data) => data.records.as_ref().map(Vec::len),
+                _ => None,
+            })
+            .sum()
+    }
+}
+
+fn parse_specifiers(count: u16, data: &[u8]) -> ParseResult<'_, Vec<TypeLengthField>> {
+    let mut rest = data;
+    let mut fields = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        let (next, type_id) = take_u16(rest)?;
+        let (next, length) = take_u16(next)?;
+
+        if type_id & ENTERPRISE_BIT != 0 {
+            let (next, enterprise) = take_u32(next)?;
+            fields.push(TypeLengthField::with_enterprise(
+                type_id & !ENTERPRISE_BIT,
+                length,
+                enterprise,
+            ));
+            rest = next;
+        } else {
+            fields.push(TypeLengthField::new(type_id, length));
+            rest = next;
+        }
+    }
+
+    Ok((rest, fields))
+}
+
+fn specifiers_to_bytes(fields: &[TypeLengthField], bytes: &mut Vec<u8>) {
+    let mut u16_buf = [0u8; 2];
+    let mut u32_buf = [0u8; 4];
+
+    for field in fields {
+        match field.enterprise {
+            Some(enterprise) => {
+                u16_to_bytes(field.type_id | ENTERPRISE_BIT, &mut u16_buf);
+                bytes.extend_from_slice(&u16_buf);
+                u16_to_bytes(field.length, &mut u16_buf);
+                bytes.extend_from_slice(&u16_buf);
+                u32_to_bytes(enterprise, &mut u32_buf);
+                bytes.extend_from_slice(&u32_buf);
+            }
+            None => bytes.append(&mut field.to_bytes()),
+        }
+    }
+}
+
+fn parse_templates(mut rest: &[u8]) -> Result<Vec<DataTemplateItem>, NetFlowError> {
+    let mut templates = Vec::new();
+
+    // anything shorter than a template header is padding
+    while rest.len() >= 4 {
+        let (next, template_id) = take_u16(rest)?;
+        let (next, field_count) = take_u16(next)?;
+        if template_id < MIN_TEMPLATE_ID && template_id != TEMPLATE_SET_ID {
+            break;
+        }
+
+        let (next, fields) = parse_specifiers(field_count, next)?;
+        templates.push(DataTemplateItem::new(template_id, fields));
+        rest = next;
+    }
+
+    Ok(templates)
+}
+
+fn parse_option_templates(mut rest: &[u8]) -> Result<Vec<OptionTemplateItem>, NetFlowError> {
+    let mut templates = Vec::new();
+
+    while rest.len() >= 4 {
+        let (next, template_id) = take_u16(rest)?;
+        let (next, field_count) = take_u16(next)?;
+        if template_id < MIN_TEMPLATE_ID && template_id != OPTION_TEMPLATE_SET_ID {
+            break;
+        }
+
+        // a withdrawal has no scope field count
+        if field_count == 0 {
+            templates.push(OptionTemplateItem::new(template_id, vec![], vec![]));
+            rest = next;
+            continue;
+        }
+
+        let (next, scope_count) = take_u16(next)?;
+        if scope_count == 0 || scope_count > field_count {
+            return Err(NetFlowError::InvalidFieldValue);
+        }
+
+        let (next, scopes) = parse_specifiers(scope_count, next)?;
+        let (next, options) = parse_specifiers(field_count - scope_count, next)?;
+        templates.push(OptionTemplateItem::new(template_id, scopes, options));
+        rest = next;
+    }
+
+    Ok(templates)
+}
+
+impl IpfixSet {
+    pub fn from_bytes(data: &[u8]) -> ParseResult<'_, IpfixSet> {
+        let (rest, set_id) = take_u16(data)?;
+        let (_, length) = take_u16(rest)?;
+
+        let length = length as usize;
+        if length < SET_HEADER_LEN || length > data.len() {
+            return Err(NetFlowError::InvalidLength);
+        }
+
+        let body = &data[SET_HEADER_LEN..length];
+        let set = match set_id {
+            TEMPLATE_SET_ID => IpfixSet::Template(parse_templates(body)?),
+            OPTION_TEMPLATE_SET_ID => IpfixSet::OptionTemplate(parse_option_templates(body)?),
+            id if id >= MIN_TEMPLATE_ID => IpfixSet::Data(DataSet {
+                set_id,
+                record_bytes: body.to_vec(),
+                records: None,
+            }),
+            _ => return Err(NetFlowError::InvalidFieldValue),
+        };
+
+        Ok((&data[length..], set))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        let mut u16_buf = [0u8; 2];
+
+        let set_id = match self {
+            IpfixSet::Template(templates) => {
+                for template in templates {
+                    u16_to_bytes(template.template_id, &mut u16_buf);
+                    body.extend_from_slice(&u16_buf);
+                    u16_to_bytes(template.fields.len() as u16, &mut u16_buf);
+                    body.extend_from_slice(&u16_buf);
+                    specifiers_to_bytes(&template.fields, &mut body);
+                }
+                TEMPLATE_SET_ID
+            }
+            IpfixSet::OptionTemplate(templates) => {
+                for template in templates {
+                    let field_count = template.scopes.len() + template.options.len();
+
+                    u16_to_bytes(template.template_id, &mut u16_buf);
+                    body.extend_from_slice(&u16_buf);
+                    u16_to_bytes(field_count as u16, &mut u16_buf);
+                    body.extend_from_slice(&u16_buf);
+                    if field_count > 0 {
+                        u16_to_bytes(template.scopes.len() as u16, &mut u16_buf);
+                        body.extend_from_slice(&u16_buf);
+                    }
+                    specifiers_to_bytes(&template.scopes, &mut body);
+                    specifiers_to_bytes(&template.options, &mut body);
+                }
+                OPTION_TEMPLATE_SET_ID
+            }
+            IpfixSet::Data(data) => {
+                body.extend_from_slice(&data.record_bytes);
+                data.set_id
+            }
+        };
+
+        let mut bytes = Vec::with_capacity(SET_HEADER_LEN + body.len());
+        u16_to_bytes(set_id, &mut u16_buf);
+        bytes.extend_from_slice(&u16_buf);
+        u16_to_bytes((SET_HEADER_LEN + body.len()) as u16, &mut u16_buf);
+        bytes.extend_from_slice(&u16_buf);
+        bytes.append(&mut body);
+
+        bytes
+    }
+
+    pub fn byte_length(&self) -> usize {
+        self.to_bytes().len()
+    }
+}
+
+fn parse_field<'a>(field: &TypeLengthField, data: &'a [u8]) -> ParseResult<'a, FlowField> {
+    let (data, length) = if field.length == VARIABLE_LENGTH {
+        match data.first() {
+            Some(255) => {
+                let (rest, length) = take_u16(&data[1..])?;
+                (rest, length)
+            }
+            Some(length) => (&data[1..], u16::from(*length)),
+            None => return Err(NetFlowError::InvalidLength),
+        }
+    } else {
+        (data, field.length)
+    };
+
+    if (length as usize) > data.len() {
+        return Err(NetFlowError::InvalidLength);
+    }
+
+    let bytes = &data[..length as usize];
+    // enterprise ids overlap the IANA ids the value types are taken from, except for the
+    // reverse elements
+    let flow_field = match field.enterprise {
+        Some(REVERSE_PEN) => FlowField::with_enterprise(
+            field.type_id,
+            length,
+            REVERSE_PEN,
+            FieldValue::new(field.type_id, bytes),
+        ),
+        Some(enterprise) => FlowField::with_enterprise(
+            field.type_id,
+            length,
+            enterprise,
+            FieldValue::ByteArray(bytes.to_vec()),
+        ),
+        None => FlowField::new(field.type_id, length, FieldValue::new(field.type_id, bytes)),
+    };
+
+    Ok((&data[length as usize..], flow_field))
+}
+
+fn parse_fields<'a>(
+    template: &[TypeLengthField],
+    mut data: &'a [u8],
+) -> ParseResult<'a, Vec<FlowField>> {
+    let mut fields = Vec::with_capacity(template.len());
+
+    for field in template {
+        let (next, flow_field) = parse_field(field, data)?;
+        fields.push(flow_field);
+        data = next;
+    }
+
+    Ok((data, fields))
+}
+
+fn fields_to_bytes(template: &[TypeLengthField], fields: &[FlowField], bytes: &mut Vec<u8>) {
+    for (spec, field) in template.iter().zip(fields) {
+        let mut value = field.to_bytes();
+
+        if spec.length == VARIABLE_LENGTH {
+            if value.len() < 255 {
+                bytes.push(value.len() as u8);
+            } else {
+                let mut u16_buf = [0u8; 2];
+                u16_to_bytes(value.len() as u16, &mut u16_buf);
+                bytes.push(255);
+                bytes.extend_from_slice(&u16_buf);
+            }
+        } else {
+            value.resize(spec.length as usize, 0);
+        }
+
+        bytes.append(&mut value);
+    }
+}
+
+// shortest record, a variable-length field takes at least its length byte
+fn min_record_len(fields: &[TypeLengthField]) -> usize {
+    fields
+        .iter()
+        .map(|field| match field.length {
+            VARIABLE_LENGTH => 1,
+            length => length as usize,
+        })
+        .sum()
+}
+
+impl DataSet {
+    /// Encode data records with the template, fields are taken in template order.
+    pub fn from_data_records(template: &DataTemplateItem, records: Vec<Record>) -> DataSet {
+        let mut record_bytes = Vec::new();
+
+        for record in &records {
+            let fields: Vec<_> = record.fields().cloned().collect();
+            fields_to_bytes(&template.fields, &fields, &mut record_bytes);
+        }
+
+        DataSet {
+            set_id: template.template_id,
+            record_bytes,
+            records: Some(records),
+        }
+    }
+
+    /// Encode option records with the template, scope fields first.
+    pub fn from_option_records(template: &OptionTemplateItem, records: Vec<Record>) -> DataSet {
+        let mut record_bytes = Vec::new();
+
+        for record in &records {
+            if let Record::OptionData(option) = record {
+                fields_to_bytes(&template.scopes, option.scope_fields(), &mut record_bytes);
+                fields_to_bytes(&template.options, option.option_fields(), &mut record_bytes);
+            }
+        }
+
+        DataSet {
+            set_id: template.template_id,
+            record_bytes,
+            records: Some(records),
+        }
+    }
+
+    pub fn apply_data_template(&mut self, template: &DataTemplateItem) -> Result<(), NetFlowError> {
+        let min_len = min_record_len(&template.fields);
+        if template.template_id != self.set_id || min_len == 0 {
+            return Err(NetFlowError::TemplateNotFound);
+        }
+
+        let mut rest = &self.record_bytes[..];
+        let mut records = Vec::new();
+        // the rest is padding once it cannot hold a record
+        while rest.len() >= min_len {
+            let (next, fields) = parse_fields(&template.fields, rest)?;
+            records.push(Record::make_data(fields));
+            rest = next;
+        }
+
+        self.records = Some(records);
+        Ok(())
+    }
+
+    pub fn apply_option_template(
+        &mut self,
+        template: &OptionTemplateItem,
+    ) -> Result<(), NetFlowError> {
+        let min_len = min_record_len(&template.scopes) + min_record_len(&template.options);
+        if template.template_id != self.set_id || min_len == 0 {
+            return Err(NetFlowError::TemplateNotFound);
+        }
+
+        let mut rest = &self.record_bytes[..];
+        let mut records = Vec::new();
+        while rest.len() >= min_len {
+            let (next, scopes) = parse_fields(&template.scopes, rest)?;
+            let (next, options) = parse_fields(&template.options, next)?;
+            records.push(Record::make_option(scopes, options));
+            rest = next;
+        }
+
+        self.records = Some(records);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_data {
+    use super::*;
+
+    pub const EXPORT_TIME: u32 = 1_523_936_618;
+
+    /// Template 256 with a variable-length and an enterprise field, and two records.
+    pub fn message() -> IpfixMessage {
+        let template = DataTemplateItem::new(
+            256,
+            vec![
+                TypeLengthField::new(8, 4),
+                TypeLengthField::new(2, 8),
+                TypeLengthField::new(82, VARIABLE_LENGTH),
+                TypeLengthField::with_enterprise(1, 2, 9),
+            ],
+        );
+
+        let record = |ip: [u8; 4], packets: u8, name: &str| {
+            Record::make_data(vec![
+                FlowField::new(8, 4, FieldValue::new(8, &ip)),
+                FlowField::new(2, 8, FieldValue::new(2, &[0, 0, 0, 0, 0, 0, 0, packets])),
+                FlowField::new(82, name.len() as u16, FieldValue::new(82, name.as_bytes())),
+                FlowField::with_enterprise(1, 2, 9, FieldValue::ByteArray(vec![0xab, 0xcd])),
+            ])
+        };
+        let records = vec![
+            record([192, 0, 2, 1], 10, "eth0"),
+            record([192, 0, 2, 2], 20, "a long interface name"),
+        ];
+
+        IpfixMessage::new(
+            EXPORT_TIME,
+            0,
+            1,
+            vec![
+                IpfixSet::Template(vec![template.clone()]),
+                IpfixSet::Data(DataSet::from_data_records(&template, records)),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod test_ipfix {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let bytes = test_data::message().to_bytes();
+        assert_eq!(&bytes[..2], &[0x00, 0x0a]);
+
+        let mut message = IpfixMessage::from_bytes(&bytes).unwrap();
+        assert_eq!(message.length as usize, bytes.len());
+        assert_eq!(message.export_time, test_data::EXPORT_TIME);
+        assert_eq!(message.observation_domain_id, 1);
+        assert_eq!(message.sets.len(), 2);
+        assert_eq!(message.to_bytes(), bytes);
+
+        let template = match &message.sets[0] {
+            IpfixSet::Template(templates) => templates[0].clone(),
+            _ => panic!("not a template set"),
+        };
+        assert_eq!(template.fields[3].enterprise, Some(9));
+
+        match &mut message.sets[1] {
+            IpfixSet::Data(data) => {
+                data.apply_data_template(&template).unwrap();
+                let records = data.records.as_ref().unwrap();
+                assert_eq!(records.len(), 2);
+                assert_eq!(records[1].get(82).unwrap().length(), 21);
+                assert_eq!(records[1].get(2).unwrap().value().to_u64(), Some(20));
+                // the enterprise field is not octetDeltaCount
+                assert!(records[1].get(1).is_none());
+                let field = records[1].get_enterprise(9, 1).unwrap();
+                assert_eq!(field.to_bytes(), vec![0xab, 0xcd]);
+            }
+            _ => panic!("not a data set"),
+        }
+    }
+
+    #[test]
+    fn test_option_template() {
+        let template = OptionTemplateItem::new(
+            300,
+            vec![TypeLengthField::new(149, 4)],
+            vec![TypeLengthField::new(41, 8)],
+        );
+        let withdrawal = OptionTemplateItem::new(301, vec![], vec![]);
+        let set = IpfixSet::OptionTemplate(vec![template, withdrawal]);
+        let bytes = set.to_bytes();
+
+        match IpfixSet::from_bytes(&bytes).unwrap().1 {
+            IpfixSet::OptionTemplate(templates) => {
+                assert_eq!(templates.len(), 2);
+                assert_eq!(templates[0].scope_count, 1);
+                assert_eq!(templates[0].option_count, 1);
+                assert_eq!(templates[1].scope_count, 0);
+            }
+            _ => panic!("not an options template set"),
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let bytes = test_data::message().to_bytes();
+        assert!(IpfixMessage::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(IpfixMessage::from_bytes(&crate::netflow::test_data::NETFLOWV9_DATA).is_err());
+    }
+}
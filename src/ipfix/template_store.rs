@@ -0,0 +1,152 @@
+use super::{IpfixMessage, IpfixSet, OPTION_TEMPLATE_SET_ID, TEMPLATE_SET_ID};
+use crate::error::NetFlowError;
+use crate::flowset::{DataTemplateItem, OptionTemplateItem};
+use std::collections::HashMap;
+
+/// Templates are scoped by observation domain within a transport session or file (RFC 7011, section 8).
+type TemplateKey = (u32, u16);
+
+/// Keeps IPFIX templates and decodes data sets with them.
+#[derive(Debug, Clone, Default)]
+pub struct IpfixTemplates {
+    data_templates: HashMap<TemplateKey, DataTemplateItem>,
+    option_templates: HashMap<TemplateKey, OptionTemplateItem>,
+}
+
+impl IpfixTemplates {
+    pub fn new() -> IpfixTemplates {
+        IpfixTemplates::default()
+    }
+
+    /// Store every template in the message, a template without fields withdraws the id.
+    pub fn learn(&mut self, message: &IpfixMessage) {
+        let domain = message.observation_domain_id;
+
+        for set in &message.sets {
+            match set {
+                IpfixSet::Template(templates) => {
+                    for template in templates {
+                        let key = (domain, template.template_id);
+                        if template.template_id == TEMPLATE_SET_ID {
+                            self.data_templates.retain(|key, _| key.0 != domain);
+                        } else if template.fields.is_empty() {
+                            self.data_templates.remove(&key);
+                        } else {
+                            debug!("learned IPFIX template: {:?}", key);
+                            self.option_templates.remove(&key);
+                            self.data_templates.insert(key, template.clone());
+                        }
+                    }
+                }
+                IpfixSet::OptionTemplate(templates) => {
+                    for template in templates {
+                        let key = (domain, template.template_id);
+                        if template.template_id == OPTION_TEMPLATE_SET_ID {
+                            self.option_templates.retain(|key, _| key.0 != domain);
+                        } else if template.scopes.is_empty() {
+                            self.option_templates.remove(&key);
+                        } else {
+                            debug!("learned IPFIX options template: {:?}", key);
+                            self.data_templates.remove(&key);
+                            self.option_templates.insert(key, template.clone());
+                        }
+                    }
+                }
+                IpfixSet::Data(_) => (),
+            }
+        }
+    }
+
+    /// Decode data sets that have no records yet.
+    /// Return the number of data sets left undecoded because the template is unknown.
+    pub fn apply(&self, message: &mut IpfixMessage) -> usize {
+        let domain = message.observation_domain_id;
+        let mut missing = 0;
+
+        for set in &mut message.sets {
+            if let IpfixSet::Data(data) = set {
+                if data.records.is_some() {
+                    continue;
+                }
+
+                let key = (domain, data.set_id);
+                let res = if let Some(template) = self.data_templates.get(&key) {
+                    data.apply_data_template(template)
+                } else if let Some(template) = self.option_templates.get(&key) {
+                    data.apply_option_template(template)
+                } else {
+                    Err(NetFlowError::TemplateNotFound)
+                };
+
+                if let Err(e) = res {
+                    debug!("cannot decode data set {:?}: {}", key, e);
+                    missing += 1;
+                }
+            }
+        }
+
+        missing
+    }
+
+    /// Parse a message, learn its templates and decode its data sets.
+    pub fn decode(&mut self, payload: &[u8]) -> Result<IpfixMessage, NetFlowError> {
+        let mut message = IpfixMessage::from_bytes(payload)?;
+        self.learn(&message);
+        self.apply(&mut message);
+
+        Ok(message)
+    }
+
+    pub fn get_data_template(&self, domain: u32, template_id: u16) -> Option<&DataTemplateItem> {
+        self.data_templates.get(&(domain, template_id))
+    }
+
+    pub fn get_option_template(
+        &self,
+        domain: u32,
+        template_id: u16,
+    ) -> Option<&OptionTemplateItem> {
+        self.option_templates.get(&(domain, template_id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.data_templates.len() + self.option_templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test_template_store {
+    use super::*;
+    use crate::ipfix::test_data;
+
+    #[test]
+    fn test_decode() {
+        let bytes = test_data::message().to_bytes();
+        let mut templates = IpfixTemplates::new();
+
+        let message = templates.decode(&bytes).unwrap();
+        assert_eq!(templates.len(), 1);
+        assert!(templates.get_data_template(1, 256).is_some());
+        assert!(templates.get_data_template(2, 256).is_none());
+        assert_eq!(message.record_count(), 2);
+    }
+
+    #[test]
+    fn test_withdrawal() {
+        let mut templates = IpfixTemplates::new();
+        templates.learn(&test_data::message());
+
+        let withdrawal = IpfixMessage::new(
+            0,
+            0,
+            1,
+            vec![IpfixSet::Template(vec![DataTemplateItem::new(256, vec![])])],
+        );
+        templates.learn(&withdrawal);
+        assert!(templates.is_empty());
+    }
+}
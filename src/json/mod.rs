@@ -0,0 +1,314 @@
+use crate::field::{element, element_name, DataType, FieldValue, FlowField, UInt, REVERSE_PEN};
+use crate::flowset::{FlowSet, Record};
+use crate::ipfix::{IpfixMessage, IpfixSet};
+use crate::netflow::NetFlow9;
+use serde_json::{Map, Number, Value};
+use std::io;
+use std::io::Write;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Packet metadata written with every record.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordContext {
+    pub exporter: SocketAddr,
+    /// source id of Netflow v9, observation domain of IPFIX
+    pub source_id: u32,
+    pub sequence: u32,
+    /// seconds since UNIX epoch
+    pub export_time: u32,
+    /// milliseconds since the exporter booted, Netflow v9 only
+    pub sys_uptime: Option<u32>,
+}
+
+impl RecordContext {
+    pub fn from_netflow9(exporter: SocketAddr, netflow: &NetFlow9) -> RecordContext {
+        RecordContext {
+            exporter,
+            source_id: netflow.source_id,
+            sequence: netflow.flow_sequence,
+            export_time: netflow.timestamp,
+            sys_uptime: Some(netflow.sys_uptime),
+        }
+    }
+
+    pub fn from_ipfix(exporter: SocketAddr, message: &IpfixMessage) -> RecordContext {
+        RecordContext {
+            exporter,
+            source_id: message.observation_domain_id,
+            sequence: message.sequence_number,
+            export_time: message.export_time,
+            sys_uptime: None,
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> Value {
+    Value::String(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn text(bytes: &[u8]) -> Value {
+    // fixed length strings are padded with zeros
+    let end = bytes
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |pos| pos + 1);
+    Value::String(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+// bytes the parser could not type, rendered by the registry type
+fn typed_bytes(data_type: Option<DataType>, bytes: &[u8]) -> Value {
+    let unsigned = matches!(
+        data_type,
+        Some(DataType::Unsigned8)
+            | Some(DataType::Unsigned16)
+            | Some(DataType::Unsigned32)
+            | Some(DataType::Unsigned64)
+            | Some(DataType::DateTimeSeconds)
+            | Some(DataType::DateTimeMilliseconds)
+            | Some(DataType::DateTimeMicroseconds)
+            | Some(DataType::DateTimeNanoseconds)
+    );
+    if unsigned {
+        if let Some(number) = FieldValue::ByteArray(bytes.to_vec()).to_u64() {
+            return Value::from(number);
+        }
+    }
+
+    match (data_type, bytes.len()) {
+        (Some(DataType::Ipv4Address), 4) => {
+            let mut octets = [0u8; 4];
+            octets.copy_from_slice(bytes);
+            Value::String(Ipv4Addr::from(octets).to_string())
+        }
+        (Some(DataType::Ipv6Address), 16) => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(bytes);
+            Value::String(Ipv6Addr::from(octets).to_string())
+        }
+        (Some(DataType::MacAddress), 6) => Value::String(
+            bytes
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(":"),
+        ),
+        (Some(DataType::String), _) => text(bytes),
+        (Some(DataType::Boolean), 1) => Value::Bool(bytes[0] == 1),
+        (Some(DataType::Float64), 8) => {
+            let mut octets = [0u8; 8];
+            octets.copy_from_slice(bytes);
+            Number::from_f64(f64::from_be_bytes(octets)).map_or(Value::Null, Value::Number)
+        }
+        _ => hex(bytes),
+    }
+}
+
+/// Key of a field in flat objects. Reverse elements are named as in RFC 5103, e.g.
+/// `reverseOctetDeltaCount`, other enterprise fields are `field_<enterprise>_<id>` as their
+/// ids are not IANA ones.
+fn field_key(field: &FlowField) -> String {
+    match field.enterprise() {
+        Some(REVERSE_PEN) => {
+            let name = element_name(field.type_id());
+            let mut chars = name.chars();
+            match chars.next() {
+                Some(first) => format!("reverse{}{}", first.to_ascii_uppercase(), chars.as_str()),
+                None => name,
+            }
+        }
+        Some(enterprise) => format!("field_{}_{}", enterprise, field.type_id()),
+        None => element_name(field.type_id()),
+    }
+}
+
+/// Render a field value as JSON: numbers as numbers, addresses and strings as strings,
+/// other bytes as a hex string.
+pub fn field_to_json(field: &FlowField) -> Value {
+    let data_type = match field.enterprise() {
+        None | Some(REVERSE_PEN) => element(field.type_id()).map(|element| element.data_type),
+        Some(_) => None,
+    };
+
+    match field.value() {
+        FieldValue::NumField(UInt::UInt128(num)) if *num > u128::from(u64::MAX) => {
+            Value::String(num.to_string())
+        }
+        FieldValue::NumField(UInt::UIntFlex(bytes)) => match field.value().to_u64() {
+            Some(num) => Value::from(num),
+            None => hex(bytes),
+        },
+        FieldValue::NumField(_) => field.value().to_u64().map_or(Value::Null, Value::from),
+        FieldValue::Ipv4Addr(ip) => Value::String(ip.to_string()),
+        FieldValue::Ipv6Addr(ip) => Value::String(ip.to_string()),
+        FieldValue::MacAddr(mac) => Value::String(mac.to_string()),
+        FieldValue::String(s) => text(s.as_bytes()),
+        FieldValue::ByteArray(bytes) | FieldValue::Unknown(bytes) => typed_bytes(data_type, bytes),
+    }
+}
+
+/// Build one flat JSON object from a record, keyed by the element names of the registry.
+/// When a field type appears more than once, the first value is kept.
+pub fn flat_record(context: &RecordContext, template_id: u16, record: &Record) -> Value {
+    let mut object = Map::new();
+    object.insert(
+        "exporter".to_string(),
+        Value::from(context.exporter.to_string()),
+    );
+    object.insert("source_id".to_string(), Value::from(context.source_id));
+    object.insert("sequence".to_string(), Value::from(context.sequence));
+    object.insert("export_time".to_string(), Value::from(context.export_time));
+    if let Some(sys_uptime) = context.sys_uptime {
+        object.insert("sys_uptime".to_string(), Value::from(sys_uptime));
+    }
+    object.insert("template_id".to_string(), Value::from(template_id));
+    if let Record::OptionData(_) = record {
+        object.insert("options".to_string(), Value::Bool(true));
+    }
+
+    for field in record.fields() {
+        object
+            .entry(field_key(field))
+            .or_insert_with(|| field_to_json(field));
+    }
+
+    Value::Object(object)
+}
+
+/// One flat JSON object per decoded record of the packet.
+pub fn flat_netflow9(exporter: SocketAddr, netflow: &NetFlow9) -> Vec<Value> {
+    let context = RecordContext::from_netflow9(exporter, netflow);
+
+    netflow
+        .flow_sets
+        .iter()
+        .filter_map(|flowset| match flowset {
+            FlowSet::DataFlow(flow) => flow
+                .records
+                .as_ref()
+                .map(|records| (flow.flowset_id, records)),
+            _ => None,
+        })
+        .flat_map(|(id, records)| {
+            records
+                .iter()
+                .map(move |record| flat_record(&context, id, record))
+        })
+        .collect()
+}
+
+/// One flat JSON object per decoded record of the message.
+pub fn flat_ipfix(exporter: SocketAddr, message: &IpfixMessage) -> Vec<Value> {
+    let context = RecordContext::from_ipfix(exporter, message);
+
+    message
+        .sets
+        .iter()
+        .filter_map(|set| match set {
+            IpfixSet::Data(data) => data.records.as_ref().map(|records| (data.set_id, records)),
+            _ => None,
+        })
+        .flat_map(|(id, records)| {
+            records
+                .iter()
+                .map(move |record| flat_record(&context, id, record))
+        })
+        .collect()
+}
+
+fn write_lines<W: Write>(writer: &mut W, objects: Vec<Value>) -> io::Result<usize> {
+    for object in &objects {
+        serde_json::to_writer(&mut *writer, object)?;
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(objects.len())
+}
+
+/// Write every record of the packet as a line of flat JSON, return the number of lines.
+pub fn write_flat_netflow9<W: Write>(
+    writer: &mut W,
+    exporter: SocketAddr,
+    netflow: &NetFlow9,
+) -> io::Result<usize> {
+    write_lines(writer, flat_netflow9(exporter, netflow))
+}
+
+/// Write every record of the message as a line of flat JSON, return the number of lines.
+pub fn write_flat_ipfix<W: Write>(
+    writer: &mut W,
+    exporter: SocketAddr,
+    message: &IpfixMessage,
+) -> io::Result<usize> {
+    write_lines(writer, flat_ipfix(exporter, message))
+}
+
+#[cfg(test)]
+mod test_json {
+    use super::*;
+    use crate::field::FieldTypes::*;
+    use crate::ipfix::test_data as ipfix_data;
+    use crate::netflow::{test_data, TemplateCache};
+
+    fn exporter() -> SocketAddr {
+        "192.0.2.1:2055".parse().unwrap()
+    }
+
+    #[test]
+    fn test_netflow9() {
+        let netflow = TemplateCache::new()
+            .decode(exporter(), &test_data::NETFLOWV9_DATA)
+            .unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(
+            write_flat_netflow9(&mut out, exporter(), &netflow).unwrap(),
+            2
+        );
+
+        let lines: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["exporter"], "192.0.2.1:2055");
+        assert_eq!(lines[0]["sequence"], 883);
+        assert_eq!(lines[0]["export_time"], 1_523_936_618);
+        assert!(lines[0]["sys_uptime"].is_u64());
+    }
+
+    #[test]
+    fn test_ipfix() {
+        let message = ipfix_data::message();
+        let objects = flat_ipfix(exporter(), &message);
+        assert_eq!(objects.len(), 2);
+
+        let object = &objects[0];
+        assert!(object["sourceIPv4Address"].is_string());
+        assert!(object["packetDeltaCount"].is_u64());
+        assert_eq!(object["interfaceName"], "eth0");
+        assert_eq!(object["template_id"], 256);
+        assert!(object.get("sys_uptime").is_none());
+        assert!(object.get("octetDeltaCount").is_none());
+        assert_eq!(object["field_9_1"], "abcd");
+    }
+
+    #[test]
+    fn test_field_to_json() {
+        let field = |type_id, bytes: &[u8]| {
+            field_to_json(&FlowField::new(
+                type_id,
+                bytes.len() as u16,
+                FieldValue::new(type_id, bytes),
+            ))
+        };
+
+        assert_eq!(field(IPV4_SRC_ADDR, &[10, 0, 0, 1]), "10.0.0.1");
+        assert_eq!(field(L4_SRC_PORT, &[0x01, 0xbb]), 443);
+        assert_eq!(field(SRC_MAC, &[0, 1, 2, 3, 4, 0xff]), "00:01:02:03:04:ff");
+        assert_eq!(field(IF_NAME, b"eth0\0\0\0\0"), "eth0");
+        // typed by the registry, not by the parser
+        assert_eq!(field(130, &[192, 0, 2, 1]), "192.0.2.1");
+        assert_eq!(field(152, &[0, 0, 1, 0, 0, 0, 0, 0]), 1u64 << 40);
+        assert_eq!(field(400, &[0xab, 0xcd]), "abcd");
+    }
+}
@@ -12,8 +12,31 @@ extern crate serde_derive;
 #[macro_use]
 extern crate failure;
 
+pub mod aggregate;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod biflow;
+pub mod collector;
+pub mod convert;
+pub mod csv;
+pub mod dedup;
 pub mod error;
+pub mod exporter;
 pub mod field;
+pub mod filter;
 pub mod flowset;
+pub mod ipfix;
+pub mod json;
+pub mod meter;
 pub mod netflow;
+pub mod nfdump;
+pub mod pcap;
+pub mod replay;
+pub mod replicate;
+pub mod report;
+pub mod sequence;
+pub mod sink;
+#[cfg(feature = "tokio")]
+pub mod stream;
+pub mod transform;
 mod util;
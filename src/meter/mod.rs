@@ -0,0 +1,565 @@
+use crate::error::NetFlowError;
+use crate::exporter::Exporter;
+use crate::field::FieldTypes::*;
+use crate::field::{FlowField, TypeLengthField};
+use crate::flowset::{DataTemplateItem, Record};
+use crate::ipfix::{DataSet, IpfixMessage, IpfixSet};
+use crate::pcap::IpPacket;
+use crate::sink::OutputSink;
+use crate::util::address_bytes;
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// IANA elements without a Netflow v9 name
+
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ICMPV6: u8 = 58;
+const IPPROTO_SCTP: u8 = 132;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_RST: u8 = 0x04;
+
+/// Template ids of the generated templates.
+pub const IPV4_TEMPLATE_ID: u16 = 256;
+pub const IPV6_TEMPLATE_ID: u16 = 257;
+
+/// Fields identifying a flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct FlowKey {
+    pub source: IpAddr,
+    pub destination: IpAddr,
+    pub source_port: u16,
+    /// ICMP type and code for ICMP flows, as type * 256 + code
+    pub destination_port: u16,
+    pub protocol: u8,
+    /// innermost VLAN id, 0 when untagged
+    pub vlan: u16,
+    pub interface: u32,
+}
+
+/// Why a flow was expired, values of IPFIX flowEndReason.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum EndReason {
+    IdleTimeout = 1,
+    ActiveTimeout = 2,
+    EndOfFlow = 3,
+    ForcedEnd = 4,
+    LackOfResources = 5,
+}
+
+/// A flow of the cache, times are capture times since UNIX epoch.
+#[derive(Debug, Clone, Serialize)]
+pub struct Flow {
+    pub key: FlowKey,
+    pub start: Duration,
+    pub end: Duration,
+    pub packets: u64,
+    pub bytes: u64,
+    /// union of the TCP flags seen
+    pub tcp_flags: u8,
+    /// type of service of the first packet
+    pub tos: u8,
+    pub end_reason: Option<EndReason>,
+}
+
+/// Timeouts and size of the flow cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeterConfig {
+    /// a flow lasting longer is exported and started again
+    pub active_timeout: Duration,
+    /// a flow without packets for this long is exported
+    pub inactive_timeout: Duration,
+    /// the oldest flow is exported when the cache is full
+    pub max_flows: usize,
+}
+
+impl Default for MeterConfig {
+    fn default() -> Self {
+        MeterConfig {
+            active_timeout: Duration::from_secs(1800),
+            inactive_timeout: Duration::from_secs(15),
+            max_flows: 65536,
+        }
+    }
+}
+
+/// Counters of a flow meter.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MeterStats {
+    pub packets: u64,
+    pub flows: u64,
+    pub expired: u64,
+}
+
+fn transport(packet: &IpPacket) -> (u16, u16, u8) {
+    // later fragments have no transport header
+    if packet.fragment_offset != 0 {
+        return (0, 0, 0);
+    }
+
+    let payload = packet.payload;
+    match packet.protocol {
+        IPPROTO_TCP if payload.len() >= 14 => (
+            BigEndian::read_u16(payload),
+            BigEndian::read_u16(&payload[2..]),
+            payload[13],
+        ),
+        IPPROTO_UDP | IPPROTO_SCTP if payload.len() >= 4 => (
+            BigEndian::read_u16(payload),
+            BigEndian::read_u16(&payload[2..]),
+            0,
+        ),
+        IPPROTO_ICMP | IPPROTO_ICMPV6 if payload.len() >= 2 => {
+            (0, u16::from(payload[0]) << 8 | u16::from(payload[1]), 0)
+        }
+        _ => (0, 0, 0),
+    }
+}
+
+/// Builds flows from packets like a router does, see softflowd.
+/// Flows are keyed by 5-tuple, VLAN and interface and expire on the active and inactive timeouts,
+/// or at once when a TCP FIN or RST is seen.
+/// Time only moves with the packets and `expire`, so a capture can be metered faster than real time.
+pub struct FlowMeter {
+    config: MeterConfig,
+    flows: HashMap<FlowKey, Flow>,
+    /// flows by last packet time, the first is evicted when the cache is full
+    by_end: BTreeSet<(Duration, FlowKey)>,
+    expired: Vec<Flow>,
+    stats: MeterStats,
+}
+
+impl FlowMeter {
+    pub fn new(config: MeterConfig) -> FlowMeter {
+        FlowMeter {
+            config,
+            flows: HashMap::new(),
+            by_end: BTreeSet::new(),
+            expired: Vec::new(),
+            stats: MeterStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &MeterStats {
+        &self.stats
+    }
+
+    /// Number of flows in the cache.
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    fn end(&mut self, key: &FlowKey, reason: EndReason) {
+        if let Some(mut flow) = self.flows.remove(key) {
+            self.by_end.remove(&(flow.end, flow.key));
+            flow.end_reason = Some(reason);
+            self.stats.expired += 1;
+            self.expired.push(flow);
+        }
+    }
+
+    /// Account a packet captured at the time on the interface.
+    pub fn observe(&mut self, packet: &IpPacket, timestamp: Duration, interface: u32) {
+        self.stats.packets += 1;
+
+        let (source_port, destination_port, tcp_flags) = transport(packet);
+        let key = FlowKey {
+            source: packet.source,
+            destination: packet.destination,
+            source_port,
+            destination_port,
+            protocol: packet.protocol,
+            vlan: packet.vlans.last().cloned().unwrap_or(0),
+            interface,
+        };
+
+        if let Some(flow) = self.flows.get(&key) {
+            if timestamp.saturating_sub(flow.end) >= self.config.inactive_timeout {
+                self.end(&key, EndReason::IdleTimeout);
+            } else if timestamp.saturating_sub(flow.start) >= self.config.active_timeout {
+                self.end(&key, EndReason::ActiveTimeout);
+            }
+        }
+
+        if !self.flows.contains_key(&key) && self.flows.len() >= self.config.max_flows {
+            let oldest = self.by_end.iter().next().map(|(_, key)| *key);
+            if let Some(oldest) = oldest {
+                self.end(&oldest, EndReason::LackOfResources);
+            }
+        }
+
+        let stats = &mut self.stats;
+        let flow = self.flows.entry(key).or_insert_with(|| {
+            stats.flows += 1;
+            Flow {
+                key,
+                start: timestamp,
+                end: timestamp,
+                packets: 0,
+                bytes: 0,
+                tcp_flags: 0,
+                tos: packet.tos,
+                end_reason: None,
+            }
+        });
+        if flow.packets == 0 || timestamp > flow.end {
+            self.by_end.remove(&(flow.end, key));
+            flow.end = flow.end.max(timestamp);
+            self.by_end.insert((flow.end, key));
+        }
+        flow.packets += 1;
+        flow.bytes += u64::from(packet.length);
+        flow.tcp_flags |= tcp_flags;
+
+        if tcp_flags & (TCP_FIN | TCP_RST) != 0 {
+            self.end(&key, EndReason::EndOfFlow);
+        }
+    }
+
+    /// Return the flows ended so far and those timed out at the time.
+    pub fn expire(&mut self, now: Duration) -> Vec<Flow> {
+        let timed_out: Vec<(FlowKey, EndReason)> = self
+            .flows
+            .values()
+            .filter_map(|flow| {
+                if now.saturating_sub(flow.end) >= self.config.inactive_timeout {
+                    Some((flow.key, EndReason::IdleTimeout))
+                } else if now.saturating_sub(flow.start) >= self.config.active_timeout {
+                    Some((flow.key, EndReason::ActiveTimeout))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        for (key, reason) in timed_out {
+            self.end(&key, reason);
+        }
+
+        self.take_expired()
+    }
+
+    /// Return every flow, e.g. at the end of a capture.
+    pub fn flush(&mut self) -> Vec<Flow> {
+        let keys: Vec<FlowKey> = self.flows.keys().cloned().collect();
+        for key in keys {
+            self.end(&key, EndReason::ForcedEnd);
+        }
+
+        self.take_expired()
+    }
+
+    fn take_expired(&mut self) -> Vec<Flow> {
+        let mut flows = std::mem::take(&mut self.expired);
+        flows.sort_by_key(|flow| flow.start);
+        flows
+    }
+}
+
+/// Export format of metered flows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeterFormat {
+    /// times are FIRST_SWITCHED and LAST_SWITCHED relative to the exporter boot time
+    Netflow9,
+    /// times are flowStartMilliseconds and flowEndMilliseconds, with flowEndReason
+    Ipfix,
+}
+
+/// The template flows of the address family are exported with.
+pub fn flow_template(ipv6: bool, format: MeterFormat) -> DataTemplateItem {
+    let (template_id, source, destination, address_len) = if ipv6 {
+        (IPV6_TEMPLATE_ID, IPV6_SRC_ADDR, IPV6_DST_ADDR, 16)
+    } else {
+        (IPV4_TEMPLATE_ID, IPV4_SRC_ADDR, IPV4_DST_ADDR, 4)
+    };
+
+    let mut fields = vec![
+        TypeLengthField::new(source, address_len),
+        TypeLengthField::new(destination, address_len),
+        TypeLengthField::new(L4_SRC_PORT, 2),
+        TypeLengthField::new(L4_DST_PORT, 2),
+        TypeLengthField::new(PROTOCOL, 1),
+        TypeLengthField::new(TOS, 1),
+        TypeLengthField::new(TCP_FLAGS, 1),
+        TypeLengthField::new(INPUT_SNMP, 4),
+        TypeLengthField::new(SRC_VLAN, 2),
+        TypeLengthField::new(IN_PKTS, 8),
+        TypeLengthField::new(IN_BYTES, 8),
+    ];
+    match format {
+        MeterFormat::Netflow9 => {
+            fields.push(TypeLengthField::new(FIRST_SWITCHED, 4));
+            fields.push(TypeLengthField::new(LAST_SWITCHED, 4));
+        }
+        MeterFormat::Ipfix => {
+            fields.push(TypeLengthField::new(FLOW_START_MILLISECONDS, 8));
+            fields.push(TypeLengthField::new(FLOW_END_MILLISECONDS, 8));
+            fields.push(TypeLengthField::new(FLOW_END_REASON, 1));
+        }
+    }
+
+    DataTemplateItem::new(template_id, fields)
+}
+
+impl Flow {
+    pub fn is_ipv6(&self) -> bool {
+        self.key.source.is_ipv6()
+    }
+
+    /// The record of the flow under `flow_template`.
+    /// Netflow v9 times are milliseconds since the boot time, IPFIX times are absolute.
+    pub fn to_record(&self, format: MeterFormat, boot: SystemTime) -> Record {
+        let (source_id, destination_id) = if self.is_ipv6() {
+            (IPV6_SRC_ADDR, IPV6_DST_ADDR)
+        } else {
+            (IPV4_SRC_ADDR, IPV4_DST_ADDR)
+        };
+        let mut fields = vec![
+            FlowField::with_bytes(source_id, &address_bytes(self.key.source)),
+            FlowField::with_bytes(destination_id, &address_bytes(self.key.destination)),
+            FlowField::with_bytes(L4_SRC_PORT, &self.key.source_port.to_be_bytes()),
+            FlowField::with_bytes(L4_DST_PORT, &self.key.destination_port.to_be_bytes()),
+            FlowField::with_bytes(PROTOCOL, &[self.key.protocol]),
+            FlowField::with_bytes(TOS, &[self.tos]),
+            FlowField::with_bytes(TCP_FLAGS, &[self.tcp_flags]),
+            FlowField::with_bytes(INPUT_SNMP, &self.key.interface.to_be_bytes()),
+            FlowField::with_bytes(SRC_VLAN, &self.key.vlan.to_be_bytes()),
+            FlowField::with_bytes(IN_PKTS, &self.packets.to_be_bytes()),
+            FlowField::with_bytes(IN_BYTES, &self.bytes.to_be_bytes()),
+        ];
+
+        match format {
+            MeterFormat::Netflow9 => {
+                let boot = boot.duration_since(UNIX_EPOCH).unwrap_or_default();
+                let uptime = |time: Duration| time.saturating_sub(boot).as_millis() as u32;
+                fields.push(FlowField::with_bytes(
+                    FIRST_SWITCHED,
+                    &uptime(self.start).to_be_bytes(),
+                ));
+                fields.push(FlowField::with_bytes(
+                    LAST_SWITCHED,
+                    &uptime(self.end).to_be_bytes(),
+                ));
+            }
+            MeterFormat::Ipfix => {
+                let millis = |time: Duration| time.as_millis() as u64;
+                let reason = self.end_reason.unwrap_or(EndReason::ForcedEnd) as u8;
+                fields.push(FlowField::with_bytes(
+                    FLOW_START_MILLISECONDS,
+                    &millis(self.start).to_be_bytes(),
+                ));
+                fields.push(FlowField::with_bytes(
+                    FLOW_END_MILLISECONDS,
+                    &millis(self.end).to_be_bytes(),
+                ));
+                fields.push(FlowField::with_bytes(FLOW_END_REASON, &[reason]));
+            }
+        }
+
+        Record::make_data(fields)
+    }
+}
+
+/// Queue flows on a Netflow v9 exporter, the templates are added when missing.
+/// The exporter clock should follow the capture, see `Exporter::set_clock`.
+pub fn export_netflow9<S: OutputSink>(
+    exporter: &mut Exporter<S>,
+    flows: &[Flow],
+) -> Result<(), NetFlowError> {
+    let boot = exporter.boot_time();
+
+    for flow in flows {
+        let template = flow_template(flow.is_ipv6(), MeterFormat::Netflow9);
+        if !exporter.has_template(template.template_id) {
+            exporter.add_template(template.clone())?;
+        }
+        exporter.push(
+            template.template_id,
+            flow.to_record(MeterFormat::Netflow9, boot),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Build IPFIX messages carrying the flows, each with the templates it uses.
+/// `sequence` is the number of data records sent before, the next one is returned.
+pub fn ipfix_messages(
+    flows: &[Flow],
+    export_time: u32,
+    domain: u32,
+    sequence: &mut u32,
+    max_records: usize,
+) -> Vec<IpfixMessage> {
+    let mut messages = Vec::new();
+
+    for chunk in flows.chunks(max_records.max(1)) {
+        let mut sets = Vec::new();
+
+        for ipv6 in &[false, true] {
+            let records: Vec<Record> = chunk
+                .iter()
+                .filter(|flow| flow.is_ipv6() == *ipv6)
+                .map(|flow| flow.to_record(MeterFormat::Ipfix, UNIX_EPOCH))
+                .collect();
+            if records.is_empty() {
+                continue;
+            }
+
+            let template = flow_template(*ipv6, MeterFormat::Ipfix);
+            sets.push(IpfixSet::Template(vec![template.clone()]));
+            sets.push(IpfixSet::Data(DataSet::from_data_records(
+                &template, records,
+            )));
+        }
+
+        messages.push(IpfixMessage::new(export_time, *sequence, domain, sets));
+        *sequence = sequence.wrapping_add(chunk.len() as u32);
+    }
+
+    messages
+}
+
+#[cfg(test)]
+mod test_meter {
+    use super::*;
+    use crate::exporter::ExporterConfig;
+    use crate::netflow::TemplateCache;
+    use crate::sink::WriteSink;
+
+    fn tcp(source: [u8; 4], flags: u8, payload_len: usize) -> Vec<u8> {
+        let total_len = 40 + payload_len;
+        let mut ip = vec![0u8; total_len];
+        ip[0] = 0x45;
+        ip[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+        ip[8] = 64;
+        ip[9] = IPPROTO_TCP;
+        ip[12..16].copy_from_slice(&source);
+        ip[16..20].copy_from_slice(&[192, 0, 2, 100]);
+        ip[20..22].copy_from_slice(&40000u16.to_be_bytes());
+        ip[22..24].copy_from_slice(&443u16.to_be_bytes());
+        ip[32] = 0x50;
+        ip[33] = flags;
+        ip
+    }
+
+    fn observe(meter: &mut FlowMeter, frame: &[u8], secs: u64) {
+        let packet = crate::pcap::parse_ip(crate::pcap::LINKTYPE_RAW, frame).unwrap();
+        meter.observe(&packet, Duration::from_secs(secs), 3);
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let config = MeterConfig {
+            active_timeout: Duration::from_secs(60),
+            inactive_timeout: Duration::from_secs(10),
+            max_flows: 2,
+        };
+        let mut meter = FlowMeter::new(config);
+
+        observe(&mut meter, &tcp([10, 0, 0, 1], 0x02, 0), 100);
+        observe(&mut meter, &tcp([10, 0, 0, 1], 0x10, 100), 105);
+        observe(&mut meter, &tcp([10, 0, 0, 2], 0x10, 0), 106);
+        assert_eq!(meter.len(), 2);
+        assert!(meter.expire(Duration::from_secs(108)).is_empty());
+
+        // inactive timeout
+        let flows = meter.expire(Duration::from_secs(116));
+        assert_eq!(flows.len(), 2);
+        assert_eq!(flows[0].packets, 2);
+        assert_eq!(flows[0].bytes, 180);
+        assert_eq!(flows[0].tcp_flags, 0x12);
+        assert_eq!(flows[0].key.interface, 3);
+        assert_eq!(flows[0].key.destination_port, 443);
+        assert_eq!(flows[0].end_reason, Some(EndReason::IdleTimeout));
+
+        // FIN ends the flow at once
+        observe(&mut meter, &tcp([10, 0, 0, 1], 0x10, 0), 200);
+        observe(&mut meter, &tcp([10, 0, 0, 1], 0x11, 0), 201);
+        let flows = meter.expire(Duration::from_secs(201));
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].end_reason, Some(EndReason::EndOfFlow));
+
+        // cache is full, the flow seen least recently goes
+        observe(&mut meter, &tcp([10, 0, 0, 1], 0x10, 0), 300);
+        observe(&mut meter, &tcp([10, 0, 0, 2], 0x10, 0), 301);
+        observe(&mut meter, &tcp([10, 0, 0, 1], 0x10, 0), 302);
+        observe(&mut meter, &tcp([10, 0, 0, 3], 0x10, 0), 302);
+        let flows = meter.expire(Duration::from_secs(302));
+        assert_eq!(flows[0].end_reason, Some(EndReason::LackOfResources));
+        assert_eq!(flows[0].key.source, "10.0.0.2".parse::<IpAddr>().unwrap());
+
+        // active timeout
+        for secs in (303..=370).step_by(5) {
+            observe(&mut meter, &tcp([10, 0, 0, 2], 0x10, 0), secs);
+        }
+        let flows = meter.expire(Duration::from_secs(370));
+        assert!(flows
+            .iter()
+            .any(|flow| flow.end_reason == Some(EndReason::ActiveTimeout)));
+
+        assert!(!meter.flush().is_empty());
+        assert!(meter.is_empty());
+    }
+
+    #[test]
+    fn test_export() {
+        let mut meter = FlowMeter::new(MeterConfig::default());
+        observe(&mut meter, &tcp([10, 0, 0, 1], 0x10, 60), 1_000);
+        observe(&mut meter, &tcp([10, 0, 0, 1], 0x10, 60), 1_002);
+        let flows = meter.flush();
+
+        let mut exporter = Exporter::new(WriteSink::new(Vec::new()), ExporterConfig::default());
+        exporter.set_boot_time(UNIX_EPOCH + Duration::from_secs(900));
+        exporter.set_clock(Some(UNIX_EPOCH + Duration::from_secs(1_010)));
+        export_netflow9(&mut exporter, &flows).unwrap();
+        let bytes = exporter.finish().unwrap().into_inner();
+
+        let mut cache = TemplateCache::new();
+        let netflow = cache
+            .decode("192.0.2.1:2055".parse().unwrap(), &bytes)
+            .unwrap();
+        assert_eq!(netflow.sys_uptime, 110_000);
+        let record = match &netflow.flow_sets[1] {
+            crate::flowset::FlowSet::DataFlow(dataflow) => &dataflow.records.as_ref().unwrap()[0],
+            flowset => panic!("unexpected flowset: {:?}", flowset),
+        };
+        let value = |id| record.get(id).unwrap().value().to_u64().unwrap();
+        assert_eq!(value(FIRST_SWITCHED), 100_000);
+        assert_eq!(value(LAST_SWITCHED), 102_000);
+        assert_eq!(value(IN_BYTES), 200);
+
+        let mut sequence = 5;
+        let messages = ipfix_messages(&flows, 1_010, 1, &mut sequence, 10);
+        assert_eq!(sequence, 6);
+        let mut templates = crate::ipfix::IpfixTemplates::new();
+        let message = templates.decode(&messages[0].to_bytes()).unwrap();
+        assert_eq!(message.sequence_number, 5);
+        match &message.sets[1] {
+            IpfixSet::Data(data) => {
+                let record = &data.records.as_ref().unwrap()[0];
+                assert_eq!(
+                    record
+                        .get(FLOW_START_MILLISECONDS)
+                        .unwrap()
+                        .value()
+                        .to_u64(),
+                    Some(1_000_000)
+                );
+                assert_eq!(
+                    record.get(FLOW_END_REASON).unwrap().value().to_u64(),
+                    Some(EndReason::ForcedEnd as u64)
+                );
+            }
+            set => panic!("unexpected set: {:?}", set),
+        }
+    }
+}
@@ -1,12 +1,28 @@
 #[cfg(test)]
-mod test_data;
+pub(crate) mod test_data;
+
+mod template_cache;
+pub use self::template_cache::*;
+
+mod v5;
+pub use self::v5::*;
 
 use crate::error::NetFlowError;
 use crate::flowset::FlowSet;
 use crate::util::{take_u16, take_u32, u16_to_bytes, u32_to_bytes};
+use std::convert::TryFrom;
 
 // Netflow V9 -> Header + (Template* Option* Data*)
 
+/// How the header count is checked when a packet is parsed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CountCheck {
+    /// a wrong count is logged, many exporters count flowsets instead of records
+    Lenient,
+    /// a wrong count fails the parse with `NetFlowError::CountMismatch`
+    Strict,
+}
+
 // TODO: need mut?
 // TODO: enum NetFlow or abstract with Netflow struct
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +36,11 @@ pub struct NetFlow9 {
     pub flow_sets: Vec<FlowSet>,
 }
 
+// a header count larger than a u16 stays at the maximum
+fn saturate(count: usize) -> u16 {
+    u16::try_from(count).unwrap_or(u16::MAX)
+}
+
 impl NetFlow9 {
     pub fn new(
         sys_uptime: u32,
@@ -28,18 +49,42 @@ impl NetFlow9 {
         source_id: u32,
         flowsets: Vec<FlowSet>,
     ) -> Self {
-        NetFlow9 {
+        let mut netflow = NetFlow9 {
             version: 9,
-            count: flowsets.len() as u16,
+            count: 0,
             sys_uptime,
             timestamp,
             flow_sequence,
             source_id,
             flow_sets: flowsets,
-        }
+        };
+        netflow.count = netflow.min_record_count();
+
+        netflow
     }
 
+    /// Parse a packet, a header count not matching the flowsets is only logged.
     pub fn from_bytes(payload: &[u8]) -> Result<Self, NetFlowError> {
+        NetFlow9::from_bytes_checked(payload, CountCheck::Lenient)
+    }
+
+    /// Parse a packet and check the header count.
+    /// Records of dataflows are not known before their template is applied,
+    /// so each one counts as at least a record here, `check_count` does the exact check later.
+    pub fn from_bytes_checked(payload: &[u8], check: CountCheck) -> Result<Self, NetFlowError> {
+        let netflow = NetFlow9::parse(payload)?;
+
+        if let Err(e) = netflow.check_count() {
+            match check {
+                CountCheck::Lenient => debug!("{}", e),
+                CountCheck::Strict => return Err(e),
+            }
+        }
+
+        Ok(netflow)
+    }
+
+    fn parse(payload: &[u8]) -> Result<Self, NetFlowError> {
         let (rest, version) = take_u16(payload)?;
 
         if version == 9 {
@@ -72,7 +117,8 @@ impl NetFlow9 {
         u16_to_bytes(self.version, &mut u16_buf);
         bytes.append(&mut u16_buf.to_vec());
 
-        u16_to_bytes(self.count, &mut u16_buf);
+        // the stored count is kept only while dataflows are not decoded
+        u16_to_bytes(self.record_count().unwrap_or(self.count), &mut u16_buf);
         bytes.append(&mut u16_buf.to_vec());
 
         u32_to_bytes(self.sys_uptime, &mut u32_buf);
@@ -98,6 +144,48 @@ impl NetFlow9 {
         self.to_bytes().len()
     }
 
+    /// Total of template, option template and data records (RFC 3954, section 5.1),
+    /// None while a dataflow is not decoded. Saturates at the most a header can count.
+    pub fn record_count(&self) -> Option<u16> {
+        self.records().map(saturate)
+    }
+
+    fn records(&self) -> Option<usize> {
+        self.flow_sets.iter().map(FlowSet::record_count).sum()
+    }
+
+    // undecoded dataflows hold at least a record
+    fn min_records(&self) -> usize {
+        self.flow_sets
+            .iter()
+            .map(|flowset| flowset.record_count().unwrap_or(1))
+            .sum()
+    }
+
+    fn min_record_count(&self) -> u16 {
+        saturate(self.min_records())
+    }
+
+    /// Check the header count against the records, exactly when every dataflow is decoded.
+    pub fn check_count(&self) -> Result<(), NetFlowError> {
+        let count = usize::from(self.count);
+        let valid = match self.records() {
+            Some(records) => records == count,
+            None => count >= self.min_records(),
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(NetFlowError::CountMismatch {
+                header: self.count,
+                records: self
+                    .record_count()
+                    .unwrap_or_else(|| self.min_record_count()),
+            })
+        }
+    }
+
     pub fn is_padding(&self) -> bool {
         self.flow_sets
             .as_slice()
@@ -124,6 +212,7 @@ impl NetFlow9 {
 #[cfg(test)]
 mod test_netflow {
     use super::test_data;
+    use crate::flowset::{DataFlow, Record};
     use crate::netflow::*;
 
     #[test]
@@ -173,4 +262,44 @@ mod test_netflow {
         let netflow_len = netflow.byte_length();
         assert_eq!(netflow_len, packet_bytes.len());
     }
+
+    #[test]
+    fn test_count() {
+        let exporter = "192.0.2.1:2055".parse().unwrap();
+        let netflow = NetFlow9::from_bytes(&test_data::NETFLOWV9_DATA).unwrap();
+        assert_eq!(netflow.record_count(), None);
+        assert!(netflow.check_count().is_ok());
+
+        let mut decoded = TemplateCache::new()
+            .decode(exporter, &test_data::NETFLOWV9_DATA)
+            .unwrap();
+        assert_eq!(decoded.record_count(), Some(7));
+
+        // the count is computed from the records when serialized
+        decoded.count = 1;
+        assert!(decoded.check_count().is_err());
+        let bytes = decoded.to_bytes();
+        assert_eq!(&bytes[2..4], &[0, 7]);
+
+        let rebuilt = NetFlow9::new(0, 0, 0, 0, decoded.flow_sets.clone());
+        assert_eq!(rebuilt.count, 7);
+
+        // more records than the header counts
+        let records = vec![Record::make_data(vec![]); 70_000];
+        let large = NetFlow9::new(0, 0, 0, 0, vec![DataFlow::new(256, records).into()]);
+        assert_eq!(large.count, u16::MAX);
+        assert_eq!(large.record_count(), Some(u16::MAX));
+        assert!(large.check_count().is_err());
+        assert_eq!(&large.to_bytes()[2..4], &[0xff, 0xff]);
+
+        let mut bytes = test_data::NETFLOWV9_DATA.to_vec();
+        bytes[3] = 3;
+        assert!(NetFlow9::from_bytes(&bytes).is_ok());
+        match NetFlow9::from_bytes_checked(&bytes, CountCheck::Strict) {
+            Err(NetFlowError::CountMismatch { header, records }) => {
+                assert_eq!((header, records), (3, 7))
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
 }
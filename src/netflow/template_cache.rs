@@ -0,0 +1,175 @@
+use super::NetFlow9;
+use crate::error::NetFlowError;
+use crate::flowset::{DataTemplateItem, FlowSet, OptionTemplateItem};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+/// Templates are scoped by exporter address and source id (RFC 3954, section 5.1).
+type TemplateKey = (SocketAddr, u32, u16);
+
+/// Keeps templates received from exporters and decodes dataflows with them.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateCache {
+    data_templates: HashMap<TemplateKey, DataTemplateItem>,
+    option_templates: HashMap<TemplateKey, OptionTemplateItem>,
+}
+
+impl TemplateCache {
+    pub fn new() -> TemplateCache {
+        TemplateCache::default()
+    }
+
+    /// Store every template in the packet, replacing old ones with the same id.
+    pub fn learn(&mut self, exporter: SocketAddr, netflow: &NetFlow9) {
+        for flowset in &netflow.flow_sets {
+            match flowset {
+                FlowSet::DataTemplate(template) => {
+                    for item in &template.templates {
+                        let key = (exporter, netflow.source_id, item.template_id);
+                        debug!("learned template: {:?}", key);
+                        self.option_templates.remove(&key);
+                        self.data_templates.insert(key, item.clone());
+                    }
+                }
+                FlowSet::OptionTemplate(option) => {
+                    let key = (exporter, netflow.source_id, option.templates.template_id);
+                    debug!("learned option template: {:?}", key);
+                    self.data_templates.remove(&key);
+                    self.option_templates.insert(key, option.templates.clone());
+                }
+                FlowSet::DataFlow(_) => (),
+            }
+        }
+    }
+
+    /// Decode dataflows that have no records yet.
+    /// Return the number of dataflows left undecoded because the template is unknown.
+    pub fn apply(&self, exporter: SocketAddr, netflow: &mut NetFlow9) -> usize {
+        let source_id = netflow.source_id;
+        let mut missing = 0;
+
+        for flowset in &mut netflow.flow_sets {
+            if let FlowSet::DataFlow(dataflow) = flowset {
+                if dataflow.records.is_some() {
+                    continue;
+                }
+
+                let key = (exporter, source_id, dataflow.flowset_id);
+                let res = if let Some(template) = self.data_templates.get(&key) {
+                    dataflow.apply_template(template)
+                } else if let Some(template) = self.option_templates.get(&key) {
+                    dataflow.apply_template(template)
+                } else {
+                    Err(NetFlowError::TemplateNotFound)
+                };
+
+                if let Err(e) = res {
+                    debug!("cannot decode dataflow {:?}: {}", key, e);
+                    missing += 1;
+                }
+            }
+        }
+
+        missing
+    }
+
+    /// Parse a packet, learn its templates and decode its dataflows.
+    pub fn decode(
+        &mut self,
+        exporter: SocketAddr,
+        payload: &[u8],
+    ) -> Result<NetFlow9, NetFlowError> {
+        let mut netflow = NetFlow9::from_bytes(payload)?;
+        self.learn(exporter, &netflow);
+        self.apply(exporter, &mut netflow);
+
+        Ok(netflow)
+    }
+
+    pub fn get_data_template(
+        &self,
+        exporter: SocketAddr,
+        source_id: u32,
+        template_id: u16,
+    ) -> Option<&DataTemplateItem> {
+        self.data_templates.get(&(exporter, source_id, template_id))
+    }
+
+    pub fn get_option_template(
+        &self,
+        exporter: SocketAddr,
+        source_id: u32,
+        template_id: u16,
+    ) -> Option<&OptionTemplateItem> {
+        self.option_templates
+            .get(&(exporter, source_id, template_id))
+    }
+
+    /// Drop every template of the exporter, e.g. when it restarted.
+    pub fn remove_exporter(&mut self, exporter: SocketAddr) {
+        self.data_templates.retain(|key, _| key.0 != exporter);
+        self.option_templates.retain(|key, _| key.0 != exporter);
+    }
+
+    pub fn len(&self) -> usize {
+        self.data_templates.len() + self.option_templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test_template_cache {
+    use super::TemplateCache;
+    use crate::flowset::FlowSet;
+    use crate::netflow::{test_data, NetFlow9};
+    use std::net::SocketAddr;
+
+    fn exporter() -> SocketAddr {
+        "192.0.2.1:2055".parse().unwrap()
+    }
+
+    fn count_records(netflow: &NetFlow9) -> usize {
+        netflow
+            .flow_sets
+            .iter()
+            .filter_map(|flowset| match flowset {
+                FlowSet::DataFlow(dataflow) => dataflow.records.as_ref().map(|r| r.len()),
+                _ => None,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut cache = TemplateCache::new();
+        let netflow = cache
+            .decode(exporter(), &test_data::NETFLOWV9_DATA)
+            .unwrap();
+
+        assert_eq!(cache.len(), 5);
+        assert!(cache.get_data_template(exporter(), 0, 1024).is_some());
+        assert!(cache.get_option_template(exporter(), 0, 4096).is_some());
+        assert_eq!(count_records(&netflow), 2);
+    }
+
+    #[test]
+    fn test_scope() {
+        let mut cache = TemplateCache::new();
+        cache
+            .decode(exporter(), &test_data::NETFLOWV9_DATA)
+            .unwrap();
+
+        let other: SocketAddr = "192.0.2.2:2055".parse().unwrap();
+        let mut netflow = NetFlow9::from_bytes(&test_data::NETFLOWV9_DATA).unwrap();
+        netflow.flow_sets.retain(|flowset| flowset.is_dataflow());
+        assert_eq!(cache.apply(other, &mut netflow), 2);
+        assert_eq!(cache.apply(exporter(), &mut netflow), 0);
+        assert_eq!(count_records(&netflow), 2);
+
+        cache.remove_exporter(exporter());
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use crate::error::NetFlowError;
+use byteorder::{BigEndian, ByteOrder};
+use std::net::Ipv4Addr;
+
+const V5_HEADER_LEN: usize = 24;
+const V5_RECORD_LEN: usize = 48;
+
+/// Most records a v5 packet carries.
+pub const V5_MAX_RECORDS: usize = 30;
+
+/// A Netflow v5 packet, the fixed format before templates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetFlow5 {
+    pub version: u16,
+    pub count: u16,
+    pub sys_uptime: u32,
+    pub unix_secs: u32,
+    pub unix_nsecs: u32,
+    /// number of flows sent before this packet, not packets as in v9
+    pub flow_sequence: u32,
+    pub engine_type: u8,
+    pub engine_id: u8,
+    /// sampling mode in the first 2 bits, interval in the other 14
+    pub sampling_interval: u16,
+    pub records: Vec<NetFlow5Record>,
+}
+
+/// A flow of a v5 packet, times are milliseconds of `sys_uptime`, which wraps after about
+/// 49 days, so `first` is larger than it for a flow started before the wrap.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetFlow5Record {
+    pub src_addr: Ipv4Addr,
+    pub dst_addr: Ipv4Addr,
+    pub next_hop: Ipv4Addr,
+    pub input: u16,
+    pub output: u16,
+    pub packets: u32,
+    pub octets: u32,
+    pub first: u32,
+    pub last: u32,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub tcp_flags: u8,
+    pub protocol: u8,
+    pub tos: u8,
+    pub src_as: u16,
+    pub dst_as: u16,
+    pub src_mask: u8,
+    pub dst_mask: u8,
+}
+
+impl NetFlow5Record {
+    fn from_bytes(b: &[u8]) -> NetFlow5Record {
+        let ip = |offset: usize| Ipv4Addr::from(BigEndian::read_u32(&b[offset..]));
+
+        NetFlow5Record {
+            src_addr: ip(0),
+            dst_addr: ip(4),
+            next_hop: ip(8),
+            input: BigEndian::read_u16(&b[12..]),
+            output: BigEndian::read_u16(&b[14..]),
+            packets: BigEndian::read_u32(&b[16..]),
+            octets: BigEndian::read_u32(&b[20..]),
+            first: BigEndian::read_u32(&b[24..]),
+            last: BigEndian::read_u32(&b[28..]),
+            src_port: BigEndian::read_u16(&b[32..]),
+            dst_port: BigEndian::read_u16(&b[34..]),
+            tcp_flags: b[37],
+            protocol: b[38],
+            tos: b[39],
+            src_as: BigEndian::read_u16(&b[40..]),
+            dst_as: BigEndian::read_u16(&b[42..]),
+            src_mask: b[44],
+            dst_mask: b[45],
+        }
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0..4].copy_from_slice(&self.src_addr.octets());
+        b[4..8].copy_from_slice(&self.dst_addr.octets());
+        b[8..12].copy_from_slice(&self.next_hop.octets());
+        BigEndian::write_u16(&mut b[12..], self.input);
+        BigEndian::write_u16(&mut b[14..], self.output);
+        BigEndian::write_u32(&mut b[16..], self.packets);
+        BigEndian::write_u32(&mut b[20..], self.octets);
+        BigEndian::write_u32(&mut b[24..], self.first);
+        BigEndian::write_u32(&mut b[28..], self.last);
+        BigEndian::write_u16(&mut b[32..], self.src_port);
+        BigEndian::write_u16(&mut b[34..], self.dst_port);
+        b[37] = self.tcp_flags;
+        b[38] = self.protocol;
+        b[39] = self.tos;
+        BigEndian::write_u16(&mut b[40..], self.src_as);
+        BigEndian::write_u16(&mut b[42..], self.dst_as);
+        b[44] = self.src_mask;
+        b[45] = self.dst_mask;
+    }
+}
+
+impl NetFlow5 {
+    pub fn from_bytes(payload: &[u8]) -> Result<NetFlow5, NetFlowError> {
+        if payload.len() < V5_HEADER_LEN {
+            return Err(NetFlowError::InvalidLength);
+        }
+
+        let version = BigEndian::read_u16(payload);
+        if version != 5 {
+            return Err(NetFlowError::InvalidFieldValue);
+        }
+
+        let count = BigEndian::read_u16(&payload[2..]);
+        if count as usize > V5_MAX_RECORDS
+            || payload.len() < V5_HEADER_LEN + count as usize * V5_RECORD_LEN
+        {
+            return Err(NetFlowError::InvalidLength);
+        }
+
+        let records = payload[V5_HEADER_LEN..]
+            .chunks_exact(V5_RECORD_LEN)
+            .take(count as usize)
+            .map(NetFlow5Record::from_bytes)
+            .collect();
+
+        Ok(NetFlow5 {
+            version,
+            count,
+            sys_uptime: BigEndian::read_u32(&payload[4..]),
+            unix_secs: BigEndian::read_u32(&payload[8..]),
+            unix_nsecs: BigEndian::read_u32(&payload[12..]),
+            flow_sequence: BigEndian::read_u32(&payload[16..]),
+            engine_type: payload[20],
+            engine_id: payload[21],
+            sampling_interval: BigEndian::read_u16(&payload[22..]),
+            records,
+        })
+    }
+
+    /// Serialize with the count taken from the records.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; V5_HEADER_LEN + self.records.len() * V5_RECORD_LEN];
+
+        BigEndian::write_u16(&mut bytes, self.version);
+        BigEndian::write_u16(&mut bytes[2..], self.records.len() as u16);
+        BigEndian::write_u32(&mut bytes[4..], self.sys_uptime);
+        BigEndian::write_u32(&mut bytes[8..], self.unix_secs);
+        BigEndian::write_u32(&mut bytes[12..], self.unix_nsecs);
+        BigEndian::write_u32(&mut bytes[16..], self.flow_sequence);
+        bytes[20] = self.engine_type;
+        bytes[21] = self.engine_id;
+        BigEndian::write_u16(&mut bytes[22..], self.sampling_interval);
+
+        for (record, chunk) in self
+            .records
+            .iter()
+            .zip(bytes[V5_HEADER_LEN..].chunks_exact_mut(V5_RECORD_LEN))
+        {
+            record.to_bytes(chunk);
+        }
+
+        bytes
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_v5 {
+    use super::*;
+
+    pub(crate) fn record(src: [u8; 4], first: u32, last: u32) -> NetFlow5Record {
+        NetFlow5Record {
+            src_addr: Ipv4Addr::from(src),
+            dst_addr: Ipv4Addr::new(192, 0, 2, 100),
+            next_hop: Ipv4Addr::new(192, 0, 2, 254),
+            input: 3,
+            output: 4,
+            packets: 10,
+            octets: 1500,
+            first,
+            last,
+            src_port: 40000,
+            dst_port: 443,
+            tcp_flags: 0x1b,
+            protocol: 6,
+            tos: 0,
+            src_as: 64500,
+            dst_as: 64501,
+            src_mask: 24,
+            dst_mask: 24,
+        }
+    }
+
+    pub(crate) fn packet(records: Vec<NetFlow5Record>) -> NetFlow5 {
+        NetFlow5 {
+            version: 5,
+            count: records.len() as u16,
+            sys_uptime: 100_000,
+            unix_secs: 1_600_000_000,
+            unix_nsecs: 500_000_000,
+            flow_sequence: 42,
+            engine_type: 1,
+            engine_id: 2,
+            sampling_interval: 0x4000 | 100,
+            records,
+        }
+    }
+
+    #[test]
+    fn test_v5() {
+        let netflow = packet(vec![record([10, 0, 0, 1], 90_000, 95_000); 2]);
+        let bytes = netflow.to_bytes();
+        assert_eq!(bytes.len(), 24 + 2 * 48);
+        assert_eq!(NetFlow5::from_bytes(&bytes).unwrap(), netflow);
+
+        assert!(NetFlow5::from_bytes(&bytes[..100]).is_err());
+        let mut v9 = bytes.clone();
+        v9[1] = 9;
+        assert!(NetFlow5::from_bytes(&v9).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+// LZO1X decompression, blocks written by nfdump with lzo1x_1_compress.
+// Follows lzo1x_decompress_safe, every read and back reference is checked.
+
+use crate::error::NetFlowError;
+
+const M2_MAX_OFFSET: usize = 0x0800;
+
+struct Input<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn byte(&mut self) -> Result<usize, NetFlowError> {
+        let byte = *self
+            .buf
+            .get(self.pos)
+            .ok_or_else(|| NetFlowError::invalid_capture("LZO input overrun"))?;
+        self.pos += 1;
+        Ok(byte as usize)
+    }
+
+    fn le16(&mut self) -> Result<usize, NetFlowError> {
+        Ok(self.byte()? | (self.byte()? << 8))
+    }
+
+    /// Length with the zero byte extension, base is added once the run of zeros ends.
+    fn length(&mut self, base: usize) -> Result<usize, NetFlowError> {
+        let mut zeros = 0;
+        loop {
+            match self.byte()? {
+                0 => zeros += 255,
+                byte => return Ok(base + zeros + byte),
+            }
+        }
+    }
+
+    fn literals(
+        &mut self,
+        len: usize,
+        output: &mut Vec<u8>,
+        max_len: usize,
+    ) -> Result<(), NetFlowError> {
+        if output.len() + len > max_len {
+            return Err(NetFlowError::invalid_capture("LZO output overrun"));
+        }
+
+        let end = self.pos + len;
+        let bytes = self
+            .buf
+            .get(self.pos..end)
+            .ok_or_else(|| NetFlowError::invalid_capture("LZO input overrun"))?;
+        output.extend_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+}
+
+fn copy_match(
+    output: &mut Vec<u8>,
+    distance: usize,
+    len: usize,
+    max_len: usize,
+) -> Result<(), NetFlowError> {
+    if distance == 0 || distance > output.len() {
+        return Err(NetFlowError::invalid_capture("LZO lookbehind overrun"));
+    }
+    if output.len() + len > max_len {
+        return Err(NetFlowError::invalid_capture("LZO output overrun"));
+    }
+
+    // byte by byte, the source may overlap the bytes being written
+    let start = output.len() - distance;
+    for i in 0..len {
+        let byte = output[start + i];
+        output.push(byte);
+    }
+
+    Ok(())
+}
+
+/// Decompress a LZO1X stream, the result must not be longer than max_len.
+pub fn decompress(buf: &[u8], max_len: usize) -> Result<Vec<u8>, NetFlowError> {
+    let mut input = Input { buf, pos: 0 };
+    let mut output = Vec::with_capacity(max_len.min(buf.len() * 4));
+    // number of literals copied after the last instruction, 4 for a literal run
+    let mut state;
+
+    if buf.first().is_some_and(|first| *first > 17) {
+        let len = input.byte()? - 17;
+        input.literals(len, &mut output, max_len)?;
+        state = if len < 4 { len } else { 4 };
+    } else {
+        state = 0;
+    }
+
+    loop {
+        let t = input.byte()?;
+        let (distance, len, next) = if t >= 64 {
+            // M2, 3 to 8 bytes within 2 KiB
+            let distance = 1 + ((t >> 2) & 7) + (input.byte()? << 3);
+            (distance, (t >> 5) + 1, t & 3)
+        } else if t >= 32 {
+            // M3, within 16 KiB
+            let len = match t & 31 {
+                0 => input.length(31)?,
+                len => len,
+            } + 2;
+            let word = input.le16()?;
+            (1 + (word >> 2), len, word & 3)
+        } else if t >= 16 {
+            // M4, within 48 KiB, distance 0 marks the end of the stream
+            let len = match t & 7 {
+                0 => input.length(7)?,
+                len => len,
+            } + 2;
+            let word = input.le16()?;
+            let distance = ((t & 8) << 11) + (word >> 2);
+            if distance == 0 {
+                break;
+            }
+            (distance + 0x4000, len, word & 3)
+        } else if state == 0 {
+            // literal run
+            let len = match t {
+                0 => input.length(15)?,
+                len => len,
+            } + 3;
+            input.literals(len, &mut output, max_len)?;
+            state = 4;
+            continue;
+        } else if state < 4 {
+            // M1, 2 bytes within 1 KiB after a short literal copy
+            let distance = 1 + (t >> 2) + (input.byte()? << 2);
+            (distance, 2, t & 3)
+        } else {
+            // M1, 3 bytes right after a literal run
+            let distance = 1 + M2_MAX_OFFSET + (t >> 2) + (input.byte()? << 2);
+            (distance, 3, t & 3)
+        };
+
+        copy_match(&mut output, distance, len, max_len)?;
+        input.literals(next, &mut output, max_len)?;
+        state = next;
+    }
+
+    if input.pos != buf.len() {
+        return Err(NetFlowError::invalid_capture("LZO trailing input"));
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test_lzo {
+    use super::*;
+
+    #[test]
+    fn test_literals() {
+        let data = [22, b'h', b'e', b'l', b'l', b'o', 0x11, 0x00, 0x00];
+        assert_eq!(decompress(&data, 100).unwrap(), b"hello");
+        assert!(decompress(&data, 4).is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        // "abc", M2 length 3 distance 3, M3 length 6 distance 3 followed by 1 literal
+        let data = [
+            20, b'a', b'b', b'c', 72, 0x00, 36, 0x09, 0x00, b'x', 0x11, 0x00, 0x00,
+        ];
+        assert_eq!(decompress(&data, 100).unwrap(), b"abcabcabcabcx");
+    }
+
+    #[test]
+    fn test_long_literal_run() {
+        // 0, zero bytes and a length byte: 15 + 255 + 2 + 3 literals
+        let mut data = vec![0x00, 0x00, 0x02];
+        data.extend(vec![7u8; 275]);
+        data.extend(&[0x11, 0x00, 0x00]);
+        assert_eq!(decompress(&data, 1000).unwrap(), vec![7u8; 275]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        // distance beyond the output
+        assert!(decompress(&[20, b'a', b'b', b'c', 72, 0x01, 0x11, 0x00, 0x00], 100).is_err());
+        // missing end of stream
+        assert!(decompress(&[22, b'h', b'e', b'l', b'l', b'o'], 100).is_err());
+    }
+}
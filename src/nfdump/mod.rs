@@ -0,0 +1,226 @@
+mod lzo;
+
+mod record;
+pub use self::record::*;
+
+mod reader;
+pub use self::reader::*;
+
+mod writer;
+pub use self::writer::*;
+
+use crate::error::NetFlowError;
+use byteorder::{ByteOrder, LittleEndian};
+
+// nfdump 1.7 file layout, nffileV2.h
+pub const NFCAPD_MAGIC: u16 = 0xa50c;
+pub const LAYOUT_VERSION: u16 = 2;
+const FILE_HEADER_LEN: usize = 40;
+const BLOCK_HEADER_LEN: usize = 12;
+const DATA_BLOCK_TYPE_3: u16 = 3;
+// block flag: stored uncompressed regardless of the file compression
+const FLAG_BLOCK_UNCOMPRESSED: u16 = 0x1;
+// largest uncompressed block nfdump creates
+const BUFFSIZE: usize = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Compression {
+    None,
+    Lzo,
+    Bz2,
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    fn from_u8(num: u8) -> Result<Compression, NetFlowError> {
+        match num {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Lzo),
+            2 => Ok(Compression::Bz2),
+            3 => Ok(Compression::Lz4),
+            4 => Ok(Compression::Zstd),
+            _ => Err(NetFlowError::invalid_capture("unknown compression")),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lzo => 1,
+            Compression::Bz2 => 2,
+            Compression::Lz4 => 3,
+            Compression::Zstd => 4,
+        }
+    }
+}
+
+/// File header of a nfcapd file, multi byte values are little endian.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileHeader {
+    pub nfd_version: u32,
+    /// creation time, seconds since UNIX epoch
+    pub created: u64,
+    pub compression: Compression,
+    pub encryption: u8,
+    pub appendix_blocks: u16,
+    pub creator: u32,
+    pub appendix_offset: u64,
+    pub block_size: u32,
+    pub num_blocks: u32,
+}
+
+impl FileHeader {
+    fn from_bytes(buf: &[u8; FILE_HEADER_LEN]) -> Result<FileHeader, NetFlowError> {
+        if LittleEndian::read_u16(buf) != NFCAPD_MAGIC {
+            return Err(NetFlowError::invalid_capture("not a nfcapd file"));
+        }
+        if LittleEndian::read_u16(&buf[2..]) != LAYOUT_VERSION {
+            return Err(NetFlowError::invalid_capture(
+                "unsupported nfcapd layout version",
+            ));
+        }
+
+        Ok(FileHeader {
+            nfd_version: LittleEndian::read_u32(&buf[4..]),
+            created: LittleEndian::read_u64(&buf[8..]),
+            compression: Compression::from_u8(buf[16])?,
+            encryption: buf[17],
+            appendix_blocks: LittleEndian::read_u16(&buf[18..]),
+            creator: LittleEndian::read_u32(&buf[20..]),
+            appendix_offset: LittleEndian::read_u64(&buf[24..]),
+            block_size: LittleEndian::read_u32(&buf[32..]),
+            num_blocks: LittleEndian::read_u32(&buf[36..]),
+        })
+    }
+
+    fn to_bytes(&self) -> [u8; FILE_HEADER_LEN] {
+        let mut buf = [0u8; FILE_HEADER_LEN];
+
+        LittleEndian::write_u16(&mut buf, NFCAPD_MAGIC);
+        LittleEndian::write_u16(&mut buf[2..], LAYOUT_VERSION);
+        LittleEndian::write_u32(&mut buf[4..], self.nfd_version);
+        LittleEndian::write_u64(&mut buf[8..], self.created);
+        buf[16] = self.compression.to_u8();
+        buf[17] = self.encryption;
+        LittleEndian::write_u16(&mut buf[18..], self.appendix_blocks);
+        LittleEndian::write_u32(&mut buf[20..], self.creator);
+        LittleEndian::write_u64(&mut buf[24..], self.appendix_offset);
+        LittleEndian::write_u32(&mut buf[32..], self.block_size);
+        LittleEndian::write_u32(&mut buf[36..], self.num_blocks);
+
+        buf
+    }
+}
+
+#[cfg(test)]
+mod test_nfdump {
+    use super::*;
+    use crate::field::MacAddr;
+    use crate::netflow::test_data::NETFLOWV9_DATA;
+    use crate::netflow::TemplateCache;
+    use std::io::Cursor;
+    use std::net::SocketAddr;
+
+    fn write_fixture(compression: Compression) -> Vec<u8> {
+        let exporter: SocketAddr = "192.0.2.1:2055".parse().unwrap();
+        let netflow = TemplateCache::new()
+            .decode(exporter, &NETFLOWV9_DATA)
+            .unwrap();
+
+        let mut writer = NfcapdWriter::new(Cursor::new(Vec::new()), compression).unwrap();
+        assert_eq!(writer.write_netflow(exporter, &netflow).unwrap(), 1);
+        writer.finish().unwrap().into_inner()
+    }
+
+    // literal only LZO1X stream, data is longer than 18 bytes
+    fn lzo_literals(data: &[u8]) -> Vec<u8> {
+        let mut stream = vec![0];
+        let mut rest = data.len() - 18;
+        while rest > 255 {
+            stream.push(0);
+            rest -= 255;
+        }
+        stream.push(rest as u8);
+        stream.extend_from_slice(data);
+        stream.extend_from_slice(&[0x11, 0x00, 0x00]);
+        stream
+    }
+
+    fn check_records(file: &[u8]) {
+        let records: Vec<_> = NfcapdReader::new(file)
+            .unwrap()
+            .map(|record| record.unwrap())
+            .collect();
+        assert_eq!(records.len(), 1);
+
+        let record = &records[0];
+        assert_eq!(record.first, 1_523_936_496_826);
+        assert_eq!(record.last, 1_523_936_496_826);
+        assert_eq!(record.received, 1_523_936_618_000);
+        assert_eq!(record.bytes, 40);
+        assert_eq!(record.packets, 1);
+        assert_eq!(record.src_addr, Some("192.168.6.101".parse().unwrap()));
+        assert_eq!(record.dst_addr, Some("192.168.6.102".parse().unwrap()));
+        assert_eq!(record.src_port, 16001);
+        assert_eq!(record.dst_port, 49578);
+        assert_eq!(record.protocol, 6);
+        assert_eq!(record.tcp_flags, 0x14);
+        assert_eq!(record.misc.unwrap().input, 3);
+        assert_eq!(
+            record.macs.unwrap().in_src,
+            MacAddr::new(0x08, 0x00, 0x27, 0x3a, 0xdd, 0x56)
+        );
+        assert_eq!(record.exporter, Some("192.0.2.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for compression in &[Compression::None, Compression::Lz4, Compression::Bz2] {
+            let file = write_fixture(*compression);
+
+            let reader = NfcapdReader::new(&file[..]).unwrap();
+            assert_eq!(reader.header().compression, *compression);
+            assert_eq!(reader.header().num_blocks, 1);
+            check_records(&file);
+        }
+    }
+
+    #[test]
+    fn test_lzo_block() {
+        let file = write_fixture(Compression::None);
+        let block = &file[FILE_HEADER_LEN + BLOCK_HEADER_LEN..];
+
+        // an exporter info record nfdump puts before the flows is skipped
+        let mut records = vec![7, 0, 8, 0, 1, 2, 3, 4];
+        records.extend_from_slice(block);
+        let compressed = lzo_literals(&records);
+
+        let mut lzo = file[..FILE_HEADER_LEN].to_vec();
+        lzo[16] = Compression::Lzo.to_u8();
+        lzo.extend_from_slice(&2u32.to_le_bytes());
+        lzo.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        lzo.extend_from_slice(&DATA_BLOCK_TYPE_3.to_le_bytes());
+        lzo.extend_from_slice(&0u16.to_le_bytes());
+        lzo.extend_from_slice(&compressed);
+
+        check_records(&lzo);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let file = write_fixture(Compression::None);
+        assert!(NfcapdReader::new(&file[..20]).is_err());
+        assert!(NfcapdReader::new(&NETFLOWV9_DATA[..]).is_err());
+
+        let mut reader = NfcapdReader::new(&file[..file.len() - 1]).unwrap();
+        assert!(reader.next_record().is_err());
+
+        for compression in &[Compression::Lzo, Compression::Zstd] {
+            assert!(matches!(
+                NfcapdWriter::new(Cursor::new(Vec::new()), *compression),
+                Err(NetFlowError::InvalidConfig { .. })
+            ));
+        }
+    }
+}
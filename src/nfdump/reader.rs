@@ -0,0 +1,156 @@
+use super::{
+    lzo, Compression, FileHeader, NfRecord, BLOCK_HEADER_LEN, BUFFSIZE, DATA_BLOCK_TYPE_3,
+    FILE_HEADER_LEN, FLAG_BLOCK_UNCOMPRESSED, V3_RECORD,
+};
+use crate::error::NetFlowError;
+use crate::util::{read_exact, read_or_eof};
+use byteorder::{ByteOrder, LittleEndian};
+use std::io::Read;
+
+const RECORD_HEADER_LEN: usize = 4;
+
+/// Reads flow records from a nfcapd file written by nfdump 1.7.
+/// Records other than V3 flow records, such as exporter and statistics records, are skipped.
+pub struct NfcapdReader<R> {
+    reader: R,
+    header: FileHeader,
+    block: Vec<u8>,
+    pos: usize,
+    records_left: u32,
+}
+
+impl<R: Read> NfcapdReader<R> {
+    pub fn new(mut reader: R) -> Result<NfcapdReader<R>, NetFlowError> {
+        let mut buf = [0u8; FILE_HEADER_LEN];
+        read_exact(&mut reader, &mut buf)?;
+        let header = FileHeader::from_bytes(&buf)?;
+
+        if header.encryption != 0 {
+            return Err(NetFlowError::invalid_capture(
+                "encrypted nfcapd files are not supported",
+            ));
+        }
+        if header.compression == Compression::Zstd {
+            return Err(NetFlowError::invalid_capture(
+                "ZSTD compression is not supported",
+            ));
+        }
+
+        Ok(NfcapdReader {
+            reader,
+            header,
+            block: Vec::new(),
+            pos: 0,
+            records_left: 0,
+        })
+    }
+
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    /// Load the next data block, return false at the end of the file.
+    fn next_block(&mut self) -> Result<bool, NetFlowError> {
+        let mut head = [0u8; BLOCK_HEADER_LEN];
+        if !read_or_eof(&mut self.reader, &mut head)? {
+            return Ok(false);
+        }
+
+        let num_records = LittleEndian::read_u32(&head);
+        let size = LittleEndian::read_u32(&head[4..]) as usize;
+        let block_type = LittleEndian::read_u16(&head[8..]);
+        let flags = LittleEndian::read_u16(&head[10..]);
+
+        let max_len = BUFFSIZE.max(self.header.block_size as usize);
+        if size > max_len {
+            return Err(NetFlowError::invalid_capture("invalid block size"));
+        }
+
+        let mut data = vec![0u8; size];
+        read_exact(&mut self.reader, &mut data)?;
+
+        // other block types belong to older layouts
+        if block_type != DATA_BLOCK_TYPE_3 {
+            self.records_left = 0;
+            return Ok(true);
+        }
+
+        self.block = if flags & FLAG_BLOCK_UNCOMPRESSED != 0 {
+            data
+        } else {
+            decompress(self.header.compression, &data, max_len)?
+        };
+        self.pos = 0;
+        self.records_left = num_records;
+
+        Ok(true)
+    }
+
+    pub fn next_record(&mut self) -> Result<Option<NfRecord>, NetFlowError> {
+        loop {
+            if self.records_left == 0 {
+                if !self.next_block()? {
+                    return Ok(None);
+                }
+                continue;
+            }
+            self.records_left -= 1;
+
+            let rest = &self.block[self.pos..];
+            if rest.len() < RECORD_HEADER_LEN {
+                return Err(NetFlowError::invalid_capture("truncated block"));
+            }
+
+            let record_type = LittleEndian::read_u16(rest);
+            let size = LittleEndian::read_u16(&rest[2..]) as usize;
+            if size < RECORD_HEADER_LEN || size > rest.len() {
+                return Err(NetFlowError::invalid_capture("invalid record size"));
+            }
+            self.pos += size;
+
+            if record_type == V3_RECORD {
+                return NfRecord::from_bytes(&rest[..size]).map(Some);
+            }
+        }
+    }
+}
+
+fn decompress(
+    compression: Compression,
+    data: &[u8],
+    max_len: usize,
+) -> Result<Vec<u8>, NetFlowError> {
+    match compression {
+        Compression::None => Ok(data.to_vec()),
+        Compression::Lzo => lzo::decompress(data, max_len),
+        Compression::Lz4 => {
+            let mut block = vec![0u8; max_len];
+            let len = lz4_flex::block::decompress_into(data, &mut block)
+                .map_err(|_| NetFlowError::invalid_capture("invalid LZ4 block"))?;
+            block.truncate(len);
+            Ok(block)
+        }
+        Compression::Bz2 => {
+            let mut block = Vec::new();
+            bzip2::read::BzDecoder::new(data)
+                .take(max_len as u64 + 1)
+                .read_to_end(&mut block)
+                .map_err(|_| NetFlowError::invalid_capture("invalid bzip2 block"))?;
+            if block.len() > max_len {
+                return Err(NetFlowError::invalid_capture("invalid bzip2 block"));
+            }
+            Ok(block)
+        }
+        Compression::Zstd => Err(NetFlowError::invalid_capture(
+            "ZSTD compression is not supported",
+        )),
+    }
+}
+
+impl<R: Read> Iterator for NfcapdReader<R> {
+    type Item = Result<NfRecord, NetFlowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
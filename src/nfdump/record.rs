@@ -0,0 +1,732 @@
+use crate::error::NetFlowError;
+use crate::field::FieldTypes::*;
+use crate::field::{FieldValue, FlowField, MacAddr, TypeLengthField};
+use crate::flowset::{DataFlow, DataTemplate, DataTemplateItem, FlowSet, Record};
+use crate::netflow::NetFlow9;
+use byteorder::{ByteOrder, LittleEndian};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+pub const V3_RECORD: u16 = 11;
+const V3_HEADER_LEN: usize = 12;
+const ELEMENT_HEADER_LEN: usize = 4;
+
+// extension element ids, nfxV3.h
+const EX_GENERIC_FLOW: u16 = 1;
+const EX_IPV4_FLOW: u16 = 2;
+const EX_IPV6_FLOW: u16 = 3;
+const EX_FLOW_MISC: u16 = 4;
+const EX_CNT_FLOW: u16 = 5;
+const EX_VLAN: u16 = 6;
+const EX_AS_ROUTING: u16 = 7;
+const EX_BGP_NEXT_HOP_V4: u16 = 8;
+const EX_BGP_NEXT_HOP_V6: u16 = 9;
+const EX_IP_NEXT_HOP_V4: u16 = 10;
+const EX_IP_NEXT_HOP_V6: u16 = 11;
+const EX_IP_RECEIVED_V4: u16 = 12;
+const EX_IP_RECEIVED_V6: u16 = 13;
+const EX_MAC_ADDR: u16 = 15;
+
+const FIRST_TEMPLATE_ID: u16 = 256;
+
+type Layout = Vec<(u16, u16)>;
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_ICMPV6: u8 = 58;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct FlowMisc {
+    pub input: u32,
+    pub output: u32,
+    pub src_mask: u8,
+    pub dst_mask: u8,
+    pub direction: u8,
+    pub dst_tos: u8,
+    pub biflow_direction: u8,
+    pub end_reason: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct FlowCounters {
+    pub flows: u64,
+    pub out_packets: u64,
+    pub out_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Vlans {
+    pub src: u32,
+    pub dst: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct AsRouting {
+    pub src: u32,
+    pub dst: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MacAddrs {
+    pub in_src: MacAddr,
+    pub out_dst: MacAddr,
+    pub in_dst: MacAddr,
+    pub out_src: MacAddr,
+}
+
+impl Default for MacAddrs {
+    fn default() -> Self {
+        let zero = MacAddr::new(0, 0, 0, 0, 0, 0);
+        MacAddrs {
+            in_src: zero,
+            out_dst: zero,
+            in_dst: zero,
+            out_src: zero,
+        }
+    }
+}
+
+/// A flow as nfdump stores it, a V3 record made of extension elements.
+/// Optional extensions are None when the record does not carry them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct NfRecord {
+    pub exporter_id: u16,
+    pub engine_type: u8,
+    pub engine_id: u8,
+    pub nf_version: u8,
+    /// flow start in milliseconds since UNIX epoch
+    pub first: u64,
+    /// flow end in milliseconds since UNIX epoch
+    pub last: u64,
+    /// time the collector received the flow in milliseconds since UNIX epoch
+    pub received: u64,
+    pub packets: u64,
+    pub bytes: u64,
+    pub src_port: u16,
+    /// destination port, ICMP type * 256 + code for ICMP
+    pub dst_port: u16,
+    pub protocol: u8,
+    pub tcp_flags: u8,
+    pub fwd_status: u8,
+    pub src_tos: u8,
+    pub src_addr: Option<IpAddr>,
+    pub dst_addr: Option<IpAddr>,
+    pub misc: Option<FlowMisc>,
+    pub counters: Option<FlowCounters>,
+    pub vlans: Option<Vlans>,
+    pub as_routing: Option<AsRouting>,
+    pub bgp_next_hop: Option<IpAddr>,
+    pub next_hop: Option<IpAddr>,
+    /// address of the exporter the flow was received from
+    pub exporter: Option<IpAddr>,
+    pub macs: Option<MacAddrs>,
+}
+
+fn read_ipv4(buf: &[u8]) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::from(LittleEndian::read_u32(buf)))
+}
+
+// two host order u64, the high half first
+fn read_ipv6(buf: &[u8]) -> IpAddr {
+    let high = u128::from(LittleEndian::read_u64(buf));
+    let low = u128::from(LittleEndian::read_u64(&buf[8..]));
+    IpAddr::V6(Ipv6Addr::from((high << 64) | low))
+}
+
+fn write_ipv6(buf: &mut Vec<u8>, ip: Ipv6Addr) {
+    let num = u128::from(ip);
+    buf.extend_from_slice(&((num >> 64) as u64).to_le_bytes());
+    buf.extend_from_slice(&(num as u64).to_le_bytes());
+}
+
+fn read_mac(buf: &[u8]) -> MacAddr {
+    let bytes = LittleEndian::read_u64(buf).to_be_bytes();
+    MacAddr::new(bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7])
+}
+
+fn write_mac(buf: &mut Vec<u8>, mac: MacAddr) {
+    let number = mac
+        .octets()
+        .iter()
+        .fold(0u64, |sum, byte| (sum << 8) | u64::from(*byte));
+    buf.extend_from_slice(&number.to_le_bytes());
+}
+
+fn push_element(buf: &mut Vec<u8>, count: &mut u8, element_id: u16, body: &[u8]) {
+    buf.extend_from_slice(&element_id.to_le_bytes());
+    buf.extend_from_slice(&((ELEMENT_HEADER_LEN + body.len()) as u16).to_le_bytes());
+    buf.extend_from_slice(body);
+    *count += 1;
+}
+
+// the same address element carries IPv4 or IPv6 under two ids
+fn push_address(buf: &mut Vec<u8>, count: &mut u8, ids: (u16, u16), ip: IpAddr) {
+    match ip {
+        IpAddr::V4(ipv4) => push_element(buf, count, ids.0, &u32::from(ipv4).to_le_bytes()),
+        IpAddr::V6(ipv6) => {
+            let mut body = Vec::with_capacity(16);
+            write_ipv6(&mut body, ipv6);
+            push_element(buf, count, ids.1, &body);
+        }
+    }
+}
+
+// value of any field as a big endian number, longer fields keep the low 8 bytes
+fn number(field: &FlowField) -> u64 {
+    field
+        .to_bytes()
+        .iter()
+        .fold(0u64, |sum, byte| (sum << 8) | u64::from(*byte))
+}
+
+fn address(field: &FlowField) -> Option<IpAddr> {
+    match field.value() {
+        FieldValue::Ipv4Addr(ipv4) => Some(IpAddr::V4(*ipv4)),
+        FieldValue::Ipv6Addr(ipv6) => Some(IpAddr::V6(*ipv6)),
+        _ => None,
+    }
+}
+
+fn mac(field: &FlowField) -> MacAddr {
+    let bytes = number(field).to_be_bytes();
+    MacAddr::new(bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7])
+}
+
+fn num_field(type_id: u16, length: u16, value: u64) -> FlowField {
+    let bytes = value.to_be_bytes();
+    FlowField::new(
+        type_id,
+        length,
+        FieldValue::new(type_id, &bytes[8 - length as usize..]),
+    )
+}
+
+fn address_field(ids: (u16, u16), ip: IpAddr) -> FlowField {
+    match ip {
+        IpAddr::V4(ipv4) => FlowField::new(ids.0, 4, FieldValue::Ipv4Addr(ipv4)),
+        IpAddr::V6(ipv6) => FlowField::new(ids.1, 16, FieldValue::Ipv6Addr(ipv6)),
+    }
+}
+
+impl NfRecord {
+    /// Parse a V3 record, buf holds exactly one record including its header.
+    pub fn from_bytes(buf: &[u8]) -> Result<NfRecord, NetFlowError> {
+        if buf.len() < V3_HEADER_LEN || LittleEndian::read_u16(buf) != V3_RECORD {
+            return Err(NetFlowError::invalid_capture("invalid V3 record"));
+        }
+
+        let mut record = NfRecord {
+            engine_type: buf[5],
+            engine_id: buf[6],
+            nf_version: buf[7],
+            exporter_id: LittleEndian::read_u16(&buf[8..]),
+            ..Default::default()
+        };
+
+        let mut rest = &buf[V3_HEADER_LEN..];
+        for _ in 0..buf[4] {
+            if rest.len() < ELEMENT_HEADER_LEN {
+                return Err(NetFlowError::invalid_capture("truncated V3 record"));
+            }
+
+            let element_id = LittleEndian::read_u16(rest);
+            let length = LittleEndian::read_u16(&rest[2..]) as usize;
+            if length < ELEMENT_HEADER_LEN || length > rest.len() {
+                return Err(NetFlowError::invalid_capture("invalid element length"));
+            }
+
+            record.read_element(element_id, &rest[ELEMENT_HEADER_LEN..length])?;
+            rest = &rest[length..];
+        }
+
+        Ok(record)
+    }
+
+    fn read_element(&mut self, element_id: u16, body: &[u8]) -> Result<(), NetFlowError> {
+        let min_len = match element_id {
+            EX_GENERIC_FLOW => 48,
+            EX_IPV4_FLOW | EX_VLAN | EX_AS_ROUTING => 8,
+            EX_IPV6_FLOW | EX_MAC_ADDR => 32,
+            EX_FLOW_MISC | EX_BGP_NEXT_HOP_V6 | EX_IP_NEXT_HOP_V6 | EX_IP_RECEIVED_V6 => 16,
+            EX_CNT_FLOW => 24,
+            EX_BGP_NEXT_HOP_V4 | EX_IP_NEXT_HOP_V4 | EX_IP_RECEIVED_V4 => 4,
+            // extensions without a counterpart here are skipped
+            _ => return Ok(()),
+        };
+        if body.len() < min_len {
+            return Err(NetFlowError::invalid_capture("short extension element"));
+        }
+
+        match element_id {
+            EX_GENERIC_FLOW => {
+                self.first = LittleEndian::read_u64(body);
+                self.last = LittleEndian::read_u64(&body[8..]);
+                self.received = LittleEndian::read_u64(&body[16..]);
+                self.packets = LittleEndian::read_u64(&body[24..]);
+                self.bytes = LittleEndian::read_u64(&body[32..]);
+                self.src_port = LittleEndian::read_u16(&body[40..]);
+                self.dst_port = LittleEndian::read_u16(&body[42..]);
+                self.protocol = body[44];
+                self.tcp_flags = body[45];
+                self.fwd_status = body[46];
+                self.src_tos = body[47];
+            }
+            EX_IPV4_FLOW => {
+                self.src_addr = Some(read_ipv4(body));
+                self.dst_addr = Some(read_ipv4(&body[4..]));
+            }
+            EX_IPV6_FLOW => {
+                self.src_addr = Some(read_ipv6(body));
+                self.dst_addr = Some(read_ipv6(&body[16..]));
+            }
+            EX_FLOW_MISC => {
+                self.misc = Some(FlowMisc {
+                    input: LittleEndian::read_u32(body),
+                    output: LittleEndian::read_u32(&body[4..]),
+                    src_mask: body[8],
+                    dst_mask: body[9],
+                    direction: body[10],
+                    dst_tos: body[11],
+                    biflow_direction: body[12],
+                    end_reason: body[13],
+                })
+            }
+            EX_CNT_FLOW => {
+                self.counters = Some(FlowCounters {
+                    flows: LittleEndian::read_u64(body),
+                    out_packets: LittleEndian::read_u64(&body[8..]),
+                    out_bytes: LittleEndian::read_u64(&body[16..]),
+                })
+            }
+            EX_VLAN => {
+                self.vlans = Some(Vlans {
+                    src: LittleEndian::read_u32(body),
+                    dst: LittleEndian::read_u32(&body[4..]),
+                })
+            }
+            EX_AS_ROUTING => {
+                self.as_routing = Some(AsRouting {
+                    src: LittleEndian::read_u32(body),
+                    dst: LittleEndian::read_u32(&body[4..]),
+                })
+            }
+            EX_BGP_NEXT_HOP_V4 => self.bgp_next_hop = Some(read_ipv4(body)),
+            EX_BGP_NEXT_HOP_V6 => self.bgp_next_hop = Some(read_ipv6(body)),
+            EX_IP_NEXT_HOP_V4 => self.next_hop = Some(read_ipv4(body)),
+            EX_IP_NEXT_HOP_V6 => self.next_hop = Some(read_ipv6(body)),
+            EX_IP_RECEIVED_V4 => self.exporter = Some(read_ipv4(body)),
+            EX_IP_RECEIVED_V6 => self.exporter = Some(read_ipv6(body)),
+            EX_MAC_ADDR => {
+                self.macs = Some(MacAddrs {
+                    in_src: read_mac(body),
+                    out_dst: read_mac(&body[8..]),
+                    in_dst: read_mac(&body[16..]),
+                    out_src: read_mac(&body[24..]),
+                })
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Serialize as a V3 record with a header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut elements = Vec::new();
+        let mut count = 0;
+
+        let mut body = Vec::with_capacity(48);
+        for num in &[
+            self.first,
+            self.last,
+            self.received,
+            self.packets,
+            self.bytes,
+        ] {
+            body.extend_from_slice(&num.to_le_bytes());
+        }
+        body.extend_from_slice(&self.src_port.to_le_bytes());
+        body.extend_from_slice(&self.dst_port.to_le_bytes());
+        body.extend_from_slice(&[self.protocol, self.tcp_flags, self.fwd_status, self.src_tos]);
+        push_element(&mut elements, &mut count, EX_GENERIC_FLOW, &body);
+
+        match (self.src_addr, self.dst_addr) {
+            (Some(IpAddr::V6(src)), Some(IpAddr::V6(dst))) => {
+                let mut body = Vec::with_capacity(32);
+                write_ipv6(&mut body, src);
+                write_ipv6(&mut body, dst);
+                push_element(&mut elements, &mut count, EX_IPV6_FLOW, &body);
+            }
+            (src, dst) if src.is_some() || dst.is_some() => {
+                let ipv4 = |ip: Option<IpAddr>| match ip {
+                    Some(IpAddr::V4(ipv4)) => u32::from(ipv4),
+                    _ => 0,
+                };
+                let mut body = Vec::with_capacity(8);
+                body.extend_from_slice(&ipv4(src).to_le_bytes());
+                body.extend_from_slice(&ipv4(dst).to_le_bytes());
+                push_element(&mut elements, &mut count, EX_IPV4_FLOW, &body);
+            }
+            _ => (),
+        }
+
+        if let Some(misc) = &self.misc {
+            let mut body = Vec::with_capacity(16);
+            body.extend_from_slice(&misc.input.to_le_bytes());
+            body.extend_from_slice(&misc.output.to_le_bytes());
+            body.extend_from_slice(&[
+                misc.src_mask,
+                misc.dst_mask,
+                misc.direction,
+                misc.dst_tos,
+                misc.biflow_direction,
+                misc.end_reason,
+                0,
+                0,
+            ]);
+            push_element(&mut elements, &mut count, EX_FLOW_MISC, &body);
+        }
+
+        if let Some(counters) = &self.counters {
+            let mut body = Vec::with_capacity(24);
+            for num in &[counters.flows, counters.out_packets, counters.out_bytes] {
+                body.extend_from_slice(&num.to_le_bytes());
+            }
+            push_element(&mut elements, &mut count, EX_CNT_FLOW, &body);
+        }
+
+        if let Some(vlans) = &self.vlans {
+            let mut body = vlans.src.to_le_bytes().to_vec();
+            body.extend_from_slice(&vlans.dst.to_le_bytes());
+            push_element(&mut elements, &mut count, EX_VLAN, &body);
+        }
+
+        if let Some(as_routing) = &self.as_routing {
+            let mut body = as_routing.src.to_le_bytes().to_vec();
+            body.extend_from_slice(&as_routing.dst.to_le_bytes());
+            push_element(&mut elements, &mut count, EX_AS_ROUTING, &body);
+        }
+
+        let addresses = [
+            (self.bgp_next_hop, (EX_BGP_NEXT_HOP_V4, EX_BGP_NEXT_HOP_V6)),
+            (self.next_hop, (EX_IP_NEXT_HOP_V4, EX_IP_NEXT_HOP_V6)),
+            (self.exporter, (EX_IP_RECEIVED_V4, EX_IP_RECEIVED_V6)),
+        ];
+        for (ip, ids) in addresses.iter() {
+            if let Some(ip) = ip {
+                push_address(&mut elements, &mut count, *ids, *ip);
+            }
+        }
+
+        if let Some(macs) = &self.macs {
+            let mut body = Vec::with_capacity(32);
+            for mac in &[macs.in_src, macs.out_dst, macs.in_dst, macs.out_src] {
+                write_mac(&mut body, *mac);
+            }
+            push_element(&mut elements, &mut count, EX_MAC_ADDR, &body);
+        }
+
+        let mut bytes = Vec::with_capacity(V3_HEADER_LEN + elements.len());
+        bytes.extend_from_slice(&V3_RECORD.to_le_bytes());
+        bytes.extend_from_slice(&((V3_HEADER_LEN + elements.len()) as u16).to_le_bytes());
+        bytes.extend_from_slice(&[count, self.engine_type, self.engine_id, self.nf_version]);
+        bytes.extend_from_slice(&self.exporter_id.to_le_bytes());
+        // flags and alignment
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.append(&mut elements);
+
+        bytes
+    }
+
+    /// Convert a decoded Netflow v9 data record, None for option records.
+    /// FIRST_SWITCHED and LAST_SWITCHED are made absolute with the packet header times.
+    pub fn from_record(record: &Record, sys_uptime: u32, timestamp: u32) -> Option<NfRecord> {
+        if let Record::OptionData(_) = record {
+            return None;
+        }
+
+        let export_ms = u64::from(timestamp) * 1000;
+        let absolute = |switched: u64| {
+            export_ms.saturating_sub(u64::from(sys_uptime.wrapping_sub(switched as u32)))
+        };
+
+        let mut nf = NfRecord {
+            nf_version: 9,
+            received: export_ms,
+            ..Default::default()
+        };
+        let mut icmp = None;
+
+        for field in record.fields().filter(|field| field.enterprise().is_none()) {
+            let value = number(field);
+
+            match field.type_id() {
+                FIRST_SWITCHED => nf.first = absolute(value),
+                LAST_SWITCHED => nf.last = absolute(value),
+                IN_BYTES => nf.bytes = value,
+                IN_PKTS => nf.packets = value,
+                PROTOCOL => nf.protocol = value as u8,
+                TOS => nf.src_tos = value as u8,
+                TCP_FLAGS => nf.tcp_flags = value as u8,
+                L4_SRC_PORT => nf.src_port = value as u16,
+                L4_DST_PORT => nf.dst_port = value as u16,
+                ICMP_TYPE => icmp = Some(value as u16),
+                FORWARDING_STATUS => nf.fwd_status = value as u8,
+                ENGINE_TYPE => nf.engine_type = value as u8,
+                ENGINE_ID => nf.engine_id = value as u8,
+                IPV4_SRC_ADDR | IPV6_SRC_ADDR => nf.src_addr = address(field),
+                IPV4_DST_ADDR | IPV6_DST_ADDR => nf.dst_addr = address(field),
+                IPV4_NEXT_HOP | IPV6_NEXT_HOP => nf.next_hop = address(field),
+                BGP_IPV4_NEXT_HOP | BGP_IPV6_NEXT_HOP => nf.bgp_next_hop = address(field),
+                INPUT_SNMP => nf.misc.get_or_insert_with(Default::default).input = value as u32,
+                OUTPUT_SNMP => nf.misc.get_or_insert_with(Default::default).output = value as u32,
+                SRC_MASK | IPV6_SRC_MASK => {
+                    nf.misc.get_or_insert_with(Default::default).src_mask = value as u8
+                }
+                DST_MASK | IPV6_DST_MASK => {
+                    nf.misc.get_or_insert_with(Default::default).dst_mask = value as u8
+                }
+                DIRECTION => nf.misc.get_or_insert_with(Default::default).direction = value as u8,
+                DST_TOS => nf.misc.get_or_insert_with(Default::default).dst_tos = value as u8,
+                FLOWS => nf.counters.get_or_insert_with(Default::default).flows = value,
+                OUT_PKTS => nf.counters.get_or_insert_with(Default::default).out_packets = value,
+                OUT_BYTES => nf.counters.get_or_insert_with(Default::default).out_bytes = value,
+                SRC_VLAN => nf.vlans.get_or_insert_with(Default::default).src = value as u32,
+                DST_VLAN => nf.vlans.get_or_insert_with(Default::default).dst = value as u32,
+                SRC_AS => nf.as_routing.get_or_insert_with(Default::default).src = value as u32,
+                DST_AS => nf.as_routing.get_or_insert_with(Default::default).dst = value as u32,
+                SRC_MAC => nf.macs.get_or_insert_with(Default::default).in_src = mac(field),
+                DST_MAC => nf.macs.get_or_insert_with(Default::default).out_dst = mac(field),
+                IN_DST_MAC => nf.macs.get_or_insert_with(Default::default).in_dst = mac(field),
+                OUT_SRC_MAC => nf.macs.get_or_insert_with(Default::default).out_src = mac(field),
+                _ => (),
+            }
+        }
+
+        // nfdump keeps ICMP type and code in the destination port
+        if nf.protocol == IPPROTO_ICMP || nf.protocol == IPPROTO_ICMPV6 {
+            if let Some(icmp) = icmp {
+                nf.dst_port = icmp;
+            }
+        }
+
+        Some(nf)
+    }
+
+    /// Netflow v9 fields of the record, switched times are relative to boot (ms since UNIX epoch).
+    pub fn to_fields(&self, boot: u64) -> Vec<FlowField> {
+        let relative = |ms: u64| ms.saturating_sub(boot) & u64::from(u32::MAX);
+        let is_ipv6 = matches!(self.src_addr, Some(IpAddr::V6(_)));
+
+        let mut fields = vec![
+            num_field(FIRST_SWITCHED, 4, relative(self.first)),
+            num_field(LAST_SWITCHED, 4, relative(self.last)),
+            num_field(IN_BYTES, 8, self.bytes),
+            num_field(IN_PKTS, 8, self.packets),
+            num_field(PROTOCOL, 1, u64::from(self.protocol)),
+            num_field(TOS, 1, u64::from(self.src_tos)),
+            num_field(TCP_FLAGS, 1, u64::from(self.tcp_flags)),
+            num_field(L4_SRC_PORT, 2, u64::from(self.src_port)),
+            num_field(L4_DST_PORT, 2, u64::from(self.dst_port)),
+            num_field(FORWARDING_STATUS, 1, u64::from(self.fwd_status)),
+        ];
+
+        if let Some(src) = self.src_addr {
+            fields.push(address_field((IPV4_SRC_ADDR, IPV6_SRC_ADDR), src));
+        }
+        if let Some(dst) = self.dst_addr {
+            fields.push(address_field((IPV4_DST_ADDR, IPV6_DST_ADDR), dst));
+        }
+
+        if let Some(misc) = &self.misc {
+            let (src_mask, dst_mask) = if is_ipv6 {
+                (IPV6_SRC_MASK, IPV6_DST_MASK)
+            } else {
+                (SRC_MASK, DST_MASK)
+            };
+            fields.push(num_field(INPUT_SNMP, 4, u64::from(misc.input)));
+            fields.push(num_field(OUTPUT_SNMP, 4, u64::from(misc.output)));
+            fields.push(num_field(src_mask, 1, u64::from(misc.src_mask)));
+            fields.push(num_field(dst_mask, 1, u64::from(misc.dst_mask)));
+            fields.push(num_field(DIRECTION, 1, u64::from(misc.direction)));
+            fields.push(num_field(DST_TOS, 1, u64::from(misc.dst_tos)));
+        }
+
+        if let Some(counters) = &self.counters {
+            fields.push(num_field(FLOWS, 8, counters.flows));
+            fields.push(num_field(OUT_PKTS, 8, counters.out_packets));
+            fields.push(num_field(OUT_BYTES, 8, counters.out_bytes));
+        }
+
+        if let Some(vlans) = &self.vlans {
+            fields.push(num_field(SRC_VLAN, 2, u64::from(vlans.src)));
+            fields.push(num_field(DST_VLAN, 2, u64::from(vlans.dst)));
+        }
+
+        if let Some(as_routing) = &self.as_routing {
+            fields.push(num_field(SRC_AS, 4, u64::from(as_routing.src)));
+            fields.push(num_field(DST_AS, 4, u64::from(as_routing.dst)));
+        }
+
+        if let Some(ip) = self.next_hop {
+            fields.push(address_field((IPV4_NEXT_HOP, IPV6_NEXT_HOP), ip));
+        }
+        if let Some(ip) = self.bgp_next_hop {
+            fields.push(address_field((BGP_IPV4_NEXT_HOP, BGP_IPV6_NEXT_HOP), ip));
+        }
+
+        if let Some(macs) = &self.macs {
+            let pairs = [
+                (SRC_MAC, macs.in_src),
+                (DST_MAC, macs.out_dst),
+                (IN_DST_MAC, macs.in_dst),
+                (OUT_SRC_MAC, macs.out_src),
+            ];
+            for (type_id, mac) in pairs.iter() {
+                fields.push(FlowField::new(*type_id, 6, FieldValue::MacAddr(*mac)));
+            }
+        }
+
+        fields
+    }
+}
+
+/// Build a Netflow v9 packet carrying the records.
+/// Records with the same fields share a template, template ids start from 256.
+/// The uptime starts with the earliest flow and the export time is the latest flow end.
+pub fn to_netflow9(records: &[NfRecord], flow_sequence: u32, source_id: u32) -> NetFlow9 {
+    let boot = records.iter().map(|record| record.first).min().unwrap_or(0);
+    let export = records.iter().map(|record| record.last).max().unwrap_or(0);
+    let timestamp = export.div_ceil(1000);
+    let sys_uptime = (timestamp * 1000).saturating_sub(boot) as u32;
+
+    // field ids and lengths with the records using them
+    let mut layouts: Vec<(Layout, Vec<Record>)> = Vec::new();
+    for record in records {
+        let fields = record.to_fields(boot);
+        let layout: Vec<_> = fields
+            .iter()
+            .map(|field| (field.type_id(), field.length()))
+            .collect();
+
+        match layouts.iter_mut().find(|(known, _)| *known == layout) {
+            Some((_, members)) => members.push(Record::make_data(fields)),
+            None => layouts.push((layout, vec![Record::make_data(fields)])),
+        }
+    }
+
+    let templates: Vec<_> = layouts
+        .iter()
+        .enumerate()
+        .map(|(i, (layout, _))| {
+            let fields = layout
+                .iter()
+                .map(|(type_id, length)| TypeLengthField::new(*type_id, *length))
+                .collect();
+            DataTemplateItem::new(FIRST_TEMPLATE_ID + i as u16, fields)
+        })
+        .collect();
+
+    let mut flowsets = Vec::new();
+    if !templates.is_empty() {
+        flowsets.push(FlowSet::from(DataTemplate::new(templates)));
+    }
+
+    for (i, (layout, members)) in layouts.into_iter().enumerate() {
+        // flowset length is 16 bits including the header and padding
+        let record_len: usize = layout.iter().map(|(_, length)| *length as usize).sum();
+        let per_flowset = (usize::from(u16::MAX) - 8) / record_len.max(1);
+
+        for chunk in members.chunks(per_flowset) {
+            let dataflow = DataFlow::new(FIRST_TEMPLATE_ID + i as u16, chunk.to_vec());
+            flowsets.push(FlowSet::from(dataflow));
+        }
+    }
+
+    NetFlow9::new(
+        sys_uptime,
+        timestamp as u32,
+        flow_sequence,
+        source_id,
+        flowsets,
+    )
+}
+
+#[cfg(test)]
+mod test_record {
+    use super::*;
+
+    fn sample() -> NfRecord {
+        NfRecord {
+            nf_version: 9,
+            first: 1_523_936_496_826,
+            last: 1_523_936_497_000,
+            received: 1_523_936_618_000,
+            packets: 3,
+            bytes: 180,
+            src_port: 16001,
+            dst_port: 53,
+            protocol: 17,
+            src_addr: Some("2001:db8::1".parse().unwrap()),
+            dst_addr: Some("2001:db8::2".parse().unwrap()),
+            misc: Some(FlowMisc {
+                input: 3,
+                output: 4,
+                src_mask: 64,
+                ..Default::default()
+            }),
+            as_routing: Some(AsRouting { src: 64512, dst: 0 }),
+            exporter: Some("192.0.2.1".parse().unwrap()),
+            macs: Some(MacAddrs {
+                in_src: MacAddr::new(0x08, 0x00, 0x27, 0x3a, 0xdd, 0x56),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bytes() {
+        let record = sample();
+        let bytes = record.to_bytes();
+        assert_eq!(LittleEndian::read_u16(&bytes[2..]) as usize, bytes.len());
+        // generic, ipv6, misc, as, received and mac
+        assert_eq!(bytes[4], 6);
+        assert_eq!(NfRecord::from_bytes(&bytes).unwrap(), record);
+
+        assert!(NfRecord::from_bytes(&bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn test_to_netflow9() {
+        let mut ipv4 = sample();
+        ipv4.src_addr = Some("192.0.2.10".parse().unwrap());
+        ipv4.dst_addr = Some("192.0.2.20".parse().unwrap());
+        let records = vec![sample(), ipv4.clone(), sample()];
+
+        let netflow = to_netflow9(&records, 10, 1);
+        assert_eq!(netflow.timestamp, 1_523_936_497);
+        assert_eq!(netflow.sys_uptime, 174);
+        assert_eq!(netflow.flow_sets.len(), 3);
+
+        let decoded = NetFlow9::from_bytes(&netflow.to_bytes()).unwrap();
+        let data: Vec<_> = decoded
+            .flow_sets
+            .iter()
+            .filter_map(|flowset| match flowset {
+                FlowSet::DataFlow(dataflow) => Some(dataflow.flowset_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(data, vec![256, 257]);
+
+        match &netflow.flow_sets[2] {
+            FlowSet::DataFlow(dataflow) => {
+                let record = &dataflow.records.as_ref().unwrap()[0];
+                let back = NfRecord::from_record(record, netflow.sys_uptime, netflow.timestamp);
+                let mut expected = ipv4;
+                expected.received = 1_523_936_497_000;
+                expected.exporter = None;
+                assert_eq!(back, Some(expected));
+            }
+            _ => panic!("not a data flowset"),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use super::{Compression, FileHeader, NfRecord, BUFFSIZE, DATA_BLOCK_TYPE_3};
+use crate::error::NetFlowError;
+use crate::flowset::FlowSet;
+use crate::netflow::NetFlow9;
+use std::io::{Seek, SeekFrom, Write};
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// nfcapd starts a new block once this much data is buffered
+const WRITE_BUFFSIZE: usize = 1024 * 1024;
+
+/// Writes flow records to a nfcapd file readable by nfdump 1.7.
+/// Blocks are compressed with LZ4, bzip2 or not at all.
+/// finish() must be called to complete the file header.
+pub struct NfcapdWriter<W: Write + Seek> {
+    writer: W,
+    header: FileHeader,
+    block: Vec<u8>,
+    block_records: u32,
+}
+
+impl<W: Write + Seek> NfcapdWriter<W> {
+    pub fn new(mut writer: W, compression: Compression) -> Result<NfcapdWriter<W>, NetFlowError> {
+        match compression {
+            Compression::None | Compression::Lz4 | Compression::Bz2 => (),
+            _ => {
+                return Err(NetFlowError::invalid_config(
+                    "only LZ4, bzip2 or no compression can be written",
+                ))
+            }
+        }
+
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+
+        let header = FileHeader {
+            nfd_version: 0,
+            created,
+            compression,
+            encryption: 0,
+            appendix_blocks: 0,
+            creator: 0,
+            appendix_offset: 0,
+            block_size: BUFFSIZE as u32,
+            num_blocks: 0,
+        };
+        writer.write_all(&header.to_bytes())?;
+
+        Ok(NfcapdWriter {
+            writer,
+            header,
+            block: Vec::with_capacity(WRITE_BUFFSIZE),
+            block_records: 0,
+        })
+    }
+
+    pub fn write_record(&mut self, record: &NfRecord) -> Result<(), NetFlowError> {
+        let bytes = record.to_bytes();
+        if self.block.len() + bytes.len() > WRITE_BUFFSIZE {
+            self.flush_block()?;
+        }
+
+        self.block.extend_from_slice(&bytes);
+        self.block_records += 1;
+        Ok(())
+    }
+
+    /// Write the decoded data records of a packet, return the number of records written.
+    /// Option records and data flowsets without a template are skipped.
+    pub fn write_netflow(
+        &mut self,
+        exporter: SocketAddr,
+        netflow: &NetFlow9,
+    ) -> Result<usize, NetFlowError> {
+        let mut count = 0;
+
+        for flowset in &netflow.flow_sets {
+            let records = match flowset {
+                FlowSet::DataFlow(dataflow) => match &dataflow.records {
+                    Some(records) => records,
+                    None => continue,
+                },
+                _ => continue,
+            };
+
+            for record in records {
+                if let Some(mut nf) =
+                    NfRecord::from_record(record, netflow.sys_uptime, netflow.timestamp)
+                {
+                    nf.exporter = Some(exporter.ip());
+                    self.write_record(&nf)?;
+                    count += 1;
+                }
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn flush_block(&mut self) -> Result<(), NetFlowError> {
+        if self.block_records == 0 {
+            return Ok(());
+        }
+
+        let data = match self.header.compression {
+            Compression::Lz4 => lz4_flex::block::compress(&self.block),
+            Compression::Bz2 => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(&self.block)?;
+                encoder.finish()?
+            }
+            _ => self.block.clone(),
+        };
+
+        self.writer.write_all(&self.block_records.to_le_bytes())?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&DATA_BLOCK_TYPE_3.to_le_bytes())?;
+        self.writer.write_all(&0u16.to_le_bytes())?;
+        self.writer.write_all(&data)?;
+
+        self.header.num_blocks += 1;
+        self.block.clear();
+        self.block_records = 0;
+        Ok(())
+    }
+
+    /// Write the buffered block and the final header, return the inner writer.
+    pub fn finish(mut self) -> Result<W, NetFlowError> {
+        self.flush_block()?;
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&self.header.to_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
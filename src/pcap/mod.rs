@@ -0,0 +1,175 @@
+#[cfg(test)]
+pub(crate) mod test_data;
+
+mod packet;
+pub use self::packet::*;
+
+mod reader;
+pub use self::reader::*;
+
+mod writer;
+pub use self::writer::*;
+
+use crate::error::NetFlowError;
+use crate::netflow::{NetFlow9, TemplateCache};
+use crate::util::take_u16;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+const NETFLOW9_HEADER_LEN: usize = 20;
+
+/// Selects datagrams carrying Netflow in a capture.
+#[derive(Debug, Clone)]
+pub enum DatagramFilter {
+    /// datagrams sent to one of the ports
+    Ports(Vec<u16>),
+    /// datagrams whose payload starts like a Netflow v9 packet
+    Auto,
+}
+
+impl DatagramFilter {
+    pub fn matches(&self, udp: &UdpPacket) -> bool {
+        match self {
+            DatagramFilter::Ports(ports) => ports.contains(&udp.destination.port()),
+            DatagramFilter::Auto => looks_like_netflow9(udp.payload),
+        }
+    }
+}
+
+/// Check version and the first flowset length without parsing the packet.
+pub fn looks_like_netflow9(payload: &[u8]) -> bool {
+    if payload.len() < NETFLOW9_HEADER_LEN {
+        return false;
+    }
+
+    match take_u16(payload) {
+        Ok((_, 9)) => (),
+        _ => return false,
+    }
+
+    let flowsets = &payload[NETFLOW9_HEADER_LEN..];
+    if flowsets.is_empty() {
+        return true;
+    }
+    if flowsets.len() < 4 {
+        return false;
+    }
+
+    match take_u16(&flowsets[2..]) {
+        Ok((_, length)) => length >= 4 && (length as usize) <= flowsets.len(),
+        Err(_) => false,
+    }
+}
+
+/// A Netflow packet found in a capture.
+#[derive(Debug, Clone, Serialize)]
+pub struct CapturedFlow {
+    /// capture time since UNIX epoch
+    pub timestamp: Duration,
+    pub exporter: SocketAddr,
+    pub collector: SocketAddr,
+    pub netflow: NetFlow9,
+}
+
+/// Reads Netflow packets from a pcap or pcapng file and decodes them with the templates seen before.
+pub struct FlowReader<R> {
+    packets: PcapReader<R>,
+    filter: DatagramFilter,
+    templates: TemplateCache,
+}
+
+impl<R: Read> FlowReader<R> {
+    pub fn new(reader: R, filter: DatagramFilter) -> Result<FlowReader<R>, NetFlowError> {
+        Ok(FlowReader {
+            packets: PcapReader::new(reader)?,
+            filter,
+            templates: TemplateCache::new(),
+        })
+    }
+
+    pub fn templates(&self) -> &TemplateCache {
+        &self.templates
+    }
+
+    /// Return the next selected datagram decoded as Netflow.
+    /// A datagram that cannot be decoded is returned as an error, later packets can still be read.
+    pub fn next_flow(&mut self) -> Result<Option<CapturedFlow>, NetFlowError> {
+        while let Some(packet) = self.packets.next_packet()? {
+            let udp = match parse_udp(packet.link_type, &packet.data) {
+                Some(udp) => udp,
+                None => continue,
+            };
+
+            if !self.filter.matches(&udp) {
+                continue;
+            }
+
+            let netflow = self.templates.decode(udp.source, udp.payload)?;
+
+            return Ok(Some(CapturedFlow {
+                timestamp: packet.timestamp,
+                exporter: udp.source,
+                collector: udp.destination,
+                netflow,
+            }));
+        }
+
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for FlowReader<R> {
+    type Item = Result<CapturedFlow, NetFlowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_flow().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test_pcap {
+    use super::*;
+    use crate::netflow::test_data::NETFLOWV9_DATA;
+
+    #[test]
+    fn test_looks_like_netflow9() {
+        assert!(looks_like_netflow9(&NETFLOWV9_DATA));
+        assert!(!looks_like_netflow9(&NETFLOWV9_DATA[..10]));
+        assert!(!looks_like_netflow9(&[0x00, 0x05, 0x00, 0x01]));
+
+        let mut payload = NETFLOWV9_DATA.to_vec();
+        payload.truncate(30);
+        assert!(!looks_like_netflow9(&payload));
+    }
+
+    #[test]
+    fn test_flow_reader() {
+        let netflow = test_data::ethernet_ipv4_udp(&[100], &NETFLOWV9_DATA);
+        let other = test_data::ethernet_ipv4_udp(&[], &[0xff; 32]);
+        let file = test_data::pcap_file(&[(1, 0, &other), (2, 0, &netflow)], false);
+
+        let flows: Vec<_> = FlowReader::new(&file[..], DatagramFilter::Auto)
+            .unwrap()
+            .map(|flow| flow.unwrap())
+            .collect();
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].timestamp, Duration::new(2, 0));
+        assert_eq!(flows[0].exporter, "192.0.2.1:2055".parse().unwrap());
+        assert_eq!(flows[0].collector, "192.0.2.100:9995".parse().unwrap());
+        assert_eq!(flows[0].netflow.flow_sequence, 883);
+    }
+
+    #[test]
+    fn test_port_filter() {
+        let netflow = test_data::ethernet_ipv4_udp(&[], &NETFLOWV9_DATA);
+        let file = test_data::pcapng_file(&[(0, &netflow)]);
+
+        let mut reader = FlowReader::new(&file[..], DatagramFilter::Ports(vec![2055])).unwrap();
+        assert!(reader.next_flow().unwrap().is_none());
+
+        let mut reader = FlowReader::new(&file[..], DatagramFilter::Ports(vec![9995])).unwrap();
+        assert!(reader.next_flow().unwrap().is_some());
+        assert_eq!(reader.templates().len(), 5);
+    }
+}
@@ -0,0 +1,308 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+// link types, http://www.tcpdump.org/linktypes.html
+pub const LINKTYPE_NULL: u32 = 0;
+pub const LINKTYPE_ETHERNET: u32 = 1;
+pub const LINKTYPE_RAW: u32 = 101;
+pub const LINKTYPE_LINUX_SLL: u32 = 113;
+pub const LINKTYPE_IPV4: u32 = 228;
+pub const LINKTYPE_IPV6: u32 = 229;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+
+const IPPROTO_HOPOPTS: u8 = 0;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ROUTING: u8 = 43;
+const IPPROTO_FRAGMENT: u8 = 44;
+const IPPROTO_DSTOPTS: u8 = 60;
+
+/// UDP datagram found in a captured frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UdpPacket<'a> {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    /// VLAN ids from outer to inner
+    pub vlans: Vec<u16>,
+    pub payload: &'a [u8],
+}
+
+/// IP packet found in a captured frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IpPacket<'a> {
+    pub source: IpAddr,
+    pub destination: IpAddr,
+    /// transport protocol, after IPv6 extension headers
+    pub protocol: u8,
+    /// type of service, or traffic class for IPv6
+    pub tos: u8,
+    /// length of the packet from the IP header on, as sent
+    pub length: u16,
+    /// VLAN ids from outer to inner
+    pub vlans: Vec<u16>,
+    /// offset in 8 byte units, only the first fragment carries the transport header
+    pub fragment_offset: u16,
+    pub more_fragments: bool,
+    /// transport header and data
+    pub payload: &'a [u8],
+}
+
+impl<'a> IpPacket<'a> {
+    pub fn is_fragment(&self) -> bool {
+        self.fragment_offset != 0 || self.more_fragments
+    }
+}
+
+/// Walk link and network headers of a frame.
+/// Return None if the frame does not carry IPv4 or IPv6, or its headers are truncated.
+pub fn parse_ip(link_type: u32, frame: &[u8]) -> Option<IpPacket<'_>> {
+    let mut vlans = Vec::new();
+
+    let (ethertype, ip) = match link_type {
+        LINKTYPE_ETHERNET => parse_ethernet(frame, &mut vlans)?,
+        LINKTYPE_NULL => {
+            // address family in host byte order of the capturing machine,
+            // the family is small so the smaller reading is the right one
+            if frame.len() < 4 {
+                return None;
+            }
+            let family = LittleEndian::read_u32(frame).min(BigEndian::read_u32(frame));
+            match family {
+                2 => (ETHERTYPE_IPV4, &frame[4..]),
+                24 | 28 | 30 => (ETHERTYPE_IPV6, &frame[4..]),
+                _ => return None,
+            }
+        }
+        LINKTYPE_LINUX_SLL => {
+            if frame.len() < 16 {
+                return None;
+            }
+            (BigEndian::read_u16(&frame[14..]), &frame[16..])
+        }
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => match frame.first()? >> 4 {
+            4 => (ETHERTYPE_IPV4, frame),
+            6 => (ETHERTYPE_IPV6, frame),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let mut packet = match ethertype {
+        ETHERTYPE_IPV4 => parse_ipv4(ip)?,
+        ETHERTYPE_IPV6 => parse_ipv6(ip)?,
+        _ => return None,
+    };
+    packet.vlans = vlans;
+
+    Some(packet)
+}
+
+/// Walk link, network and transport headers of a frame.
+/// Return None if the frame is not a complete UDP datagram, fragments are not reassembled.
+pub fn parse_udp(link_type: u32, frame: &[u8]) -> Option<UdpPacket<'_>> {
+    let ip = parse_ip(link_type, frame)?;
+    if ip.protocol != IPPROTO_UDP || ip.is_fragment() {
+        return None;
+    }
+
+    let udp = ip.payload;
+    if udp.len() < 8 {
+        return None;
+    }
+
+    let udp_len = BigEndian::read_u16(&udp[4..]) as usize;
+    if udp_len < 8 || udp_len > udp.len() {
+        return None;
+    }
+
+    Some(UdpPacket {
+        source: SocketAddr::new(ip.source, BigEndian::read_u16(udp)),
+        destination: SocketAddr::new(ip.destination, BigEndian::read_u16(&udp[2..])),
+        vlans: ip.vlans,
+        payload: &udp[8..udp_len],
+    })
+}
+
+fn parse_ethernet<'a>(frame: &'a [u8], vlans: &mut Vec<u16>) -> Option<(u16, &'a [u8])> {
+    if frame.len() < 14 {
+        return None;
+    }
+
+    let mut ethertype = BigEndian::read_u16(&frame[12..]);
+    let mut rest = &frame[14..];
+
+    while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+        if rest.len() < 4 {
+            return None;
+        }
+        vlans.push(BigEndian::read_u16(rest) & 0x0fff);
+        ethertype = BigEndian::read_u16(&rest[2..]);
+        rest = &rest[4..];
+    }
+
+    Some((ethertype, rest))
+}
+
+fn parse_ipv4(ip: &[u8]) -> Option<IpPacket<'_>> {
+    if ip.len() < 20 || ip[0] >> 4 != 4 {
+        return None;
+    }
+
+    let header_len = ((ip[0] & 0x0f) as usize) * 4;
+    let total_len = BigEndian::read_u16(&ip[2..]) as usize;
+    let fragment = BigEndian::read_u16(&ip[6..]);
+
+    if header_len < 20 || total_len < header_len || total_len > ip.len() {
+        return None;
+    }
+
+    let source = Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]);
+    let destination = Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]);
+
+    Some(IpPacket {
+        source: IpAddr::V4(source),
+        destination: IpAddr::V4(destination),
+        protocol: ip[9],
+        tos: ip[1],
+        length: total_len as u16,
+        vlans: Vec::new(),
+        fragment_offset: fragment & 0x1fff,
+        more_fragments: fragment & 0x2000 != 0,
+        payload: &ip[header_len..total_len],
+    })
+}
+
+fn parse_ipv6(ip: &[u8]) -> Option<IpPacket<'_>> {
+    if ip.len() < 40 || ip[0] >> 4 != 6 {
+        return None;
+    }
+
+    let payload_len = BigEndian::read_u16(&ip[4..]) as usize;
+    if 40 + payload_len > ip.len() {
+        return None;
+    }
+
+    let mut source = [0u8; 16];
+    source.copy_from_slice(&ip[8..24]);
+    let mut destination = [0u8; 16];
+    destination.copy_from_slice(&ip[24..40]);
+
+    let mut next_header = ip[6];
+    let mut rest = &ip[40..40 + payload_len];
+    let mut fragment_offset = 0;
+    let mut more_fragments = false;
+
+    loop {
+        match next_header {
+            IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS => {
+                if rest.len() < 8 {
+                    return None;
+                }
+                let len = (rest[1] as usize + 1) * 8;
+                if len > rest.len() {
+                    return None;
+                }
+                next_header = rest[0];
+                rest = &rest[len..];
+            }
+            IPPROTO_FRAGMENT => {
+                if rest.len() < 8 {
+                    return None;
+                }
+                let fragment = BigEndian::read_u16(&rest[2..]);
+                fragment_offset = fragment >> 3;
+                more_fragments = fragment & 1 != 0;
+                next_header = rest[0];
+                rest = &rest[8..];
+            }
+            _ => break,
+        }
+    }
+
+    Some(IpPacket {
+        source: IpAddr::V6(Ipv6Addr::from(source)),
+        destination: IpAddr::V6(Ipv6Addr::from(destination)),
+        protocol: next_header,
+        tos: ((BigEndian::read_u16(ip) >> 4) & 0xff) as u8,
+        length: (40 + payload_len) as u16,
+        vlans: Vec::new(),
+        fragment_offset,
+        more_fragments,
+        payload: rest,
+    })
+}
+
+#[cfg(test)]
+mod test_packet {
+    use super::*;
+    use crate::pcap::test_data;
+
+    #[test]
+    fn test_ethernet_ipv4() {
+        let payload = [1, 2, 3, 4, 5];
+        let frame = test_data::ethernet_ipv4_udp(&[], &payload);
+
+        let udp = parse_udp(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(udp.source, "192.0.2.1:2055".parse().unwrap());
+        assert_eq!(udp.destination, "192.0.2.100:9995".parse().unwrap());
+        assert!(udp.vlans.is_empty());
+        assert_eq!(udp.payload, &payload);
+    }
+
+    #[test]
+    fn test_vlan() {
+        let payload = [1, 2, 3];
+        let frame = test_data::ethernet_ipv4_udp(&[10, 20], &payload);
+
+        let udp = parse_udp(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(udp.vlans, vec![10, 20]);
+        assert_eq!(udp.payload, &payload);
+    }
+
+    #[test]
+    fn test_ipv6() {
+        let payload = [9; 11];
+        let frame = test_data::ipv6_udp(&payload);
+
+        let udp = parse_udp(LINKTYPE_RAW, &frame).unwrap();
+        assert_eq!(udp.source, "[2001:db8::1]:2055".parse().unwrap());
+        assert_eq!(udp.destination, "[2001:db8::100]:9995".parse().unwrap());
+        assert_eq!(udp.payload, &payload);
+    }
+
+    #[test]
+    fn test_ip() {
+        let mut frame = test_data::ethernet_ipv4_udp(&[7], &[0; 4]);
+        // protocol = TCP, more fragments
+        frame[18 + 9] = 6;
+        frame[18 + 6] = 0x20;
+
+        let ip = parse_ip(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(ip.protocol, 6);
+        assert_eq!(ip.length, 32);
+        assert_eq!(ip.vlans, vec![7]);
+        assert!(ip.is_fragment());
+        assert!(parse_udp(LINKTYPE_ETHERNET, &frame).is_none());
+
+        let frame = test_data::ipv6_udp(&[0; 3]);
+        let ip = parse_ip(LINKTYPE_RAW, &frame).unwrap();
+        assert_eq!(ip.protocol, 17);
+        assert_eq!(ip.length, 51);
+        assert!(!ip.is_fragment());
+    }
+
+    #[test]
+    fn test_not_udp() {
+        let mut frame = test_data::ethernet_ipv4_udp(&[], &[0; 4]);
+        // protocol = TCP
+        frame[14 + 9] = 6;
+        assert!(parse_udp(LINKTYPE_ETHERNET, &frame).is_none());
+
+        let frame = test_data::ethernet_ipv4_udp(&[], &[0; 4]);
+        assert!(parse_udp(LINKTYPE_ETHERNET, &frame[..30]).is_none());
+        assert!(parse_udp(147, &frame).is_none());
+    }
+}
@@ -0,0 +1,434 @@
+use crate::error::NetFlowError;
+use crate::util::{read_exact, read_or_eof};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::io::Read;
+use std::time::Duration;
+
+pub const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+pub const PCAP_MAGIC_NANO: u32 = 0xa1b2_3c4d;
+pub const PCAPNG_SHB: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+const PCAPNG_IDB: u32 = 1;
+const PCAPNG_OPB: u32 = 2;
+const PCAPNG_SPB: u32 = 3;
+const PCAPNG_EPB: u32 = 6;
+const IF_TSRESOL: u16 = 9;
+
+const PCAP_HEADER_LEN: usize = 24;
+// a block larger than this is taken as a broken file
+const MAX_BLOCK_LEN: usize = 16 * 1024 * 1024;
+
+/// A frame read from a capture file.
+#[derive(Debug, Clone)]
+pub struct CapturedPacket {
+    /// time since UNIX epoch
+    pub timestamp: Duration,
+    pub link_type: u32,
+    /// length on the wire, data may be truncated by snaplen
+    pub original_len: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Endian {
+    Big,
+    Little,
+}
+
+impl Endian {
+    fn u16(self, buf: &[u8]) -> u16 {
+        match self {
+            Endian::Big => BigEndian::read_u16(buf),
+            Endian::Little => LittleEndian::read_u16(buf),
+        }
+    }
+
+    fn u32(self, buf: &[u8]) -> u32 {
+        match self {
+            Endian::Big => BigEndian::read_u32(buf),
+            Endian::Little => LittleEndian::read_u32(buf),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    link_type: u32,
+    snaplen: u32,
+    // timestamp units per second
+    resolution: u64,
+}
+
+#[derive(Debug)]
+enum Format {
+    Pcap {
+        endian: Endian,
+        link_type: u32,
+        nano: bool,
+    },
+    PcapNg {
+        endian: Endian,
+        interfaces: Vec<Interface>,
+    },
+}
+
+/// Reads frames from a pcap or pcapng file, the format is detected from the magic number.
+pub struct PcapReader<R> {
+    reader: R,
+    format: Format,
+}
+
+fn to_duration(units: u64, resolution: u64) -> Duration {
+    let secs = units / resolution;
+    // u128 as the remainder times 10^9 overflows for resolutions finer than 10^-10
+    let nanos = u128::from(units % resolution) * 1_000_000_000 / u128::from(resolution);
+
+    Duration::new(secs, nanos as u32)
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut reader: R) -> Result<PcapReader<R>, NetFlowError> {
+        let mut magic = [0u8; 4];
+        read_exact(&mut reader, &mut magic)?;
+
+        let format = if BigEndian::read_u32(&magic) == PCAPNG_SHB {
+            let mut head = [0u8; 8];
+            read_exact(&mut reader, &mut head)?;
+            PcapReader::read_section(&head, &mut reader)?
+        } else {
+            PcapReader::read_pcap_header(&mut reader, &magic)?
+        };
+
+        Ok(PcapReader { reader, format })
+    }
+
+    fn read_pcap_header(reader: &mut R, magic: &[u8; 4]) -> Result<Format, NetFlowError> {
+        let (endian, nano) = match (BigEndian::read_u32(magic), LittleEndian::read_u32(magic)) {
+            (PCAP_MAGIC, _) => (Endian::Big, false),
+            (PCAP_MAGIC_NANO, _) => (Endian::Big, true),
+            (_, PCAP_MAGIC) => (Endian::Little, false),
+            (_, PCAP_MAGIC_NANO) => (Endian::Little, true),
+            _ => return Err(NetFlowError::invalid_capture("unknown magic number")),
+        };
+
+        let mut header = [0u8; PCAP_HEADER_LEN - 4];
+        read_exact(reader, &mut header)?;
+
+        Ok(Format::Pcap {
+            endian,
+            // upper bits of network field may hold FCS length
+            link_type: endian.u32(&header[16..]) & 0x0fff_ffff,
+            nano,
+        })
+    }
+
+    /// Parse a section header block, head is block length and byte-order magic.
+    fn read_section(head: &[u8; 8], reader: &mut R) -> Result<Format, NetFlowError> {
+        let endian = match (
+            BigEndian::read_u32(&head[4..]),
+            LittleEndian::read_u32(&head[4..]),
+        ) {
+            (PCAPNG_BYTE_ORDER_MAGIC, _) => Endian::Big,
+            (_, PCAPNG_BYTE_ORDER_MAGIC) => Endian::Little,
+            _ => return Err(NetFlowError::invalid_capture("unknown byte-order magic")),
+        };
+
+        let total_len = endian.u32(head) as usize;
+        if !(28..=MAX_BLOCK_LEN).contains(&total_len) || !total_len.is_multiple_of(4) {
+            return Err(NetFlowError::invalid_capture(
+                "invalid section header length",
+            ));
+        }
+
+        // version, section length, options and trailing length are not needed
+        let mut rest = vec![0u8; total_len - 12];
+        read_exact(reader, &mut rest)?;
+
+        Ok(Format::PcapNg {
+            endian,
+            interfaces: Vec::new(),
+        })
+    }
+
+    pub fn next_packet(&mut self) -> Result<Option<CapturedPacket>, NetFlowError> {
+        match self.format {
+            Format::Pcap {
+                endian,
+                link_type,
+                nano,
+            } => self.next_pcap_packet(endian, link_type, nano),
+            Format::PcapNg { .. } => self.next_pcapng_packet(),
+        }
+    }
+
+    fn next_pcap_packet(
+        &mut self,
+        endian: Endian,
+        link_type: u32,
+        nano: bool,
+    ) -> Result<Option<CapturedPacket>, NetFlowError> {
+        let mut header = [0u8; 16];
+        if !read_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+
+        let secs = u64::from(endian.u32(&header));
+        let fraction = u64::from(endian.u32(&header[4..]));
+        let captured_len = endian.u32(&header[8..]) as usize;
+        let original_len = endian.u32(&header[12..]);
+
+        if captured_len > MAX_BLOCK_LEN {
+            return Err(NetFlowError::invalid_capture("invalid packet length"));
+        }
+
+        let mut data = vec![0u8; captured_len];
+        read_exact(&mut self.reader, &mut data)?;
+
+        let timestamp = if nano {
+            Duration::new(secs, 0) + Duration::from_nanos(fraction)
+        } else {
+            Duration::new(secs, 0) + Duration::from_micros(fraction)
+        };
+
+        Ok(Some(CapturedPacket {
+            timestamp,
+            link_type,
+            original_len,
+            data,
+        }))
+    }
+
+    fn next_pcapng_packet(&mut self) -> Result<Option<CapturedPacket>, NetFlowError> {
+        loop {
+            let mut head = [0u8; 8];
+            if !read_or_eof(&mut self.reader, &mut head)? {
+                return Ok(None);
+            }
+
+            if BigEndian::read_u32(&head) == PCAPNG_SHB {
+                // a new section, may change byte order and has its own interfaces
+                let mut section = [0u8; 8];
+                section[..4].copy_from_slice(&head[4..]);
+                read_exact(&mut self.reader, &mut section[4..])?;
+                self.format = PcapReader::read_section(&section, &mut self.reader)?;
+                continue;
+            }
+
+            let endian = match self.format {
+                Format::PcapNg { endian, .. } => endian,
+                _ => unreachable!(),
+            };
+
+            let block_type = endian.u32(&head);
+            let total_len = endian.u32(&head[4..]) as usize;
+            if !(12..=MAX_BLOCK_LEN).contains(&total_len) || !total_len.is_multiple_of(4) {
+                return Err(NetFlowError::invalid_capture("invalid block length"));
+            }
+
+            // body without type, length and trailing length
+            let mut body = vec![0u8; total_len - 8];
+            read_exact(&mut self.reader, &mut body)?;
+            body.truncate(total_len - 12);
+
+            if let Some(packet) = self.parse_block(endian, block_type, &body)? {
+                return Ok(Some(packet));
+            }
+        }
+    }
+
+    fn parse_block(
+        &mut self,
+        endian: Endian,
+        block_type: u32,
+        body: &[u8],
+    ) -> Result<Option<CapturedPacket>, NetFlowError> {
+        let interfaces = match &mut self.format {
+            Format::PcapNg { interfaces, .. } => interfaces,
+            _ => unreachable!(),
+        };
+
+        match block_type {
+            PCAPNG_IDB => {
+                if body.len() < 8 {
+                    return Err(NetFlowError::invalid_capture(
+                        "invalid interface description block",
+                    ));
+                }
+
+                let mut interface = Interface {
+                    link_type: u32::from(endian.u16(body)),
+                    snaplen: endian.u32(&body[4..]),
+                    resolution: 1_000_000,
+                };
+
+                let mut options = &body[8..];
+                while options.len() >= 4 {
+                    let code = endian.u16(options);
+                    let len = endian.u16(&options[2..]) as usize;
+                    let padded = len.div_ceil(4) * 4;
+                    if code == 0 || options.len() < 4 + padded {
+                        break;
+                    }
+                    if code == IF_TSRESOL && len == 1 {
+                        let exp = u32::from(options[4] & 0x7f);
+                        let base: u64 = if options[4] & 0x80 == 0 { 10 } else { 2 };
+                        interface.resolution = base.checked_pow(exp).ok_or_else(|| {
+                            NetFlowError::invalid_capture("invalid timestamp resolution")
+                        })?;
+                    }
+                    options = &options[4 + padded..];
+                }
+
+                interfaces.push(interface);
+                Ok(None)
+            }
+            PCAPNG_EPB | PCAPNG_OPB => {
+                if body.len() < 20 {
+                    return Err(NetFlowError::invalid_capture("invalid packet block"));
+                }
+
+                // obsolete packet block has 16 bit interface id and drops count
+                let interface_id = if block_type == PCAPNG_EPB {
+                    endian.u32(body) as usize
+                } else {
+                    endian.u16(body) as usize
+                };
+                let interface = interfaces
+                    .get(interface_id)
+                    .ok_or_else(|| NetFlowError::invalid_capture("unknown interface"))?;
+
+                let units =
+                    (u64::from(endian.u32(&body[4..])) << 32) | u64::from(endian.u32(&body[8..]));
+                let captured_len = endian.u32(&body[12..]) as usize;
+                let original_len = endian.u32(&body[16..]);
+
+                if body.len() < 20 + captured_len {
+                    return Err(NetFlowError::invalid_capture("invalid packet length"));
+                }
+
+                Ok(Some(CapturedPacket {
+                    timestamp: to_duration(units, interface.resolution),
+                    link_type: interface.link_type,
+                    original_len,
+                    data: body[20..20 + captured_len].to_vec(),
+                }))
+            }
+            PCAPNG_SPB => {
+                if body.len() < 4 {
+                    return Err(NetFlowError::invalid_capture("invalid simple packet block"));
+                }
+
+                let interface = interfaces
+                    .first()
+                    .ok_or_else(|| NetFlowError::invalid_capture("unknown interface"))?;
+                let original_len = endian.u32(body);
+                let mut captured_len = (original_len as usize).min(body.len() - 4);
+                if interface.snaplen > 0 {
+                    captured_len = captured_len.min(interface.snaplen as usize);
+                }
+
+                Ok(Some(CapturedPacket {
+                    timestamp: Duration::new(0, 0),
+                    link_type: interface.link_type,
+                    original_len,
+                    data: body[4..4 + captured_len].to_vec(),
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<CapturedPacket, NetFlowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test_reader {
+    use super::PcapReader;
+    use crate::pcap::packet::LINKTYPE_ETHERNET;
+    use crate::pcap::test_data;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pcap() {
+        let frame = test_data::ethernet_ipv4_udp(&[], &[1, 2, 3]);
+        let file = test_data::pcap_file(&[(1_500_000_000, 250_000, &frame)], false);
+
+        let mut reader = PcapReader::new(&file[..]).unwrap();
+        let packet = reader.next_packet().unwrap().unwrap();
+        assert_eq!(packet.timestamp, Duration::new(1_500_000_000, 250_000_000));
+        assert_eq!(packet.link_type, LINKTYPE_ETHERNET);
+        assert_eq!(packet.data, frame);
+        assert!(reader.next_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pcap_big_endian() {
+        let frame = test_data::ethernet_ipv4_udp(&[], &[1, 2, 3]);
+        let file = test_data::pcap_file(&[(10, 1, &frame), (11, 2, &frame)], true);
+
+        let packets: Vec<_> = PcapReader::new(&file[..])
+            .unwrap()
+            .map(|p| p.unwrap())
+            .collect();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[1].timestamp, Duration::new(11, 2000));
+    }
+
+    #[test]
+    fn test_pcapng() {
+        let frame = test_data::ethernet_ipv4_udp(&[], &[1, 2, 3]);
+        let file = test_data::pcapng_file(&[(1_500_000_000_123_456, &frame), (0, &frame)]);
+
+        let packets: Vec<_> = PcapReader::new(&file[..])
+            .unwrap()
+            .map(|p| p.unwrap())
+            .collect();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(
+            packets[0].timestamp,
+            Duration::new(1_500_000_000, 123_456_000)
+        );
+        assert_eq!(packets[0].data, frame);
+        assert_eq!(packets[0].link_type, LINKTYPE_ETHERNET);
+    }
+
+    #[test]
+    fn test_pcapng_fine_resolution() {
+        let frame = test_data::ethernet_ipv4_udp(&[], &[1, 2, 3]);
+        let picos = 1_500_000_123_456_789_012;
+        let file = test_data::pcapng_file_tsresol(12, &[(picos, &frame)]);
+        let packet = PcapReader::new(&file[..])
+            .unwrap()
+            .next_packet()
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.timestamp, Duration::new(1_500_000, 123_456_789));
+
+        // 2^-40 s
+        let file = test_data::pcapng_file_tsresol(0x80 | 40, &[(3 << 39, &frame)]);
+        let packet = PcapReader::new(&file[..])
+            .unwrap()
+            .next_packet()
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.timestamp, Duration::new(1, 500_000_000));
+    }
+
+    #[test]
+    fn test_invalid_capture() {
+        assert!(PcapReader::new(&[0u8; 24][..]).is_err());
+        assert!(PcapReader::new(&[0xd4, 0xc3][..]).is_err());
+
+        let frame = test_data::ethernet_ipv4_udp(&[], &[1, 2, 3]);
+        let file = test_data::pcap_file(&[(10, 1, &frame)], false);
+        let mut reader = PcapReader::new(&file[..file.len() - 1]).unwrap();
+        assert!(reader.next_packet().is_err());
+    }
+}
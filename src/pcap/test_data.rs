@@ -0,0 +1,161 @@
+// builders of capture files, frames carry a datagram from 192.0.2.1:2055 to 192.0.2.100:9995
+
+pub fn ethernet_ipv4_udp(vlans: &[u16], payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![
+        0x00, 0x00, 0x5e, 0x00, 0x53, 0x02, 0x00, 0x00, 0x5e, 0x00, 0x53, 0x01,
+    ];
+
+    for vlan in vlans {
+        frame.extend_from_slice(&[0x81, 0x00, (vlan >> 8) as u8, *vlan as u8]);
+    }
+    frame.extend_from_slice(&[0x08, 0x00]);
+
+    let total_len = 20 + 8 + payload.len();
+    frame.extend_from_slice(&[
+        0x45,
+        0x00,
+        (total_len >> 8) as u8,
+        total_len as u8,
+        0x00,
+        0x00,
+        0x40,
+        0x00,
+        0x40,
+        0x11,
+        0x00,
+        0x00,
+        192,
+        0,
+        2,
+        1,
+        192,
+        0,
+        2,
+        100,
+    ]);
+    frame.extend(udp(payload));
+
+    frame
+}
+
+pub fn ipv6_udp(payload: &[u8]) -> Vec<u8> {
+    let udp = udp(payload);
+    let mut packet = vec![
+        0x60,
+        0x00,
+        0x00,
+        0x00,
+        (udp.len() >> 8) as u8,
+        udp.len() as u8,
+        0x11,
+        0x40,
+    ];
+
+    packet.extend_from_slice(&[
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01,
+    ]);
+    packet.extend_from_slice(&[
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x00,
+    ]);
+    packet.extend(udp);
+
+    packet
+}
+
+fn udp(payload: &[u8]) -> Vec<u8> {
+    let len = 8 + payload.len();
+    let mut udp = vec![
+        0x08,
+        0x07,
+        0x27,
+        0x0b,
+        (len >> 8) as u8,
+        len as u8,
+        0x00,
+        0x00,
+    ];
+    udp.extend_from_slice(payload);
+
+    udp
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32, big_endian: bool) {
+    if big_endian {
+        buf.extend_from_slice(&value.to_be_bytes());
+    } else {
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Ethernet capture with (seconds, microseconds, frame).
+pub fn pcap_file(packets: &[(u32, u32, &[u8])], big_endian: bool) -> Vec<u8> {
+    let mut file = Vec::new();
+
+    push_u32(&mut file, 0xa1b2_c3d4, big_endian);
+    if big_endian {
+        file.extend_from_slice(&[0x00, 0x02, 0x00, 0x04]);
+    } else {
+        file.extend_from_slice(&[0x02, 0x00, 0x04, 0x00]);
+    }
+    push_u32(&mut file, 0, big_endian);
+    push_u32(&mut file, 0, big_endian);
+    push_u32(&mut file, 65535, big_endian);
+    push_u32(&mut file, 1, big_endian);
+
+    for (secs, micros, frame) in packets {
+        push_u32(&mut file, *secs, big_endian);
+        push_u32(&mut file, *micros, big_endian);
+        push_u32(&mut file, frame.len() as u32, big_endian);
+        push_u32(&mut file, frame.len() as u32, big_endian);
+        file.extend_from_slice(frame);
+    }
+
+    file
+}
+
+fn pcapng_block(file: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+    let padded = body.len().div_ceil(4) * 4;
+    let total_len = (12 + padded) as u32;
+
+    push_u32(file, block_type, false);
+    push_u32(file, total_len, false);
+    file.extend_from_slice(body);
+    file.extend(vec![0; padded - body.len()]);
+    push_u32(file, total_len, false);
+}
+
+/// Little endian pcapng with one Ethernet interface and (microseconds, frame).
+pub fn pcapng_file(packets: &[(u64, &[u8])]) -> Vec<u8> {
+    pcapng_file_tsresol(6, packets)
+}
+
+/// Same with the if_tsresol option of the interface, timestamps are in its units.
+pub fn pcapng_file_tsresol(tsresol: u8, packets: &[(u64, &[u8])]) -> Vec<u8> {
+    let mut file = Vec::new();
+
+    let mut shb = Vec::new();
+    push_u32(&mut shb, 0x1a2b_3c4d, false);
+    shb.extend_from_slice(&[0x01, 0x00, 0x00, 0x00]);
+    shb.extend_from_slice(&[0xff; 8]);
+    pcapng_block(&mut file, 0x0a0d_0d0a, &shb);
+
+    // link type, reserved, snaplen, if_tsresol, end of options
+    let mut idb = vec![0x01, 0x00, 0x00, 0x00];
+    push_u32(&mut idb, 0, false);
+    idb.extend_from_slice(&[0x09, 0x00, 0x01, 0x00, tsresol, 0x00, 0x00, 0x00]);
+    idb.extend_from_slice(&[0x00; 4]);
+    pcapng_block(&mut file, 1, &idb);
+
+    for (units, frame) in packets {
+        let mut epb = Vec::new();
+        push_u32(&mut epb, 0, false);
+        push_u32(&mut epb, (units >> 32) as u32, false);
+        push_u32(&mut epb, *units as u32, false);
+        push_u32(&mut epb, frame.len() as u32, false);
+        push_u32(&mut epb, frame.len() as u32, false);
+        epb.extend_from_slice(frame);
+        pcapng_block(&mut file, 6, &epb);
+    }
+
+    file
+}
@@ -0,0 +1,339 @@
+use super::packet::LINKTYPE_ETHERNET;
+use super::reader::PCAP_MAGIC_NANO;
+use crate::error::NetFlowError;
+use crate::field::MacAddr;
+use crate::netflow::NetFlow9;
+use crate::util::{u16_to_bytes, u32_to_bytes};
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+const SNAPLEN: u32 = 65535;
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const UDP_HEADER_LEN: usize = 8;
+const IPPROTO_UDP: u8 = 17;
+const TTL: u8 = 64;
+
+/// Writes UDP payloads as Ethernet frames into a pcap file.
+/// Headers are synthesized from the configured addresses, so the file can be read by Wireshark or tcpreplay.
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    source: SocketAddr,
+    destination: SocketAddr,
+    source_mac: MacAddr,
+    destination_mac: MacAddr,
+    ip_id: u16,
+}
+
+fn check_endpoints(source: SocketAddr, destination: SocketAddr) -> Result<(), NetFlowError> {
+    if source.is_ipv4() == destination.is_ipv4() {
+        Ok(())
+    } else {
+        Err(NetFlowError::invalid_config(
+            "source and destination address families differ",
+        ))
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Write the file header, records use nanosecond timestamps.
+    pub fn new(
+        mut writer: W,
+        source: SocketAddr,
+        destination: SocketAddr,
+    ) -> Result<PcapWriter<W>, NetFlowError> {
+        check_endpoints(source, destination)?;
+
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MAGIC_NANO.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&0i32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        writer.write_all(&header)?;
+
+        Ok(PcapWriter {
+            writer,
+            source,
+            destination,
+            // locally administered addresses
+            source_mac: MacAddr::new(0x02, 0, 0, 0, 0, 0x01),
+            destination_mac: MacAddr::new(0x02, 0, 0, 0, 0, 0x02),
+            ip_id: 0,
+        })
+    }
+
+    pub fn set_endpoints(
+        &mut self,
+        source: SocketAddr,
+        destination: SocketAddr,
+    ) -> Result<(), NetFlowError> {
+        check_endpoints(source, destination)?;
+        self.source = source;
+        self.destination = destination;
+
+        Ok(())
+    }
+
+    pub fn set_mac(&mut self, source: MacAddr, destination: MacAddr) {
+        self.source_mac = source;
+        self.destination_mac = destination;
+    }
+
+    /// Write a datagram captured at timestamp, time since UNIX epoch.
+    pub fn write_payload(
+        &mut self,
+        timestamp: Duration,
+        payload: &[u8],
+    ) -> Result<(), NetFlowError> {
+        let frame = self.build_frame(payload)?;
+        let mut header = Vec::with_capacity(16);
+
+        header.extend_from_slice(&(timestamp.as_secs() as u32).to_le_bytes());
+        header.extend_from_slice(&timestamp.subsec_nanos().to_le_bytes());
+        header.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&frame)?;
+
+        Ok(())
+    }
+
+    /// Write a packet with its export time as capture time.
+    pub fn write_netflow(&mut self, netflow: &NetFlow9) -> Result<(), NetFlowError> {
+        let timestamp = Duration::from_secs(u64::from(netflow.timestamp));
+        self.write_payload(timestamp, &netflow.to_bytes())
+    }
+
+    pub fn flush(&mut self) -> Result<(), NetFlowError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn build_frame(&mut self, payload: &[u8]) -> Result<Vec<u8>, NetFlowError> {
+        let ip_header_len = if self.source.is_ipv4() {
+            IPV4_HEADER_LEN
+        } else {
+            IPV6_HEADER_LEN
+        };
+        let udp_len = UDP_HEADER_LEN + payload.len();
+        if ip_header_len + udp_len > 65535 {
+            return Err(NetFlowError::InvalidLength);
+        }
+
+        let mut frame = Vec::with_capacity(14 + ip_header_len + udp_len);
+        frame.extend_from_slice(&self.destination_mac.octets());
+        frame.extend_from_slice(&self.source_mac.octets());
+
+        let mut udp = Vec::with_capacity(udp_len);
+        push_u16(&mut udp, self.source.port());
+        push_u16(&mut udp, self.destination.port());
+        push_u16(&mut udp, udp_len as u16);
+        push_u16(&mut udp, 0);
+        udp.extend_from_slice(payload);
+
+        match (self.source.ip(), self.destination.ip()) {
+            (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                let mut pseudo = Vec::with_capacity(12);
+                pseudo.extend_from_slice(&source.octets());
+                pseudo.extend_from_slice(&destination.octets());
+                pseudo.extend_from_slice(&[0, IPPROTO_UDP]);
+                push_u16(&mut pseudo, udp_len as u16);
+                set_udp_checksum(&pseudo, &mut udp);
+
+                let mut ip = Vec::with_capacity(IPV4_HEADER_LEN);
+                ip.extend_from_slice(&[0x45, 0x00]);
+                push_u16(&mut ip, (IPV4_HEADER_LEN + udp_len) as u16);
+                push_u16(&mut ip, self.ip_id);
+                // don't fragment
+                ip.extend_from_slice(&[0x40, 0x00, TTL, IPPROTO_UDP, 0x00, 0x00]);
+                ip.extend_from_slice(&source.octets());
+                ip.extend_from_slice(&destination.octets());
+                let checksum = checksum(&[&ip]);
+                ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+                self.ip_id = self.ip_id.wrapping_add(1);
+                frame.extend_from_slice(&[0x08, 0x00]);
+                frame.extend(ip);
+            }
+            (IpAddr::V6(source), IpAddr::V6(destination)) => {
+                let mut pseudo = Vec::with_capacity(40);
+                pseudo.extend_from_slice(&source.octets());
+                pseudo.extend_from_slice(&destination.octets());
+                push_u32(&mut pseudo, udp_len as u32);
+                pseudo.extend_from_slice(&[0, 0, 0, IPPROTO_UDP]);
+                set_udp_checksum(&pseudo, &mut udp);
+
+                let mut ip = Vec::with_capacity(IPV6_HEADER_LEN);
+                ip.extend_from_slice(&[0x60, 0x00, 0x00, 0x00]);
+                push_u16(&mut ip, udp_len as u16);
+                ip.extend_from_slice(&[IPPROTO_UDP, TTL]);
+                ip.extend_from_slice(&source.octets());
+                ip.extend_from_slice(&destination.octets());
+
+                frame.extend_from_slice(&[0x86, 0xdd]);
+                frame.extend(ip);
+            }
+            _ => {
+                return Err(NetFlowError::invalid_config(
+                    "source and destination address families differ",
+                ))
+            }
+        }
+
+        frame.extend(udp);
+        Ok(frame)
+    }
+}
+
+fn push_u16(buf: &mut Vec<u8>, value: u16) {
+    let mut u16_buf = [0u8; 2];
+    u16_to_bytes(value, &mut u16_buf);
+    buf.extend_from_slice(&u16_buf);
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    let mut u32_buf = [0u8; 4];
+    u32_to_bytes(value, &mut u32_buf);
+    buf.extend_from_slice(&u32_buf);
+}
+
+/// Internet checksum (RFC 1071) over concatenated parts, each part but the last must have even length.
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+
+    for part in parts {
+        for chunk in part.chunks(2) {
+            let word = if chunk.len() == 2 {
+                u32::from(chunk[0]) << 8 | u32::from(chunk[1])
+            } else {
+                u32::from(chunk[0]) << 8
+            };
+            sum += word;
+        }
+    }
+
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+fn set_udp_checksum(pseudo: &[u8], udp: &mut [u8]) {
+    let mut sum = checksum(&[pseudo, udp]);
+    // zero means no checksum in UDP
+    if sum == 0 {
+        sum = 0xffff;
+    }
+    udp[6..8].copy_from_slice(&sum.to_be_bytes());
+}
+
+#[cfg(test)]
+mod test_writer {
+    use super::{checksum, PcapWriter};
+    use crate::error::NetFlowError;
+    use crate::netflow::test_data::NETFLOWV9_DATA;
+    use crate::netflow::NetFlow9;
+    use crate::pcap::{parse_udp, DatagramFilter, FlowReader, PcapReader};
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    #[test]
+    fn test_checksum() {
+        // example of RFC 1071
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(checksum(&[&data]), !0xddf2);
+    }
+
+    #[test]
+    fn test_write_ipv4() {
+        let source: SocketAddr = "192.0.2.1:2055".parse().unwrap();
+        let destination: SocketAddr = "192.0.2.100:9995".parse().unwrap();
+        let mut writer = PcapWriter::new(Vec::new(), source, destination).unwrap();
+
+        writer
+            .write_payload(Duration::new(100, 5), &[1, 2, 3])
+            .unwrap();
+        let file = writer.into_inner();
+
+        let packets: Vec<_> = PcapReader::new(&file[..])
+            .unwrap()
+            .map(|p| p.unwrap())
+            .collect();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].timestamp, Duration::new(100, 5));
+
+        let frame = &packets[0].data;
+        // IPv4 header checksum verifies to zero
+        assert_eq!(checksum(&[&frame[14..34]]), 0);
+
+        let udp = parse_udp(packets[0].link_type, frame).unwrap();
+        assert_eq!(udp.source, source);
+        assert_eq!(udp.destination, destination);
+        assert_eq!(udp.payload, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_write_ipv6() {
+        let source: SocketAddr = "[2001:db8::1]:2055".parse().unwrap();
+        let destination: SocketAddr = "[2001:db8::2]:4739".parse().unwrap();
+        let mut writer = PcapWriter::new(Vec::new(), source, destination).unwrap();
+
+        writer
+            .write_payload(Duration::new(1, 0), &[0xab; 9])
+            .unwrap();
+        let file = writer.into_inner();
+
+        let packet = PcapReader::new(&file[..])
+            .unwrap()
+            .next_packet()
+            .unwrap()
+            .unwrap();
+        let udp = parse_udp(packet.link_type, &packet.data).unwrap();
+        assert_eq!(udp.source, source);
+        assert_eq!(udp.destination, destination);
+        assert_eq!(udp.payload, &[0xab; 9]);
+    }
+
+    #[test]
+    fn test_mixed_family() {
+        let source: SocketAddr = "192.0.2.1:2055".parse().unwrap();
+        let destination: SocketAddr = "[2001:db8::2]:4739".parse().unwrap();
+        assert!(matches!(
+            PcapWriter::new(Vec::new(), source, destination),
+            Err(NetFlowError::InvalidConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn test_write_netflow() {
+        let source: SocketAddr = "192.0.2.1:2055".parse().unwrap();
+        let destination: SocketAddr = "192.0.2.100:9995".parse().unwrap();
+        let netflow = NetFlow9::from_bytes(&NETFLOWV9_DATA).unwrap();
+
+        let mut writer = PcapWriter::new(Vec::new(), source, destination).unwrap();
+        writer.write_netflow(&netflow).unwrap();
+        writer.write_netflow(&netflow).unwrap();
+        let file = writer.into_inner();
+
+        let flows: Vec<_> = FlowReader::new(&file[..], DatagramFilter::Ports(vec![9995]))
+            .unwrap()
+            .map(|flow| flow.unwrap())
+            .collect();
+        assert_eq!(flows.len(), 2);
+        assert_eq!(
+            flows[0].timestamp,
+            Duration::from_secs(u64::from(netflow.timestamp))
+        );
+        assert_eq!(flows[1].netflow.to_bytes(), &NETFLOWV9_DATA[..]);
+    }
+}
@@ -0,0 +1,335 @@
+use crate::error::NetFlowError;
+use crate::netflow::NetFlow9;
+use crate::pcap::{parse_udp, DatagramFilter, PcapReader};
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, Read};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A datagram to replay with the time it was captured.
+#[derive(Debug, Clone)]
+pub struct ReplayPacket {
+    /// capture time since UNIX epoch
+    pub timestamp: Duration,
+    pub exporter: SocketAddr,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct JsonPacket {
+    exporter: SocketAddr,
+    packet: NetFlow9,
+}
+
+/// Read the selected datagrams of a pcap or pcapng file.
+pub fn read_pcap<R: Read>(
+    reader: R,
+    filter: &DatagramFilter,
+) -> Result<Vec<ReplayPacket>, NetFlowError> {
+    let mut reader = PcapReader::new(reader)?;
+    let mut packets = Vec::new();
+
+    while let Some(packet) = reader.next_packet()? {
+        if let Some(udp) = parse_udp(packet.link_type, &packet.data) {
+            if filter.matches(&udp) {
+                packets.push(ReplayPacket {
+                    timestamp: packet.timestamp,
+                    exporter: udp.source,
+                    payload: udp.payload.to_vec(),
+                });
+            }
+        }
+    }
+
+    Ok(packets)
+}
+
+/// Read packets archived by `collector::write_json_line`.
+/// The archive has no capture time, packets are timed by their export time, which has a resolution of a second.
+pub fn read_json_lines<R: BufRead>(reader: R) -> Result<Vec<ReplayPacket>, NetFlowError> {
+    let mut packets = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let json: JsonPacket = serde_json::from_str(&line).map_err(io::Error::from)?;
+        packets.push(ReplayPacket {
+            timestamp: Duration::from_secs(u64::from(json.packet.timestamp)),
+            exporter: json.exporter,
+            payload: json.packet.to_bytes(),
+        });
+    }
+
+    Ok(packets)
+}
+
+/// How packets are replayed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayOptions {
+    /// 2.0 replays twice as fast as captured, 0 sends without waiting
+    pub speed: f64,
+    /// set `timestamp` to the current time, and move `sys_uptime` and `flow_sequence`
+    /// forward on every loop, so the packets look like a live export
+    pub rewrite: bool,
+    /// number of times the packets are sent, None repeats forever
+    pub loops: Option<u32>,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        ReplayOptions {
+            speed: 1.0,
+            rewrite: false,
+            loops: Some(1),
+        }
+    }
+}
+
+/// Counters of a replay.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplayStats {
+    pub loops: u32,
+    pub packets: u64,
+    pub bytes: u64,
+    /// packets sent unchanged because they are not Netflow v9
+    pub not_rewritten: u64,
+}
+
+/// Sequence numbers of an export stream, keyed by exporter and source id.
+#[derive(Debug, Clone, Copy)]
+struct SequenceSpan {
+    first: u32,
+    last: u32,
+}
+
+impl SequenceSpan {
+    // the exporter would continue with last + 1
+    fn len(&self) -> u32 {
+        self.last.wrapping_sub(self.first).wrapping_add(1)
+    }
+}
+
+/// Sends captured packets to a collector with their original gaps.
+pub struct Replayer {
+    packets: Vec<ReplayPacket>,
+    options: ReplayOptions,
+    loop_duration: Duration,
+    sequences: HashMap<(SocketAddr, u32), SequenceSpan>,
+}
+
+impl Replayer {
+    pub fn new(mut packets: Vec<ReplayPacket>, options: ReplayOptions) -> Replayer {
+        packets.sort_by_key(|packet| packet.timestamp);
+
+        // the next loop starts one average gap after the last packet
+        let loop_duration = match (packets.first(), packets.last()) {
+            (Some(first), Some(last)) if packets.len() > 1 => {
+                let span = last.timestamp - first.timestamp;
+                span + span / (packets.len() as u32 - 1)
+            }
+            _ => Duration::from_secs(1),
+        };
+
+        let mut sequences = HashMap::new();
+        for packet in &packets {
+            if let Ok(netflow) = NetFlow9::from_bytes(&packet.payload) {
+                sequences
+                    .entry((packet.exporter, netflow.source_id))
+                    .and_modify(|span: &mut SequenceSpan| span.last = netflow.flow_sequence)
+                    .or_insert(SequenceSpan {
+                        first: netflow.flow_sequence,
+                        last: netflow.flow_sequence,
+                    });
+            }
+        }
+
+        Replayer {
+            packets,
+            options,
+            loop_duration,
+            sequences,
+        }
+    }
+
+    pub fn packets(&self) -> &[ReplayPacket] {
+        &self.packets
+    }
+
+    /// Time of the packet from the start of the replay, scaled by the speed.
+    pub fn offset(&self, iteration: u32, index: usize) -> Duration {
+        if self.options.speed <= 0.0 {
+            return Duration::from_secs(0);
+        }
+
+        let first = self
+            .packets
+            .first()
+            .map_or(Duration::from_secs(0), |p| p.timestamp);
+        let offset = self.loop_duration * iteration + (self.packets[index].timestamp - first);
+        offset.div_f64(self.options.speed)
+    }
+
+    /// The payload to send for the packet in the given loop, None if it cannot be rewritten.
+    pub fn rewrite(&self, iteration: u32, index: usize, now: SystemTime) -> Option<Vec<u8>> {
+        let packet = &self.packets[index];
+        let mut netflow = NetFlow9::from_bytes(&packet.payload).ok()?;
+
+        let uptime_shift = self.loop_duration.as_millis() as u32;
+        let sequence_shift = self
+            .sequences
+            .get(&(packet.exporter, netflow.source_id))
+            .map_or(0, SequenceSpan::len);
+
+        netflow.timestamp = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32;
+        netflow.sys_uptime = netflow
+            .sys_uptime
+            .wrapping_add(uptime_shift.wrapping_mul(iteration));
+        netflow.flow_sequence = netflow
+            .flow_sequence
+            .wrapping_add(sequence_shift.wrapping_mul(iteration));
+
+        Some(netflow.to_bytes())
+    }
+
+    /// Send the packets to the destination, waiting between them as the options say.
+    pub fn run<A: ToSocketAddrs>(
+        &self,
+        socket: &UdpSocket,
+        destination: A,
+    ) -> io::Result<ReplayStats> {
+        let destination = destination
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address"))?;
+        let mut stats = ReplayStats::default();
+        let start = Instant::now();
+
+        if self.packets.is_empty() {
+            return Ok(stats);
+        }
+
+        let mut iteration = 0;
+        while self.options.loops.is_none_or(|loops| iteration < loops) {
+            for index in 0..self.packets.len() {
+                let due = start + self.offset(iteration, index);
+                let now = Instant::now();
+                if due > now {
+                    thread::sleep(due - now);
+                }
+
+                let rewritten = if self.options.rewrite {
+                    let payload = self.rewrite(iteration, index, SystemTime::now());
+                    if payload.is_none() {
+                        stats.not_rewritten += 1;
+                    }
+                    payload
+                } else {
+                    None
+                };
+                let payload = rewritten.as_ref().unwrap_or(&self.packets[index].payload);
+
+                stats.bytes += socket.send_to(payload, destination)? as u64;
+                stats.packets += 1;
+            }
+
+            iteration += 1;
+            stats.loops = iteration;
+            debug!("replayed loop {}", iteration);
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod test_replay {
+    use super::*;
+    use crate::netflow::test_data::NETFLOWV9_DATA;
+    use crate::pcap::test_data;
+
+    fn packet(secs: u64, sequence: u32) -> ReplayPacket {
+        let mut netflow = NetFlow9::from_bytes(&NETFLOWV9_DATA).unwrap();
+        netflow.flow_sequence = sequence;
+
+        ReplayPacket {
+            timestamp: Duration::from_secs(secs),
+            exporter: "192.0.2.1:2055".parse().unwrap(),
+            payload: netflow.to_bytes(),
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        let frame = test_data::ethernet_ipv4_udp(&[], &NETFLOWV9_DATA);
+        let file = test_data::pcap_file(&[(5, 0, &frame), (6, 500_000, &frame)], false);
+        let packets = read_pcap(&file[..], &DatagramFilter::Auto).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[1].timestamp, Duration::new(6, 500_000_000));
+        assert_eq!(packets[0].payload, NETFLOWV9_DATA.to_vec());
+
+        let mut archive = Vec::new();
+        let netflow = NetFlow9::from_bytes(&NETFLOWV9_DATA).unwrap();
+        crate::collector::write_json_line(&mut archive, packets[0].exporter, &netflow).unwrap();
+        let packets = read_json_lines(&archive[..]).unwrap();
+        assert_eq!(packets[0].timestamp, Duration::from_secs(1_523_936_618));
+        assert_eq!(packets[0].payload, NETFLOWV9_DATA.to_vec());
+    }
+
+    #[test]
+    fn test_schedule() {
+        let options = ReplayOptions {
+            speed: 2.0,
+            rewrite: true,
+            loops: None,
+        };
+        let replayer = Replayer::new(vec![packet(12, 11), packet(10, 10)], options);
+
+        assert_eq!(replayer.offset(0, 0), Duration::from_secs(0));
+        assert_eq!(replayer.offset(0, 1), Duration::from_secs(1));
+        // a loop lasts the capture plus an average gap
+        assert_eq!(replayer.offset(1, 0), Duration::from_secs(2));
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let netflow = NetFlow9::from_bytes(&replayer.rewrite(2, 1, now).unwrap()).unwrap();
+        assert_eq!(netflow.flow_sequence, 15);
+        assert_eq!(netflow.timestamp, 1_600_000_000);
+        assert_eq!(netflow.sys_uptime, 5_502_099 + 2 * 4000);
+    }
+
+    #[test]
+    fn test_run() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let options = ReplayOptions {
+            speed: 100.0,
+            rewrite: true,
+            loops: Some(2),
+        };
+        let replayer = Replayer::new(vec![packet(0, 1), packet(1, 2)], options);
+        let start = Instant::now();
+        let stats = replayer
+            .run(&socket, receiver.local_addr().unwrap())
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(stats.packets, 4);
+        assert_eq!(stats.loops, 2);
+
+        let mut buf = vec![0u8; 65535];
+        let sequences: Vec<u32> = (0..4)
+            .map(|_| {
+                let len = receiver.recv(&mut buf).unwrap();
+                NetFlow9::from_bytes(&buf[..len]).unwrap().flow_sequence
+            })
+            .collect();
+        assert_eq!(sequences, vec![1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,521 @@
+use crate::collector::Datagram;
+use crate::error::NetFlowError;
+use crate::filter::Filter;
+use crate::flowset::{DataFlow, FlowSet, Record};
+use crate::ipfix::IPFIX_VERSION;
+use crate::netflow::{NetFlow9, TemplateCache};
+use crate::util::{take_u16, take_u32};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Instant;
+
+const WRAP_VERSION: u8 = 1;
+
+/// Prefix the payload with the exporter address, so the receiver still knows who sent it.
+///
+/// Layout: version (1), address family 4 or 6 (1), exporter port (2), exporter address (4 or 16),
+/// then the original datagram.
+pub fn wrap(exporter: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(payload.len() + 20);
+    bytes.push(WRAP_VERSION);
+
+    match exporter.ip() {
+        IpAddr::V4(ip) => {
+            bytes.push(4);
+            bytes.extend_from_slice(&exporter.port().to_be_bytes());
+            bytes.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            bytes.push(6);
+            bytes.extend_from_slice(&exporter.port().to_be_bytes());
+            bytes.extend_from_slice(&ip.octets());
+        }
+    }
+
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Split a wrapped datagram into the exporter address and the original payload.
+pub fn unwrap(bytes: &[u8]) -> Option<(SocketAddr, &[u8])> {
+    if bytes.len() < 4 || bytes[0] != WRAP_VERSION {
+        return None;
+    }
+
+    let port = u16::from_be_bytes([bytes[2], bytes[3]]);
+    let rest = &bytes[4..];
+
+    match bytes[1] {
+        4 if rest.len() >= 4 => {
+            let mut octets = [0u8; 4];
+            octets.copy_from_slice(&rest[..4]);
+            Some((
+                SocketAddr::new(Ipv4Addr::from(octets).into(), port),
+                &rest[4..],
+            ))
+        }
+        6 if rest.len() >= 16 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&rest[..16]);
+            Some((
+                SocketAddr::new(Ipv6Addr::from(octets).into(), port),
+                &rest[16..],
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// How a datagram is sent to a destination.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Encapsulation {
+    /// the datagram as it is, its source becomes the replicator
+    Plain,
+    /// the datagram behind a header with the exporter address, see `wrap`
+    Wrapped,
+}
+
+/// Which datagrams a destination receives, an empty list matches everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selector {
+    pub exporters: Vec<IpAddr>,
+    /// v9 source ids or IPFIX observation domain ids, datagrams of other versions do not match
+    pub source_ids: Vec<u32>,
+    /// only flowsets of these templates are forwarded, the packet is re-encoded
+    pub templates: Vec<u16>,
+}
+
+impl Selector {
+    fn matches_exporter(&self, exporter: SocketAddr) -> bool {
+        self.exporters.is_empty() || self.exporters.contains(&exporter.ip())
+    }
+
+    fn matches_source_id(&self, payload: &[u8]) -> bool {
+        if self.source_ids.is_empty() {
+            return true;
+        }
+
+        // the v9 source id ends the 20 byte header, the IPFIX observation domain id the 16
+        // byte one, other versions have neither
+        let range = match payload.get(0..2).map(take_u16) {
+            Some(Ok((_, 9))) => 16..20,
+            Some(Ok((_, IPFIX_VERSION))) => 12..16,
+            _ => return false,
+        };
+        match payload.get(range).map(take_u32) {
+            Some(Ok((_, source_id))) => self.source_ids.contains(&source_id),
+            _ => false,
+        }
+    }
+
+    fn matches_template(&self, template_id: u16) -> bool {
+        self.templates.is_empty() || self.templates.contains(&template_id)
+    }
+}
+
+/// Counters of a destination.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DestinationStats {
+    pub packets: u64,
+    pub bytes: u64,
+    /// datagrams not matching the selector or left empty by filtering
+    pub filtered: u64,
+    pub errors: u64,
+}
+
+/// Packets and bytes per second of a destination since the previous call to `Replicator::rates`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DestinationRate {
+    pub destination: SocketAddr,
+    pub packets_per_sec: f64,
+    pub bytes_per_sec: f64,
+}
+
+pub type RecordFilter = Box<dyn Fn(&Record) -> bool + Send>;
+
+pub struct Destination {
+    addr: SocketAddr,
+    encapsulation: Encapsulation,
+    selector: Selector,
+    record_filter: Option<RecordFilter>,
+    stats: DestinationStats,
+    last: (Instant, u64, u64),
+}
+
+impl Destination {
+    pub fn new<A: ToSocketAddrs>(addr: A) -> io::Result<Destination> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address"))?;
+
+        Ok(Destination {
+            addr,
+            encapsulation: Encapsulation::Plain,
+            selector: Selector::default(),
+            record_filter: None,
+            stats: DestinationStats::default(),
+            last: (Instant::now(), 0, 0),
+        })
+    }
+
+    pub fn encapsulation(mut self, encapsulation: Encapsulation) -> Destination {
+        self.encapsulation = encapsulation;
+        self
+    }
+
+    pub fn selector(mut self, selector: Selector) -> Destination {
+        self.selector = selector;
+        self
+    }
+
+    /// Forward only the data records the filter accepts, the packet is re-encoded.
+    /// Dataflows without a known template are forwarded as they are.
+    pub fn record_filter(mut self, filter: RecordFilter) -> Destination {
+        self.record_filter = Some(filter);
+        self
+    }
+
+    /// Forward only the data records matching the filter expression.
+    pub fn filter(self, filter: Filter) -> Destination {
+        self.record_filter(Box::new(move |record| filter.matches(record)))
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn stats(&self) -> &DestinationStats {
+        &self.stats
+    }
+
+    fn needs_decode(&self) -> bool {
+        !self.selector.templates.is_empty() || self.record_filter.is_some()
+    }
+
+    /// Keep the flowsets and records this destination wants, None if nothing is left.
+    fn filter_packet(&self, netflow: &NetFlow9) -> Option<NetFlow9> {
+        let mut flow_sets = Vec::new();
+
+        for flowset in &netflow.flow_sets {
+            match flowset {
+                FlowSet::DataTemplate(template) => {
+                    let mut template = template.clone();
+                    template
+                        .templates
+                        .retain(|item| self.selector.matches_template(item.template_id));
+                    if !template.templates.is_empty() {
+                        flow_sets.push(FlowSet::DataTemplate(template));
+                    }
+                }
+                FlowSet::OptionTemplate(option) => {
+                    if self.selector.matches_template(option.templates.template_id) {
+                        flow_sets.push(flowset.clone());
+                    }
+                }
+                FlowSet::DataFlow(dataflow) => {
+                    if !self.selector.matches_template(dataflow.flowset_id) {
+                        continue;
+                    }
+
+                    match (&self.record_filter, &dataflow.records) {
+                        (Some(filter), Some(records)) => {
+                            let records: Vec<Record> = records
+                                .iter()
+                                .filter(|record| filter(record))
+                                .cloned()
+                                .collect();
+                            if !records.is_empty() {
+                                flow_sets.push(DataFlow::new(dataflow.flowset_id, records).into());
+                            }
+                        }
+                        _ => flow_sets.push(flowset.clone()),
+                    }
+                }
+            }
+        }
+
+        if flow_sets.is_empty() {
+            return None;
+        }
+
+        Some(NetFlow9::new(
+            netflow.sys_uptime,
+            netflow.timestamp,
+            netflow.flow_sequence,
+            netflow.source_id,
+            flow_sets,
+        ))
+    }
+}
+
+/// Forwards received datagrams to several destinations.
+/// Datagrams are sent unchanged unless a destination filters templates or records,
+/// then the packet is decoded with the templates seen so far and re-encoded with `NetFlow9::to_bytes`.
+///
+/// Sending with the exporter address as source needs raw sockets, which this crate does not use,
+/// so `Encapsulation::Wrapped` is the way to keep it.
+pub struct Replicator {
+    socket: UdpSocket,
+    destinations: Vec<Destination>,
+    templates: TemplateCache,
+}
+
+impl Replicator {
+    /// Bind the socket datagrams are sent from.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Replicator> {
+        Ok(Replicator {
+            socket: UdpSocket::bind(addr)?,
+            destinations: Vec::new(),
+            templates: TemplateCache::new(),
+        })
+    }
+
+    pub fn add_destination(&mut self, destination: Destination) {
+        info!("forwarding to {}", destination.addr);
+        self.destinations.push(destination);
+    }
+
+    pub fn destinations(&self) -> &[Destination] {
+        &self.destinations
+    }
+
+    /// Send the datagram to every matching destination, return the number of datagrams sent.
+    pub fn forward(&mut self, datagram: &Datagram) -> usize {
+        let decoded = if self.destinations.iter().any(Destination::needs_decode) {
+            match self.templates.decode(datagram.exporter, &datagram.payload) {
+                Ok(netflow) => Some(netflow),
+                Err(e) => {
+                    debug!("cannot decode packet from {}: {}", datagram.exporter, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut sent = 0;
+        for destination in &mut self.destinations {
+            if !destination.selector.matches_exporter(datagram.exporter)
+                || !destination.selector.matches_source_id(&datagram.payload)
+            {
+                destination.stats.filtered += 1;
+                continue;
+            }
+
+            let payload = if destination.needs_decode() {
+                match decoded
+                    .as_ref()
+                    .and_then(|netflow| destination.filter_packet(netflow))
+                {
+                    Some(netflow) => netflow.to_bytes(),
+                    None => {
+                        destination.stats.filtered += 1;
+                        continue;
+                    }
+                }
+            } else {
+                datagram.payload.clone()
+            };
+
+            let payload = match destination.encapsulation {
+                Encapsulation::Plain => payload,
+                Encapsulation::Wrapped => wrap(datagram.exporter, &payload),
+            };
+
+            match self.socket.send_to(&payload, destination.addr) {
+                Ok(len) => {
+                    destination.stats.packets += 1;
+                    destination.stats.bytes += len as u64;
+                    sent += 1;
+                }
+                Err(e) => {
+                    warn!("cannot send to {}: {}", destination.addr, e);
+                    destination.stats.errors += 1;
+                }
+            }
+        }
+
+        sent
+    }
+
+    /// Rates of every destination since the previous call, or since it was added.
+    pub fn rates(&mut self) -> Vec<DestinationRate> {
+        let now = Instant::now();
+
+        self.destinations
+            .iter_mut()
+            .map(|destination| {
+                let (since, packets, bytes) = destination.last;
+                let secs = now.duration_since(since).as_secs_f64().max(1e-3);
+                destination.last = (now, destination.stats.packets, destination.stats.bytes);
+
+                DestinationRate {
+                    destination: destination.addr,
+                    packets_per_sec: (destination.stats.packets - packets) as f64 / secs,
+                    bytes_per_sec: (destination.stats.bytes - bytes) as f64 / secs,
+                }
+            })
+            .collect()
+    }
+
+    /// Counters of every destination.
+    pub fn stats(&self) -> HashMap<SocketAddr, DestinationStats> {
+        self.destinations
+            .iter()
+            .map(|destination| (destination.addr, destination.stats.clone()))
+            .collect()
+    }
+}
+
+/// Parse a wrapped datagram received from a replicator.
+pub fn unwrap_datagram(datagram: &Datagram) -> Result<Datagram, NetFlowError> {
+    let (exporter, payload) = unwrap(&datagram.payload).ok_or(NetFlowError::InvalidLength)?;
+
+    Ok(Datagram {
+        exporter,
+        local: datagram.local,
+        payload: payload.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod test_replicate {
+    use super::*;
+    use crate::netflow::test_data;
+    use std::time::Duration;
+
+    fn receiver() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+    }
+
+    fn recv(socket: &UdpSocket) -> Vec<u8> {
+        let mut buf = vec![0u8; 65535];
+        let len = socket.recv(&mut buf).unwrap();
+        buf.truncate(len);
+        buf
+    }
+
+    fn datagram(exporter: &str) -> Datagram {
+        Datagram {
+            exporter: exporter.parse().unwrap(),
+            local: "127.0.0.1:2055".parse().unwrap(),
+            payload: test_data::NETFLOWV9_DATA.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_wrap() {
+        for exporter in &["192.0.2.1:2055", "[2001:db8::1]:9995"] {
+            let exporter: SocketAddr = exporter.parse().unwrap();
+            let wrapped = wrap(exporter, b"payload");
+            assert_eq!(unwrap(&wrapped), Some((exporter, &b"payload"[..])));
+        }
+        assert_eq!(unwrap(&[WRAP_VERSION, 4, 0, 1]), None);
+    }
+
+    #[test]
+    fn test_forward() {
+        let plain = receiver();
+        let wrapped = receiver();
+        let other_source = receiver();
+
+        let mut replicator = Replicator::bind("127.0.0.1:0").unwrap();
+        replicator.add_destination(Destination::new(plain.local_addr().unwrap()).unwrap());
+        replicator.add_destination(
+            Destination::new(wrapped.local_addr().unwrap())
+                .unwrap()
+                .encapsulation(Encapsulation::Wrapped)
+                .selector(Selector {
+                    exporters: vec!["192.0.2.1".parse().unwrap()],
+                    ..Selector::default()
+                }),
+        );
+        replicator.add_destination(
+            Destination::new(other_source.local_addr().unwrap())
+                .unwrap()
+                .selector(Selector {
+                    source_ids: vec![7],
+                    ..Selector::default()
+                }),
+        );
+
+        assert_eq!(replicator.forward(&datagram("192.0.2.1:2055")), 2);
+        assert_eq!(replicator.forward(&datagram("192.0.2.2:2055")), 1);
+
+        assert_eq!(recv(&plain), test_data::NETFLOWV9_DATA.to_vec());
+        assert_eq!(recv(&plain), test_data::NETFLOWV9_DATA.to_vec());
+        let bytes = recv(&wrapped);
+        let (exporter, payload) = unwrap(&bytes).unwrap();
+        assert_eq!(exporter, "192.0.2.1:2055".parse().unwrap());
+        assert_eq!(payload, &test_data::NETFLOWV9_DATA[..]);
+
+        let stats = replicator.stats();
+        assert_eq!(stats[&plain.local_addr().unwrap()].packets, 2);
+        assert_eq!(stats[&wrapped.local_addr().unwrap()].filtered, 1);
+        assert_eq!(stats[&other_source.local_addr().unwrap()].filtered, 2);
+
+        let rates = replicator.rates();
+        assert_eq!(rates.len(), 3);
+        assert!(rates[0].packets_per_sec > 0.0);
+        assert_eq!(rates[2].bytes_per_sec, 0.0);
+    }
+
+    #[test]
+    fn test_source_id() {
+        let selector = |id| Selector {
+            source_ids: vec![id],
+            ..Selector::default()
+        };
+        let v9 = &test_data::NETFLOWV9_DATA[..];
+        let source_id = NetFlow9::from_bytes(v9).unwrap().source_id;
+        assert!(selector(source_id).matches_source_id(v9));
+        assert!(!selector(source_id + 1).matches_source_id(v9));
+
+        // the observation domain, not the bytes where v9 has its source id
+        let ipfix = crate::ipfix::test_data::message().to_bytes();
+        assert!(selector(1).matches_source_id(&ipfix));
+        let v9_offset = u32::from_be_bytes([ipfix[16], ipfix[17], ipfix[18], ipfix[19]]);
+        assert!(!selector(v9_offset).matches_source_id(&ipfix));
+
+        let v5 = crate::netflow::test_v5::packet(vec![]).to_bytes();
+        let v9_offset = u32::from_be_bytes([v5[16], v5[17], v5[18], v5[19]]);
+        assert!(!selector(v9_offset).matches_source_id(&v5));
+    }
+
+    #[test]
+    fn test_filter_templates() {
+        let filtered = receiver();
+
+        let mut replicator = Replicator::bind("127.0.0.1:0").unwrap();
+        replicator.add_destination(
+            Destination::new(filtered.local_addr().unwrap())
+                .unwrap()
+                .selector(Selector {
+                    templates: vec![1024],
+                    ..Selector::default()
+                })
+                .record_filter(Box::new(|record| record.get(8).is_some())),
+        );
+        assert_eq!(replicator.forward(&datagram("192.0.2.1:2055")), 1);
+
+        let netflow = NetFlow9::from_bytes(&recv(&filtered)).unwrap();
+        let ids: Vec<u16> = netflow
+            .flow_sets
+            .iter()
+            .map(|flowset| match flowset {
+                FlowSet::DataTemplate(template) => template.templates[0].template_id,
+                FlowSet::OptionTemplate(option) => option.templates.template_id,
+                FlowSet::DataFlow(dataflow) => dataflow.flowset_id,
+            })
+            .collect();
+        assert_eq!(ids, vec![1024, 1024]);
+        assert_eq!(netflow.count, 2);
+        assert_eq!(netflow.flow_sequence, 883);
+    }
+}
@@ -0,0 +1,524 @@
+use crate::aggregate::{flow_time, FlowTime};
+use crate::field::FieldTypes::*;
+use crate::field::FlowField;
+use crate::flowset::{FlowSet, Record};
+use crate::ipfix::{IpfixMessage, IpfixSet};
+use crate::json::field_to_json;
+use crate::netflow::NetFlow9;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+/// What the report groups records by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    SrcAddr,
+    DstAddr,
+    SrcPort,
+    DstPort,
+    Protocol,
+    SrcAs,
+    DstAs,
+    /// both addresses, whichever way the flow went
+    Conversation,
+}
+
+pub const ALL_DIMENSIONS: [Dimension; 8] = [
+    Dimension::SrcAddr,
+    Dimension::DstAddr,
+    Dimension::SrcPort,
+    Dimension::DstPort,
+    Dimension::Protocol,
+    Dimension::SrcAs,
+    Dimension::DstAs,
+    Dimension::Conversation,
+];
+
+impl Dimension {
+    /// Name used on the command line and in JSON.
+    pub fn name(self) -> &'static str {
+        match self {
+            Dimension::SrcAddr => "src-ip",
+            Dimension::DstAddr => "dst-ip",
+            Dimension::SrcPort => "src-port",
+            Dimension::DstPort => "dst-port",
+            Dimension::Protocol => "proto",
+            Dimension::SrcAs => "src-as",
+            Dimension::DstAs => "dst-as",
+            Dimension::Conversation => "conversation",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Dimension::SrcAddr => "source addresses",
+            Dimension::DstAddr => "destination addresses",
+            Dimension::SrcPort => "source ports",
+            Dimension::DstPort => "destination ports",
+            Dimension::Protocol => "protocols",
+            Dimension::SrcAs => "source AS",
+            Dimension::DstAs => "destination AS",
+            Dimension::Conversation => "conversations",
+        }
+    }
+
+    /// Key of the record, None when it lacks the fields.
+    fn key(self, record: &Record) -> Option<String> {
+        let first = |ids: &[u16]| ids.iter().filter_map(|id| record.get(*id)).next();
+        let src = || first(&[IPV4_SRC_ADDR, IPV6_SRC_ADDR]).map(label);
+        let dst = || first(&[IPV4_DST_ADDR, IPV6_DST_ADDR]).map(label);
+
+        match self {
+            Dimension::SrcAddr => src(),
+            Dimension::DstAddr => dst(),
+            Dimension::SrcPort => first(&[L4_SRC_PORT]).map(label),
+            Dimension::DstPort => first(&[L4_DST_PORT]).map(label),
+            Dimension::Protocol => first(&[PROTOCOL])
+                .and_then(|field| field.value().to_u64())
+                .map(|protocol| protocol_name(protocol as u8)),
+            Dimension::SrcAs => first(&[SRC_AS]).map(label),
+            Dimension::DstAs => first(&[DST_AS]).map(label),
+            Dimension::Conversation => {
+                let (a, b) = (src()?, dst()?);
+                if a <= b {
+                    Some(format!("{} <-> {}", a, b))
+                } else {
+                    Some(format!("{} <-> {}", b, a))
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Dimension {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Dimension, String> {
+        ALL_DIMENSIONS
+            .iter()
+            .find(|dimension| dimension.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown key: {}", s))
+    }
+}
+
+/// What the entries are ranked by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Bytes,
+    Packets,
+    Flows,
+}
+
+impl Metric {
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::Bytes => "bytes",
+            Metric::Packets => "packets",
+            Metric::Flows => "flows",
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Metric, String> {
+        match s {
+            "bytes" => Ok(Metric::Bytes),
+            "packets" => Ok(Metric::Packets),
+            "flows" => Ok(Metric::Flows),
+            _ => Err(format!("unknown metric: {}", s)),
+        }
+    }
+}
+
+fn label(field: &FlowField) -> String {
+    match field_to_json(field) {
+        Value::String(s) => s,
+        value => value.to_string(),
+    }
+}
+
+fn protocol_name(protocol: u8) -> String {
+    match protocol {
+        1 => "icmp".to_string(),
+        6 => "tcp".to_string(),
+        17 => "udp".to_string(),
+        47 => "gre".to_string(),
+        50 => "esp".to_string(),
+        58 => "ipv6-icmp".to_string(),
+        132 => "sctp".to_string(),
+        _ => protocol.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Totals {
+    pub bytes: u64,
+    pub packets: u64,
+    pub flows: u64,
+}
+
+impl Totals {
+    /// Counters of a record, egress counters when the ingress ones are missing.
+    /// A record is one flow unless it has a FLOWS count.
+    fn of(record: &Record) -> Totals {
+        let number = |ids: &[u16]| {
+            ids.iter()
+                .filter_map(|id| record.get(*id))
+                .filter_map(|field| field.value().to_u64())
+                .next()
+        };
+
+        Totals {
+            bytes: number(&[IN_BYTES, OUT_BYTES]).unwrap_or(0),
+            packets: number(&[IN_PKTS, OUT_PKTS]).unwrap_or(0),
+            flows: number(&[FLOWS]).unwrap_or(1),
+        }
+    }
+
+    fn add(&mut self, other: Totals) {
+        self.bytes = self.bytes.saturating_add(other.bytes);
+        self.packets = self.packets.saturating_add(other.packets);
+        self.flows = self.flows.saturating_add(other.flows);
+    }
+
+    pub fn get(&self, metric: Metric) -> u64 {
+        match metric {
+            Metric::Bytes => self.bytes,
+            Metric::Packets => self.packets,
+            Metric::Flows => self.flows,
+        }
+    }
+}
+
+/// An entry of a top-N table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopEntry {
+    pub key: String,
+    #[serde(flatten)]
+    pub totals: Totals,
+}
+
+/// Totals of decoded records per source, destination, port, protocol, AS and conversation,
+/// ranked into top-N tables.
+///
+/// Records are timed by their flow end, see `aggregate::flow_time`; with a window set, records
+/// ending outside of it are left out.
+#[derive(Debug, Clone)]
+pub struct Report {
+    dimensions: Vec<Dimension>,
+    /// start and end in milliseconds since the epoch, the end is excluded
+    window: Option<(u64, u64)>,
+    tables: Vec<HashMap<String, Totals>>,
+    total: Totals,
+    records: u64,
+}
+
+impl Report {
+    pub fn new(dimensions: &[Dimension]) -> Report {
+        Report {
+            dimensions: dimensions.to_vec(),
+            window: None,
+            tables: vec![HashMap::new(); dimensions.len()],
+            total: Totals::default(),
+            records: 0,
+        }
+    }
+
+    /// Only count records ending in [start, end), milliseconds since the epoch.
+    pub fn set_window(&mut self, start: u64, end: u64) {
+        self.window = Some((start, end));
+    }
+
+    pub fn dimensions(&self) -> &[Dimension] {
+        &self.dimensions
+    }
+
+    /// Number of records counted.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Totals of every record counted.
+    pub fn total(&self) -> Totals {
+        self.total
+    }
+
+    /// Count a data record ending at `time`, option records are ignored.
+    pub fn add_record(&mut self, record: &Record, time: u64) {
+        if let Record::OptionData(_) = record {
+            return;
+        }
+        if let Some((start, end)) = self.window {
+            if time < start || time >= end {
+                return;
+            }
+        }
+
+        let totals = Totals::of(record);
+        self.records += 1;
+        self.total.add(totals);
+        for (dimension, table) in self.dimensions.iter().zip(self.tables.iter_mut()) {
+            if let Some(key) = dimension.key(record) {
+                table.entry(key).or_default().add(totals);
+            }
+        }
+    }
+
+    /// Count the decoded data records of a v9 packet.
+    pub fn add_netflow9(&mut self, netflow: &NetFlow9) {
+        for flowset in &netflow.flow_sets {
+            if let FlowSet::DataFlow(dataflow) = flowset {
+                for record in dataflow.records.iter().flatten() {
+                    let time = flow_time(
+                        record,
+                        FlowTime::End,
+                        netflow.timestamp,
+                        Some(netflow.sys_uptime),
+                    );
+                    self.add_record(record, time);
+                }
+            }
+        }
+    }
+
+    /// Count the decoded data records of an IPFIX message.
+    pub fn add_ipfix(&mut self, message: &IpfixMessage) {
+        for set in &message.sets {
+            if let IpfixSet::Data(data) = set {
+                for record in data.records.iter().flatten() {
+                    let time = flow_time(record, FlowTime::End, message.export_time, None);
+                    self.add_record(record, time);
+                }
+            }
+        }
+    }
+
+    /// Forget the records counted so far, e.g. to start the next window.
+    pub fn clear(&mut self) {
+        for table in &mut self.tables {
+            table.clear();
+        }
+        self.total = Totals::default();
+        self.records = 0;
+    }
+
+    /// The `count` largest entries of the dimension by the metric, ties by key.
+    /// Empty if the report does not count the dimension.
+    pub fn top(&self, dimension: Dimension, metric: Metric, count: usize) -> Vec<TopEntry> {
+        let table = match self.dimensions.iter().position(|d| *d == dimension) {
+            Some(index) => &self.tables[index],
+            None => return Vec::new(),
+        };
+
+        let mut entries: Vec<TopEntry> = table
+            .iter()
+            .map(|(key, totals)| TopEntry {
+                key: key.clone(),
+                totals: *totals,
+            })
+            .collect();
+        entries.sort_by(
+            |a, b| match b.totals.get(metric).cmp(&a.totals.get(metric)) {
+                Ordering::Equal => a.key.cmp(&b.key),
+                ordering => ordering,
+            },
+        );
+        entries.truncate(count);
+        entries
+    }
+
+    /// The tables as one JSON object keyed by dimension name.
+    pub fn to_json(&self, metric: Metric, count: usize) -> Value {
+        let mut object = Map::new();
+        object.insert("metric".to_string(), Value::from(metric.name()));
+        object.insert("records".to_string(), Value::from(self.records));
+        object.insert(
+            "total".to_string(),
+            serde_json::to_value(self.total).unwrap_or(Value::Null),
+        );
+        if let Some((start, end)) = self.window {
+            object.insert("start".to_string(), Value::from(start));
+            object.insert("end".to_string(), Value::from(end));
+        }
+        for dimension in &self.dimensions {
+            let entries = self.top(*dimension, metric, count);
+            object.insert(
+                dimension.name().to_string(),
+                serde_json::to_value(entries).unwrap_or(Value::Null),
+            );
+        }
+
+        Value::Object(object)
+    }
+
+    /// Write the tables as text, with the share of the metric total of each entry.
+    pub fn write_table<W: Write>(
+        &self,
+        writer: &mut W,
+        metric: Metric,
+        count: usize,
+    ) -> io::Result<()> {
+        let total = self.total.get(metric);
+
+        for (i, dimension) in self.dimensions.iter().enumerate() {
+            let entries = self.top(*dimension, metric, count);
+            let width = entries
+                .iter()
+                .map(|entry| entry.key.len())
+                .max()
+                .unwrap_or(0)
+                .max(dimension.name().len());
+
+            if i > 0 {
+                writeln!(writer)?;
+            }
+            writeln!(
+                writer,
+                "Top {} {} by {}",
+                count,
+                dimension.title(),
+                metric.name()
+            )?;
+            writeln!(
+                writer,
+                "{:<width$} {:>14} {:>12} {:>10} {:>7}",
+                dimension.name(),
+                "bytes",
+                "packets",
+                "flows",
+                "%",
+                width = width
+            )?;
+            for entry in entries {
+                let share = if total == 0 {
+                    0.0
+                } else {
+                    entry.totals.get(metric) as f64 * 100.0 / total as f64
+                };
+                writeln!(
+                    writer,
+                    "{:<width$} {:>14} {:>12} {:>10} {:>7.2}",
+                    entry.key,
+                    entry.totals.bytes,
+                    entry.totals.packets,
+                    entry.totals.flows,
+                    share,
+                    width = width
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_report {
+    use super::*;
+    use crate::flowset::DataTemplateItem;
+    use std::net::Ipv4Addr;
+
+    fn template() -> DataTemplateItem {
+        DataTemplateItem::builder(256)
+            .field(IPV4_SRC_ADDR)
+            .field(IPV4_DST_ADDR)
+            .field(L4_DST_PORT)
+            .field(PROTOCOL)
+            .field_len(IN_BYTES, 4)
+            .field_len(IN_PKTS, 4)
+            .build()
+            .unwrap()
+    }
+
+    fn record(src: [u8; 4], dst: [u8; 4], protocol: u8, bytes: u32) -> Record {
+        template()
+            .record_builder()
+            .set(IPV4_SRC_ADDR, Ipv4Addr::from(src))
+            .set(IPV4_DST_ADDR, Ipv4Addr::from(dst))
+            .set(L4_DST_PORT, 443u16)
+            .set(PROTOCOL, protocol)
+            .set(IN_BYTES, bytes)
+            .set(IN_PKTS, bytes / 100)
+            .build()
+            .unwrap()
+    }
+
+    fn report() -> Report {
+        let mut report = Report::new(&ALL_DIMENSIONS);
+        report.add_record(&record([10, 0, 0, 1], [192, 0, 2, 1], 6, 1000), 0);
+        report.add_record(&record([192, 0, 2, 1], [10, 0, 0, 1], 6, 3000), 0);
+        report.add_record(&record([10, 0, 0, 2], [192, 0, 2, 1], 17, 2000), 0);
+        report
+    }
+
+    #[test]
+    fn test_top() {
+        let report = report();
+        assert_eq!(report.records(), 3);
+        assert_eq!(report.total().bytes, 6000);
+
+        let top = report.top(Dimension::SrcAddr, Metric::Bytes, 2);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].key, "192.0.2.1");
+        assert_eq!(top[1].key, "10.0.0.2");
+
+        let top = report.top(Dimension::SrcAddr, Metric::Flows, 3);
+        assert_eq!(top[0].key, "10.0.0.1");
+        assert_eq!(top[0].totals.flows, 1);
+
+        let top = report.top(Dimension::Conversation, Metric::Bytes, 10);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].key, "10.0.0.1 <-> 192.0.2.1");
+        assert_eq!(top[0].totals.bytes, 4000);
+        assert_eq!(top[0].totals.packets, 40);
+
+        let top = report.top(Dimension::Protocol, Metric::Packets, 10);
+        assert_eq!(top[0].key, "tcp");
+        assert_eq!(top[1].key, "udp");
+
+        // no AS fields in the records
+        assert!(report.top(Dimension::SrcAs, Metric::Bytes, 10).is_empty());
+    }
+
+    #[test]
+    fn test_output() {
+        let report = report();
+
+        let json = report.to_json(Metric::Bytes, 1);
+        assert_eq!(json["records"], 3);
+        // a tie goes to the lower key
+        assert_eq!(json["dst-ip"][0]["key"], "10.0.0.1");
+        assert_eq!(json["dst-ip"][0]["bytes"], 3000);
+        assert_eq!(json["dst-port"][0]["flows"], 3);
+
+        let mut out = Vec::new();
+        report.write_table(&mut out, Metric::Bytes, 1).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "Top 1 source addresses by bytes");
+        assert!(lines[2].starts_with("192.0.2.1 "));
+        assert!(lines[2].ends_with(" 50.00"));
+    }
+
+    #[test]
+    fn test_window() {
+        let mut report = Report::new(&[Dimension::SrcAddr]);
+        report.set_window(60_000, 120_000);
+        report.add_record(&record([10, 0, 0, 1], [192, 0, 2, 1], 6, 1000), 59_999);
+        report.add_record(&record([10, 0, 0, 1], [192, 0, 2, 1], 6, 1000), 60_000);
+        report.add_record(&record([10, 0, 0, 1], [192, 0, 2, 1], 6, 1000), 120_000);
+        assert_eq!(report.records(), 1);
+
+        report.clear();
+        assert_eq!(report.records(), 0);
+        assert!(report.top(Dimension::SrcAddr, Metric::Bytes, 10).is_empty());
+        assert_eq!("conversation".parse(), Ok(Dimension::Conversation));
+        assert!("bits".parse::<Metric>().is_err());
+    }
+}
@@ -0,0 +1,604 @@
+use crate::netflow::NetFlow9;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+
+const DEFAULT_WINDOW: u32 = 1024;
+// missing ranges kept, packets of older ones still arriving are taken as late
+const MAX_GAPS: usize = 64;
+const MAX_RECENT: usize = 64;
+
+/// A sequence stream: exporter address with Netflow v9 source id or IPFIX observation domain id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StreamKey {
+    pub exporter: SocketAddr,
+    pub domain: u32,
+}
+
+impl StreamKey {
+    pub fn new(exporter: SocketAddr, domain: u32) -> StreamKey {
+        StreamKey { exporter, domain }
+    }
+}
+
+/// What the sequence number counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SequenceUnit {
+    /// Netflow v9, incremented by every export packet (RFC 3954)
+    Packet,
+    /// IPFIX, incremented by every data record (RFC 7011)
+    Record,
+}
+
+/// Sequence related values of a received packet.
+#[derive(Debug, Clone, Copy)]
+pub struct Observation {
+    pub sequence: u32,
+    pub records: u32,
+    /// not available in IPFIX
+    pub sys_uptime: Option<u32>,
+    pub unit: SequenceUnit,
+}
+
+impl Observation {
+    fn increment(&self) -> u32 {
+        match self.unit {
+            SequenceUnit::Packet => 1,
+            SequenceUnit::Record => self.records,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SequenceEvent {
+    First,
+    InOrder,
+    /// lost is counted in SequenceUnit of the stream
+    Gap {
+        expected: u32,
+        received: u32,
+        lost: u32,
+    },
+    Duplicate,
+    Reordered,
+    /// sequence reset or sys_uptime went backwards
+    Restart,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SequenceStats {
+    pub packets: u64,
+    pub records: u64,
+    pub lost_packets: u64,
+    /// estimated from average records per packet when sequence counts packets
+    pub lost_records: u64,
+    pub duplicates: u64,
+    pub reordered: u64,
+    pub restarts: u64,
+}
+
+impl SequenceStats {
+    // convert lost units to (packets, records)
+    fn estimate(&self, unit: SequenceUnit, lost: u32) -> (u64, u64) {
+        let lost = u64::from(lost);
+        let packets = self.packets.max(1);
+        let records = self.records.max(1);
+
+        match unit {
+            SequenceUnit::Packet => (lost, lost * self.records / packets),
+            SequenceUnit::Record => (((lost * self.packets) / records).max(1), lost),
+        }
+    }
+
+    fn add_loss(&mut self, unit: SequenceUnit, lost: u32) {
+        let (packets, records) = self.estimate(unit, lost);
+        self.lost_packets += packets;
+        self.lost_records += records;
+    }
+
+    fn remove_loss(&mut self, unit: SequenceUnit, found: u32) {
+        let (packets, records) = self.estimate(unit, found);
+        self.lost_packets = self.lost_packets.saturating_sub(packets);
+        self.lost_records = self.lost_records.saturating_sub(records);
+    }
+
+    /// Ratio of lost records to all records sent.
+    pub fn loss_ratio(&self) -> f64 {
+        let sent = self.records + self.lost_records;
+
+        if sent == 0 {
+            0.0
+        } else {
+            self.lost_records as f64 / sent as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StreamState {
+    next: u32,
+    sys_uptime: Option<u32>,
+    // (start, length) of sequence ranges not received yet
+    gaps: VecDeque<(u32, u32)>,
+    // start of the oldest gap dropped from gaps, packets from there on are late, not a restart
+    evicted: Option<u32>,
+    // (sequence, sys_uptime) of the last packets, a copy has the same sys_uptime
+    recent: VecDeque<(u32, Option<u32>)>,
+    stats: SequenceStats,
+}
+
+impl StreamState {
+    fn new(observation: &Observation) -> StreamState {
+        StreamState {
+            next: observation.sequence.wrapping_add(observation.increment()),
+            sys_uptime: observation.sys_uptime,
+            gaps: VecDeque::new(),
+            evicted: None,
+            recent: VecDeque::new(),
+            stats: SequenceStats::default(),
+        }
+    }
+
+    /// Remove the received range from gaps, return the length found.
+    fn fill_gap(&mut self, sequence: u32, length: u32) -> Option<u32> {
+        let length = length.max(1);
+        let pos = self
+            .gaps
+            .iter()
+            .position(|&(start, len)| sequence.wrapping_sub(start) < len)?;
+        let (start, len) = self.gaps.remove(pos).unwrap();
+
+        let head = sequence.wrapping_sub(start);
+        let found = length.min(len - head);
+        let tail = len - head - found;
+
+        if head > 0 {
+            self.gaps.push_back((start, head));
+        }
+        if tail > 0 {
+            self.gaps.push_back((sequence.wrapping_add(found), tail));
+        }
+
+        Some(found)
+    }
+
+    fn push_gap(&mut self, start: u32, len: u32) {
+        if self.gaps.len() == MAX_GAPS {
+            if let Some((dropped, _)) = self.gaps.pop_front() {
+                let behind = |sequence: u32| self.next.wrapping_sub(sequence);
+                if self
+                    .evicted
+                    .is_none_or(|evicted| behind(dropped) > behind(evicted))
+                {
+                    self.evicted = Some(dropped);
+                }
+            }
+        }
+        self.gaps.push_back((start, len));
+    }
+
+    /// Whether the sequence is behind next but not before the oldest gap dropped.
+    fn in_evicted_gaps(&self, sequence: u32) -> bool {
+        let behind = |sequence: u32| self.next.wrapping_sub(sequence);
+        self.evicted.is_some_and(|evicted| {
+            (sequence.wrapping_sub(self.next) as i32) < 0 && behind(sequence) <= behind(evicted)
+        })
+    }
+
+    fn push_recent(&mut self, observation: &Observation) {
+        if self.recent.len() == MAX_RECENT {
+            self.recent.pop_front();
+        }
+        self.recent
+            .push_back((observation.sequence, observation.sys_uptime));
+    }
+}
+
+/// Follows sequence numbers of every exporter to detect lost, duplicated and reordered packets.
+#[derive(Debug, Clone)]
+pub struct SequenceTracker {
+    window: u32,
+    streams: HashMap<StreamKey, StreamState>,
+}
+
+impl Default for SequenceTracker {
+    fn default() -> Self {
+        SequenceTracker::new()
+    }
+}
+
+impl SequenceTracker {
+    pub fn new() -> SequenceTracker {
+        SequenceTracker::with_window(DEFAULT_WINDOW)
+    }
+
+    /// window is the distance in sequence units a late packet can have, and the most a
+    /// sequence may jump ahead. A packet further behind or ahead is taken as a restart of
+    /// the exporter.
+    pub fn with_window(window: u32) -> SequenceTracker {
+        SequenceTracker {
+            window,
+            streams: HashMap::new(),
+        }
+    }
+
+    pub fn observe(&mut self, key: StreamKey, observation: Observation) -> SequenceEvent {
+        let window = self.window;
+        let state = match self.streams.get_mut(&key) {
+            Some(state) => state,
+            None => {
+                let mut state = StreamState::new(&observation);
+                state.stats.packets = 1;
+                state.stats.records = u64::from(observation.records);
+                if observation.increment() > 0 {
+                    state.push_recent(&observation);
+                }
+                self.streams.insert(key, state);
+
+                return SequenceEvent::First;
+            }
+        };
+
+        let unit = observation.unit;
+        let sequence = observation.sequence;
+        let increment = observation.increment();
+        let diff = i64::from(sequence.wrapping_sub(state.next) as i32);
+        let uptime_back = match (state.sys_uptime, observation.sys_uptime) {
+            (Some(last), Some(now)) => now < last,
+            _ => false,
+        };
+
+        // a restarted exporter may reuse a recent sequence, with a lower sys_uptime
+        let duplicate = increment > 0 && state.recent.contains(&(sequence, observation.sys_uptime));
+
+        let event = if duplicate {
+            SequenceEvent::Duplicate
+        } else if uptime_back && state.recent.iter().any(|(recent, _)| *recent == sequence) {
+            SequenceEvent::Restart
+        } else if let Some(found) = state.fill_gap(sequence, increment) {
+            state.stats.remove_loss(unit, found);
+            SequenceEvent::Reordered
+        } else if state.in_evicted_gaps(sequence) {
+            // lost for good when its gap was dropped, found after all, a late packet has an
+            // older sys_uptime like one that fills a gap
+            state.stats.remove_loss(unit, increment.max(1));
+            SequenceEvent::Reordered
+        } else if uptime_back || diff < -i64::from(window) || diff > i64::from(window) {
+            SequenceEvent::Restart
+        } else if diff < 0 {
+            SequenceEvent::Reordered
+        } else if diff == 0 {
+            SequenceEvent::InOrder
+        } else {
+            SequenceEvent::Gap {
+                expected: state.next,
+                received: sequence,
+                lost: diff as u32,
+            }
+        };
+
+        match event {
+            SequenceEvent::Duplicate => {
+                state.stats.duplicates += 1;
+                return event;
+            }
+            SequenceEvent::Restart => {
+                debug!("exporter restarted: {:?}", key);
+                let stats = state.stats.clone();
+                *state = StreamState::new(&observation);
+                state.stats = stats;
+                state.stats.restarts += 1;
+            }
+            SequenceEvent::Reordered => state.stats.reordered += 1,
+            SequenceEvent::Gap { expected, lost, .. } => {
+                debug!("lost {} in {:?}", lost, key);
+                state.stats.add_loss(unit, lost);
+                state.push_gap(expected, lost);
+                state.next = sequence.wrapping_add(increment);
+            }
+            _ => state.next = sequence.wrapping_add(increment),
+        }
+
+        if event != SequenceEvent::Reordered {
+            state.sys_uptime = observation.sys_uptime.or(state.sys_uptime);
+        }
+        if increment > 0 {
+            state.push_recent(&observation);
+        }
+        state.stats.packets += 1;
+        state.stats.records += u64::from(observation.records);
+
+        event
+    }
+
+    pub fn observe_netflow9(&mut self, exporter: SocketAddr, netflow: &NetFlow9) -> SequenceEvent {
+        self.observe(
+            StreamKey::new(exporter, netflow.source_id),
+            Observation {
+                sequence: netflow.flow_sequence,
+                records: u32::from(netflow.record_count().unwrap_or(netflow.count)),
+                sys_uptime: Some(netflow.sys_uptime),
+                unit: SequenceUnit::Packet,
+            },
+        )
+    }
+
+    pub fn stats(&self, key: &StreamKey) -> Option<&SequenceStats> {
+        self.streams.get(key).map(|state| &state.stats)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&StreamKey, &SequenceStats)> {
+        self.streams.iter().map(|(key, state)| (key, &state.stats))
+    }
+}
+
+#[cfg(test)]
+mod test_sequence {
+    use super::*;
+
+    fn key() -> StreamKey {
+        StreamKey::new("192.0.2.1:2055".parse().unwrap(), 0)
+    }
+
+    fn packet(sequence: u32, sys_uptime: u32) -> Observation {
+        Observation {
+            sequence,
+            records: 10,
+            sys_uptime: Some(sys_uptime),
+            unit: SequenceUnit::Packet,
+        }
+    }
+
+    fn message(sequence: u32, records: u32) -> Observation {
+        Observation {
+            sequence,
+            records,
+            sys_uptime: None,
+            unit: SequenceUnit::Record,
+        }
+    }
+
+    #[test]
+    fn test_in_order() {
+        let mut tracker = SequenceTracker::new();
+        assert_eq!(tracker.observe(key(), packet(1, 100)), SequenceEvent::First);
+        assert_eq!(
+            tracker.observe(key(), packet(2, 200)),
+            SequenceEvent::InOrder
+        );
+        assert_eq!(
+            tracker.observe(key(), packet(3, 300)),
+            SequenceEvent::InOrder
+        );
+
+        let stats = tracker.stats(&key()).unwrap();
+        assert_eq!(stats.packets, 3);
+        assert_eq!(stats.records, 30);
+        assert_eq!(stats.lost_packets, 0);
+    }
+
+    #[test]
+    fn test_gap_and_reorder() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe(key(), packet(1, 100));
+        assert_eq!(
+            tracker.observe(key(), packet(4, 400)),
+            SequenceEvent::Gap {
+                expected: 2,
+                received: 4,
+                lost: 2
+            }
+        );
+        assert_eq!(tracker.stats(&key()).unwrap().lost_packets, 2);
+        assert_eq!(tracker.stats(&key()).unwrap().lost_records, 20);
+
+        // late packet fills the gap
+        assert_eq!(
+            tracker.observe(key(), packet(3, 300)),
+            SequenceEvent::Reordered
+        );
+        assert_eq!(
+            tracker.observe(key(), packet(5, 500)),
+            SequenceEvent::InOrder
+        );
+
+        let stats = tracker.stats(&key()).unwrap();
+        assert_eq!(stats.lost_packets, 1);
+        assert_eq!(stats.reordered, 1);
+        assert!(stats.loss_ratio() > 0.0);
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe(key(), packet(1, 100));
+        tracker.observe(key(), packet(2, 200));
+        assert_eq!(
+            tracker.observe(key(), packet(2, 200)),
+            SequenceEvent::Duplicate
+        );
+        assert_eq!(
+            tracker.observe(key(), packet(3, 300)),
+            SequenceEvent::InOrder
+        );
+        assert_eq!(tracker.stats(&key()).unwrap().duplicates, 1);
+        assert_eq!(tracker.stats(&key()).unwrap().packets, 3);
+    }
+
+    #[test]
+    fn test_restart() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe(key(), packet(5000, 100_000));
+        tracker.observe(key(), packet(5001, 101_000));
+
+        // sys_uptime went backwards
+        assert_eq!(
+            tracker.observe(key(), packet(0, 10)),
+            SequenceEvent::Restart
+        );
+        assert_eq!(
+            tracker.observe(key(), packet(1, 20)),
+            SequenceEvent::InOrder
+        );
+
+        // sequence reset without sys_uptime
+        let other = StreamKey::new("192.0.2.1:2055".parse().unwrap(), 1);
+        tracker.observe(other, message(100_000, 10));
+        assert_eq!(
+            tracker.observe(other, message(0, 10)),
+            SequenceEvent::Restart
+        );
+
+        assert_eq!(tracker.stats(&key()).unwrap().restarts, 1);
+        assert_eq!(tracker.stats(&key()).unwrap().lost_packets, 0);
+        assert_eq!(tracker.stats(&other).unwrap().restarts, 1);
+    }
+
+    #[test]
+    fn test_restart_reusing_sequence() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe(key(), packet(1, 100_000));
+        tracker.observe(key(), packet(2, 101_000));
+
+        // same sequence as a recent packet, but sent after a reboot
+        assert_eq!(
+            tracker.observe(key(), packet(2, 10)),
+            SequenceEvent::Restart
+        );
+        assert_eq!(
+            tracker.observe(key(), packet(3, 20)),
+            SequenceEvent::InOrder
+        );
+        // a copy of the packet is still a duplicate
+        assert_eq!(
+            tracker.observe(key(), packet(3, 20)),
+            SequenceEvent::Duplicate
+        );
+
+        let stats = tracker.stats(&key()).unwrap();
+        assert_eq!(stats.restarts, 1);
+        assert_eq!(stats.duplicates, 1);
+    }
+
+    #[test]
+    fn test_restart_jump_ahead() {
+        let mut tracker = SequenceTracker::with_window(100);
+        tracker.observe(key(), message(0, 10));
+        assert_eq!(
+            tracker.observe(key(), message(110, 10)),
+            SequenceEvent::Gap {
+                expected: 10,
+                received: 110,
+                lost: 100
+            }
+        );
+
+        // further ahead than the window
+        assert_eq!(
+            tracker.observe(key(), message(1 << 30, 10)),
+            SequenceEvent::Restart
+        );
+        assert_eq!(
+            tracker.observe(key(), message((1 << 30) + 10, 10)),
+            SequenceEvent::InOrder
+        );
+
+        let stats = tracker.stats(&key()).unwrap();
+        assert_eq!(stats.restarts, 1);
+        assert_eq!(stats.lost_records, 100);
+    }
+
+    #[test]
+    fn test_large_window() {
+        let mut tracker = SequenceTracker::with_window(u32::MAX);
+        tracker.observe(key(), message(100, 10));
+        tracker.observe(key(), message(110, 10));
+        assert_eq!(
+            tracker.observe(key(), message(50, 10)),
+            SequenceEvent::Reordered
+        );
+        assert_eq!(tracker.stats(&key()).unwrap().restarts, 0);
+    }
+
+    #[test]
+    fn test_evicted_gap() {
+        let mut tracker = SequenceTracker::with_window(10);
+        tracker.observe(key(), packet(1, 100));
+        // every other packet is lost, more gaps than are kept
+        for sequence in (3..=(3 + 2 * MAX_GAPS as u32)).step_by(2) {
+            tracker.observe(key(), packet(sequence, 100 + sequence));
+        }
+        let lost = tracker.stats(&key()).unwrap().lost_packets;
+        assert_eq!(lost, MAX_GAPS as u64 + 1);
+
+        // the first gap was dropped, its packet is late rather than a restart
+        assert_eq!(
+            tracker.observe(key(), packet(2, 102)),
+            SequenceEvent::Reordered
+        );
+        let stats = tracker.stats(&key()).unwrap();
+        assert_eq!(stats.restarts, 0);
+        assert_eq!(stats.lost_packets, lost - 1);
+
+        // further behind than any gap and the window is still a restart
+        assert_eq!(
+            tracker.observe(key(), packet(0, 1000)),
+            SequenceEvent::Restart
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe(key(), packet(u32::MAX, 100));
+        assert_eq!(
+            tracker.observe(key(), packet(0, 200)),
+            SequenceEvent::InOrder
+        );
+    }
+
+    #[test]
+    fn test_record_unit() {
+        let mut tracker = SequenceTracker::new();
+        tracker.observe(key(), message(0, 10));
+        // template only message doesn't increment
+        assert_eq!(
+            tracker.observe(key(), message(10, 0)),
+            SequenceEvent::InOrder
+        );
+        assert_eq!(
+            tracker.observe(key(), message(10, 10)),
+            SequenceEvent::InOrder
+        );
+        assert_eq!(
+            tracker.observe(key(), message(40, 10)),
+            SequenceEvent::Gap {
+                expected: 20,
+                received: 40,
+                lost: 20
+            }
+        );
+        assert_eq!(tracker.stats(&key()).unwrap().lost_records, 20);
+
+        assert_eq!(
+            tracker.observe(key(), message(20, 10)),
+            SequenceEvent::Reordered
+        );
+        assert_eq!(tracker.stats(&key()).unwrap().lost_records, 10);
+    }
+
+    #[test]
+    fn test_netflow9() {
+        let netflow = NetFlow9::new(1000, 1523936618, 883, 0, Vec::new());
+        let exporter = "192.0.2.1:2055".parse().unwrap();
+        let mut tracker = SequenceTracker::new();
+
+        assert_eq!(
+            tracker.observe_netflow9(exporter, &netflow),
+            SequenceEvent::First
+        );
+        assert_eq!(
+            tracker.observe_netflow9(exporter, &netflow),
+            SequenceEvent::Duplicate
+        );
+    }
+}
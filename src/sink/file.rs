@@ -0,0 +1,444 @@
+use super::OutputSink;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// When a file sink starts a new file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rotation {
+    /// files cover aligned windows of this length, like the 5 minute files of nfcapd
+    pub interval: Option<Duration>,
+    /// start a new file before the uncompressed size grows past this
+    pub max_size: Option<u64>,
+}
+
+impl Rotation {
+    pub fn none() -> Rotation {
+        Rotation {
+            interval: None,
+            max_size: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.interval.is_some() || self.max_size.is_some()
+    }
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation {
+            interval: Some(Duration::from_secs(300)),
+            max_size: None,
+        }
+    }
+}
+
+/// UTC time as YYYYMMDDhhmm, the timestamp format of nfcapd file names.
+fn file_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let minutes = secs % 86400 / 60;
+
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+enum Output {
+    Plain(BufWriter<File>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Output {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Output::Plain(writer) => writer,
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => encoder,
+        }
+    }
+
+    fn close(self) -> io::Result<()> {
+        match self {
+            Output::Plain(mut writer) => writer.flush(),
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+struct OpenFile {
+    path: PathBuf,
+    output: Output,
+    size: u64,
+    window: u64,
+}
+
+/// Writes records to a file, or to a series of files named `<prefix>.YYYYMMDDhhmm` when rotated.
+/// A window split by size continues in `<prefix>.YYYYMMDDhhmm.1` and so on. Without an interval,
+/// files are named by the minute they are opened in. Existing files are appended to and count
+/// towards the size limit.
+pub struct FileSink {
+    prefix: PathBuf,
+    rotation: Rotation,
+    #[cfg(feature = "zstd")]
+    zstd_level: Option<i32>,
+    current: Option<OpenFile>,
+    part: u32,
+    /// timestamp in the name of the last file opened
+    stamp: Option<u64>,
+    paths: Vec<PathBuf>,
+}
+
+impl FileSink {
+    /// Write to a single file, rotate() reopens it for appending.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<FileSink> {
+        let mut sink = FileSink::rotating(path, Rotation::none());
+        let file = File::create(&sink.prefix)?;
+        sink.current = Some(OpenFile {
+            path: sink.prefix.clone(),
+            output: Output::Plain(BufWriter::new(file)),
+            size: 0,
+            window: 0,
+        });
+        sink.paths.push(sink.prefix.clone());
+
+        Ok(sink)
+    }
+
+    /// Write to files starting with the prefix, a file is opened with the first record of a window.
+    pub fn rotating<P: AsRef<Path>>(prefix: P, rotation: Rotation) -> FileSink {
+        FileSink {
+            prefix: prefix.as_ref().to_path_buf(),
+            rotation,
+            #[cfg(feature = "zstd")]
+            zstd_level: None,
+            current: None,
+            part: 0,
+            stamp: None,
+            paths: Vec::new(),
+        }
+    }
+
+    /// Compress new files with zstd, they get a `.zst` suffix.
+    #[cfg(feature = "zstd")]
+    pub fn set_zstd_level(&mut self, level: Option<i32>) {
+        self.zstd_level = level;
+    }
+
+    /// Paths of the files opened so far.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    fn window(&self, now: SystemTime) -> u64 {
+        let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        match self.rotation.interval {
+            Some(interval) if interval.as_secs() > 0 => secs - secs % interval.as_secs(),
+            _ => 0,
+        }
+    }
+
+    fn path(&self, stamp: u64) -> PathBuf {
+        if !self.rotation.is_enabled() {
+            return self.prefix.clone();
+        }
+
+        let mut name = self.prefix.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(file_timestamp(stamp));
+        if self.part > 0 {
+            name.push(format!(".{}", self.part));
+        }
+        #[cfg(feature = "zstd")]
+        {
+            if self.zstd_level.is_some() {
+                name.push(".zst");
+            }
+        }
+
+        self.prefix.with_file_name(name)
+    }
+
+    fn open(&mut self, window: u64, now: SystemTime) -> io::Result<()> {
+        // size-only rotation names files by the minute they are opened in
+        let stamp = match self.rotation.interval {
+            Some(_) => window,
+            None => {
+                let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                secs - secs % 60
+            }
+        };
+        if self.stamp != Some(stamp) {
+            self.stamp = Some(stamp);
+            self.part = 0;
+        }
+
+        let path = self.path(stamp);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        // a file left by an earlier run counts towards the size limit, compressed files by
+        // their compressed size
+        let size = file.metadata()?.len();
+        let writer = BufWriter::new(file);
+        info!("writing {}", path.display());
+
+        #[cfg(feature = "zstd")]
+        let output = match self.zstd_level {
+            Some(level) => Output::Zstd(zstd::Encoder::new(writer, level)?),
+            None => Output::Plain(writer),
+        };
+        #[cfg(not(feature = "zstd"))]
+        let output = Output::Plain(writer);
+
+        if !self.paths.contains(&path) {
+            self.paths.push(path.clone());
+        }
+        self.current = Some(OpenFile {
+            path,
+            output,
+            size,
+            window,
+        });
+
+        Ok(())
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        match self.current.take() {
+            Some(file) => {
+                debug!("closing {}", file.path.display());
+                file.output.close()
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Write a record that belongs to the given time, e.g. the capture time of a replayed packet.
+    pub fn write_record_at(&mut self, record: &[u8], now: SystemTime) -> io::Result<()> {
+        let window = self.window(now);
+        let len = record.len() as u64;
+
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|file| file.window != window)
+        {
+            self.close()?;
+            self.part = 0;
+        }
+
+        // the next part once the file is full, it may have been filled by an earlier run
+        loop {
+            if self.current.is_none() {
+                self.open(window, now)?;
+            }
+            let full = self.current.as_ref().is_some_and(|file| {
+                self.rotation
+                    .max_size
+                    .is_some_and(|max| file.size > 0 && file.size + len > max)
+            });
+            if !full {
+                break;
+            }
+            self.close()?;
+            self.part += 1;
+        }
+
+        let file = self.current.as_mut().expect("file is open");
+        file.output.writer().write_all(record)?;
+        file.size += len;
+
+        Ok(())
+    }
+
+    /// Close the file, return the paths of every file written.
+    pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        self.close()?;
+        Ok(mem::take(&mut self.paths))
+    }
+}
+
+impl OutputSink for FileSink {
+    fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        self.write_record_at(record, SystemTime::now())
+    }
+
+    /// Flush the file, or close it when its window is over.
+    fn flush(&mut self) -> io::Result<()> {
+        let window = self.window(SystemTime::now());
+
+        match &mut self.current {
+            Some(file) if file.window != window => {
+                self.close()?;
+                self.part = 0;
+                Ok(())
+            }
+            Some(file) => file.output.writer().flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.close()?;
+        if self.rotation.is_enabled() {
+            self.part += 1;
+            Ok(())
+        } else {
+            self.open(0, SystemTime::now())
+        }
+    }
+}
+
+impl Drop for FileSink {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            warn!("cannot close {}: {}", self.prefix.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_file {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("netflow-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_file_timestamp() {
+        assert_eq!(file_timestamp(0), "197001010000");
+        assert_eq!(file_timestamp(1_523_936_618), "201804170343");
+        assert_eq!(file_timestamp(951_827_700), "200002291235");
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = temp_dir("rotation");
+        let rotation = Rotation {
+            interval: Some(Duration::from_secs(300)),
+            max_size: Some(12),
+        };
+        let mut sink = FileSink::rotating(dir.join("flows"), rotation);
+
+        let start = UNIX_EPOCH + Duration::from_secs(1_523_936_618);
+        sink.write_record_at(b"12345\n", start).unwrap();
+        sink.write_record_at(b"6789\n", start).unwrap();
+        // over the size limit
+        sink.write_record_at(b"abc\n", start).unwrap();
+        // next window
+        sink.write_record_at(b"def\n", start + Duration::from_secs(300))
+            .unwrap();
+
+        let paths = sink.finish().unwrap();
+        let names: Vec<_> = paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "flows.201804170340",
+                "flows.201804170340.1",
+                "flows.201804170345"
+            ]
+        );
+        assert_eq!(fs::read(&paths[0]).unwrap(), b"12345\n6789\n");
+        assert_eq!(fs::read(&paths[1]).unwrap(), b"abc\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_size_rotation() {
+        let dir = temp_dir("size");
+        let rotation = Rotation {
+            interval: None,
+            max_size: Some(12),
+        };
+        let start = UNIX_EPOCH + Duration::from_secs(1_523_936_618);
+
+        let mut sink = FileSink::rotating(dir.join("flows"), rotation);
+        sink.write_record_at(b"12345\n", start).unwrap();
+        sink.finish().unwrap();
+
+        // a restart appends to the file and keeps to the limit
+        let mut sink = FileSink::rotating(dir.join("flows"), rotation);
+        sink.write_record_at(b"6789\n", start).unwrap();
+        sink.write_record_at(b"abc\n", start).unwrap();
+        // only the size starts a new file
+        sink.write_record_at(b"def\n", start + Duration::from_secs(60))
+            .unwrap();
+
+        let paths = sink.finish().unwrap();
+        let names: Vec<_> = paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(names, vec!["flows.201804170343", "flows.201804170343.1"]);
+        assert_eq!(fs::read(&paths[0]).unwrap(), b"12345\n6789\n");
+        assert_eq!(fs::read(&paths[1]).unwrap(), b"abc\ndef\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_single_file() {
+        let dir = temp_dir("single");
+        let path = dir.join("flows.json");
+
+        let mut sink = FileSink::create(&path).unwrap();
+        sink.write_record(b"a\n").unwrap();
+        fs::rename(&path, dir.join("flows.json.1")).unwrap();
+        sink.rotate().unwrap();
+        sink.write_record(b"b\n").unwrap();
+        sink.finish().unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"b\n");
+        assert_eq!(fs::read(dir.join("flows.json.1")).unwrap(), b"a\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        let dir = temp_dir("zstd");
+        let mut sink = FileSink::rotating(dir.join("flows"), Rotation::default());
+        sink.set_zstd_level(Some(3));
+        sink.write_record(b"compressed\n").unwrap();
+
+        let paths = sink.finish().unwrap();
+        assert!(paths[0].to_str().unwrap().ends_with(".zst"));
+        let data = zstd::decode_all(File::open(&paths[0]).unwrap()).unwrap();
+        assert_eq!(data, b"compressed\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+mod file;
+pub use self::file::*;
+
+use std::io;
+use std::io::{BufWriter, Stdout, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+/// Destination of encoded records, such as JSON lines or Netflow packets.
+/// Stream outputs write the bytes as they are, so lines must carry their newline.
+/// Datagram outputs send a record per datagram.
+pub trait OutputSink {
+    fn write_record(&mut self, record: &[u8]) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Close the current output and start a new one, e.g. after logrotate moved a file.
+    fn rotate(&mut self) -> io::Result<()>;
+}
+
+impl<S: OutputSink + ?Sized> OutputSink for Box<S> {
+    fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        (**self).write_record(record)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        (**self).rotate()
+    }
+}
+
+/// Write a value as a line of JSON.
+pub fn write_json_record<S: OutputSink + ?Sized, T: serde::Serialize>(
+    sink: &mut S,
+    value: &T,
+) -> io::Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    sink.write_record(&line)
+}
+
+/// Adapts a sink to `io::Write`, every complete line written is a record.
+/// A partial line is kept until its newline arrives.
+pub struct SinkWriter<S> {
+    sink: S,
+    line: Vec<u8>,
+}
+
+impl<S: OutputSink> SinkWriter<S> {
+    pub fn new(sink: S) -> SinkWriter<S> {
+        SinkWriter {
+            sink,
+            line: Vec::new(),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+}
+
+impl<S: OutputSink> Write for SinkWriter<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+
+        while let Some(pos) = rest.iter().position(|byte| *byte == b'\n') {
+            if self.line.is_empty() {
+                self.sink.write_record(&rest[..=pos])?;
+            } else {
+                self.line.extend_from_slice(&rest[..=pos]);
+                self.sink.write_record(&self.line)?;
+                self.line.clear();
+            }
+            rest = &rest[pos + 1..];
+        }
+        self.line.extend_from_slice(rest);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+pub struct StdoutSink {
+    out: BufWriter<Stdout>,
+}
+
+impl StdoutSink {
+    pub fn new() -> StdoutSink {
+        StdoutSink {
+            out: BufWriter::new(io::stdout()),
+        }
+    }
+}
+
+impl Default for StdoutSink {
+    fn default() -> Self {
+        StdoutSink::new()
+    }
+}
+
+impl OutputSink for StdoutSink {
+    fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        self.out.write_all(record)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Writes records one after another to any writer.
+pub struct WriteSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> WriteSink<W> {
+    pub fn new(writer: W) -> WriteSink<W> {
+        WriteSink { writer }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> OutputSink for WriteSink<W> {
+    fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        self.writer.write_all(record)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Sends every record as a UDP datagram.
+pub struct UdpSink {
+    socket: UdpSocket,
+}
+
+impl UdpSink {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<UdpSink> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address"))?;
+        let local = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+
+        UdpSink::from_socket(UdpSocket::bind(local)?, addr)
+    }
+
+    /// Send from a socket bound by the caller, e.g. to choose the source port.
+    pub fn from_socket<A: ToSocketAddrs>(socket: UdpSocket, addr: A) -> io::Result<UdpSink> {
+        socket.connect(addr)?;
+        Ok(UdpSink { socket })
+    }
+}
+
+impl OutputSink for UdpSink {
+    fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        self.socket.send(record).map(|_| ())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes records to a Unix domain stream socket, reconnecting once when a write fails.
+#[cfg(unix)]
+pub struct UnixSink {
+    path: PathBuf,
+    stream: BufWriter<UnixStream>,
+}
+
+#[cfg(unix)]
+impl UnixSink {
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<UnixSink> {
+        let path = path.as_ref().to_path_buf();
+        let stream = BufWriter::new(UnixStream::connect(&path)?);
+
+        Ok(UnixSink { path, stream })
+    }
+
+    fn reconnect(&mut self) -> io::Result<()> {
+        self.stream = BufWriter::new(UnixStream::connect(&self.path)?);
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl OutputSink for UnixSink {
+    fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        if let Err(e) = self.stream.write_all(record) {
+            warn!(
+                "write to {} failed, reconnecting: {}",
+                self.path.display(),
+                e
+            );
+            self.reconnect()?;
+            self.stream.write_all(record)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // the peer may be gone, a new connection is made anyway
+        let _ = self.stream.flush();
+        self.reconnect()
+    }
+}
+
+/// Open a sink from a command line value:
+/// "-" for stdout, "udp:HOST:PORT", "unix:PATH", or a file path.
+/// A file is rotated when the rotation has an interval or a size.
+pub fn open_sink(target: &str, rotation: Rotation) -> io::Result<Box<dyn OutputSink>> {
+    if target == "-" {
+        return Ok(Box::new(StdoutSink::new()));
+    }
+    if let Some(addr) = target.strip_prefix("udp:") {
+        return Ok(Box::new(UdpSink::connect(addr)?));
+    }
+    if let Some(path) = target.strip_prefix("unix:") {
+        #[cfg(unix)]
+        return Ok(Box::new(UnixSink::connect(path)?));
+        #[cfg(not(unix))]
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unix sockets are not supported: {}", path),
+        ));
+    }
+
+    if rotation.is_enabled() {
+        Ok(Box::new(FileSink::rotating(target, rotation)))
+    } else {
+        Ok(Box::new(FileSink::create(target)?))
+    }
+}
+
+#[cfg(test)]
+mod test_sink {
+    use super::*;
+    use std::io::Read;
+    use std::time::Duration;
+
+    #[test]
+    fn test_udp() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let target = format!("udp:{}", receiver.local_addr().unwrap());
+        let mut sink = open_sink(&target, Rotation::none()).unwrap();
+        sink.write_record(b"first").unwrap();
+        write_json_record(&mut sink, &serde_json::json!({ "a": 1 })).unwrap();
+
+        let mut buf = [0u8; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"first");
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"{\"a\":1}\n");
+    }
+
+    #[test]
+    fn test_sink_writer() {
+        struct Records(Vec<Vec<u8>>);
+
+        impl OutputSink for Records {
+            fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+                self.0.push(record.to_vec());
+                Ok(())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+
+            fn rotate(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = SinkWriter::new(Records(Vec::new()));
+        writer.write_all(b"a\nb").unwrap();
+        writer.write_all(b"c\nd\n").unwrap();
+        assert_eq!(
+            writer.get_mut().0,
+            vec![b"a\n".to_vec(), b"bc\n".to_vec(), b"d\n".to_vec()]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix() {
+        use std::os::unix::net::UnixListener;
+
+        let path = std::env::temp_dir().join(format!("netflow-sink-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let mut sink = open_sink(&format!("unix:{}", path.display()), Rotation::none()).unwrap();
+        sink.write_record(b"line 1\n").unwrap();
+        sink.flush().unwrap();
+        drop(sink);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "line 1\n");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
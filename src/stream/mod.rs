@@ -0,0 +1,180 @@
+use crate::error::NetFlowError;
+use crate::flowset::{FlowSet, Record};
+use crate::netflow::{NetFlow9, TemplateCache};
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::ReadBuf;
+use tokio::net::{ToSocketAddrs, UdpSocket};
+
+const MAX_DATAGRAM_LEN: usize = 65535;
+
+/// Stream of decoded packets received on a tokio UdpSocket.
+///
+/// A datagram is read from the socket only when the stream is polled,
+/// so a slow consumer leaves packets in the socket buffer instead of queueing them in memory.
+/// Templates are kept per exporter address and source id.
+pub struct FlowStream {
+    socket: UdpSocket,
+    buf: Vec<u8>,
+    templates: TemplateCache,
+}
+
+impl FlowStream {
+    pub fn new(socket: UdpSocket) -> FlowStream {
+        FlowStream {
+            socket,
+            buf: vec![0u8; MAX_DATAGRAM_LEN],
+            templates: TemplateCache::new(),
+        }
+    }
+
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<FlowStream> {
+        Ok(FlowStream::new(UdpSocket::bind(addr).await?))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn templates(&self) -> &TemplateCache {
+        &self.templates
+    }
+
+    /// Receive the next packet, for callers not using a Stream combinator library.
+    pub async fn next_packet(&mut self) -> Option<Result<(SocketAddr, NetFlow9), NetFlowError>> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Turn into a stream yielding each decoded record with its exporter.
+    pub fn records(self) -> RecordStream {
+        RecordStream {
+            packets: self,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl Stream for FlowStream {
+    type Item = Result<(SocketAddr, NetFlow9), NetFlowError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut buf = ReadBuf::new(&mut this.buf);
+
+        match this.socket.poll_recv_from(cx, &mut buf) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(NetFlowError::from(e)))),
+            Poll::Ready(Ok(exporter)) => {
+                let res = this
+                    .templates
+                    .decode(exporter, buf.filled())
+                    .map(|netflow| (exporter, netflow));
+
+                Poll::Ready(Some(res))
+            }
+        }
+    }
+}
+
+/// Stream of decoded records, made by FlowStream::records.
+/// Dataflows whose template is not received yet are skipped.
+pub struct RecordStream {
+    packets: FlowStream,
+    pending: VecDeque<(SocketAddr, Record)>,
+}
+
+impl RecordStream {
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.packets.local_addr()
+    }
+
+    pub fn templates(&self) -> &TemplateCache {
+        self.packets.templates()
+    }
+
+    pub async fn next_record(&mut self) -> Option<Result<(SocketAddr, Record), NetFlowError>> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for RecordStream {
+    type Item = Result<(SocketAddr, Record), NetFlowError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(record) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(record)));
+            }
+
+            let (exporter, netflow) = match Pin::new(&mut this.packets).poll_next(cx) {
+                Poll::Ready(Some(Ok(packet))) => packet,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            for flowset in netflow.flow_sets {
+                if let FlowSet::DataFlow(dataflow) = flowset {
+                    if let Some(records) = dataflow.records {
+                        this.pending
+                            .extend(records.into_iter().map(|record| (exporter, record)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_stream {
+    use super::FlowStream;
+    use crate::netflow::test_data;
+    use tokio::net::UdpSocket;
+
+    #[tokio::test]
+    async fn test_packets() {
+        let mut stream = FlowStream::bind("127.0.0.1:0").await.unwrap();
+        let addr = stream.local_addr().unwrap();
+
+        let exporter = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        exporter.send_to(&[0x00, 0x09], addr).await.unwrap();
+        exporter
+            .send_to(&test_data::NETFLOWV9_DATA, addr)
+            .await
+            .unwrap();
+
+        assert!(stream.next_packet().await.unwrap().is_err());
+
+        let (from, netflow) = stream.next_packet().await.unwrap().unwrap();
+        assert_eq!(from, exporter.local_addr().unwrap());
+        assert_eq!(netflow.flow_sequence, 883);
+        assert_eq!(stream.templates().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_records() {
+        let stream = FlowStream::bind("127.0.0.1:0").await.unwrap();
+        let addr = stream.local_addr().unwrap();
+        let mut records = stream.records();
+
+        let exporter = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for _ in 0..2 {
+            exporter
+                .send_to(&test_data::NETFLOWV9_DATA, addr)
+                .await
+                .unwrap();
+        }
+
+        for _ in 0..4 {
+            let (from, _record) = records.next_record().await.unwrap().unwrap();
+            assert_eq!(from, exporter.local_addr().unwrap());
+        }
+    }
+}
@@ -0,0 +1,456 @@
+use crate::error::NetFlowError;
+use crate::field::{element_name, FieldValue, TypeLengthField};
+use crate::flowset::{
+    template_field, DataFlow, DataTemplate, DataTemplateItem, FlowSet, Record, MIN_TEMPLATE_ID,
+};
+use crate::netflow::NetFlow9;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Computes a field of the output record from the input record.
+pub type ComputeFn = Box<dyn Fn(&Record) -> FieldValue + Send + Sync>;
+
+enum Source {
+    /// index of the field in the input template
+    Input(usize),
+    Constant(FieldValue),
+    Computed(ComputeFn),
+}
+
+/// Maps records of an input template to records of an output template.
+///
+/// Output fields are copied from the input, constant, or computed from the input record,
+/// in the order they were added to the `ProjectionBuilder`.
+pub struct Projection {
+    input: DataTemplateItem,
+    output: DataTemplateItem,
+    sources: Vec<Source>,
+}
+
+/// Builds a `Projection`, see `Projection::builder`.
+pub struct ProjectionBuilder {
+    input: DataTemplateItem,
+    fields: Vec<(TypeLengthField, Source)>,
+    error: Option<NetFlowError>,
+}
+
+impl ProjectionBuilder {
+    fn add(mut self, field: Result<TypeLengthField, NetFlowError>, source: Source) -> Self {
+        if self.error.is_none() {
+            match field {
+                Ok(field) => self.fields.push((field, source)),
+                Err(error) => self.error = Some(error),
+            }
+        }
+        self
+    }
+
+    fn copied(&self, index: usize) -> bool {
+        self.fields
+            .iter()
+            .any(|(_, source)| matches!(source, Source::Input(i) if *i == index))
+    }
+
+    /// Copy the input fields with the id, they go after the fields added so far.
+    pub fn keep(mut self, type_id: u16) -> Self {
+        let indexes: Vec<usize> = (0..self.input.fields.len())
+            .filter(|i| {
+                let field = &self.input.fields[*i];
+                field.type_id == type_id && field.enterprise.is_none() && !self.copied(*i)
+            })
+            .collect();
+
+        if indexes.is_empty() {
+            if self.error.is_none() {
+                self.error = Some(NetFlowError::invalid_template(format!(
+                    "{} is not in template {}",
+                    element_name(type_id),
+                    self.input.template_id
+                )));
+            }
+            return self;
+        }
+
+        indexes.into_iter().fold(self, |builder, index| {
+            let field = builder.input.fields[index];
+            builder.add(Ok(field), Source::Input(index))
+        })
+    }
+
+    /// Copy every input field not copied yet, in input order.
+    pub fn keep_all(self) -> Self {
+        (0..self.input.fields.len()).fold(self, |builder, index| {
+            if builder.copied(index) {
+                builder
+            } else {
+                let field = builder.input.fields[index];
+                builder.add(Ok(field), Source::Input(index))
+            }
+        })
+    }
+
+    /// Remove the output fields with the id, e.g. after `keep_all`.
+    pub fn drop(mut self, type_id: u16) -> Self {
+        self.fields.retain(|(field, _)| field.type_id != type_id);
+        self
+    }
+
+    /// Add a field with the same value in every record.
+    pub fn constant<V: Into<FieldValue>>(self, type_id: u16, length: u16, value: V) -> Self {
+        self.add(
+            template_field(type_id, Some(length)),
+            Source::Constant(value.into()),
+        )
+    }
+
+    /// Add a field computed from the input record.
+    pub fn computed<F>(self, type_id: u16, length: u16, compute: F) -> Self
+    where
+        F: Fn(&Record) -> FieldValue + Send + Sync + 'static,
+    {
+        self.add(
+            template_field(type_id, Some(length)),
+            Source::Computed(Box::new(compute)),
+        )
+    }
+
+    /// Return the projection with the output template id.
+    pub fn build(self, template_id: u16) -> Result<Projection, NetFlowError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if template_id < MIN_TEMPLATE_ID {
+            return Err(NetFlowError::invalid_template(format!(
+                "template id {} is below {}",
+                template_id, MIN_TEMPLATE_ID
+            )));
+        }
+        if self.fields.is_empty() {
+            return Err(NetFlowError::invalid_template("no fields"));
+        }
+
+        let (fields, sources): (Vec<_>, Vec<_>) = self.fields.into_iter().unzip();
+        let projection = Projection {
+            input: self.input,
+            output: DataTemplateItem::new(template_id, fields),
+            sources,
+        };
+
+        // constants are checked once, computed values with each record
+        for (field, source) in projection.output.fields.iter().zip(&projection.sources) {
+            if let Source::Constant(value) = source {
+                DataTemplateItem::new(template_id, vec![*field])
+                    .record_builder()
+                    .set(field.type_id, value.clone())
+                    .build()
+                    .map_err(|e| match e {
+                        NetFlowError::InvalidRecord { desc } => {
+                            NetFlowError::invalid_template(desc)
+                        }
+                        e => e,
+                    })?;
+            }
+        }
+
+        Ok(projection)
+    }
+}
+
+impl Projection {
+    /// Start a projection of the input template.
+    pub fn builder(input: &DataTemplateItem) -> ProjectionBuilder {
+        ProjectionBuilder {
+            input: input.clone(),
+            fields: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn input(&self) -> &DataTemplateItem {
+        &self.input
+    }
+
+    pub fn output(&self) -> &DataTemplateItem {
+        &self.output
+    }
+
+    /// The same projection with another output template id.
+    pub fn with_template_id(mut self, template_id: u16) -> Projection {
+        self.output.template_id = template_id;
+        self
+    }
+
+    /// Map a record of the input template.
+    pub fn project(&self, record: &Record) -> Result<Record, NetFlowError> {
+        let input: Vec<_> = record.fields().collect();
+        if input.len() != self.input.fields.len() {
+            return Err(NetFlowError::InvalidRecord {
+                desc: format!(
+                    "{} fields for template {} of {} fields",
+                    input.len(),
+                    self.input.template_id,
+                    self.input.fields.len()
+                ),
+            });
+        }
+
+        let mut builder = self.output.record_builder();
+        for (field, source) in self.output.fields.iter().zip(&self.sources) {
+            let value = match source {
+                Source::Input(index) => input[*index].value().clone(),
+                Source::Constant(value) => value.clone(),
+                Source::Computed(compute) => compute(record),
+            };
+            builder = builder.set(field.type_id, value);
+        }
+
+        builder.build()
+    }
+
+    /// The template flowset of the output template.
+    pub fn data_template(&self) -> DataTemplate {
+        DataTemplate::new(vec![self.output.clone()])
+    }
+
+    /// The output dataflow of a decoded input dataflow.
+    pub fn dataflow(&self, dataflow: &DataFlow) -> Result<DataFlow, NetFlowError> {
+        let records = dataflow
+            .records
+            .as_ref()
+            .ok_or(NetFlowError::TemplateNotFound)?
+            .iter()
+            .map(|record| self.project(record))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(DataFlow::new(self.output.template_id, records))
+    }
+}
+
+/// Allocates output template ids, the same key always gets the same id.
+///
+/// Use it when templates of several exporters or source ids go into one export stream,
+/// keyed e.g. by exporter, source id and input template id.
+#[derive(Debug, Clone)]
+pub struct TemplateIds<K: Hash + Eq> {
+    next: u16,
+    ids: HashMap<K, u16>,
+}
+
+impl<K: Hash + Eq> Default for TemplateIds<K> {
+    fn default() -> Self {
+        TemplateIds::new()
+    }
+}
+
+impl<K: Hash + Eq> TemplateIds<K> {
+    pub fn new() -> TemplateIds<K> {
+        TemplateIds {
+            next: MIN_TEMPLATE_ID,
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Id of the key, None once the 65280 template ids are used.
+    pub fn id(&mut self, key: K) -> Option<u16> {
+        if let Some(id) = self.ids.get(&key) {
+            return Some(*id);
+        }
+        if self.ids.len() > usize::from(u16::MAX - MIN_TEMPLATE_ID) {
+            return None;
+        }
+
+        let id = self.next;
+        self.next = self.next.wrapping_add(1);
+        self.ids.insert(key, id);
+        Some(id)
+    }
+
+    /// Id of the key if it was allocated.
+    pub fn get(&self, key: &K) -> Option<u16> {
+        self.ids.get(key).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+/// Project a decoded packet, projections are keyed by input template id.
+/// Templates and data of the projected templates are replaced, every other flowset is dropped,
+/// so no field leaves without a projection selecting it.
+pub fn project_netflow(
+    netflow: &NetFlow9,
+    projections: &HashMap<u16, Projection>,
+) -> Result<NetFlow9, NetFlowError> {
+    let mut flowsets = Vec::new();
+
+    for flowset in &netflow.flow_sets {
+        match flowset {
+            FlowSet::DataTemplate(template) => {
+                let items: Vec<DataTemplateItem> = template
+                    .templates
+                    .iter()
+                    .filter_map(|item| projections.get(&item.template_id))
+                    .map(|projection| projection.output.clone())
+                    .collect();
+                if !items.is_empty() {
+                    flowsets.push(DataTemplate::new(items).into());
+                }
+            }
+            FlowSet::DataFlow(dataflow) => {
+                if let Some(projection) = projections.get(&dataflow.flowset_id) {
+                    flowsets.push(projection.dataflow(dataflow)?.into());
+                }
+            }
+            FlowSet::OptionTemplate(_) => (),
+        }
+    }
+
+    Ok(NetFlow9::new(
+        netflow.sys_uptime,
+        netflow.timestamp,
+        netflow.flow_sequence,
+        netflow.source_id,
+        flowsets,
+    ))
+}
+
+#[cfg(test)]
+mod test_transform {
+    use super::*;
+    use crate::field::FieldTypes::*;
+    use crate::field::MacAddr;
+    use crate::netflow::TemplateCache;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    fn input() -> DataTemplateItem {
+        DataTemplateItem::builder(300)
+            .field(IPV4_SRC_ADDR)
+            .field(IPV4_NEXT_HOP)
+            .field(SRC_MAC)
+            .field(IN_BYTES)
+            .field(IN_PKTS)
+            .build()
+            .unwrap()
+    }
+
+    fn record(template: &DataTemplateItem) -> Record {
+        template
+            .record_builder()
+            .set(IPV4_SRC_ADDR, Ipv4Addr::new(10, 0, 0, 1))
+            .set(IPV4_NEXT_HOP, Ipv4Addr::new(10, 255, 0, 1))
+            .set(SRC_MAC, MacAddr::new(0, 1, 2, 3, 4, 5))
+            .set(IN_BYTES, 3000u32)
+            .set(IN_PKTS, 3u32)
+            .build()
+            .unwrap()
+    }
+
+    fn projection() -> Projection {
+        Projection::builder(&input())
+            .keep(IN_BYTES)
+            .keep_all()
+            .drop(SRC_MAC)
+            .drop(IPV4_NEXT_HOP)
+            .constant(DIRECTION, 1, 1u8)
+            .computed(MAX_PKT_LENGTH, 2, |record| {
+                let value = |id| record.get(id).and_then(|f| f.value().to_u64()).unwrap_or(0);
+                let packets = value(IN_PKTS).max(1);
+                ((value(IN_BYTES) / packets) as u16).into()
+            })
+            .build(400)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_project() {
+        let projection = projection();
+        let ids: Vec<u16> = projection
+            .output()
+            .fields
+            .iter()
+            .map(|f| f.type_id)
+            .collect();
+        assert_eq!(
+            ids,
+            vec![IN_BYTES, IPV4_SRC_ADDR, IN_PKTS, DIRECTION, MAX_PKT_LENGTH]
+        );
+
+        let output = projection.project(&record(&input())).unwrap();
+        assert_eq!(
+            output.to_bytes().len(),
+            projection
+                .output()
+                .fields
+                .iter()
+                .map(|f| f.length as usize)
+                .sum::<usize>()
+        );
+        assert_eq!(output.get(IN_BYTES).unwrap().value().to_u64(), Some(3000));
+        assert_eq!(output.get(DIRECTION).unwrap().value().to_u64(), Some(1));
+        assert_eq!(
+            output.get(MAX_PKT_LENGTH).unwrap().value().to_u64(),
+            Some(1000)
+        );
+        assert!(output.get(SRC_MAC).is_none());
+
+        let invalid = |builder: ProjectionBuilder| match builder.build(400) {
+            Err(NetFlowError::InvalidTemplate { desc }) => desc,
+            other => panic!("unexpected result: {:?}", other.map(|p| p.output().clone())),
+        };
+        assert_eq!(
+            invalid(Projection::builder(&input()).keep(L4_SRC_PORT)),
+            "sourceTransportPort is not in template 300"
+        );
+        assert_eq!(
+            invalid(Projection::builder(&input()).constant(DIRECTION, 1, 300u16)),
+            "flowDirection: number does not fit in 1 bytes"
+        );
+    }
+
+    #[test]
+    fn test_project_netflow() {
+        let input = input();
+        let exporter: SocketAddr = "192.0.2.1:2055".parse().unwrap();
+        let netflow = NetFlow9::new(
+            1000,
+            1_600_000_000,
+            7,
+            1,
+            vec![
+                DataTemplate::new(vec![input.clone()]).into(),
+                DataFlow::new(300, vec![record(&input); 2]).into(),
+            ],
+        );
+        let mut cache = TemplateCache::new();
+        let decoded = cache.decode(exporter, &netflow.to_bytes()).unwrap();
+
+        let mut ids = TemplateIds::new();
+        let id = ids.id((exporter, 1u32, 300u16)).unwrap();
+        assert_eq!(id, 256);
+        assert_eq!(ids.id((exporter, 2, 300)), Some(257));
+        assert_eq!(ids.id((exporter, 1, 300)), Some(256));
+
+        let mut projections = HashMap::new();
+        projections.insert(300, projection().with_template_id(id));
+        let projected = project_netflow(&decoded, &projections).unwrap();
+        assert_eq!(projected.flow_sequence, 7);
+        assert_eq!(projected.count, 3);
+
+        let mut cache = TemplateCache::new();
+        let decoded = cache.decode(exporter, &projected.to_bytes()).unwrap();
+        match &decoded.flow_sets[1] {
+            FlowSet::DataFlow(dataflow) => {
+                assert_eq!(dataflow.flowset_id, 256);
+                let records = dataflow.records.as_ref().unwrap();
+                assert_eq!(records.len(), 2);
+                assert!(records[0].get(IPV4_NEXT_HOP).is_none());
+            }
+            flowset => panic!("unexpected flowset: {:?}", flowset),
+        }
+    }
+}
@@ -1,6 +1,10 @@
-use byteorder::{BigEndian, ByteOrder};
 use crate::error;
+use crate::error::NetFlowError;
+use byteorder::{BigEndian, ByteOrder};
 use nom::{be_u16, be_u32, be_u64};
+use std::io;
+use std::io::Read;
+use std::net::IpAddr;
 
 named!(inner_take_u16 <&[u8], u16>, map!(take!(2), |i| be_u16(i).unwrap().1));
 named!(inner_take_u32 <&[u8], u32>, map!(take!(4), |i| be_u32(i).unwrap().1));
@@ -58,6 +62,38 @@ pub fn u128_to_bytes(src: u128, dst: &mut [u8; 16]) {
     }
 }
 
+pub fn address_bytes(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(ipv4) => ipv4.octets().to_vec(),
+        IpAddr::V6(ipv6) => ipv6.octets().to_vec(),
+    }
+}
+
+/// Read exactly buf.len() bytes, return false on EOF before the first byte.
+pub fn read_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, NetFlowError> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(NetFlowError::invalid_capture("truncated file")),
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(NetFlowError::from(e)),
+        }
+    }
+
+    Ok(true)
+}
+
+pub fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), NetFlowError> {
+    if read_or_eof(reader, buf)? {
+        Ok(())
+    } else {
+        Err(NetFlowError::invalid_capture("truncated file"))
+    }
+}
+
 #[cfg(test)]
 mod test_util {
     use crate::util;